@@ -0,0 +1,120 @@
+//! Throughput of parsing normalized messages and moving them through the channels and buffers
+//! a consumer usually holds them in.
+//!
+//! Run with `cargo bench --features machine --bench message`.
+
+use std::{collections::VecDeque, sync::mpsc};
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use tardis_rs::machine::{micros::MessageMicros, Message, MessageRef};
+
+const CORPUS: &str = include_str!("../fixtures/golden/corpus.ndjson");
+
+/// The number of messages moved per iteration.
+const BATCH: usize = 10_000;
+
+fn messages() -> Vec<Message> {
+    let corpus = CORPUS
+        .lines()
+        .map(|line| serde_json::from_str::<Message>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    corpus.iter().cycle().take(BATCH).cloned().collect()
+}
+
+fn parse(c: &mut Criterion) {
+    let lines = CORPUS.lines().collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Elements(lines.len() as u64));
+    group.bench_function("corpus", |b| {
+        b.iter(|| {
+            for line in &lines {
+                black_box(serde_json::from_str::<Message>(line).unwrap());
+            }
+        })
+    });
+    group.bench_function("corpus_borrowed", |b| {
+        b.iter(|| {
+            for line in &lines {
+                black_box(serde_json::from_str::<MessageRef>(line).unwrap());
+            }
+        })
+    });
+    group.bench_function("corpus_micros", |b| {
+        b.iter(|| {
+            for line in &lines {
+                black_box(serde_json::from_str::<MessageMicros>(line).unwrap());
+            }
+        })
+    });
+    group.finish();
+
+    // A full order book snapshot, where parsing the levels dominates.
+    let levels = |side: f64| {
+        (0..1_000)
+            .map(|i| {
+                format!(
+                    r#"{{"price":{},"amount":{}}}"#,
+                    20_000.0 + side * i as f64 * 0.5,
+                    1.25
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let book = format!(
+        r#"{{"type":"book_change","symbol":"XBTUSD","exchange":"bitmex","isSnapshot":true,"bids":[{}],"asks":[{}],"timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}}"#,
+        levels(-1.0),
+        levels(1.0)
+    );
+
+    let mut group = c.benchmark_group("parse_book");
+    group.throughput(Throughput::Bytes(book.len() as u64));
+    group.bench_function("snapshot", |b| {
+        b.iter(|| black_box(serde_json::from_str::<Message>(&book).unwrap()))
+    });
+    group.finish();
+}
+
+fn transfer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transfer");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    group.bench_function("channel", |b| {
+        b.iter_batched(
+            messages,
+            |messages| {
+                let (sender, receiver) = mpsc::channel();
+                for message in messages {
+                    sender.send(message).unwrap();
+                }
+                drop(sender);
+                receiver.iter().for_each(|message| drop(black_box(message)));
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("buffer", |b| {
+        b.iter_batched(
+            messages,
+            |messages| {
+                let mut buffer = VecDeque::new();
+                for message in messages {
+                    buffer.push_back(message);
+                    if buffer.len() > 100 {
+                        black_box(buffer.pop_front());
+                    }
+                }
+                buffer
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse, transfer);
+criterion_main!(benches);
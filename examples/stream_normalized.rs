@@ -1,7 +1,7 @@
 use futures_util::StreamExt;
 use tardis_rs::{
-    machine::{Client, StreamNormalizedRequestOptions},
-    Exchange,
+    machine::{Client, DataType, IntervalUnit, StreamNormalizedRequestOptions},
+    Exchange, RestartPolicy,
 };
 
 #[tokio::main]
@@ -10,46 +10,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_max_level(tracing::Level::INFO)
         .init();
 
-    let client = Client::new(std::env::var("TARDIS_MACHINE_WS_URL").unwrap());
+    let client = Client::builder(std::env::var("TARDIS_MACHINE_WS_URL").unwrap())
+        .reconnect(RestartPolicy::default())
+        .build();
 
-    let option = StreamNormalizedRequestOptions {
-        exchange: Exchange::Bybit,
-        symbols: Some(vec!["BTCUSDT".to_string()]),
-        data_types: vec!["trade_bar_15m".to_string()],
-        with_disconnect_messages: None,
-        timeout_interval_ms: None,
-    };
+    let option = StreamNormalizedRequestOptions::builder(Exchange::Bybit)
+        .symbols_iter(["BTCUSDT"])
+        .data_type(DataType::TradeBar {
+            interval: 15,
+            unit: IntervalUnit::Minutes,
+        })
+        .build();
 
-    let mut stream = Box::pin(
-        client
-            .stream_normalized(vec![option.clone()])
-            .await
-            .unwrap(),
-    );
+    let stream = client.stream_normalized(vec![option]).await.unwrap();
+    futures_util::pin_mut!(stream);
 
-    loop {
-        match stream.next().await {
-            Some(Ok(message)) => {
-                tracing::info!("{:?}", message)
-            }
-            Some(Err(e)) => {
-                tracing::error!("Err: {}", e);
-                stream = Box::pin(
-                    client
-                        .stream_normalized(vec![option.clone()])
-                        .await
-                        .unwrap(),
-                );
-            }
-            None => {
-                tracing::error!("Stream got to None, reconnecting");
-                stream = Box::pin(
-                    client
-                        .stream_normalized(vec![option.clone()])
-                        .await
-                        .unwrap(),
-                );
-            }
+    while let Some(message) = stream.next().await {
+        match message {
+            Ok(message) => tracing::info!("{}", message),
+            Err(e) => tracing::error!("Err: {}", e),
         }
     }
+
+    Ok(())
 }
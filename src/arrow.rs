@@ -0,0 +1,716 @@
+//! Conversion of normalized messages and dataset rows to [Arrow](https://arrow.apache.org)
+//! record batches, eg. to hand them over to DataFusion or Polars.
+//!
+//! The columns follow the CSV layout of the
+//! [Tardis datasets](https://docs.tardis.dev/downloadable-csv-files#data-types), see
+//! [`CsvRecord`](crate::sink::CsvRecord), with typed arrays: timestamps in microseconds since the
+//! epoch in UTC, strings for the exchange, symbol, id, side and other names, booleans, integers
+//! for counts and intervals, and floats for everything else. Empty fields are null.
+//!
+//! With the `machine` feature, `RecordBatchBuilder` builds the batches of normalized messages,
+//! eg. `Vec<Trade>`, from their fields.
+//!
+//! ```
+//! use tardis_rs::arrow::schema;
+//!
+//! let schema = schema(&["exchange", "symbol", "timestamp", "local_timestamp", "price"]);
+//! assert_eq!(schema.field(2).data_type().to_string(), "Timestamp(Microsecond, Some(\"UTC\"))");
+//! ```
+
+use std::sync::Arc;
+
+pub use arrow_array::RecordBatch;
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray, TimestampMicrosecondArray,
+};
+use arrow_schema::{ArrowError, DataType, Field, TimeUnit};
+pub use arrow_schema::{Schema, SchemaRef};
+use csv::StringRecord;
+
+/// Returns the type of a column of the CSV layout of the datasets, by its name.
+fn column_type(name: &str) -> DataType {
+    match name {
+        "exchange" | "symbol" | "id" | "side" | "type" | "name" | "underlying_index" => {
+            DataType::Utf8
+        }
+        "timestamp" | "local_timestamp" | "funding_timestamp" | "expiration" | "open_timestamp"
+        | "close_timestamp" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        "is_snapshot" => DataType::Boolean,
+        "interval" | "trades" => DataType::Int64,
+        _ => DataType::Float64,
+    }
+}
+
+/// Returns the schema of the rows with `columns` in the CSV layout of the datasets, eg. the
+/// [`header`](crate::sink::CsvRecord::header) of a normalized message. Every column is nullable,
+/// as empty fields are missing values.
+pub fn schema(columns: &[impl AsRef<str>]) -> SchemaRef {
+    Arc::new(Schema::new(
+        columns
+            .iter()
+            .map(|name| Field::new(name.as_ref(), column_type(name.as_ref()), true))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Builds a batch of `rows`, parsing their fields as the types of the columns of `schema`.
+pub(crate) fn batch_from_rows(
+    schema: &SchemaRef,
+    rows: &[StringRecord],
+) -> std::result::Result<RecordBatch, ArrowError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let values = rows
+                .iter()
+                .map(move |row| row.get(i).filter(|value| !value.is_empty()));
+            column(field, values)
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+fn column<'a>(
+    field: &Field,
+    values: impl Iterator<Item = Option<&'a str>>,
+) -> std::result::Result<ArrayRef, ArrowError> {
+    fn parse<'a, T: std::str::FromStr>(
+        field: &Field,
+        values: impl Iterator<Item = Option<&'a str>>,
+    ) -> std::result::Result<Vec<Option<T>>, ArrowError> {
+        values
+            .map(|value| {
+                value
+                    .map(|value| {
+                        value.parse::<T>().map_err(|_| {
+                            ArrowError::ParseError(format!(
+                                "Invalid {} value of column {}: {}",
+                                field.data_type(),
+                                field.name(),
+                                value
+                            ))
+                        })
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+
+    Ok(match field.data_type() {
+        DataType::Utf8 => Arc::new(values.collect::<StringArray>()),
+        DataType::Boolean => Arc::new(BooleanArray::from(parse::<bool>(field, values)?)),
+        DataType::Int64 => Arc::new(Int64Array::from(parse::<i64>(field, values)?)),
+        DataType::Timestamp(_, timezone) => Arc::new(
+            TimestampMicrosecondArray::from(parse::<i64>(field, values)?)
+                .with_timezone_opt(timezone.clone()),
+        ),
+        _ => Arc::new(Float64Array::from(parse::<f64>(field, values)?)),
+    })
+}
+
+#[cfg(feature = "machine")]
+mod messages {
+    use std::{borrow::Cow, fmt, marker::PhantomData};
+
+    use arrow_array::builder::{
+        ArrayBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder,
+        TimestampMicrosecondBuilder,
+    };
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+    use crate::{
+        machine::{
+            BookChange, BookSnapshot, BookTicker, DerivativeTicker, Liquidation, OptionSummary,
+            Trade, TradeBar, TradeSide,
+        },
+        sink::CsvRecord,
+        Result,
+    };
+
+    /// A value of a row of an [`ArrowRecord`], `None` being null.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum ArrowValue<'a> {
+        /// A value of a `Utf8` column.
+        Utf8(Option<Cow<'a, str>>),
+
+        /// A value of a `Timestamp` column, in microseconds since the epoch.
+        Timestamp(Option<i64>),
+
+        /// A value of a `Boolean` column.
+        Boolean(Option<bool>),
+
+        /// A value of an `Int64` column.
+        Int64(Option<i64>),
+
+        /// A value of a `Float64` column.
+        Float64(Option<f64>),
+    }
+
+    impl ArrowValue<'_> {
+        /// Returns whether the value belongs in a column of `data_type`.
+        fn fits(&self, data_type: &DataType) -> bool {
+            matches!(
+                (self, data_type),
+                (Self::Utf8(_), DataType::Utf8)
+                    | (
+                        Self::Timestamp(_),
+                        DataType::Timestamp(TimeUnit::Microsecond, _)
+                    )
+                    | (Self::Boolean(_), DataType::Boolean)
+                    | (Self::Int64(_), DataType::Int64)
+                    | (Self::Float64(_), DataType::Float64)
+            )
+        }
+    }
+
+    impl<'a> From<&'a str> for ArrowValue<'a> {
+        fn from(value: &'a str) -> Self {
+            Self::Utf8(Some(value.into()))
+        }
+    }
+
+    impl<'a> From<Option<&'a str>> for ArrowValue<'a> {
+        fn from(value: Option<&'a str>) -> Self {
+            Self::Utf8(value.map(Cow::Borrowed))
+        }
+    }
+
+    impl From<&DateTime<Utc>> for ArrowValue<'_> {
+        fn from(value: &DateTime<Utc>) -> Self {
+            Self::Timestamp(Some(value.timestamp_micros()))
+        }
+    }
+
+    impl From<bool> for ArrowValue<'_> {
+        fn from(value: bool) -> Self {
+            Self::Boolean(Some(value))
+        }
+    }
+
+    impl From<u64> for ArrowValue<'_> {
+        /// Values over `i64::MAX` are null.
+        fn from(value: u64) -> Self {
+            Self::Int64(i64::try_from(value).ok())
+        }
+    }
+
+    impl From<f64> for ArrowValue<'_> {
+        fn from(value: f64) -> Self {
+            Self::Float64(Some(value))
+        }
+    }
+
+    impl From<Option<f64>> for ArrowValue<'_> {
+        fn from(value: Option<f64>) -> Self {
+            Self::Float64(value)
+        }
+    }
+
+    /// Returns an empty builder of the arrays of `data_type`, one of the types of [`schema`].
+    fn builder(data_type: &DataType) -> Box<dyn ArrayBuilder> {
+        match data_type {
+            DataType::Utf8 => Box::new(StringBuilder::new()),
+            DataType::Timestamp(_, timezone) => {
+                Box::new(TimestampMicrosecondBuilder::new().with_timezone_opt(timezone.clone()))
+            }
+            DataType::Boolean => Box::new(BooleanBuilder::new()),
+            DataType::Int64 => Box::new(Int64Builder::new()),
+            _ => Box::new(Float64Builder::new()),
+        }
+    }
+
+    /// Appends `value` to `builder`, whose type matches it.
+    fn append(builder: &mut dyn ArrayBuilder, value: &ArrowValue<'_>) {
+        let builder = builder.as_any_mut();
+        let mismatch = "values are checked against the schema";
+        match value {
+            ArrowValue::Utf8(value) => builder
+                .downcast_mut::<StringBuilder>()
+                .expect(mismatch)
+                .append_option(value.as_deref()),
+            ArrowValue::Timestamp(value) => builder
+                .downcast_mut::<TimestampMicrosecondBuilder>()
+                .expect(mismatch)
+                .append_option(*value),
+            ArrowValue::Boolean(value) => builder
+                .downcast_mut::<BooleanBuilder>()
+                .expect(mismatch)
+                .append_option(*value),
+            ArrowValue::Int64(value) => builder
+                .downcast_mut::<Int64Builder>()
+                .expect(mismatch)
+                .append_option(*value),
+            ArrowValue::Float64(value) => builder
+                .downcast_mut::<Float64Builder>()
+                .expect(mismatch)
+                .append_option(*value),
+        }
+    }
+
+    /// The typed columns of the rows appended by [`ArrowRecord`]s.
+    pub struct ArrowRows {
+        schema: SchemaRef,
+        builders: Vec<Box<dyn ArrayBuilder>>,
+    }
+
+    impl fmt::Debug for ArrowRows {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("ArrowRows")
+                .field("schema", &self.schema)
+                .field("len", &self.len())
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl ArrowRows {
+        pub(super) fn new(schema: SchemaRef) -> Self {
+            let builders = schema
+                .fields()
+                .iter()
+                .map(|field| builder(field.data_type()))
+                .collect();
+            Self { schema, builders }
+        }
+
+        /// Appends a row, with a value of the type of each column of the schema. A row that
+        /// doesn't match the schema is rejected, leaving the columns untouched.
+        pub fn push(&mut self, row: &[ArrowValue<'_>]) -> Result<()> {
+            let fields = self.schema.fields();
+            if row.len() != fields.len() {
+                return Err(ArrowError::InvalidArgumentError(format!(
+                    "Row of {} values for a schema of {} columns",
+                    row.len(),
+                    fields.len()
+                ))
+                .into());
+            }
+            if let Some((field, value)) = fields
+                .iter()
+                .zip(row)
+                .find(|(field, value)| !value.fits(field.data_type()))
+            {
+                return Err(ArrowError::InvalidArgumentError(format!(
+                    "Invalid {} value of column {}: {:?}",
+                    field.data_type(),
+                    field.name(),
+                    value
+                ))
+                .into());
+            }
+            for (builder, value) in self.builders.iter_mut().zip(row) {
+                append(builder.as_mut(), value);
+            }
+            Ok(())
+        }
+
+        pub(super) fn len(&self) -> usize {
+            self.builders.first().map_or(0, |builder| builder.len())
+        }
+
+        pub(super) fn finish(&mut self) -> Result<RecordBatch> {
+            let columns = self
+                .builders
+                .iter_mut()
+                .map(|builder| builder.finish())
+                .collect();
+            Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+        }
+    }
+
+    /// A normalized message with a typed Arrow layout: the columns of its [`CsvRecord`], typed as
+    /// by [`schema`].
+    pub trait ArrowRecord: CsvRecord {
+        /// Returns the schema of the rows of the message, which may depend on the message, eg.
+        /// the depth of a book snapshot.
+        fn schema(&self) -> SchemaRef {
+            schema(&self.header())
+        }
+
+        /// Appends the rows of the message, most messages having a single one.
+        fn append_rows(&self, rows: &mut ArrowRows) -> Result<()>;
+    }
+
+    fn side(side: TradeSide) -> &'static str {
+        match side {
+            TradeSide::Buy => "buy",
+            TradeSide::Sell => "sell",
+            TradeSide::Unknown => "unknown",
+        }
+    }
+
+    impl ArrowRecord for Trade {
+        fn append_rows(&self, rows: &mut ArrowRows) -> Result<()> {
+            rows.push(&[
+                self.exchange.as_str().into(),
+                self.symbol.as_str().into(),
+                (&self.timestamp).into(),
+                (&self.local_timestamp).into(),
+                self.id.as_deref().into(),
+                side(self.side).into(),
+                self.price.into(),
+                self.amount.into(),
+            ])
+        }
+    }
+
+    impl ArrowRecord for Liquidation {
+        fn append_rows(&self, rows: &mut ArrowRows) -> Result<()> {
+            rows.push(&[
+                self.exchange.as_str().into(),
+                self.symbol.as_str().into(),
+                (&self.timestamp).into(),
+                (&self.local_timestamp).into(),
+                self.id.as_deref().into(),
+                side(self.side).into(),
+                self.price.into(),
+                self.amount.into(),
+            ])
+        }
+    }
+
+    impl ArrowRecord for BookChange {
+        /// Appends a row per level, as in the `incremental_book_L2` dataset.
+        fn append_rows(&self, rows: &mut ArrowRows) -> Result<()> {
+            let levels = self
+                .bids
+                .iter()
+                .map(|level| ("bid", level))
+                .chain(self.asks.iter().map(|level| ("ask", level)));
+            for (side, level) in levels {
+                rows.push(&[
+                    self.exchange.as_str().into(),
+                    self.symbol.as_str().into(),
+                    (&self.timestamp).into(),
+                    (&self.local_timestamp).into(),
+                    self.is_snapshot.into(),
+                    side.into(),
+                    level.price.into(),
+                    level.amount.into(),
+                ])?;
+            }
+            Ok(())
+        }
+    }
+
+    impl ArrowRecord for DerivativeTicker {
+        fn append_rows(&self, rows: &mut ArrowRows) -> Result<()> {
+            rows.push(&[
+                self.exchange.as_str().into(),
+                self.symbol.as_str().into(),
+                (&self.timestamp).into(),
+                (&self.local_timestamp).into(),
+                self.funding_rate.into(),
+                self.open_interest.into(),
+                self.last_price.into(),
+                self.index_price.into(),
+                self.mark_price.into(),
+            ])
+        }
+    }
+
+    impl ArrowRecord for BookTicker {
+        fn append_rows(&self, rows: &mut ArrowRows) -> Result<()> {
+            rows.push(&[
+                self.exchange.as_str().into(),
+                self.symbol.as_str().into(),
+                (&self.timestamp).into(),
+                (&self.local_timestamp).into(),
+                self.ask_amount.into(),
+                self.ask_price.into(),
+                self.bid_price.into(),
+                self.bid_amount.into(),
+            ])
+        }
+    }
+
+    impl ArrowRecord for OptionSummary {
+        fn append_rows(&self, rows: &mut ArrowRows) -> Result<()> {
+            let option_type = self.option_type.to_string();
+            rows.push(&[
+                self.exchange.as_str().into(),
+                self.symbol.as_str().into(),
+                (&self.timestamp).into(),
+                (&self.local_timestamp).into(),
+                option_type.as_str().into(),
+                self.strike_price.into(),
+                (&self.expiration_date).into(),
+                self.open_interest.into(),
+                self.last_price.into(),
+                self.best_bid_price.into(),
+                self.best_bid_amount.into(),
+                self.best_bid_iv.into(),
+                self.best_ask_price.into(),
+                self.best_ask_amount.into(),
+                self.best_ask_iv.into(),
+                self.mark_price.into(),
+                self.mark_iv.into(),
+                self.underlying_index.as_str().into(),
+                self.underlying_price.into(),
+                self.delta.into(),
+                self.gamma.into(),
+                self.vega.into(),
+                self.theta.into(),
+                self.rho.into(),
+            ])
+        }
+    }
+
+    impl ArrowRecord for BookSnapshot {
+        fn append_rows(&self, rows: &mut ArrowRows) -> Result<()> {
+            let mut row = vec![
+                self.exchange.as_str().into(),
+                self.symbol.as_str().into(),
+                (&self.timestamp).into(),
+                (&self.local_timestamp).into(),
+            ];
+            for i in 0..self.depth as usize {
+                for levels in [&self.asks, &self.bids] {
+                    let level = levels.get(i);
+                    row.push(level.map(|level| level.price).into());
+                    row.push(level.map(|level| level.amount).into());
+                }
+            }
+            rows.push(&row)
+        }
+    }
+
+    impl ArrowRecord for TradeBar {
+        fn append_rows(&self, rows: &mut ArrowRows) -> Result<()> {
+            rows.push(&[
+                self.exchange.as_str().into(),
+                self.symbol.as_str().into(),
+                (&self.timestamp).into(),
+                (&self.local_timestamp).into(),
+                self.name.as_str().into(),
+                self.interval.into(),
+                self.open.into(),
+                self.high.into(),
+                self.low.into(),
+                self.close.into(),
+                self.volume.into(),
+                self.buy_volume.into(),
+                self.sell_volume.into(),
+                self.trades.into(),
+                self.vwap.into(),
+                (&self.open_timestamp).into(),
+                (&self.close_timestamp).into(),
+            ])
+        }
+    }
+
+    /// Builds [`RecordBatch`]es of normalized messages of type `T`, eg. [`Trade`] or
+    /// [`BookSnapshot`], appending their fields straight to typed arrays.
+    ///
+    /// The schema is decided by the first message appended, see [`ArrowRecord::schema`]. The
+    /// messages whose rows don't match it, eg. book snapshots of another depth, are rejected.
+    pub struct RecordBatchBuilder<T> {
+        rows: Option<ArrowRows>,
+        _marker: PhantomData<fn(&T)>,
+    }
+
+    impl<T> fmt::Debug for RecordBatchBuilder<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("RecordBatchBuilder")
+                .field("rows", &self.rows)
+                .finish()
+        }
+    }
+
+    impl<T> Default for RecordBatchBuilder<T> {
+        fn default() -> Self {
+            Self {
+                rows: None,
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    impl<T: ArrowRecord> RecordBatchBuilder<T> {
+        /// Creates a new instance of [`RecordBatchBuilder`].
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Appends the rows of a message, failing if they don't match the schema.
+        pub fn append(&mut self, message: &T) -> Result<()> {
+            let rows = self
+                .rows
+                .get_or_insert_with(|| ArrowRows::new(message.schema()));
+            message.append_rows(rows)
+        }
+
+        /// Appends the rows of every message, stopping at the first one not matching the schema.
+        pub fn extend<'a>(&mut self, messages: impl IntoIterator<Item = &'a T>) -> Result<()>
+        where
+            T: 'a,
+        {
+            for message in messages {
+                self.append(message)?;
+            }
+            Ok(())
+        }
+
+        /// Returns the number of rows appended since the last batch.
+        pub fn len(&self) -> usize {
+            self.rows.as_ref().map_or(0, ArrowRows::len)
+        }
+
+        /// Returns whether no row was appended since the last batch.
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Returns the schema of the batches, `None` until a message is appended.
+        pub fn schema(&self) -> Option<SchemaRef> {
+            self.rows.as_ref().map(|rows| rows.schema.clone())
+        }
+
+        /// Builds a batch of the rows appended since the last batch, keeping the schema for the
+        /// next ones. The batch has no columns if no message was ever appended.
+        pub fn finish(&mut self) -> Result<RecordBatch> {
+            match &mut self.rows {
+                Some(rows) => rows.finish(),
+                None => Ok(RecordBatch::new_empty(Arc::new(Schema::empty()))),
+            }
+        }
+    }
+
+    /// Builds a [`RecordBatch`] of normalized messages of one type, see [`RecordBatchBuilder`].
+    ///
+    /// ```
+    /// use tardis_rs::{arrow::record_batch, machine::Trade};
+    ///
+    /// let trades: Vec<Trade> = serde_json::from_str(
+    ///     r#"[{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}]"#,
+    /// )
+    /// .unwrap();
+    /// let batch = record_batch(&trades).unwrap();
+    /// assert_eq!(batch.num_rows(), 1);
+    /// assert_eq!(batch.schema().field(6).name(), "price");
+    /// ```
+    pub fn record_batch<T: ArrowRecord>(messages: &[T]) -> Result<RecordBatch> {
+        let mut builder = RecordBatchBuilder::new();
+        builder.extend(messages)?;
+        builder.finish()
+    }
+}
+
+#[cfg(feature = "machine")]
+pub use messages::*;
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::Array;
+
+    use super::*;
+
+    #[test]
+    fn test_record_batch() {
+        let columns = ["exchange", "timestamp", "is_snapshot", "trades", "price"]
+            .map(String::from)
+            .to_vec();
+        let schema = schema(&columns);
+        let rows = [
+            StringRecord::from(vec!["bybit", "1664582400100000", "true", "3", "19310.5"]),
+            StringRecord::from(vec!["bybit", "1664582400200000", "false", "", ""]),
+        ];
+        let batch = batch_from_rows(&schema, &rows).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(
+            batch.schema().field(1).data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        );
+        let price = batch
+            .column(4)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(price.value(0), 19310.5);
+        assert!(price.is_null(1));
+
+        let invalid = [StringRecord::from(vec!["bybit", "now", "true", "3", "1"])];
+        assert!(batch_from_rows(&schema, &invalid).is_err());
+    }
+
+    #[cfg(feature = "machine")]
+    #[test]
+    fn test_record_batch_builder() {
+        use crate::machine::{BookSnapshot, Trade, TradeBar};
+
+        let trades = [
+            r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#,
+            r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":null,"price":7996.5,"amount":1,"side":"buy","timestamp":"2019-10-23T10:32:50.000Z","localTimestamp":"2019-10-23T10:32:50.100Z"}"#,
+        ]
+        .map(|trade| serde_json::from_str::<Trade>(trade).unwrap())
+        .to_vec();
+        let batch = record_batch(&trades).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 8);
+        let ids = batch
+            .column(4)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(ids.value(0), "a");
+        assert!(ids.is_null(1));
+        let timestamps = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(timestamps.value(0), 1571826769669000);
+        assert_eq!(timestamps.timezone(), Some("UTC"));
+
+        let bar = serde_json::from_str::<TradeBar>(
+            r#"{"type":"trade_bar","symbol":"XBTUSD","exchange":"bitmex","name":"trade_bar_10000ms","interval":10000,"kind":"time","open":7996,"high":7997,"low":7995.5,"close":7996.5,"volume":51,"buyVolume":1,"sellVolume":50,"trades":2,"vwap":7996.01,"openTimestamp":"2019-10-23T10:32:49.669Z","closeTimestamp":"2019-10-23T10:32:50.000Z","timestamp":"2019-10-23T10:32:50.000Z","localTimestamp":"2019-10-23T10:32:50.100Z"}"#,
+        )
+        .unwrap();
+        let batch = record_batch(&[bar]).unwrap();
+        let trades = batch
+            .column(13)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(trades.value(0), 2);
+
+        let snapshot = |depth: usize| {
+            serde_json::from_str::<BookSnapshot>(&format!(
+                r#"{{"type":"book_snapshot","symbol":"XBTUSD","exchange":"bitmex","name":"book_snapshot_{depth}_0ms","depth":{depth},"interval":0,"bids":[{{"price":7985,"amount":283318}}],"asks":[{{"price":7985.5,"amount":2}},{{"price":7986,"amount":5}}],"timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}}"#
+            ))
+            .unwrap()
+        };
+        let mut builder = RecordBatchBuilder::new();
+        assert!(builder.schema().is_none());
+        assert_eq!(builder.finish().unwrap().num_columns(), 0);
+        builder.append(&snapshot(2)).unwrap();
+        // Snapshots of another depth don't match the schema.
+        assert!(builder.append(&snapshot(3)).is_err());
+        assert_eq!(builder.len(), 1);
+        let batch = builder.finish().unwrap();
+        assert!(builder.is_empty());
+        assert_eq!(batch.num_columns(), 12);
+        let bid = |column: &str| {
+            let index = batch.schema().index_of(column).unwrap();
+            let array = batch.column(index).clone();
+            let prices = array.as_any().downcast_ref::<Float64Array>().unwrap();
+            (!prices.is_null(0)).then(|| prices.value(0))
+        };
+        assert_eq!(bid("bids[0].price"), Some(7985.0));
+        assert_eq!(bid("bids[1].price"), None);
+    }
+
+    #[cfg(feature = "machine")]
+    #[test]
+    fn test_arrow_rows() {
+        let columns = ["symbol", "price"];
+        let mut rows = ArrowRows::new(schema(&columns));
+        rows.push(&["XBTUSD".into(), 7996.0.into()]).unwrap();
+        assert!(rows.push(&["XBTUSD".into()]).is_err());
+        assert!(rows.push(&[7996.0.into(), "XBTUSD".into()]).is_err());
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows.finish().unwrap().num_rows(), 1);
+    }
+}
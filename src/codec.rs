@@ -0,0 +1,328 @@
+//! Codecs for decoding the newline-delimited JSON (NDJSON) bodies returned by Tardis, eg. the HTTP
+//! replay and data-feeds endpoints or local recordings of a stream.
+
+use std::marker::PhantomData;
+
+use async_compression::tokio::bufread::GzipDecoder;
+use bytes::BytesMut;
+use futures_util::{Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio_util::{
+    codec::{Decoder, FramedRead},
+    either::Either,
+    io::StreamReader,
+};
+
+use crate::{Error, Result};
+
+/// The magic bytes every gzip stream starts with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A [`Decoder`] that splits a byte stream on `\n` and deserializes every non-empty line into `T`.
+///
+/// Trailing `\r` characters are stripped so that CRLF terminated input is accepted as well, and
+/// a final line without a terminating newline is decoded once the underlying stream ends.
+///
+/// ```ignore
+/// use tokio_util::codec::FramedRead;
+/// use tardis_rs::codec::NdjsonCodec;
+///
+/// let file = tokio::fs::File::open("recording.ndjson").await?;
+/// let mut frames = FramedRead::new(file, NdjsonCodec::<serde_json::Value>::new());
+/// ```
+#[derive(Debug)]
+pub struct NdjsonCodec<T> {
+    /// The index in the buffer up to which we have already searched for a newline.
+    next_index: usize,
+    /// The maximum length of a single line, `None` meaning unbounded.
+    max_length: Option<usize>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> NdjsonCodec<T> {
+    /// Creates a new instance of [`NdjsonCodec`] with no limit on the line length.
+    pub fn new() -> Self {
+        Self {
+            next_index: 0,
+            max_length: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a new instance of [`NdjsonCodec`] that fails with an [`std::io::ErrorKind::InvalidData`]
+    /// error when a single line grows beyond `max_length` bytes.
+    pub fn with_max_length(max_length: usize) -> Self {
+        Self {
+            max_length: Some(max_length),
+            ..Self::new()
+        }
+    }
+
+    fn check_length(&self, length: usize) -> Result<()> {
+        match self.max_length {
+            Some(max_length) if length > max_length => Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "line length {} exceeds the maximum of {}",
+                    length, max_length
+                ),
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T> Default for NdjsonCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for NdjsonCodec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            next_index: self.next_index,
+            max_length: self.max_length,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Strips the trailing `\r` and surrounding whitespace of a line.
+fn trim_line(line: &[u8]) -> &[u8] {
+    let start = line
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(line.len());
+    let end = line
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    &line[start..end]
+}
+
+impl<T> Decoder for NdjsonCodec<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = T;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<T>> {
+        loop {
+            let newline = buf[self.next_index..].iter().position(|b| *b == b'\n');
+
+            let Some(offset) = newline else {
+                self.next_index = buf.len();
+                self.check_length(buf.len())?;
+                return Ok(None);
+            };
+
+            let line = buf.split_to(self.next_index + offset + 1);
+            self.next_index = 0;
+            self.check_length(line.len() - 1)?;
+
+            let line = trim_line(&line);
+            if line.is_empty() {
+                continue;
+            }
+
+            return Ok(Some(serde_json::from_slice(line)?));
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<T>> {
+        if let Some(item) = self.decode(buf)? {
+            return Ok(Some(item));
+        }
+
+        let line = buf.split();
+        self.next_index = 0;
+
+        let line = trim_line(&line);
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_slice(line)?))
+    }
+}
+
+/// Returns whether `data` starts with the magic bytes of gzip.
+pub(crate) fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+/// Decompresses the given reader if it is gzip compressed, detected from its magic bytes.
+///
+/// The reader is filled until the two magic bytes are buffered or the input ends, as a chunked
+/// body may start with a chunk of a single byte.
+pub(crate) async fn decompress<R>(mut reader: R) -> Result<impl AsyncBufRead + Unpin>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut magic = Vec::with_capacity(GZIP_MAGIC.len());
+    while magic.len() < GZIP_MAGIC.len() {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            break;
+        }
+        let len = buf.len().min(GZIP_MAGIC.len() - magic.len());
+        magic.extend_from_slice(&buf[..len]);
+        reader.consume(len);
+    }
+
+    let compressed = is_gzip(&magic);
+    let reader = std::io::Cursor::new(magic).chain(reader);
+    Ok(if compressed {
+        let mut decoder = GzipDecoder::new(reader);
+        // Tardis concatenates gzip members when serving multiple slices in a single response.
+        decoder.multiple_members(true);
+        Either::Left(BufReader::new(decoder))
+    } else {
+        Either::Right(reader)
+    })
+}
+
+/// Decodes a NDJSON body from the given reader into a stream of `T`.
+///
+/// Gzip compressed input (as returned by the HTTP replay and data-feeds endpoints) is detected
+/// from its magic bytes and decompressed incrementally, so only the line currently being decoded
+/// is held in memory regardless of the size of the body.
+pub async fn decode_reader<T, R>(reader: R) -> Result<impl Stream<Item = Result<T>>>
+where
+    T: DeserializeOwned,
+    R: AsyncBufRead + Unpin,
+{
+    Ok(FramedRead::new(
+        decompress(reader).await?,
+        NdjsonCodec::new(),
+    ))
+}
+
+/// Decodes the body of a HTTP response as NDJSON into a stream of `T`, see [`decode_reader`].
+pub async fn decode_response<T>(
+    response: reqwest::Response,
+) -> Result<impl Stream<Item = Result<T>>>
+where
+    T: DeserializeOwned,
+{
+    let body = Box::pin(response.bytes_stream().map_err(std::io::Error::other));
+
+    decode_reader(StreamReader::new(body)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use async_compression::tokio::bufread::GzipEncoder;
+    use futures_util::StreamExt;
+    use serde::Deserialize;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Line {
+        id: u64,
+    }
+
+    #[test]
+    fn test_decode_multiple_lines() {
+        let mut codec = NdjsonCodec::<Line>::new();
+        let mut buf = BytesMut::from("{\"id\":1}\n\n{\"id\":2}\r\n");
+
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Line { id: 1 }));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Line { id: 2 }));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decode_partial_line() {
+        let mut codec = NdjsonCodec::<Line>::new();
+        let mut buf = BytesMut::from("{\"id\":");
+
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(b"3}\n{\"id\":4}");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Line { id: 3 }));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(Line { id: 4 }));
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn test_decode_invalid_json() {
+        let mut codec = NdjsonCodec::<Line>::new();
+        let mut buf = BytesMut::from("not json\n");
+
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(Error::Deserialization(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_line_too_long() {
+        let mut codec = NdjsonCodec::<Line>::with_max_length(4);
+        let mut buf = BytesMut::from("{\"id\":10}");
+
+        assert!(matches!(codec.decode(&mut buf), Err(Error::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn test_decode_reader_plain() {
+        let body: &[u8] = b"{\"id\":1}\n{\"id\":2}";
+
+        let lines = decode_reader::<Line, _>(body)
+            .await
+            .unwrap()
+            .map(|line| line.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(lines, vec![Line { id: 1 }, Line { id: 2 }]);
+    }
+
+    #[tokio::test]
+    async fn test_decode_reader_gzip() {
+        let body: &[u8] = b"{\"id\":1}\n{\"id\":2}\n";
+        let mut compressed = vec![];
+        GzipEncoder::new(body)
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+
+        let lines = decode_reader::<Line, _>(compressed.as_slice())
+            .await
+            .unwrap()
+            .map(|line| line.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(lines, vec![Line { id: 1 }, Line { id: 2 }]);
+    }
+
+    #[tokio::test]
+    async fn test_decode_reader_gzip_split_magic() {
+        let body: &[u8] = b"{\"id\":1}\n";
+        let mut compressed = vec![];
+        GzipEncoder::new(body)
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+
+        // A chunked body whose first chunk holds a single byte of the magic.
+        let chunks = [compressed[..1].to_vec(), compressed[1..].to_vec()]
+            .map(|chunk| Ok::<_, std::io::Error>(bytes::Bytes::from(chunk)));
+        let lines = decode_reader::<Line, _>(StreamReader::new(futures_util::stream::iter(chunks)))
+            .await
+            .unwrap()
+            .map(|line| line.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(lines, vec![Line { id: 1 }]);
+    }
+}
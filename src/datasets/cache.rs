@@ -0,0 +1,211 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::OpenOptions,
+    io::AsyncWriteExt,
+    sync::{Mutex, OnceCell},
+};
+
+use super::DatasetKey;
+use crate::{log, Result};
+
+/// The name of the manifest in the directory of a [`DatasetCache`].
+const MANIFEST: &str = "manifest.ndjson";
+
+/// The record of a file completely downloaded into a [`DatasetCache`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedFile {
+    /// Size of the file, gzip compressed
+    pub size: u64,
+
+    /// When the download completed
+    pub completed_at: DateTime<Utc>,
+}
+
+/// The manifest of a [`DatasetCache`], listing the complete files by name.
+#[derive(Debug, Default)]
+struct Manifest {
+    files: BTreeMap<String, CachedFile>,
+}
+
+/// A line of the manifest, recording a file once complete. A file downloaded again is recorded
+/// again, the last line winning.
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+    name: String,
+
+    #[serde(flatten)]
+    file: CachedFile,
+}
+
+/// A directory of downloaded dataset files, named by [`DatasetKey::file_name`].
+///
+/// Files are downloaded into a `.part` file first, which is renamed once complete and appended to
+/// the `manifest.ndjson` of the directory. Only the files of the manifest are considered cached, so
+/// an interrupted download is resumed from its `.part` file rather than mistaken for a complete
+/// one.
+#[derive(Debug)]
+pub struct DatasetCache {
+    directory: PathBuf,
+    manifest: OnceCell<Mutex<Manifest>>,
+}
+
+impl DatasetCache {
+    /// Creates a new instance of [`DatasetCache`] over `directory`, which is created on the first
+    /// download if missing.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            manifest: OnceCell::new(),
+        }
+    }
+
+    /// Returns the directory of the cache.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the path of a file in the cache, whether it was downloaded or not.
+    pub fn path(&self, key: &DatasetKey) -> PathBuf {
+        self.directory.join(key.file_name())
+    }
+
+    /// Returns the path a file is downloaded into before being complete.
+    pub(crate) fn part_path(&self, key: &DatasetKey) -> PathBuf {
+        self.directory.join(format!("{}.part", key.file_name()))
+    }
+
+    /// Returns the record of a file if it was completely downloaded and is still on disk with
+    /// the recorded size.
+    pub async fn get(&self, key: &DatasetKey) -> Result<Option<CachedFile>> {
+        let file = self
+            .manifest()
+            .await?
+            .lock()
+            .await
+            .files
+            .get(&key.file_name())
+            .cloned();
+        let Some(file) = file else {
+            return Ok(None);
+        };
+        match tokio::fs::metadata(self.path(key)).await {
+            Ok(metadata) if metadata.len() == file.size => Ok(Some(file)),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Moves the downloaded `.part` file of `key` to its final path and records it in the
+    /// manifest.
+    pub(crate) async fn complete(&self, key: &DatasetKey) -> Result<CachedFile> {
+        let mut manifest = self.manifest().await?.lock().await;
+        let path = self.path(key);
+        tokio::fs::rename(self.part_path(key), &path).await?;
+
+        let file = CachedFile {
+            size: tokio::fs::metadata(&path).await?.len(),
+            completed_at: Utc::now(),
+        };
+        manifest.files.insert(key.file_name(), file.clone());
+
+        let mut line = serde_json::to_vec(&ManifestEntry {
+            name: key.file_name(),
+            file: file.clone(),
+        })?;
+        line.push(b'\n');
+        let mut manifest_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.directory.join(MANIFEST))
+            .await?;
+        manifest_file.write_all(&line).await?;
+        manifest_file.flush().await?;
+        Ok(file)
+    }
+
+    /// Returns the manifest, loading it from disk on first use.
+    async fn manifest(&self) -> Result<&Mutex<Manifest>> {
+        self.manifest
+            .get_or_try_init(|| async {
+                tokio::fs::create_dir_all(&self.directory).await?;
+                let path = self.directory.join(MANIFEST);
+                let bytes = match tokio::fs::read(&path).await {
+                    Ok(bytes) => bytes,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+                    Err(e) => return Err(e.into()),
+                };
+
+                // A line cut short by an interrupted write is dropped, so that the next line
+                // appended starts on its own.
+                let complete = bytes.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+                if complete < bytes.len() {
+                    log::warn!("Dropping the incomplete last line of {:?}", path);
+                    OpenOptions::new()
+                        .write(true)
+                        .open(&path)
+                        .await?
+                        .set_len(complete as u64)
+                        .await?;
+                }
+
+                let mut manifest = Manifest::default();
+                for line in bytes[..complete].split(|b| *b == b'\n') {
+                    if !line.is_empty() {
+                        let entry = serde_json::from_slice::<ManifestEntry>(line)?;
+                        manifest.files.insert(entry.name, entry.file);
+                    }
+                }
+                Ok(Mutex::new(manifest))
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::{datasets::DatasetType, Exchange};
+
+    #[tokio::test]
+    async fn test_manifest() {
+        let directory =
+            std::env::temp_dir().join(format!("tardis-rs-manifest-{}", std::process::id()));
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let keys = ["BTCUSDT", "ETHUSDT"]
+            .map(|symbol| DatasetKey::new(Exchange::Bybit, DatasetType::Trades, symbol, date));
+
+        let cache = DatasetCache::new(&directory);
+        for key in &keys {
+            tokio::fs::create_dir_all(&directory).await.unwrap();
+            tokio::fs::write(cache.part_path(key), "data")
+                .await
+                .unwrap();
+            cache.complete(key).await.unwrap();
+        }
+        // Every file appends a line rather than rewriting the manifest.
+        let manifest = directory.join(MANIFEST);
+        let lines = std::fs::read_to_string(&manifest).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+
+        // A line cut short is dropped on load.
+        std::fs::write(&manifest, format!("{}{{\"name\":", lines)).unwrap();
+        let cache = DatasetCache::new(&directory);
+        for key in &keys {
+            assert_eq!(cache.get(key).await.unwrap().unwrap().size, 4);
+        }
+        assert_eq!(std::fs::read_to_string(&manifest).unwrap(), lines);
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+}
@@ -0,0 +1,679 @@
+use std::{
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use async_compression::tokio::bufread::GzipDecoder;
+
+use chrono::NaiveDate;
+use futures_util::StreamExt;
+use tokio::{
+    io::{AsyncWriteExt, BufReader},
+    sync::watch,
+};
+
+use super::{
+    file_symbol, DatasetCache, DatasetType, DownloadPlan, DownloadProgress, DownloadReport,
+    FileProgress,
+};
+use crate::{log, Client, Error, Exchange, RestartPolicy, Result};
+
+/// A daily file of a dataset.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DatasetKey {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Type of the data stored in the file
+    pub data_type: DatasetType,
+
+    /// Symbol as in the URL of the file, eg. `BTCUSDT` or `PERPETUALS`
+    pub symbol: String,
+
+    /// Day of the data stored in the file
+    pub date: NaiveDate,
+}
+
+impl DatasetKey {
+    /// Creates a new instance of [`DatasetKey`], normalizing the symbol as in the URL of the file.
+    pub fn new(exchange: Exchange, data_type: DatasetType, symbol: &str, date: NaiveDate) -> Self {
+        Self {
+            exchange,
+            data_type,
+            symbol: file_symbol(symbol),
+            date,
+        }
+    }
+
+    /// Returns the name of the downloaded file, the one given by the official clients, eg.
+    /// `bybit_trades_2022-10-01_BTCUSDT.csv.gz`.
+    pub fn file_name(&self) -> String {
+        format!(
+            "{}_{}_{}_{}.csv.gz",
+            self.exchange,
+            self.data_type,
+            self.date.format("%Y-%m-%d"),
+            self.symbol
+        )
+    }
+}
+
+/// The outcome of the download of a file by [`DatasetDownloader::fetch`].
+#[derive(Debug)]
+pub struct FileDownload {
+    /// The file downloaded
+    pub key: DatasetKey,
+
+    /// The path the file was written to, or the error the download failed with once the retries
+    /// were exhausted
+    pub result: Result<PathBuf>,
+
+    /// Whether the file was already in the cache, and wasn't downloaded again
+    pub cached: bool,
+}
+
+/// Downloads the daily files of datasets over ranges of dates into a [`DatasetCache`], a few
+/// files at a time. Files already in the cache are skipped, and partially downloaded ones are
+/// resumed where they stopped.
+///
+/// ```no_run
+/// use chrono::NaiveDate;
+/// use tardis_rs::{datasets::{DatasetDownloader, DatasetType}, Client, Exchange};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let downloader = DatasetDownloader::new(Client::new("key"), "./datasets").concurrency(8);
+/// let from = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+/// let to = NaiveDate::from_ymd_opt(2022, 11, 1).unwrap();
+/// for file in downloader
+///     .fetch(Exchange::Bybit, DatasetType::Trades, ["BTCUSDT", "ETHUSDT"], from..to)
+///     .await
+/// {
+///     if let Err(e) = file.result {
+///         eprintln!("Failed to download {}: {}", file.key.file_name(), e);
+///     }
+/// }
+/// # }
+/// ```
+pub struct DatasetDownloader {
+    client: Client,
+    cache: DatasetCache,
+    concurrency: usize,
+    restart_policy: RestartPolicy,
+    verify_checksum: bool,
+    progress: watch::Sender<DownloadProgress>,
+}
+
+impl fmt::Debug for DatasetDownloader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DatasetDownloader")
+            .field("cache", &self.cache)
+            .field("concurrency", &self.concurrency)
+            .field("restart_policy", &self.restart_policy)
+            .field("verify_checksum", &self.verify_checksum)
+            .field("progress", &*self.progress.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+impl DatasetDownloader {
+    /// Creates a new instance of [`DatasetDownloader`] writing the files into the
+    /// [`DatasetCache`] at `directory`, which is created if missing.
+    pub fn new(client: Client, directory: impl Into<PathBuf>) -> Self {
+        Self {
+            client,
+            cache: DatasetCache::new(directory),
+            concurrency: 4,
+            restart_policy: RestartPolicy::default().max_attempts(5),
+            verify_checksum: false,
+            progress: watch::channel(DownloadProgress::new(0)).0,
+        }
+    }
+
+    /// Sets the maximum number of files downloaded at the same time, 4 by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `concurrency` is zero.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "concurrency must not be zero");
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Sets the policy retrying the downloads that failed transiently, eg. on a dropped
+    /// connection or a server error, exponential backoff with jitter for up to 5 retries by
+    /// default. It replaces the [`ClientBuilder::restart_policy`](crate::ClientBuilder::restart_policy)
+    /// of the client for the requests of the files.
+    pub fn restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// Sets whether every downloaded file is decompressed to verify the checksum of its content,
+    /// on top of its size, before being moved into the cache. Disabled by default, as it reads
+    /// every file again.
+    pub fn verify_checksum(mut self, verify_checksum: bool) -> Self {
+        self.verify_checksum = verify_checksum;
+        self
+    }
+
+    /// Returns the cache the files are written into, eg. to find the path of a file.
+    pub fn cache(&self) -> &DatasetCache {
+        &self.cache
+    }
+
+    /// Returns a receiver of the progress of the downloads, updated as every chunk of a file is
+    /// received. The progress is reset when a fetch starts, so it only makes sense with a single
+    /// fetch at a time.
+    pub fn progress(&self) -> watch::Receiver<DownloadProgress> {
+        self.progress.subscribe()
+    }
+
+    /// Downloads the daily files of `data_type` for each of `symbols` and each day of `dates`,
+    /// whose end is excluded. Returns the outcome of every file, sorted by date and then by
+    /// [`DatasetKey`], ie. by symbol.
+    pub async fn fetch<S: AsRef<str>>(
+        &self,
+        exchange: Exchange,
+        data_type: DatasetType,
+        symbols: impl IntoIterator<Item = S>,
+        dates: Range<NaiveDate>,
+    ) -> Vec<FileDownload> {
+        let symbols = symbols.into_iter().collect::<Vec<_>>();
+        let keys = dates
+            .start
+            .iter_days()
+            .take_while(|date| *date < dates.end)
+            .flat_map(|date| {
+                symbols
+                    .iter()
+                    .map(move |symbol| DatasetKey::new(exchange, data_type, symbol.as_ref(), date))
+            })
+            .collect::<Vec<_>>();
+        self.fetch_keys(keys).await
+    }
+
+    /// Downloads the files of `plan`, at most [`concurrency`](Self::concurrency) files at a time
+    /// over the whole plan. Fails only if the symbols of the plan can't be resolved, the outcome of
+    /// every file being in the report.
+    pub async fn fetch_plan(&self, plan: &DownloadPlan) -> Result<DownloadReport> {
+        let started_at = Instant::now();
+        let keys = plan.resolve(&self.client).await?;
+        log::debug!("Fetching {} files of the download plan", keys.len());
+        let files = self.fetch_keys(keys).await;
+        Ok(DownloadReport {
+            files,
+            bytes_downloaded: self.progress.borrow().bytes_downloaded,
+            elapsed: started_at.elapsed(),
+        })
+    }
+
+    /// Downloads the files of `keys`, returning their outcomes sorted by date and then by
+    /// [`DatasetKey`], ie. by exchange, data type and symbol.
+    async fn fetch_keys(&self, keys: Vec<DatasetKey>) -> Vec<FileDownload> {
+        self.progress
+            .send_replace(DownloadProgress::new(keys.len()));
+        let mut downloads = futures_util::stream::iter(keys)
+            .map(|key| async move {
+                match self.cache.get(&key).await {
+                    Ok(Some(_)) => FileDownload {
+                        result: Ok(self.cache.path(&key)),
+                        key,
+                        cached: true,
+                    },
+                    Ok(None) => FileDownload {
+                        result: self.download(&key).await,
+                        key,
+                        cached: false,
+                    },
+                    Err(e) => FileDownload {
+                        key,
+                        result: Err(e),
+                        cached: false,
+                    },
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .inspect(|download| {
+                self.progress.send_modify(|progress| {
+                    progress.in_progress.remove(&download.key);
+                    match download.result {
+                        Ok(_) => progress.files_done += 1,
+                        Err(_) => progress.files_failed += 1,
+                    }
+                })
+            })
+            .collect::<Vec<_>>()
+            .await;
+        downloads.sort_by(|a, b| (a.key.date, &a.key).cmp(&(b.key.date, &b.key)));
+        downloads
+    }
+
+    /// Downloads a file, retrying as decided by the restart policy.
+    async fn download(&self, key: &DatasetKey) -> Result<PathBuf> {
+        self.restart_policy
+            .retry(
+                || async {
+                    let result = self.try_download(key).await;
+                    if let Err(e) = &result {
+                        log::warn!("Failed to download {}: {}", key.file_name(), e);
+                    }
+                    result
+                },
+                is_transient,
+            )
+            .await?;
+        Ok(self.cache.path(key))
+    }
+
+    /// Downloads a file into its `.part` file in the cache, resuming from the end of the part
+    /// already downloaded if any, and moves it into the cache once complete and verified. A
+    /// corrupt part is deleted, so that it is downloaded from the start when retried.
+    async fn try_download(&self, key: &DatasetKey) -> Result<()> {
+        let part = self.cache.part_path(key);
+        let offset = match tokio::fs::metadata(&part).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        // Sent once, the whole download being retried by the restart policy of the downloader.
+        let once = RestartPolicy::never();
+        let file = match self.client.request_dataset(key, offset, &once).await {
+            // The part is already as long as the file, or the file changed since.
+            Err(Error::Api { code: 416, .. }) if offset > 0 => {
+                tokio::fs::remove_file(&part).await?;
+                self.client.request_dataset(key, 0, &once).await?
+            }
+            file => file?,
+        };
+
+        let expected = file.content_length().map(|length| {
+            if file.is_partial() {
+                offset + length
+            } else {
+                length
+            }
+        });
+        let mut writer = if file.is_partial() {
+            log::debug!("Resuming {} from byte {}", key.file_name(), offset);
+            tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&part)
+                .await?
+        } else {
+            tokio::fs::File::create(&part).await?
+        };
+        self.progress.send_modify(|progress| {
+            progress.in_progress.insert(
+                key.clone(),
+                FileProgress {
+                    bytes_downloaded: 0,
+                    bytes_total: file.content_length(),
+                },
+            );
+        });
+        let mut body = file.response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            writer.write_all(&chunk).await?;
+            self.progress.send_modify(|progress| {
+                progress.bytes_downloaded += chunk.len() as u64;
+                if let Some(file) = progress.in_progress.get_mut(key) {
+                    file.bytes_downloaded += chunk.len() as u64;
+                }
+            });
+        }
+        writer.flush().await?;
+        drop(writer);
+
+        if let Err(reason) = self.verify(&part, expected).await {
+            tokio::fs::remove_file(&part).await?;
+            return Err(Error::CorruptDownload {
+                file: key.file_name(),
+                reason,
+            });
+        }
+        self.cache.complete(key).await?;
+        Ok(())
+    }
+
+    /// Verifies a downloaded file against its expected size and, if enabled, its checksum,
+    /// returning what is wrong with it otherwise.
+    async fn verify(&self, path: &Path, expected: Option<u64>) -> std::result::Result<(), String> {
+        let size = tokio::fs::metadata(path)
+            .await
+            .map_err(|e| e.to_string())?
+            .len();
+        if let Some(expected) = expected.filter(|expected| *expected != size) {
+            return Err(format!("expected {} bytes, found {}", expected, size));
+        }
+
+        if self.verify_checksum {
+            // The decoder checks the CRC-32 of the content stored at the end of every member.
+            let file = tokio::fs::File::open(path)
+                .await
+                .map_err(|e| e.to_string())?;
+            let mut decoder = GzipDecoder::new(BufReader::new(file));
+            decoder.multiple_members(true);
+            tokio::io::copy(&mut decoder, &mut tokio::io::sink())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether a download may succeed if retried: failures to reach the server or to read the
+/// body, corrupt files, rate limiting and server errors.
+fn is_transient(e: &Error) -> bool {
+    match e {
+        Error::Request(_) | Error::CorruptDownload { .. } | Error::RateLimited { .. } => true,
+        Error::Api { code, .. } => (500..600).contains(code),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::{
+        test_server::{serve, serve_replies, Reply, TestServer},
+        InstrumentFilter,
+    };
+
+    /// Serves the files, failing the first request of each with a `503`, and every request of the
+    /// `DOWN` files.
+    async fn serve_flaky() -> TestServer {
+        let mut failed = std::collections::HashSet::new();
+        serve(move |request| {
+            let path = request.path().to_string();
+            Some(if path.contains("UNKNOWN") {
+                Reply::new("404 Not Found", path)
+            } else if path.contains("DOWN") || failed.insert(path.clone()) {
+                Reply::new("503 Service Unavailable", "")
+            } else {
+                Reply::ok(path)
+            })
+        })
+        .await
+    }
+
+    /// Serves `body` for every file, honoring the ranges requested.
+    async fn serve_ranges(body: &'static [u8]) -> TestServer {
+        serve(move |request| {
+            let range = request.header("range").map(|range| {
+                let start = range.trim_start_matches("bytes=").trim_end_matches('-');
+                start.parse::<usize>().unwrap()
+            });
+            Some(match range {
+                Some(start) => Reply::new("206 Partial Content", &body[start..]),
+                None => Reply::ok(body),
+            })
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_fetch_resume() {
+        let server = serve_ranges(b"0123456789").await;
+        let mut client = Client::new("key");
+        client.datasets_url = server.url.clone();
+        let directory =
+            std::env::temp_dir().join(format!("tardis-rs-resume-{}", std::process::id()));
+
+        let downloader = DatasetDownloader::new(client, &directory);
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let key = DatasetKey::new(Exchange::Bybit, DatasetType::Trades, "BTCUSDT", date);
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(downloader.cache().part_path(&key), "0123")
+            .await
+            .unwrap();
+
+        let range = date..date.succ_opt().unwrap();
+        let downloads = downloader
+            .fetch(
+                Exchange::Bybit,
+                DatasetType::Trades,
+                ["BTCUSDT"],
+                range.clone(),
+            )
+            .await;
+        assert!(!downloads[0].cached);
+        let path = downloads[0].result.as_ref().unwrap();
+        assert_eq!(tokio::fs::read(path).await.unwrap(), b"0123456789");
+        let progress = downloader.progress().borrow().clone();
+        assert!(progress.is_finished());
+        assert_eq!(progress.files_done, 1);
+        assert_eq!(progress.bytes_downloaded, 6);
+        assert!(progress.in_progress.is_empty());
+        assert_eq!(
+            downloader.cache().get(&key).await.unwrap().unwrap().size,
+            10
+        );
+
+        // A new downloader reads the manifest and skips the file.
+        let downloader = DatasetDownloader::new(Client::new("key"), &directory);
+        let downloads = downloader
+            .fetch(Exchange::Bybit, DatasetType::Trades, ["BTCUSDT"], range)
+            .await;
+        assert!(downloads[0].cached);
+        let ranges = server
+            .requests()
+            .iter()
+            .map(|request| request.header("range").unwrap_or_default().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec!["bytes=4-"]);
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_download() {
+        use async_compression::tokio::bufread::GzipEncoder;
+
+        let mut valid = vec![];
+        GzipEncoder::new(&b"exchange,symbol\nbybit,BTCUSDT\n"[..])
+            .read_to_end(&mut valid)
+            .await
+            .unwrap();
+        // Flips a bit of the CRC-32 of the content, stored before its length at the end.
+        let mut corrupt = valid.clone();
+        let crc = corrupt.len() - 6;
+        corrupt[crc] ^= 1;
+
+        let directory =
+            std::env::temp_dir().join(format!("tardis-rs-corrupt-{}", std::process::id()));
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let range = date..date.succ_opt().unwrap();
+        let downloader = |url| {
+            let mut client = Client::new("key");
+            client.datasets_url = url;
+            DatasetDownloader::new(client, &directory).verify_checksum(true)
+        };
+
+        let downloads = downloader(serve_replies(vec![Reply::ok(corrupt.clone())]).await.url)
+            .restart_policy(RestartPolicy::never())
+            .fetch(
+                Exchange::Bybit,
+                DatasetType::Trades,
+                ["BTCUSDT"],
+                range.clone(),
+            )
+            .await;
+        assert!(matches!(
+            &downloads[0].result,
+            Err(Error::CorruptDownload { file, .. }) if file == "bybit_trades_2022-10-01_BTCUSDT.csv.gz"
+        ));
+
+        // Downloaded again from the start once found corrupt.
+        let downloads = downloader(
+            serve_replies(vec![Reply::ok(corrupt), Reply::ok(valid.clone())])
+                .await
+                .url,
+        )
+        .restart_policy(RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(1))
+        .fetch(Exchange::Bybit, DatasetType::Trades, ["BTCUSDT"], range)
+        .await;
+        let path = downloads[0].result.as_ref().unwrap();
+        assert_eq!(tokio::fs::read(path).await.unwrap(), valid);
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+
+    #[test]
+    fn test_file_name() {
+        let key = DatasetKey::new(
+            Exchange::Deribit,
+            DatasetType::OptionsChain,
+            "options",
+            NaiveDate::from_ymd_opt(2022, 10, 1).unwrap(),
+        );
+        assert_eq!(
+            key.file_name(),
+            "deribit_options_chain_2022-10-01_OPTIONS.csv.gz"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let server = serve_flaky().await;
+        let mut client = Client::new("key");
+        client.datasets_url = server.url.clone();
+        let directory =
+            std::env::temp_dir().join(format!("tardis-rs-fetch-{}", std::process::id()));
+
+        let downloader = DatasetDownloader::new(client, &directory)
+            .concurrency(2)
+            .restart_policy(RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(1));
+        let from = NaiveDate::from_ymd_opt(2022, 10, 30).unwrap();
+        let to = NaiveDate::from_ymd_opt(2022, 11, 1).unwrap();
+        let downloads = downloader
+            .fetch(
+                Exchange::Bybit,
+                DatasetType::Trades,
+                ["btcusdt", "UNKNOWN"],
+                from..to,
+            )
+            .await;
+
+        let outcomes = downloads
+            .iter()
+            .map(|download| {
+                (
+                    download.key.file_name(),
+                    download.result.as_ref().map_err(|e| e.to_string()),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes.len(), 4);
+        assert_eq!(outcomes[0].0, "bybit_trades_2022-10-30_BTCUSDT.csv.gz");
+        assert_eq!(outcomes[3].0, "bybit_trades_2022-10-31_UNKNOWN.csv.gz");
+        assert!(matches!(downloads[1].result, Err(Error::NotFound { .. })));
+
+        let path = downloads[2].result.as_ref().unwrap();
+        assert_eq!(
+            tokio::fs::read_to_string(path).await.unwrap(),
+            "/bybit/trades/2022/10/31/BTCUSDT.csv.gz"
+        );
+        // Each existing file was requested twice, the unknown ones once.
+        assert_eq!(server.requests().len(), 6);
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_retries() {
+        let server = serve_flaky().await;
+        let mut client = Client::new("key");
+        client.datasets_url = server.url.clone();
+        let directory =
+            std::env::temp_dir().join(format!("tardis-rs-retries-{}", std::process::id()));
+
+        let downloader = DatasetDownloader::new(client, &directory)
+            .restart_policy(RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(2));
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let downloads = downloader
+            .fetch(
+                Exchange::Bybit,
+                DatasetType::Trades,
+                ["DOWN"],
+                date..date.succ_opt().unwrap(),
+            )
+            .await;
+
+        assert!(matches!(
+            downloads[0].result,
+            Err(Error::Api { code: 503, .. })
+        ));
+        // Retried by the downloader alone, not by the client as well.
+        assert_eq!(server.requests().len(), 3);
+
+        tokio::fs::remove_dir_all(&directory).await.ok();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_plan() {
+        let instruments = r#"[
+            {"id":"BTCUSDT","exchange":"bybit","baseCurrency":"BTC","quoteCurrency":"USDT","type":"perpetual","active":true,"availableSince":"2020-03-25T00:00:00.000Z","priceIncrement":0.5,"amountIncrement":0.001,"minTradeAmount":0.001,"makerFee":0.0001,"takerFee":0.0006},
+            {"id":"ETHUSDT","exchange":"bybit","baseCurrency":"ETH","quoteCurrency":"USDT","type":"spot","active":true,"availableSince":"2021-07-05T00:00:00.000Z","priceIncrement":0.01,"amountIncrement":0.001,"minTradeAmount":0.001,"makerFee":0.001,"takerFee":0.001}
+        ]"#;
+        let url = serve_replies(vec![
+            Reply::ok(instruments),
+            Reply::ok("trades BTCUSDT"),
+            Reply::ok("trades ETHUSDT"),
+            Reply::ok("derivative_ticker BTCUSDT"),
+        ])
+        .await
+        .url;
+        let mut client = Client::new("key");
+        client.base_url = url.clone();
+        client.datasets_url = url;
+        let directory = std::env::temp_dir().join(format!("tardis-rs-plan-{}", std::process::id()));
+
+        // The listed symbol is also an instrument, and spot instruments have no derivative ticker.
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let plan = DownloadPlan::new(date..date.succ_opt().unwrap())
+            .exchange(Exchange::Bybit)
+            .symbol("btcusdt")
+            .instruments(InstrumentFilter::new().quote_currency("USDT"))
+            .data_type(DatasetType::Trades)
+            .data_type(DatasetType::DerivativeTicker);
+        let downloader = DatasetDownloader::new(client, &directory).concurrency(1);
+        let report = downloader.fetch_plan(&plan).await.unwrap();
+
+        let files = report
+            .files
+            .iter()
+            .map(|file| file.key.file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                "bybit_trades_2022-10-01_BTCUSDT.csv.gz",
+                "bybit_trades_2022-10-01_ETHUSDT.csv.gz",
+                "bybit_derivative_ticker_2022-10-01_BTCUSDT.csv.gz",
+            ]
+        );
+        let path = report.files[2].result.as_ref().unwrap();
+        assert_eq!(
+            tokio::fs::read(path).await.unwrap(),
+            b"derivative_ticker BTCUSDT"
+        );
+        assert!(report.is_success());
+        assert_eq!(report.downloaded(), 3);
+        assert_eq!(report.bytes_downloaded, 53);
+        assert!(report
+            .to_string()
+            .starts_with("3 files: 3 downloaded, 0 cached, 0 failed"));
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+}
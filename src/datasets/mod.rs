@@ -0,0 +1,438 @@
+//! Downloads of the daily CSV files of the
+//! [downloadable CSV datasets](https://docs.tardis.dev/downloadable-csv-files) of Tardis, eg. all
+//! the trades of a symbol on a given day.
+//!
+//! The rows of the files are read as typed records, eg. [`TradeRecord`], with
+//! [`DatasetFile::records`] while downloading, or with [`read_records`] and its shortcuts, eg.
+//! [`read_trades`], once stored at a path or in a [`DatasetCache`].
+//!
+//! ```no_run
+//! use chrono::NaiveDate;
+//! use tardis_rs::{Client, Exchange};
+//! use tokio::io::AsyncBufReadExt;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> tardis_rs::Result<()> {
+//! let client = Client::new(std::env::var("TARDIS_API_KEY").unwrap());
+//! let file = client
+//!     .download_trades(Exchange::Bybit, "BTCUSDT", NaiveDate::from_ymd_opt(2022, 10, 1).unwrap())
+//!     .await?;
+//!
+//! let mut lines = file.into_reader().lines();
+//! while let Some(line) = lines.next_line().await? {
+//!     println!("{}", line);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use async_compression::tokio::bufread::GzipDecoder;
+use bytes::Bytes;
+use chrono::NaiveDate;
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio_util::io::StreamReader;
+
+use crate::{client::error_for_status, Client, Exchange, RestartPolicy, Result};
+
+mod cache;
+mod downloader;
+mod plan;
+mod progress;
+mod records;
+
+pub use cache::*;
+pub use downloader::*;
+pub use plan::*;
+pub use progress::*;
+pub use records::*;
+
+/// The type of the data stored in a dataset.
+/// See <https://docs.tardis.dev/downloadable-csv-files#data-types>
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DatasetType {
+    /// Individual trades, `trades`.
+    Trades,
+
+    /// Incremental updates of the order book by price level, `incremental_book_L2`.
+    IncrementalBookL2,
+
+    /// Top of the order book, `quotes`.
+    Quotes,
+
+    /// Funding, open interest and reference prices of derivative instruments,
+    /// `derivative_ticker`.
+    DerivativeTicker,
+
+    /// Top 5 levels of each side of the order book, `book_snapshot_5`.
+    BookSnapshot5,
+
+    /// Top 25 levels of each side of the order book, `book_snapshot_25`.
+    BookSnapshot25,
+
+    /// Liquidations of positions, `liquidations`.
+    Liquidations,
+
+    /// Quotes, implied volatilities and greeks of the options of an exchange, `options_chain`.
+    OptionsChain,
+}
+
+impl DatasetType {
+    /// Returns the name of the dataset type in the URLs of the files, eg. `trades`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DatasetType::Trades => "trades",
+            DatasetType::IncrementalBookL2 => "incremental_book_L2",
+            DatasetType::Quotes => "quotes",
+            DatasetType::DerivativeTicker => "derivative_ticker",
+            DatasetType::BookSnapshot5 => "book_snapshot_5",
+            DatasetType::BookSnapshot25 => "book_snapshot_25",
+            DatasetType::Liquidations => "liquidations",
+            DatasetType::OptionsChain => "options_chain",
+        }
+    }
+}
+
+impl std::fmt::Display for DatasetType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Returns the symbol as it appears in the URLs of the files: upper cased, with `/` and `:`
+/// replaced by `-`, eg. `BTC-PERPETUAL` for `btc-perpetual`.
+fn file_symbol(symbol: &str) -> String {
+    symbol.to_uppercase().replace(['/', ':'], "-")
+}
+
+/// Returns the URL of the daily file of a dataset below the `base` URL of the datasets API, eg.
+/// `{base}/bybit/trades/2022/10/01/BTCUSDT.csv.gz`.
+fn file_url(
+    base: &str,
+    exchange: Exchange,
+    data_type: DatasetType,
+    date: NaiveDate,
+    symbol: &str,
+) -> String {
+    format!(
+        "{}/{}/{}/{}/{}.csv.gz",
+        base,
+        exchange,
+        data_type,
+        date.format("%Y/%m/%d"),
+        file_symbol(symbol)
+    )
+}
+
+/// A daily file of a dataset being downloaded, whose gzip compressed CSV body is yet to be read.
+#[derive(Debug)]
+pub struct DatasetFile {
+    response: reqwest::Response,
+}
+
+impl DatasetFile {
+    /// Returns the size of the compressed file, if announced by the server.
+    pub fn content_length(&self) -> Option<u64> {
+        self.response.content_length()
+    }
+
+    /// Returns whether the server answered only the end of the file, as requested when resuming
+    /// a download.
+    pub fn is_partial(&self) -> bool {
+        self.response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+    }
+
+    /// Reads the whole file as returned by the server, ie. gzip compressed, eg. to store it as
+    /// is.
+    pub async fn bytes(self) -> Result<Bytes> {
+        Ok(self.response.bytes().await?)
+    }
+
+    /// Writes the file as returned by the server, ie. gzip compressed, into `writer` as it is
+    /// received, returning the number of bytes written.
+    pub async fn copy_to<W: AsyncWrite + Unpin>(self, writer: &mut W) -> Result<u64> {
+        let mut body = self.response.bytes_stream();
+        let mut written = 0;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        Ok(written)
+    }
+
+    /// Returns a reader of the decompressed CSV, decompressing the body incrementally as it is
+    /// received.
+    pub fn into_reader(self) -> impl AsyncBufRead + Send + Unpin {
+        let body = self.response.bytes_stream().map_err(std::io::Error::other);
+        let mut decoder = GzipDecoder::new(StreamReader::new(body));
+        decoder.multiple_members(true);
+        BufReader::new(decoder)
+    }
+
+    /// Returns a stream of the rows of the file as `T`, eg. [`QuoteRecord`], see
+    /// [`parse_records`].
+    pub fn records<T: DatasetRecord>(self) -> impl Stream<Item = Result<T>> {
+        parse_records(self.into_reader())
+    }
+}
+
+impl Client {
+    /// Downloads the daily file of a dataset of `symbol`. Besides symbols, the datasets of the
+    /// grouped symbols of an exchange can be downloaded, eg. `PERPETUALS`.
+    /// See <https://docs.tardis.dev/downloadable-csv-files#download-via-api>
+    pub async fn download_dataset(
+        &self,
+        exchange: Exchange,
+        data_type: DatasetType,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<DatasetFile> {
+        let key = DatasetKey::new(exchange, data_type, symbol, date);
+        self.request_dataset(&key, 0, &self.restart_policy).await
+    }
+
+    /// Requests the daily file of a dataset, starting at byte `offset` of the file if not zero,
+    /// in which case the server may still answer the whole file, see [`DatasetFile::is_partial`].
+    /// Failures to reach the server, rate limiting and server errors are retried by `policy`.
+    pub(crate) async fn request_dataset(
+        &self,
+        key: &DatasetKey,
+        offset: u64,
+        policy: &RestartPolicy,
+    ) -> Result<DatasetFile> {
+        let url = file_url(
+            &self.datasets_url,
+            key.exchange,
+            key.data_type,
+            key.date,
+            &key.symbol,
+        );
+        let response = self
+            .send_with(policy, || {
+                let request = self.client.get(&url).bearer_auth(&self.api_key);
+                if offset > 0 {
+                    request.header(reqwest::header::RANGE, format!("bytes={}-", offset))
+                } else {
+                    request
+                }
+            })
+            .await?;
+
+        Ok(DatasetFile {
+            response: error_for_status(response).await?,
+        })
+    }
+
+    /// Downloads the daily file of the trades of `symbol`, see [`Client::download_dataset`].
+    pub async fn download_trades(
+        &self,
+        exchange: Exchange,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<DatasetFile> {
+        self.download_dataset(exchange, DatasetType::Trades, symbol, date)
+            .await
+    }
+
+    /// Downloads the daily file of the incremental updates of the order book of `symbol`, whose
+    /// first rows of each day are a snapshot of the book, see [`Client::download_dataset`].
+    pub async fn download_incremental_book_l2(
+        &self,
+        exchange: Exchange,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<DatasetFile> {
+        self.download_dataset(exchange, DatasetType::IncrementalBookL2, symbol, date)
+            .await
+    }
+
+    /// Downloads the daily file of the top of the order book of `symbol`, whose rows are read as
+    /// [`QuoteRecord`] with [`DatasetFile::records`], see [`Client::download_dataset`].
+    pub async fn download_quotes(
+        &self,
+        exchange: Exchange,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<DatasetFile> {
+        self.download_dataset(exchange, DatasetType::Quotes, symbol, date)
+            .await
+    }
+
+    /// Downloads the daily file of the funding, open interest and reference prices of `symbol`,
+    /// whose rows are read as [`DerivativeTickerRecord`] with [`DatasetFile::records`], see
+    /// [`Client::download_dataset`].
+    pub async fn download_derivative_ticker(
+        &self,
+        exchange: Exchange,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<DatasetFile> {
+        self.download_dataset(exchange, DatasetType::DerivativeTicker, symbol, date)
+            .await
+    }
+
+    /// Downloads the daily file of the snapshots of the top 5 levels of the order book of
+    /// `symbol`, whose rows are read as [`BookSnapshot5Record`] with [`DatasetFile::records`],
+    /// see [`Client::download_dataset`].
+    pub async fn download_book_snapshot_5(
+        &self,
+        exchange: Exchange,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<DatasetFile> {
+        self.download_dataset(exchange, DatasetType::BookSnapshot5, symbol, date)
+            .await
+    }
+
+    /// Downloads the daily file of the snapshots of the top 25 levels of the order book of
+    /// `symbol`, whose rows are read as [`BookSnapshot25Record`] with [`DatasetFile::records`],
+    /// see [`Client::download_dataset`].
+    pub async fn download_book_snapshot_25(
+        &self,
+        exchange: Exchange,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<DatasetFile> {
+        self.download_dataset(exchange, DatasetType::BookSnapshot25, symbol, date)
+            .await
+    }
+
+    /// Downloads the daily file of the liquidations of `symbol`, whose rows are read as
+    /// [`LiquidationRecord`] with [`DatasetFile::records`], see [`Client::download_dataset`].
+    pub async fn download_liquidations(
+        &self,
+        exchange: Exchange,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<DatasetFile> {
+        self.download_dataset(exchange, DatasetType::Liquidations, symbol, date)
+            .await
+    }
+
+    /// Downloads the daily file of the options chain of an exchange, whose rows are read as
+    /// [`OptionsChainRecord`] with [`DatasetFile::records`], see [`Client::download_dataset`].
+    /// The dataset is only available for the grouped `OPTIONS` symbol.
+    pub async fn download_options_chain(
+        &self,
+        exchange: Exchange,
+        date: NaiveDate,
+    ) -> Result<DatasetFile> {
+        self.download_dataset(exchange, DatasetType::OptionsChain, "OPTIONS", date)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_compression::tokio::bufread::GzipEncoder;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::{
+        test_server::{serve_reply, Reply},
+        Error,
+    };
+
+    const TRADES: &str = "exchange,symbol,timestamp,local_timestamp,id,side,price,amount\n\
+        bybit,BTCUSDT,1664582400100000,1664582400104000,1,buy,19310.5,0.1\n";
+
+    async fn gzip(data: &str) -> Vec<u8> {
+        let mut compressed = vec![];
+        GzipEncoder::new(data.as_bytes())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        compressed
+    }
+
+    #[test]
+    fn test_file_url() {
+        let date = NaiveDate::from_ymd_opt(2022, 1, 5).unwrap();
+        assert_eq!(
+            file_url(
+                "https://datasets.tardis.dev/v1",
+                Exchange::Deribit,
+                DatasetType::Trades,
+                date,
+                "btc-perpetual"
+            ),
+            "https://datasets.tardis.dev/v1/deribit/trades/2022/01/05/BTC-PERPETUAL.csv.gz"
+        );
+        assert_eq!(
+            file_url(
+                "https://datasets.tardis.dev/v1",
+                Exchange::Bitmex,
+                DatasetType::IncrementalBookL2,
+                date,
+                "XBTUSD"
+            ),
+            "https://datasets.tardis.dev/v1/bitmex/incremental_book_L2/2022/01/05/XBTUSD.csv.gz"
+        );
+        assert_eq!(file_symbol("BTC/USD:BTC"), "BTC-USD-BTC");
+    }
+
+    #[tokio::test]
+    async fn test_download_trades() {
+        let compressed = gzip(TRADES).await;
+        let server = serve_reply(Reply::ok(compressed.clone())).await;
+
+        let mut client = Client::new("key");
+        client.datasets_url = server.url.clone();
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+
+        let file = client
+            .download_trades(Exchange::Bybit, "btcusdt", date)
+            .await
+            .unwrap();
+        assert_eq!(file.content_length(), Some(compressed.len() as u64));
+        assert_eq!(file.bytes().await.unwrap(), compressed);
+
+        let mut csv = String::new();
+        client
+            .download_trades(Exchange::Bybit, "BTCUSDT", date)
+            .await
+            .unwrap()
+            .into_reader()
+            .read_to_string(&mut csv)
+            .await
+            .unwrap();
+        assert_eq!(csv, TRADES);
+
+        let request = &server.requests()[0];
+        assert!(request
+            .line()
+            .starts_with("GET /bybit/trades/2022/10/01/BTCUSDT.csv.gz "));
+        assert_eq!(request.header("authorization"), Some("Bearer key"));
+    }
+
+    #[tokio::test]
+    async fn test_download_error() {
+        let body = r#"{"code":401,"message":"Invalid API key"}"#;
+        let server = serve_reply(Reply::new("401 Unauthorized", body)).await;
+
+        let mut client = Client::new("key");
+        client.datasets_url = server.url;
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let result = client
+            .download_trades(Exchange::Bybit, "BTCUSDT", date)
+            .await;
+        assert!(
+            matches!(result, Err(Error::Unauthorized { message }) if message == "Invalid API key")
+        );
+
+        client.datasets_url = serve_reply(Reply::new("404 Not Found", "Not Found"))
+            .await
+            .url;
+        let result = client
+            .download_trades(Exchange::Bybit, "BTCUSDT", date)
+            .await;
+        assert!(matches!(result, Err(Error::NotFound { message }) if message == "Not Found"));
+
+        client.datasets_url = serve_reply(Reply::new("418 I'm a teapot", "")).await.url;
+        let result = client
+            .download_trades(Exchange::Bybit, "BTCUSDT", date)
+            .await;
+        assert!(matches!(result, Err(Error::Api { code: 418, .. })));
+    }
+}
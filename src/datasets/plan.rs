@@ -0,0 +1,304 @@
+use std::{collections::BTreeSet, fmt, ops::Range, time::Duration};
+
+use chrono::NaiveDate;
+
+use super::{DatasetKey, DatasetType, FileDownload};
+use crate::{Client, Exchange, InstrumentFilter, InstrumentInfo, Result, SymbolType};
+
+/// The files of datasets to download over several exchanges, symbols, data types and days,
+/// fetched at once by [`DatasetDownloader::fetch_plan`](super::DatasetDownloader::fetch_plan).
+///
+/// The symbols are either listed, downloaded for every exchange of the plan, or resolved through
+/// the [instruments API](Client::instruments) of each exchange, in which case only the days the
+/// instruments were available are downloaded, and derivative data types are skipped for spot
+/// instruments. The options chains are downloaded once per exchange and day, whatever the symbols.
+///
+/// ```no_run
+/// use chrono::NaiveDate;
+/// use tardis_rs::{
+///     datasets::{DatasetDownloader, DatasetType, DownloadPlan},
+///     Client, Exchange, InstrumentFilter, SymbolType,
+/// };
+///
+/// # #[tokio::main]
+/// # async fn main() -> tardis_rs::Result<()> {
+/// let from = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+/// let to = NaiveDate::from_ymd_opt(2022, 11, 1).unwrap();
+/// let plan = DownloadPlan::new(from..to)
+///     .exchange(Exchange::Bybit)
+///     .exchange(Exchange::BinanceFutures)
+///     .instruments(
+///         InstrumentFilter::new()
+///             .symbol_type(SymbolType::Perpetual)
+///             .base_currency("BTC"),
+///     )
+///     .data_type(DatasetType::Trades)
+///     .data_type(DatasetType::DerivativeTicker);
+///
+/// let downloader = DatasetDownloader::new(Client::new("key"), "./datasets").concurrency(16);
+/// let report = downloader.fetch_plan(&plan).await?;
+/// println!("{}", report);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DownloadPlan {
+    exchanges: Vec<Exchange>,
+    symbols: Vec<String>,
+    instruments: Vec<InstrumentFilter>,
+    data_types: Vec<DatasetType>,
+    dates: Range<NaiveDate>,
+}
+
+impl DownloadPlan {
+    /// Creates an empty plan over the days of `dates`, whose end is excluded.
+    pub fn new(dates: Range<NaiveDate>) -> Self {
+        Self {
+            exchanges: vec![],
+            symbols: vec![],
+            instruments: vec![],
+            data_types: vec![],
+            dates,
+        }
+    }
+
+    /// Adds an exchange to the plan.
+    pub fn exchange(mut self, exchange: Exchange) -> Self {
+        self.exchanges.push(exchange);
+        self
+    }
+
+    /// Adds a symbol downloaded for every exchange of the plan.
+    pub fn symbol(mut self, symbol: impl ToString) -> Self {
+        self.symbols.push(symbol.to_string());
+        self
+    }
+
+    /// Adds symbols downloaded for every exchange of the plan.
+    pub fn symbols(mut self, symbols: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.symbols
+            .extend(symbols.into_iter().map(|symbol| symbol.to_string()));
+        self
+    }
+
+    /// Adds the instruments of every exchange of the plan matching `filter`.
+    pub fn instruments(mut self, filter: InstrumentFilter) -> Self {
+        self.instruments.push(filter);
+        self
+    }
+
+    /// Adds a data type to the plan.
+    pub fn data_type(mut self, data_type: DatasetType) -> Self {
+        self.data_types.push(data_type);
+        self
+    }
+
+    /// Resolves the plan into the files to download, without duplicates and sorted, requesting
+    /// the instruments of every exchange if the plan filters them.
+    pub async fn resolve(&self, client: &Client) -> Result<Vec<DatasetKey>> {
+        let mut keys = BTreeSet::new();
+        for exchange in unique(&self.exchanges) {
+            for symbol in &self.symbols {
+                for data_type in unique(&self.data_types) {
+                    keys.extend(Self::keys(exchange, data_type, symbol, self.dates.clone()));
+                }
+            }
+            for filter in &self.instruments {
+                for instrument in client.instruments(exchange, filter.clone()).await? {
+                    let dates = available_dates(&instrument, &self.dates);
+                    for data_type in unique(&self.data_types) {
+                        if instrument.symbol_type != SymbolType::Spot || !is_derivative(data_type) {
+                            keys.extend(Self::keys(
+                                exchange,
+                                data_type,
+                                &instrument.id,
+                                dates.clone(),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(keys.into_iter().collect())
+    }
+
+    /// Returns the files of `symbol` for each day of `dates`.
+    fn keys(
+        exchange: Exchange,
+        data_type: DatasetType,
+        symbol: &str,
+        dates: Range<NaiveDate>,
+    ) -> impl Iterator<Item = DatasetKey> {
+        let symbol = match data_type {
+            DatasetType::OptionsChain => "OPTIONS".to_string(),
+            _ => symbol.to_string(),
+        };
+        dates
+            .start
+            .iter_days()
+            .take_while(move |date| *date < dates.end)
+            .map(move |date| DatasetKey::new(exchange, data_type, &symbol, date))
+    }
+}
+
+/// Returns the items of `items` once each, in order.
+fn unique<T: Copy + Ord>(items: &[T]) -> impl Iterator<Item = T> + '_ {
+    let mut seen = BTreeSet::new();
+    items.iter().copied().filter(move |item| seen.insert(*item))
+}
+
+/// Returns the days of `dates` the instrument was available on, including the days it was listed
+/// and delisted.
+fn available_dates(instrument: &InstrumentInfo, dates: &Range<NaiveDate>) -> Range<NaiveDate> {
+    let date = |timestamp: &str| timestamp.get(..10)?.parse::<NaiveDate>().ok();
+    let since = date(&instrument.available_since).unwrap_or(dates.start);
+    let until = instrument
+        .available_to
+        .as_deref()
+        .and_then(date)
+        .and_then(|date| date.succ_opt())
+        .unwrap_or(dates.end);
+    since.max(dates.start)..until.min(dates.end)
+}
+
+/// Returns whether the data type only exists for derivative instruments.
+fn is_derivative(data_type: DatasetType) -> bool {
+    matches!(
+        data_type,
+        DatasetType::DerivativeTicker | DatasetType::Liquidations
+    )
+}
+
+/// The summary of a [`DownloadPlan`] fetched by
+/// [`DatasetDownloader::fetch_plan`](super::DatasetDownloader::fetch_plan), printed as a short
+/// report listing the failed files.
+#[derive(Debug)]
+pub struct DownloadReport {
+    /// The outcome of every file of the plan, sorted by date and then file
+    pub files: Vec<FileDownload>,
+
+    /// Number of bytes downloaded, including the failed attempts
+    pub bytes_downloaded: u64,
+
+    /// Time taken to resolve and download the plan
+    pub elapsed: Duration,
+}
+
+impl DownloadReport {
+    /// Returns the number of files downloaded, excluding the ones found in the cache.
+    pub fn downloaded(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| file.result.is_ok() && !file.cached)
+            .count()
+    }
+
+    /// Returns the number of files found in the cache.
+    pub fn cached(&self) -> usize {
+        self.files.iter().filter(|file| file.cached).count()
+    }
+
+    /// Returns the files whose download failed.
+    pub fn failures(&self) -> impl Iterator<Item = &FileDownload> {
+        self.files.iter().filter(|file| file.result.is_err())
+    }
+
+    /// Returns whether every file was downloaded or found in the cache.
+    pub fn is_success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+impl fmt::Display for DownloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} files: {} downloaded, {} cached, {} failed ({:.1} MB in {:.1?})",
+            self.files.len(),
+            self.downloaded(),
+            self.cached(),
+            self.failures().count(),
+            self.bytes_downloaded as f64 / 1_000_000.0,
+            self.elapsed
+        )?;
+        for file in self.failures() {
+            if let Err(e) = &file.result {
+                write!(f, "\n  {}: {}", file.key.file_name(), e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instrument(id: &str, symbol_type: &str, since: &str, to: Option<&str>) -> InstrumentInfo {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "exchange": "bybit",
+            "baseCurrency": "BTC",
+            "quoteCurrency": "USDT",
+            "type": symbol_type,
+            "active": to.is_none(),
+            "availableSince": since,
+            "availableTo": to,
+            "priceIncrement": 0.5,
+            "amountIncrement": 0.001,
+            "minTradeAmount": 0.001,
+            "makerFee": 0.0001,
+            "takerFee": 0.0006,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_available_dates() {
+        let date = |day| NaiveDate::from_ymd_opt(2022, 10, day).unwrap();
+        let dates = date(1)..date(10);
+        let listed = instrument("BTCUSDT", "perpetual", "2020-03-25T00:00:00.000Z", None);
+        assert_eq!(available_dates(&listed, &dates), dates);
+
+        let delisted = instrument(
+            "BTC-7OCT22",
+            "future",
+            "2022-10-03T08:00:00.000Z",
+            Some("2022-10-07T08:00:00.000Z"),
+        );
+        assert_eq!(available_dates(&delisted, &dates), date(3)..date(8));
+
+        let expired = instrument(
+            "BTC-30SEP22",
+            "future",
+            "2022-07-01T08:00:00.000Z",
+            Some("2022-09-30T08:00:00.000Z"),
+        );
+        assert!(available_dates(&expired, &dates).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_plan() {
+        let date = |day| NaiveDate::from_ymd_opt(2022, 10, day).unwrap();
+        let plan = DownloadPlan::new(date(1)..date(3))
+            .exchange(Exchange::Bybit)
+            .exchange(Exchange::Bybit)
+            .symbols(["btcusdt", "BTCUSDT"])
+            .data_type(DatasetType::Trades)
+            .data_type(DatasetType::OptionsChain)
+            .data_type(DatasetType::Trades);
+
+        // Listed symbols don't request the instruments.
+        let keys = plan.resolve(&Client::new("key")).await.unwrap();
+        let files = keys.iter().map(|key| key.file_name()).collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                "bybit_trades_2022-10-01_BTCUSDT.csv.gz",
+                "bybit_trades_2022-10-02_BTCUSDT.csv.gz",
+                "bybit_options_chain_2022-10-01_OPTIONS.csv.gz",
+                "bybit_options_chain_2022-10-02_OPTIONS.csv.gz",
+            ]
+        );
+    }
+}
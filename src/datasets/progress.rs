@@ -0,0 +1,119 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use super::DatasetKey;
+
+/// The progress of the download of a file, see [`DownloadProgress::in_progress`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileProgress {
+    /// Number of bytes downloaded by the current attempt
+    pub bytes_downloaded: u64,
+
+    /// Number of bytes to download, if announced by the server, which excludes the part of a
+    /// resumed file downloaded before
+    pub bytes_total: Option<u64>,
+}
+
+impl FileProgress {
+    /// Returns the fraction of the file downloaded, between 0 and 1, if the size is known.
+    pub fn fraction(&self) -> Option<f64> {
+        self.bytes_total
+            .map(|total| (self.bytes_downloaded as f64 / total.max(1) as f64).min(1.0))
+    }
+}
+
+/// The progress of a [`DatasetDownloader::fetch`](super::DatasetDownloader::fetch), published
+/// through [`DatasetDownloader::progress`](super::DatasetDownloader::progress), eg. to render a
+/// progress bar.
+#[derive(Debug, Clone)]
+pub struct DownloadProgress {
+    /// Number of files to download
+    pub files_total: usize,
+
+    /// Number of files downloaded or found in the cache
+    pub files_done: usize,
+
+    /// Number of files whose download failed
+    pub files_failed: usize,
+
+    /// Number of bytes downloaded over all the files, including the failed attempts
+    pub bytes_downloaded: u64,
+
+    /// The files being downloaded
+    pub in_progress: BTreeMap<DatasetKey, FileProgress>,
+
+    /// When the fetch started
+    pub started_at: Instant,
+}
+
+impl DownloadProgress {
+    pub(crate) fn new(files_total: usize) -> Self {
+        Self {
+            files_total,
+            files_done: 0,
+            files_failed: 0,
+            bytes_downloaded: 0,
+            in_progress: BTreeMap::new(),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Returns whether every file was either downloaded or failed.
+    pub fn is_finished(&self) -> bool {
+        self.files_done + self.files_failed >= self.files_total
+    }
+
+    /// Returns the time elapsed since the fetch started.
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Returns the average number of bytes downloaded per second since the fetch started.
+    pub fn throughput(&self) -> f64 {
+        self.bytes_downloaded as f64 / self.elapsed().as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Returns the estimated time until every file is downloaded, extrapolated from the time the
+    /// finished files took, `None` until a file finished. Files found in the cache finish at once
+    /// and make the estimate optimistic.
+    pub fn eta(&self) -> Option<Duration> {
+        let finished = self.files_done + self.files_failed;
+        if finished == 0 {
+            return None;
+        }
+        let remaining = self.files_total.saturating_sub(finished);
+        Some(self.elapsed().mul_f64(remaining as f64 / finished as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_download_progress() {
+        let mut progress = DownloadProgress::new(4);
+        assert_eq!(progress.eta(), None);
+        assert!(!progress.is_finished());
+
+        progress.started_at = Instant::now() - Duration::from_secs(10);
+        progress.files_done = 1;
+        progress.bytes_downloaded = 1000;
+        let eta = progress.eta().unwrap().as_secs_f64();
+        assert!((30.0..31.0).contains(&eta), "{}", eta);
+        assert!(progress.throughput() <= 100.0);
+
+        progress.files_done = 3;
+        progress.files_failed = 1;
+        assert!(progress.is_finished());
+        assert_eq!(progress.eta(), Some(Duration::ZERO));
+
+        let file = FileProgress {
+            bytes_downloaded: 50,
+            bytes_total: Some(200),
+        };
+        assert_eq!(file.fraction(), Some(0.25));
+    }
+}
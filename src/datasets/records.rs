@@ -0,0 +1,1151 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream};
+use serde::{
+    de::{DeserializeOwned, IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+
+use super::{DatasetCache, DatasetKey, DatasetType};
+use crate::{codec, Exchange, OptionType, Result};
+
+/// The timestamps of the datasets, microseconds since the Unix epoch.
+mod epoch_micros {
+    use chrono::{DateTime, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        timestamp: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(timestamp.timestamp_micros())
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        let micros = i64::deserialize(deserializer)?;
+        DateTime::from_timestamp_micros(micros)
+            .ok_or_else(|| D::Error::custom(format!("timestamp out of range: {}", micros)))
+    }
+}
+
+/// A timestamp deserialized with [`epoch_micros`] where a field can't be annotated.
+#[derive(Deserialize)]
+struct Micros(#[serde(with = "epoch_micros")] DateTime<Utc>);
+
+/// The optional timestamps of the datasets, see [`epoch_micros`], empty fields being `None`.
+mod option_epoch_micros {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        timestamp: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match timestamp {
+            Some(timestamp) => super::epoch_micros::serialize(timestamp, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<super::Micros>::deserialize(deserializer)?.map(|micros| micros.0))
+    }
+}
+
+/// A row of the CSV file of a dataset.
+pub trait DatasetRecord: DeserializeOwned {
+    /// The type of the dataset the rows belong to.
+    const DATASET_TYPE: DatasetType;
+}
+
+/// A row of the `trades` dataset: an individual trade.
+/// See <https://docs.tardis.dev/downloadable-csv-files#trades>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradeRecord {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Instrument symbol as provided by exchange
+    pub symbol: String,
+
+    /// Trade timestamp provided by exchange, or the local timestamp if not provided
+    #[serde(with = "epoch_micros")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    #[serde(with = "epoch_micros")]
+    pub local_timestamp: DateTime<Utc>,
+
+    /// Trade id if provided by exchange
+    pub id: Option<String>,
+
+    /// Liquidity taker side (aggressor)
+    pub side: RecordSide,
+
+    /// Trade price as provided by exchange
+    pub price: f64,
+
+    /// Trade amount as provided by exchange
+    pub amount: f64,
+}
+
+impl DatasetRecord for TradeRecord {
+    const DATASET_TYPE: DatasetType = DatasetType::Trades;
+}
+
+/// Side of the order book of an [`IncrementalBookL2Record`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordBookSide {
+    /// Bid side.
+    Bid,
+
+    /// Ask side.
+    Ask,
+}
+
+/// A row of the `incremental_book_L2` dataset: the new amount at a price level of the order book,
+/// zero when the level was removed. The rows of each day start with a snapshot of the book, whose
+/// rows have `is_snapshot` set.
+/// See <https://docs.tardis.dev/downloadable-csv-files#incremental_book_l2>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncrementalBookL2Record {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Instrument symbol as provided by exchange
+    pub symbol: String,
+
+    /// Timestamp provided by exchange, or the local timestamp if not provided
+    #[serde(with = "epoch_micros")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    #[serde(with = "epoch_micros")]
+    pub local_timestamp: DateTime<Utc>,
+
+    /// Whether the row is part of a snapshot of the book rather than an update, the book being
+    /// reset when a new snapshot starts
+    pub is_snapshot: bool,
+
+    /// Side of the book the level belongs to
+    pub side: RecordBookSide,
+
+    /// Price of the level
+    pub price: f64,
+
+    /// New amount at the price, zero when the level was removed
+    pub amount: f64,
+}
+
+impl DatasetRecord for IncrementalBookL2Record {
+    const DATASET_TYPE: DatasetType = DatasetType::IncrementalBookL2;
+}
+
+/// A row of the `quotes` dataset: the top of the order book, with the best bid and ask.
+/// See <https://docs.tardis.dev/downloadable-csv-files#quotes>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuoteRecord {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Instrument symbol as provided by exchange
+    pub symbol: String,
+
+    /// Timestamp provided by exchange, or the local timestamp if not provided
+    #[serde(with = "epoch_micros")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    #[serde(with = "epoch_micros")]
+    pub local_timestamp: DateTime<Utc>,
+
+    /// Amount at the best ask, `None` if the ask side is empty
+    pub ask_amount: Option<f64>,
+
+    /// Best ask price, `None` if the ask side is empty
+    pub ask_price: Option<f64>,
+
+    /// Best bid price, `None` if the bid side is empty
+    pub bid_price: Option<f64>,
+
+    /// Amount at the best bid, `None` if the bid side is empty
+    pub bid_amount: Option<f64>,
+}
+
+impl DatasetRecord for QuoteRecord {
+    const DATASET_TYPE: DatasetType = DatasetType::Quotes;
+}
+
+/// A row of the `derivative_ticker` dataset: the funding, open interest and reference prices of
+/// a derivative instrument, one row per change of any of them.
+/// See <https://docs.tardis.dev/downloadable-csv-files#derivative_ticker>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DerivativeTickerRecord {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Instrument symbol as provided by exchange
+    pub symbol: String,
+
+    /// Timestamp provided by exchange, or the local timestamp if not provided
+    #[serde(with = "epoch_micros")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    #[serde(with = "epoch_micros")]
+    pub local_timestamp: DateTime<Utc>,
+
+    /// Timestamp of the next funding event, if provided by exchange
+    #[serde(with = "option_epoch_micros")]
+    pub funding_timestamp: Option<DateTime<Utc>>,
+
+    /// Funding rate applied at the next funding event, if provided by exchange
+    pub funding_rate: Option<f64>,
+
+    /// Funding rate estimated for the funding event after the next one, if provided by exchange
+    pub predicted_funding_rate: Option<f64>,
+
+    /// Total number of contracts open, if provided by exchange
+    pub open_interest: Option<f64>,
+
+    /// Last traded price, if provided by exchange
+    pub last_price: Option<f64>,
+
+    /// Index price, if provided by exchange
+    pub index_price: Option<f64>,
+
+    /// Mark price, if provided by exchange
+    pub mark_price: Option<f64>,
+}
+
+impl DatasetRecord for DerivativeTickerRecord {
+    const DATASET_TYPE: DatasetType = DatasetType::DerivativeTicker;
+}
+
+/// Side of a trade or a liquidation in the datasets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordSide {
+    /// Buy order.
+    Buy,
+
+    /// Sell order.
+    Sell,
+
+    /// Unknown order.
+    Unknown,
+}
+
+/// A row of the `liquidations` dataset: a forced closure of a position by the exchange.
+/// See <https://docs.tardis.dev/downloadable-csv-files#liquidations>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiquidationRecord {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Instrument symbol as provided by exchange
+    pub symbol: String,
+
+    /// Liquidation timestamp provided by exchange, or the local timestamp if not provided
+    #[serde(with = "epoch_micros")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    #[serde(with = "epoch_micros")]
+    pub local_timestamp: DateTime<Utc>,
+
+    /// Liquidation id if provided by exchange
+    pub id: Option<String>,
+
+    /// Liquidation side, `buy` when a short position was liquidated and `sell` for a long one
+    pub side: RecordSide,
+
+    /// Liquidation price as provided by exchange
+    pub price: f64,
+
+    /// Liquidation amount as provided by exchange
+    pub amount: f64,
+}
+
+impl DatasetRecord for LiquidationRecord {
+    const DATASET_TYPE: DatasetType = DatasetType::Liquidations;
+}
+
+/// A row of the `options_chain` dataset: the quotes, implied volatilities and greeks of an option.
+/// See <https://docs.tardis.dev/downloadable-csv-files#options_chain>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OptionsChainRecord {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Option symbol as provided by exchange
+    pub symbol: String,
+
+    /// Timestamp provided by exchange, or the local timestamp if not provided
+    #[serde(with = "epoch_micros")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    #[serde(with = "epoch_micros")]
+    pub local_timestamp: DateTime<Utc>,
+
+    /// Option type
+    #[serde(rename = "type")]
+    pub option_type: OptionType,
+
+    /// Option strike price
+    pub strike_price: f64,
+
+    /// Option expiration date
+    #[serde(with = "epoch_micros")]
+    pub expiration: DateTime<Utc>,
+
+    /// Open interest, if provided by exchange
+    pub open_interest: Option<f64>,
+
+    /// Last traded price, if provided by exchange
+    pub last_price: Option<f64>,
+
+    /// Best bid price, if provided by exchange
+    pub bid_price: Option<f64>,
+
+    /// Amount at the best bid, if provided by exchange
+    pub bid_amount: Option<f64>,
+
+    /// Implied volatility of the best bid, if provided by exchange
+    pub bid_iv: Option<f64>,
+
+    /// Best ask price, if provided by exchange
+    pub ask_price: Option<f64>,
+
+    /// Amount at the best ask, if provided by exchange
+    pub ask_amount: Option<f64>,
+
+    /// Implied volatility of the best ask, if provided by exchange
+    pub ask_iv: Option<f64>,
+
+    /// Mark price, if provided by exchange
+    pub mark_price: Option<f64>,
+
+    /// Implied volatility of the mark price, if provided by exchange
+    pub mark_iv: Option<f64>,
+
+    /// Underlying index name, eg. `BTC-USD`
+    pub underlying_index: String,
+
+    /// Underlying price, if provided by exchange
+    pub underlying_price: Option<f64>,
+
+    /// Delta, if provided by exchange
+    pub delta: Option<f64>,
+
+    /// Gamma, if provided by exchange
+    pub gamma: Option<f64>,
+
+    /// Vega, if provided by exchange
+    pub vega: Option<f64>,
+
+    /// Theta, if provided by exchange
+    pub theta: Option<f64>,
+
+    /// Rho, if provided by exchange
+    pub rho: Option<f64>,
+}
+
+impl DatasetRecord for OptionsChainRecord {
+    const DATASET_TYPE: DatasetType = DatasetType::OptionsChain;
+}
+
+/// A price level of a [`BookSnapshotRecord`].
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookLevelRecord {
+    /// Price of the level
+    pub price: f64,
+
+    /// Total amount at the price
+    pub amount: f64,
+}
+
+/// A row of the `book_snapshot_5` and `book_snapshot_25` datasets: the top `DEPTH` levels of each
+/// side of the order book, read from the `asks[0].price`, `asks[0].amount`, ... columns.
+/// See <https://docs.tardis.dev/downloadable-csv-files#book_snapshot_25>
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookSnapshotRecord<const DEPTH: usize> {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Instrument symbol as provided by exchange
+    pub symbol: String,
+
+    /// Timestamp provided by exchange, or the local timestamp if not provided
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    pub local_timestamp: DateTime<Utc>,
+
+    /// Bid levels, best first, up to `DEPTH` of them as sides thinner than `DEPTH` levels leave
+    /// the last columns empty
+    pub bids: Vec<BookLevelRecord>,
+
+    /// Ask levels, best first, see `bids`
+    pub asks: Vec<BookLevelRecord>,
+}
+
+/// A row of the `book_snapshot_5` dataset.
+pub type BookSnapshot5Record = BookSnapshotRecord<5>;
+
+/// A row of the `book_snapshot_25` dataset.
+pub type BookSnapshot25Record = BookSnapshotRecord<25>;
+
+impl DatasetRecord for BookSnapshot5Record {
+    const DATASET_TYPE: DatasetType = DatasetType::BookSnapshot5;
+}
+
+impl DatasetRecord for BookSnapshot25Record {
+    const DATASET_TYPE: DatasetType = DatasetType::BookSnapshot25;
+}
+
+/// Parses a column of a level of a book snapshot, eg. `bids[3].amount` into `(true, 3, false)`
+/// for the side, the index of the level and whether it is the price.
+fn parse_level_column(column: &str) -> Option<(bool, usize, bool)> {
+    let (bids, rest) = match column.strip_prefix("bids[") {
+        Some(rest) => (true, rest),
+        None => (false, column.strip_prefix("asks[")?),
+    };
+    let (index, field) = rest.split_once("].")?;
+    let price = match field {
+        "price" => true,
+        "amount" => false,
+        _ => return None,
+    };
+    Some((bids, index.parse().ok()?, price))
+}
+
+impl<'de, const DEPTH: usize> Deserialize<'de> for BookSnapshotRecord<DEPTH> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct SnapshotVisitor<const DEPTH: usize>;
+
+        impl<'de, const DEPTH: usize> Visitor<'de> for SnapshotVisitor<DEPTH> {
+            type Value = BookSnapshotRecord<DEPTH>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a row of a book snapshot of depth {}", DEPTH)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                use serde::de::Error;
+
+                let mut exchange = None;
+                let mut symbol = None;
+                let mut timestamp = None;
+                let mut local_timestamp = None;
+                let mut levels = [[[None; 2]; DEPTH]; 2];
+
+                while let Some(column) = map.next_key::<String>()? {
+                    match column.as_str() {
+                        "exchange" => exchange = Some(map.next_value()?),
+                        "symbol" => symbol = Some(map.next_value()?),
+                        "timestamp" => timestamp = Some(map.next_value::<Micros>()?.0),
+                        "local_timestamp" => local_timestamp = Some(map.next_value::<Micros>()?.0),
+                        column => match parse_level_column(column) {
+                            Some((bids, index, price)) if index < DEPTH => {
+                                levels[usize::from(bids)][index][usize::from(!price)] =
+                                    map.next_value::<Option<f64>>()?;
+                            }
+                            _ => {
+                                map.next_value::<IgnoredAny>()?;
+                            }
+                        },
+                    }
+                }
+
+                let side = |levels: [[Option<f64>; 2]; DEPTH]| {
+                    levels
+                        .into_iter()
+                        .map_while(|[price, amount]| {
+                            Some(BookLevelRecord {
+                                price: price?,
+                                amount: amount?,
+                            })
+                        })
+                        .collect()
+                };
+                let [asks, bids] = levels;
+                Ok(BookSnapshotRecord {
+                    exchange: exchange.ok_or_else(|| A::Error::missing_field("exchange"))?,
+                    symbol: symbol.ok_or_else(|| A::Error::missing_field("symbol"))?,
+                    timestamp: timestamp.ok_or_else(|| A::Error::missing_field("timestamp"))?,
+                    local_timestamp: local_timestamp
+                        .ok_or_else(|| A::Error::missing_field("local_timestamp"))?,
+                    bids: side(bids),
+                    asks: side(asks),
+                })
+            }
+        }
+
+        deserializer.deserialize_map(SnapshotVisitor::<DEPTH>)
+    }
+}
+
+/// Reads the rows of CSV from a buffered reader with a single streaming parser, so that quoted
+/// fields may span several lines.
+struct RowReader<R> {
+    reader: R,
+    parser: csv_core::Reader,
+    output: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl<R: AsyncBufRead + Unpin> RowReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            parser: csv_core::Reader::new(),
+            output: vec![0; 1024],
+            ends: vec![0; 32],
+        }
+    }
+
+    /// Reads the next row into `row`, returning `false` at the end of the input. Empty lines are
+    /// skipped.
+    async fn read_row(&mut self, row: &mut csv::StringRecord) -> Result<bool> {
+        let (mut output_len, mut ends_len) = (0, 0);
+        loop {
+            let input = self.reader.fill_buf().await?;
+            let (result, read, written, ended) = self.parser.read_record(
+                input,
+                &mut self.output[output_len..],
+                &mut self.ends[ends_len..],
+            );
+            self.reader.consume(read);
+            output_len += written;
+            ends_len += ended;
+
+            match result {
+                csv_core::ReadRecordResult::InputEmpty => {}
+                csv_core::ReadRecordResult::OutputFull => {
+                    self.output.resize(self.output.len() * 2, 0)
+                }
+                csv_core::ReadRecordResult::OutputEndsFull => {
+                    self.ends.resize(self.ends.len() * 2, 0)
+                }
+                csv_core::ReadRecordResult::Record => {
+                    let fields = std::str::from_utf8(&self.output[..output_len])
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    row.clear();
+                    let mut start = 0;
+                    for end in &self.ends[..ends_len] {
+                        row.push_field(&fields[start..*end]);
+                        start = *end;
+                    }
+                    return Ok(true);
+                }
+                csv_core::ReadRecordResult::End => return Ok(false),
+            }
+        }
+    }
+}
+
+/// Parses the decompressed CSV file of a dataset from the given reader into a stream of `T`,
+/// matching the columns by the names in the header row of the file. Rows are parsed one at a
+/// time as they are read.
+pub fn parse_records<T, R>(reader: R) -> impl Stream<Item = Result<T>>
+where
+    T: DeserializeOwned,
+    R: AsyncBufRead + Unpin,
+{
+    stream::try_unfold(
+        (
+            RowReader::new(reader),
+            None::<csv::StringRecord>,
+            csv::StringRecord::new(),
+        ),
+        |(mut rows, mut header, mut row)| async move {
+            while rows.read_row(&mut row).await? {
+                match &header {
+                    None => header = Some(row.clone()),
+                    Some(columns) => {
+                        let record = row.deserialize(Some(columns))?;
+                        return Ok(Some((record, (rows, header, row))));
+                    }
+                }
+            }
+            Ok(None)
+        },
+    )
+}
+
+/// The CSV file of a dataset read by [`read_records`], either at a path or in a [`DatasetCache`].
+#[derive(Debug, Clone)]
+pub enum RecordSource<'a> {
+    /// The path of a file, gzip compressed or not
+    Path(PathBuf),
+
+    /// A file completely downloaded into a cache
+    Cache(&'a DatasetCache, DatasetKey),
+}
+
+impl RecordSource<'_> {
+    /// Returns the path of the file, failing if it isn't in the cache or, for a cached file, isn't
+    /// of `data_type`.
+    async fn path(self, data_type: Option<DatasetType>) -> Result<PathBuf> {
+        let (cache, key) = match self {
+            Self::Path(path) => return Ok(path),
+            Self::Cache(cache, key) => (cache, key),
+        };
+        if let Some(data_type) = data_type.filter(|data_type| *data_type != key.data_type) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} isn't a file of {}", key.file_name(), data_type),
+            )
+            .into());
+        }
+        match cache.get(&key).await? {
+            Some(_) => Ok(cache.path(&key)),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} isn't in the cache", key.file_name()),
+            )
+            .into()),
+        }
+    }
+}
+
+impl From<PathBuf> for RecordSource<'_> {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<&PathBuf> for RecordSource<'_> {
+    fn from(path: &PathBuf) -> Self {
+        Self::Path(path.clone())
+    }
+}
+
+impl From<&Path> for RecordSource<'_> {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_path_buf())
+    }
+}
+
+impl From<&str> for RecordSource<'_> {
+    fn from(path: &str) -> Self {
+        Self::Path(path.into())
+    }
+}
+
+impl From<String> for RecordSource<'_> {
+    fn from(path: String) -> Self {
+        Self::Path(path.into())
+    }
+}
+
+impl<'a> From<(&'a DatasetCache, DatasetKey)> for RecordSource<'a> {
+    fn from((cache, key): (&'a DatasetCache, DatasetKey)) -> Self {
+        Self::Cache(cache, key)
+    }
+}
+
+/// Reads the CSV file of a dataset into a stream of `T`, see [`parse_records`]. Files compressed
+/// with gzip, as downloaded, are detected and decompressed as they are read.
+///
+/// ```no_run
+/// use chrono::NaiveDate;
+/// use futures_util::TryStreamExt;
+/// use tardis_rs::{
+///     datasets::{read_trades, DatasetCache, DatasetKey, DatasetType},
+///     Exchange,
+/// };
+///
+/// # #[tokio::main]
+/// # async fn main() -> tardis_rs::Result<()> {
+/// let trades = read_trades("./bybit_trades_2022-10-01_BTCUSDT.csv.gz").await?;
+/// let volume = trades.try_fold(0.0, |volume, trade| async move { Ok(volume + trade.amount) }).await?;
+///
+/// let cache = DatasetCache::new("./datasets");
+/// let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+/// let key = DatasetKey::new(Exchange::Bybit, DatasetType::Trades, "BTCUSDT", date);
+/// let trades = read_trades((&cache, key)).await?.try_collect::<Vec<_>>().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn read_records<'a, T: DatasetRecord>(
+    source: impl Into<RecordSource<'a>>,
+) -> Result<impl Stream<Item = Result<T>>> {
+    let path = source.into().path(Some(T::DATASET_TYPE)).await?;
+    Ok(parse_records(open(&path).await?))
+}
+
+/// Opens the CSV file at `path`, decompressing it if compressed with gzip.
+async fn open(path: &Path) -> Result<impl AsyncBufRead + Unpin> {
+    codec::decompress(BufReader::new(tokio::fs::File::open(path).await?)).await
+}
+
+/// Converts the CSV file of a dataset to Parquet, written by `sink`, see
+/// [`ParquetSink`](crate::sink::ParquetSink). Returns the number of rows converted.
+///
+/// ```no_run
+/// use tardis_rs::{datasets::convert_to_parquet, sink::ParquetSink};
+///
+/// # #[tokio::main]
+/// # async fn main() -> tardis_rs::sink::Result<()> {
+/// let sink = ParquetSink::new("./bybit_trades_2022-10-01_BTCUSDT.parquet").zstd(3);
+/// convert_to_parquet("./bybit_trades_2022-10-01_BTCUSDT.csv.gz", sink).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "parquet")]
+pub async fn convert_to_parquet<'a>(
+    source: impl Into<RecordSource<'a>>,
+    mut sink: crate::sink::ParquetSink,
+) -> crate::sink::Result<u64> {
+    let path = source.into().path(None).await?;
+    let mut rows = RowReader::new(open(&path).await?);
+    let mut header = None::<Vec<String>>;
+    let mut converted = 0;
+    let mut row = csv::StringRecord::new();
+    while rows.read_row(&mut row).await? {
+        match &header {
+            None => header = Some(row.iter().map(String::from).collect()),
+            Some(columns) => {
+                sink.write_row(|| columns.clone(), std::mem::take(&mut row))
+                    .await?;
+                converted += 1;
+            }
+        }
+    }
+    sink.finish().await?;
+    Ok(converted)
+}
+
+/// Reads the trades of a file, see [`read_records`].
+pub async fn read_trades<'a>(
+    source: impl Into<RecordSource<'a>>,
+) -> Result<impl Stream<Item = Result<TradeRecord>>> {
+    read_records(source).await
+}
+
+/// Reads the incremental updates of the order book of a file, see [`read_records`].
+pub async fn read_incremental_book_l2<'a>(
+    source: impl Into<RecordSource<'a>>,
+) -> Result<impl Stream<Item = Result<IncrementalBookL2Record>>> {
+    read_records(source).await
+}
+
+/// Reads the top of the order book of a file, see [`read_records`].
+pub async fn read_quotes<'a>(
+    source: impl Into<RecordSource<'a>>,
+) -> Result<impl Stream<Item = Result<QuoteRecord>>> {
+    read_records(source).await
+}
+
+/// Reads the funding, open interest and reference prices of a file, see [`read_records`].
+pub async fn read_derivative_ticker<'a>(
+    source: impl Into<RecordSource<'a>>,
+) -> Result<impl Stream<Item = Result<DerivativeTickerRecord>>> {
+    read_records(source).await
+}
+
+/// Reads the snapshots of the top 5 levels of the order book of a file, see [`read_records`].
+pub async fn read_book_snapshot_5<'a>(
+    source: impl Into<RecordSource<'a>>,
+) -> Result<impl Stream<Item = Result<BookSnapshot5Record>>> {
+    read_records(source).await
+}
+
+/// Reads the snapshots of the top 25 levels of the order book of a file, see [`read_records`].
+pub async fn read_book_snapshot_25<'a>(
+    source: impl Into<RecordSource<'a>>,
+) -> Result<impl Stream<Item = Result<BookSnapshot25Record>>> {
+    read_records(source).await
+}
+
+/// Reads the liquidations of a file, see [`read_records`].
+pub async fn read_liquidations<'a>(
+    source: impl Into<RecordSource<'a>>,
+) -> Result<impl Stream<Item = Result<LiquidationRecord>>> {
+    read_records(source).await
+}
+
+/// Reads the options chain of a file, see [`read_records`].
+pub async fn read_options_chain<'a>(
+    source: impl Into<RecordSource<'a>>,
+) -> Result<impl Stream<Item = Result<OptionsChainRecord>>> {
+    read_records(source).await
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::TryStreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_records() {
+        use async_compression::tokio::bufread::GzipEncoder;
+        use tokio::io::AsyncReadExt;
+
+        let csv = "exchange,symbol,timestamp,local_timestamp,id,side,price,amount\n\
+            bybit,BTCUSDT,1664582400100000,1664582400104000,1,buy,19310.5,0.1\n\
+            bybit,BTCUSDT,1664582400200000,1664582400204000,,unknown,19311,0.2\n";
+        let mut compressed = vec![];
+        GzipEncoder::new(csv.as_bytes())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        let directory = std::env::temp_dir();
+        let gzip = directory.join(format!("tardis-rs-trades-{}.csv.gz", std::process::id()));
+        let plain = directory.join(format!("tardis-rs-trades-{}.csv", std::process::id()));
+        tokio::fs::write(&gzip, compressed).await.unwrap();
+        tokio::fs::write(&plain, csv).await.unwrap();
+
+        for path in [&gzip, &plain] {
+            let trades = read_records::<TradeRecord>(path)
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(trades.len(), 2);
+            assert_eq!(trades[0].id.as_deref(), Some("1"));
+            assert_eq!(trades[0].side, RecordSide::Buy);
+            assert_eq!(trades[1].id, None);
+            assert_eq!(trades[1].side, RecordSide::Unknown);
+            tokio::fs::remove_file(path).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_cached_records() {
+        let directory =
+            std::env::temp_dir().join(format!("tardis-rs-read-cached-{}", std::process::id()));
+        let cache = DatasetCache::new(&directory);
+        let date = chrono::NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let key = DatasetKey::new(Exchange::Bybit, DatasetType::Quotes, "BTCUSDT", date);
+
+        // Only the files completely downloaded are read.
+        let missing = read_quotes((&cache, key.clone())).await.err().unwrap();
+        assert!(
+            missing.to_string().contains("isn't in the cache"),
+            "{}",
+            missing
+        );
+
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(
+            cache.part_path(&key),
+            "exchange,symbol,timestamp,local_timestamp,ask_amount,ask_price,bid_price,bid_amount\n\
+            bybit,BTCUSDT,1664582400100000,1664582400104000,1.5,19311,19310.5,2\n",
+        )
+        .await
+        .unwrap();
+        cache.complete(&key).await.unwrap();
+        let quotes = read_quotes((&cache, key.clone()))
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].ask_price, Some(19311.0));
+
+        assert!(read_trades((&cache, key)).await.is_err());
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+
+    #[cfg(feature = "parquet")]
+    #[tokio::test]
+    async fn test_convert_to_parquet() {
+        use arrow_array::{Array, StringArray, TimestampMicrosecondArray};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let directory =
+            std::env::temp_dir().join(format!("tardis-rs-parquet-{}", std::process::id()));
+        let csv = directory.join("trades.csv");
+        let parquet = directory.join("trades.parquet");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(
+            &csv,
+            "exchange,symbol,timestamp,local_timestamp,id,side,price,amount\n\
+            bybit,BTCUSDT,1664582400100000,1664582400104000,1,buy,19310.5,0.1\n\
+            bybit,BTCUSDT,1664582400200000,1664582400204000,,sell,19311,0.2\n",
+        )
+        .await
+        .unwrap();
+
+        let sink = crate::sink::ParquetSink::new(&parquet).zstd(3);
+        assert_eq!(convert_to_parquet(&csv, sink).await.unwrap(), 2);
+
+        let batches =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&parquet).unwrap())
+                .unwrap()
+                .build()
+                .unwrap()
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        let column = |name: &str| batch.column(batch.schema().index_of(name).unwrap()).clone();
+        let timestamps = column("timestamp");
+        let timestamps = timestamps
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(timestamps.value(1), 1664582400200000);
+        let ids = column("id");
+        let ids = ids.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(ids.value(0), "1");
+        assert!(ids.is_null(1));
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+
+    #[cfg(feature = "parquet")]
+    #[tokio::test]
+    async fn test_convert_derivative_ticker_to_parquet() {
+        use arrow_array::{Array, TimestampMicrosecondArray};
+        use arrow_schema::{DataType, TimeUnit};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let directory =
+            std::env::temp_dir().join(format!("tardis-rs-parquet-ticker-{}", std::process::id()));
+        let csv = directory.join("derivative_ticker.csv");
+        let parquet = directory.join("derivative_ticker.parquet");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(
+            &csv,
+            "exchange,symbol,timestamp,local_timestamp,funding_timestamp,funding_rate,predicted_funding_rate,open_interest,last_price,index_price,mark_price\n\
+            bybit,BTCUSDT,1664582400100000,1664582400104000,1664611200000000,0.0001,,52345.2,19310.5,19309.12,19310.01\n\
+            deribit,BTC-PERPETUAL,1664582400200000,1664582400204000,,-0.00002,,,,19309.1,\n",
+        )
+        .await
+        .unwrap();
+
+        let sink = crate::sink::ParquetSink::new(&parquet);
+        assert_eq!(convert_to_parquet(&csv, sink).await.unwrap(), 2);
+
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&parquet).unwrap())
+                .unwrap();
+        let schema = reader.schema().clone();
+        let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+        for column in ["timestamp", "local_timestamp", "funding_timestamp"] {
+            assert_eq!(
+                schema.field_with_name(column).unwrap().data_type(),
+                &timestamp
+            );
+        }
+        for column in ["funding_rate", "predicted_funding_rate", "mark_price"] {
+            assert_eq!(
+                schema.field_with_name(column).unwrap().data_type(),
+                &DataType::Float64
+            );
+        }
+
+        let batches = reader
+            .build()
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        let column = batches[0].column(schema.index_of("funding_timestamp").unwrap());
+        let funding = column
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(funding.value(0), 1664611200000000);
+        assert!(funding.is_null(1));
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_parse_incremental_book_l2() {
+        let csv = "exchange,symbol,timestamp,local_timestamp,is_snapshot,side,price,amount\n\
+            bitmex,XBTUSD,1664582400100000,1664582400104000,true,ask,19311,1000\n\
+            bitmex,XBTUSD,1664582400200000,1664582400204000,false,bid,19310.5,0\n";
+        let updates = parse_records::<IncrementalBookL2Record, _>(csv.as_bytes())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert!(updates[0].is_snapshot);
+        assert_eq!(updates[0].side, RecordBookSide::Ask);
+        assert!(!updates[1].is_snapshot);
+        assert_eq!(updates[1].side, RecordBookSide::Bid);
+        assert_eq!(updates[1].amount, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_parse_quotes() {
+        let csv =
+            "exchange,symbol,timestamp,local_timestamp,ask_amount,ask_price,bid_price,bid_amount\n\
+            bybit,BTCUSDT,1664582400100000,1664582400104321,1.5,19311,19310.5,0.25\n\
+            \n\
+            bybit,BTCUSDT,1664582400200000,1664582400204000,,,19310.5,0.5\n";
+        let quotes = parse_records::<QuoteRecord, _>(csv.as_bytes())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(quotes.len(), 2);
+        assert_eq!(quotes[0].exchange, Exchange::Bybit);
+        assert_eq!(
+            quotes[0].local_timestamp.to_rfc3339(),
+            "2022-10-01T00:00:00.104321+00:00"
+        );
+        assert_eq!(quotes[0].ask_price, Some(19311.0));
+        assert_eq!(quotes[1].ask_amount, None);
+        assert_eq!(quotes[1].bid_amount, Some(0.5));
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(&quotes[1]).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            "exchange,symbol,timestamp,local_timestamp,ask_amount,ask_price,bid_price,bid_amount\n\
+            bybit,BTCUSDT,1664582400200000,1664582400204000,,,19310.5,0.5\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_derivative_tickers() {
+        let csv = "exchange,symbol,timestamp,local_timestamp,funding_timestamp,funding_rate,predicted_funding_rate,open_interest,last_price,index_price,mark_price\n\
+            bybit,BTCUSDT,1664582400100000,1664582400104000,1664611200000000,0.0001,,52345.2,19310.5,19309.12,19310.01\n\
+            deribit,BTC-PERPETUAL,1664582400200000,1664582400204000,,-0.00002,,,,19309.1,\n";
+        let tickers = parse_records::<DerivativeTickerRecord, _>(csv.as_bytes())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            tickers[0].funding_timestamp.unwrap().to_rfc3339(),
+            "2022-10-01T08:00:00+00:00"
+        );
+        assert_eq!(tickers[0].funding_rate, Some(0.0001));
+        assert_eq!(tickers[0].predicted_funding_rate, None);
+        assert_eq!(tickers[0].open_interest, Some(52345.2));
+        assert_eq!(tickers[1].funding_timestamp, None);
+        assert_eq!(tickers[1].funding_rate, Some(-0.00002));
+        assert_eq!(tickers[1].mark_price, None);
+    }
+
+    #[tokio::test]
+    async fn test_parse_liquidations() {
+        let csv = "exchange,symbol,timestamp,local_timestamp,id,side,price,amount\n\
+            binance-futures,BTCUSDT,1664582400100000,1664582400104000,,sell,19250.1,0.5\n\
+            bitmex,XBTUSD,1664582400200000,1664582400204000,a1b2,buy,19400,100\n";
+        let liquidations = parse_records::<LiquidationRecord, _>(csv.as_bytes())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(liquidations[0].exchange, Exchange::BinanceFutures);
+        assert_eq!(liquidations[0].id, None);
+        assert_eq!(liquidations[0].side, RecordSide::Sell);
+        assert_eq!(liquidations[1].id.as_deref(), Some("a1b2"));
+        assert_eq!(liquidations[1].amount, 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_parse_options_chain() {
+        let csv = "exchange,symbol,timestamp,local_timestamp,type,strike_price,expiration,open_interest,last_price,bid_price,bid_amount,bid_iv,ask_price,ask_amount,ask_iv,mark_price,mark_iv,underlying_index,underlying_price,delta,gamma,vega,theta,rho\n\
+            deribit,BTC-28OCT22-20000-C,1664582400100000,1664582400104000,call,20000,1666944000000000,1250.5,0.031,0.03,12,61.2,0.0315,5,63.4,0.0307,62.1,BTC-28OCT22,19320.5,0.42,0.0001,14.2,-22.8,5.1\n\
+            deribit,BTC-28OCT22-18000-P,1664582400200000,1664582400204000,put,18000,1666944000000000,,,,,,0.02,3,70.1,0.018,68,BTC-28OCT22,19320.5,-0.25,0.00008,11.9,-20.4,-3.2\n";
+        let options = parse_records::<OptionsChainRecord, _>(csv.as_bytes())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(options[0].option_type, OptionType::Call);
+        assert_eq!(options[0].strike_price, 20000.0);
+        assert_eq!(
+            options[0].expiration.to_rfc3339(),
+            "2022-10-28T08:00:00+00:00"
+        );
+        assert_eq!(options[0].bid_iv, Some(61.2));
+        assert_eq!(options[0].underlying_index, "BTC-28OCT22");
+        assert_eq!(options[1].option_type, OptionType::Put);
+        assert_eq!(options[1].bid_price, None);
+        assert_eq!(options[1].delta, Some(-0.25));
+    }
+
+    #[tokio::test]
+    async fn test_parse_quoted_rows() {
+        let long_id = "x".repeat(5000);
+        let csv = format!(
+            "exchange,symbol,timestamp,local_timestamp,id,side,price,amount\n\n\
+            bybit,BTCUSDT,1664582400100000,1664582400104000,\"a\nb\",buy,19310.5,0.1\r\n\
+            bybit,BTCUSDT,1664582400200000,1664582400204000,{},sell,19311,0.2",
+            long_id
+        );
+        // Fed a few bytes at a time, so rows and fields span several reads.
+        let reader = BufReader::with_capacity(3, csv.as_bytes());
+        let trades = parse_records::<TradeRecord, _>(reader)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].id.as_deref(), Some("a\nb"));
+        assert_eq!(trades[0].amount, 0.1);
+        assert_eq!(trades[1].id, Some(long_id));
+        assert_eq!(trades[1].amount, 0.2);
+    }
+
+    #[tokio::test]
+    async fn test_parse_book_snapshots() {
+        let mut header = "exchange,symbol,timestamp,local_timestamp".to_string();
+        for i in 0..5 {
+            header +=
+                &format!(",asks[{i}].price,asks[{i}].amount,bids[{i}].price,bids[{i}].amount");
+        }
+        let csv = format!(
+            "{}\n\
+            bybit,BTCUSDT,1664582400100000,1664582400104000,19311,1,19310.5,2,19311.5,3,19310,4,19312,5,,,19312.5,6,,,19313,7,,\n",
+            header
+        );
+        let snapshots = parse_records::<BookSnapshot5Record, _>(csv.as_bytes())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let level = |price, amount| BookLevelRecord { price, amount };
+        assert_eq!(
+            snapshots[0].asks,
+            vec![
+                level(19311.0, 1.0),
+                level(19311.5, 3.0),
+                level(19312.0, 5.0),
+                level(19312.5, 6.0),
+                level(19313.0, 7.0)
+            ]
+        );
+        assert_eq!(
+            snapshots[0].bids,
+            vec![level(19310.5, 2.0), level(19310.0, 4.0)]
+        );
+        assert_eq!(
+            snapshots[0].timestamp.to_rfc3339(),
+            "2022-10-01T00:00:00.100+00:00"
+        );
+
+        assert_eq!(
+            parse_level_column("bids[24].amount"),
+            Some((true, 24, false))
+        );
+        assert_eq!(parse_level_column("asks[x].price"), None);
+    }
+}
@@ -0,0 +1,495 @@
+//! Custom deserializers for the hot fields of messages: lenient numeric fields, as some exchanges
+//! encode numbers as strings (eg. `"19310.5"`) or in scientific notation (eg. `"1e-8"`), and a
+//! fast path for the fixed format timestamps Tardis produces.
+//!
+//! Numbers are only looked for in strings in the human-readable formats, eg. JSON, which is where
+//! the exchanges quote them. The other formats, eg. bincode, are given the hint of the type the
+//! field is serialized as, since they may not describe their own content.
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use serde::{
+    de::{Error, Visitor},
+    Deserializer,
+};
+
+struct F64Visitor;
+
+impl Visitor<'_> for F64Visitor {
+    type Value = f64;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a number or a string containing a number")
+    }
+
+    fn visit_f64<E: Error>(self, value: f64) -> Result<f64, E> {
+        Ok(value)
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<f64, E> {
+        Ok(value as f64)
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<f64, E> {
+        Ok(value as f64)
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<f64, E> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(value), &self))
+    }
+}
+
+struct OptionF64Visitor;
+
+impl<'de> Visitor<'de> for OptionF64Visitor {
+    type Value = Option<f64>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("null, a number or a string containing a number")
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Option<f64>, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Option<f64>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<f64>, D::Error> {
+        deserialize_f64(deserializer, self)
+    }
+
+    fn visit_f64<E: Error>(self, value: f64) -> Result<Option<f64>, E> {
+        F64Visitor.visit_f64(value).map(Some)
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<Option<f64>, E> {
+        F64Visitor.visit_i64(value).map(Some)
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<Option<f64>, E> {
+        F64Visitor.visit_u64(value).map(Some)
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<Option<f64>, E> {
+        if value.trim().is_empty() {
+            return Ok(None);
+        }
+        F64Visitor.visit_str(value).map(Some)
+    }
+}
+
+/// Deserializes a number of a human-readable format, given either as a number or a string, with
+/// `visitor`, or of any other format as the `f64` it is serialized as.
+fn deserialize_f64<'de, D, V>(deserializer: D, visitor: V) -> Result<V::Value, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Visitor<'de>,
+{
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(visitor)
+    } else {
+        deserializer.deserialize_f64(visitor)
+    }
+}
+
+/// Deserializes a `f64` given either as a JSON number or a string.
+pub(crate) fn f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    deserialize_f64(deserializer, F64Visitor)
+}
+
+/// Deserializes an optional `f64` given either as a JSON number or a string, `null` and empty
+/// strings being `None`.
+pub(crate) fn option_f64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error> {
+    deserializer.deserialize_option(OptionF64Visitor)
+}
+
+#[cfg(feature = "decimal")]
+struct DecimalVisitor;
+
+#[cfg(feature = "decimal")]
+impl Visitor<'_> for DecimalVisitor {
+    type Value = rust_decimal::Decimal;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a number or a string containing a number")
+    }
+
+    // JSON numbers reach the visitor as `f64`, whose shortest representation is the one written
+    // by the server, unless it has more than 17 significant digits.
+    fn visit_f64<E: Error>(self, value: f64) -> Result<rust_decimal::Decimal, E> {
+        self.visit_str(&value.to_string())
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<rust_decimal::Decimal, E> {
+        Ok(value.into())
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<rust_decimal::Decimal, E> {
+        Ok(value.into())
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<rust_decimal::Decimal, E> {
+        let trimmed = value.trim();
+        trimmed
+            .parse()
+            .or_else(|_| rust_decimal::Decimal::from_scientific(trimmed))
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(value), &self))
+    }
+}
+
+#[cfg(feature = "decimal")]
+struct OptionDecimalVisitor;
+
+#[cfg(feature = "decimal")]
+impl<'de> Visitor<'de> for OptionDecimalVisitor {
+    type Value = Option<rust_decimal::Decimal>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("null, a number or a string containing a number")
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserialize_decimal(deserializer, self)
+    }
+
+    fn visit_f64<E: Error>(self, value: f64) -> Result<Self::Value, E> {
+        DecimalVisitor.visit_f64(value).map(Some)
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<Self::Value, E> {
+        DecimalVisitor.visit_i64(value).map(Some)
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<Self::Value, E> {
+        DecimalVisitor.visit_u64(value).map(Some)
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+        if value.trim().is_empty() {
+            return Ok(None);
+        }
+        DecimalVisitor.visit_str(value).map(Some)
+    }
+}
+
+/// Same as [`deserialize_f64`], for the decimals which are serialized as strings.
+#[cfg(feature = "decimal")]
+fn deserialize_decimal<'de, D, V>(deserializer: D, visitor: V) -> Result<V::Value, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Visitor<'de>,
+{
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(visitor)
+    } else {
+        deserializer.deserialize_str(visitor)
+    }
+}
+
+/// Deserializes a [`Decimal`](rust_decimal::Decimal) given either as a JSON number or a string,
+/// see [`f64`].
+#[cfg(feature = "decimal")]
+pub(crate) fn decimal<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<rust_decimal::Decimal, D::Error> {
+    deserialize_decimal(deserializer, DecimalVisitor)
+}
+
+/// Deserializes an optional [`Decimal`](rust_decimal::Decimal), see [`option_f64`].
+#[cfg(feature = "decimal")]
+pub(crate) fn option_decimal<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<rust_decimal::Decimal>, D::Error> {
+    deserializer.deserialize_option(OptionDecimalVisitor)
+}
+
+/// Parses a timestamp in the format used by Tardis, eg. `2022-10-01T00:00:00.012Z`, with any
+/// number of fractional digits up to nanoseconds. Returns `None` for any other format.
+pub(crate) fn parse_timestamp_fast(value: &str) -> Option<DateTime<Utc>> {
+    let bytes = value.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || bytes[10] != b'T'
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+        || bytes[bytes.len() - 1] != b'Z'
+    {
+        return None;
+    }
+
+    let digits = |range: std::ops::Range<usize>| {
+        bytes[range].iter().try_fold(0u32, |acc, byte| {
+            byte.is_ascii_digit()
+                .then(|| acc * 10 + u32::from(byte - b'0'))
+        })
+    };
+
+    let nanos = match &bytes[19..bytes.len() - 1] {
+        [] => 0,
+        [b'.', fraction @ ..] if !fraction.is_empty() && fraction.len() <= 9 => {
+            digits(20..bytes.len() - 1)? * 10u32.pow(9 - fraction.len() as u32)
+        }
+        _ => return None,
+    };
+
+    NaiveDate::from_ymd_opt(digits(0..4)? as i32, digits(5..7)?, digits(8..10)?)?
+        .and_hms_nano_opt(digits(11..13)?, digits(14..16)?, digits(17..19)?, nanos)
+        .map(|datetime| datetime.and_utc())
+}
+
+/// Same as [`parse_timestamp_fast`], returning the microseconds since the Unix epoch without
+/// going through chrono. Digits past the microseconds are truncated.
+pub(crate) fn parse_micros_fast(value: &str) -> Option<i64> {
+    let bytes = value.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || bytes[10] != b'T'
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+        || bytes[bytes.len() - 1] != b'Z'
+    {
+        return None;
+    }
+
+    let digits = |bytes: &[u8]| {
+        bytes.iter().try_fold(0i64, |acc, byte| {
+            byte.is_ascii_digit()
+                .then(|| acc * 10 + i64::from(byte - b'0'))
+        })
+    };
+
+    let micros = match &bytes[19..bytes.len() - 1] {
+        [] => 0,
+        [b'.', fraction @ ..] if !fraction.is_empty() && fraction.len() <= 9 => {
+            let fraction = &fraction[..fraction.len().min(6)];
+            digits(fraction)? * 10i64.pow(6 - fraction.len() as u32)
+        }
+        _ => return None,
+    };
+
+    let (year, month, day) = (
+        digits(&bytes[0..4])?,
+        digits(&bytes[5..7])?,
+        digits(&bytes[8..10])?,
+    );
+    let (hour, minute, second) = (
+        digits(&bytes[11..13])?,
+        digits(&bytes[14..16])?,
+        digits(&bytes[17..19])?,
+    );
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if day == 0 || day > days_in_month || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // Days from the civil date, see http://howardhinnant.github.io/date_algorithms.html.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some(((days * 24 + hour) * 60 + minute) * 60_000_000 + second * 1_000_000 + micros)
+}
+
+struct TimestampVisitor;
+
+impl Visitor<'_> for TimestampVisitor {
+    type Value = DateTime<Utc>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an ISO 8601 timestamp")
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<DateTime<Utc>, E> {
+        if let Some(timestamp) = parse_timestamp_fast(value) {
+            return Ok(timestamp);
+        }
+
+        DateTime::parse_from_rfc3339(value)
+            .map(|timestamp| timestamp.with_timezone(&Utc))
+            .map_err(E::custom)
+    }
+}
+
+/// Deserializes a timestamp, parsing the format used by Tardis without going through chrono's
+/// general RFC 3339 parser and falling back to it for any other format.
+pub(crate) fn timestamp<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<DateTime<Utc>, D::Error> {
+    deserializer.deserialize_str(TimestampVisitor)
+}
+
+struct MicrosVisitor;
+
+impl Visitor<'_> for MicrosVisitor {
+    type Value = i64;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an ISO 8601 timestamp")
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<i64, E> {
+        if let Some(micros) = parse_micros_fast(value) {
+            return Ok(micros);
+        }
+
+        TimestampVisitor
+            .visit_str(value)
+            .map(|timestamp| timestamp.timestamp_micros())
+    }
+}
+
+/// Deserializes a timestamp into the microseconds since the Unix epoch, see [`timestamp`].
+pub(crate) fn timestamp_micros<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<i64, D::Error> {
+    deserializer.deserialize_str(MicrosVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Level {
+        #[serde(deserialize_with = "super::f64")]
+        price: f64,
+        #[serde(default, deserialize_with = "super::option_f64")]
+        amount: Option<f64>,
+    }
+
+    fn parse(json: &str) -> (f64, Option<f64>) {
+        let level = serde_json::from_str::<Level>(json).unwrap();
+        (level.price, level.amount)
+    }
+
+    #[test]
+    fn test_lenient_numbers() {
+        assert_eq!(
+            parse(r#"{"price":19310.5,"amount":2}"#),
+            (19310.5, Some(2.0))
+        );
+        assert_eq!(
+            parse(r#"{"price":"19310.5","amount":"1e-8"}"#),
+            (19310.5, Some(1e-8))
+        );
+        assert_eq!(parse(r#"{"price":"1.5E+3","amount":null}"#), (1500.0, None));
+        assert_eq!(parse(r#"{"price":1,"amount":""}"#), (1.0, None));
+        assert_eq!(parse(r#"{"price":1}"#), (1.0, None));
+        assert!(serde_json::from_str::<Level>(r#"{"price":"abc"}"#).is_err());
+    }
+
+    /// A binary format holding a single `f64`, which doesn't describe its own content.
+    struct Binary(f64);
+
+    impl<'de> Deserializer<'de> for Binary {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(Self::Error::custom("deserialize_any isn't supported"))
+        }
+
+        fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            visitor.visit_f64(self.0)
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            visitor.visit_some(self)
+        }
+
+        fn is_human_readable(&self) -> bool {
+            false
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 char str string bytes byte_buf unit
+            unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+            ignored_any
+        }
+    }
+
+    #[test]
+    fn test_binary_numbers() {
+        assert_eq!(super::f64(Binary(19310.5)), Ok(19310.5));
+        assert_eq!(super::option_f64(Binary(1e-8)), Ok(Some(1e-8)));
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let expected = |nanos: u32| {
+            Utc.with_ymd_and_hms(2022, 10, 1, 23, 59, 58).unwrap()
+                + chrono::Duration::nanoseconds(nanos as i64)
+        };
+
+        for (value, nanos) in [
+            ("2022-10-01T23:59:58Z", 0),
+            ("2022-10-01T23:59:58.012Z", 12_000_000),
+            ("2022-10-01T23:59:58.012345Z", 12_345_000),
+            ("2022-10-01T23:59:58.123456789Z", 123_456_789),
+        ] {
+            assert_eq!(parse_timestamp_fast(value), Some(expected(nanos)));
+        }
+
+        for value in [
+            "1970-01-01T00:00:00Z",
+            "1969-12-31T23:59:59.999999Z",
+            "2000-02-29T12:00:00.5Z",
+            "2022-10-01T23:59:58.123456789Z",
+            "2100-03-01T00:00:00.000Z",
+        ] {
+            assert_eq!(
+                parse_micros_fast(value),
+                parse_timestamp_fast(value).map(|timestamp| timestamp.timestamp_micros()),
+                "{value}"
+            );
+        }
+        assert_eq!(parse_micros_fast("2022-02-30T00:00:00.000Z"), None);
+        assert_eq!(parse_micros_fast("2100-02-29T00:00:00.000Z"), None);
+
+        assert_eq!(parse_timestamp_fast("2022-02-30T00:00:00.000Z"), None);
+        assert_eq!(parse_timestamp_fast("2022-10-01T23:59:58.Z"), None);
+        assert_eq!(parse_timestamp_fast("2022-10-02T01:59:58+02:00"), None);
+
+        #[derive(Deserialize)]
+        struct Message {
+            #[serde(deserialize_with = "super::timestamp")]
+            timestamp: DateTime<Utc>,
+        }
+
+        let message =
+            serde_json::from_str::<Message>(r#"{"timestamp":"2022-10-02T01:59:58+02:00"}"#)
+                .unwrap();
+        assert_eq!(message.timestamp, expected(0));
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use async_compression::tokio::bufread::GzipEncoder;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::AsyncReadExt,
+    sync::{Mutex, OnceCell},
+};
+
+use super::filters_json;
+use crate::{codec, log, Exchange, Filter, Result};
+
+/// A directory of the slices of the [data feeds](super), laid out as the cache of the official
+/// [Node.js client](https://github.com/tardis-dev/tardis-node) so that both can share it.
+///
+/// Each slice is stored gzip compressed as served by the API, at
+/// `{root}/feeds/{exchange}/{hash}/{YYYY}/{MM}/{DD}/{HH}/{mm}.json.gz`, where `hash` is the
+/// hex encoded SHA-256 of the filters of the replay serialized to JSON.
+///
+/// ```
+/// use tardis_rs::{feeds::FeedCache, Client};
+///
+/// let client = Client::builder("key")
+///     .feed_cache(FeedCache::new("/var/cache/tardis").max_size(50 << 30))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FeedCache {
+    root: PathBuf,
+    max_size: Option<u64>,
+    size: OnceCell<Mutex<u64>>,
+}
+
+impl Default for FeedCache {
+    /// Creates a [`FeedCache`] in the default directory of the Node.js client, `.tardis-cache` in
+    /// the temporary directory of the system.
+    fn default() -> Self {
+        Self::new(std::env::temp_dir().join(".tardis-cache"))
+    }
+}
+
+impl FeedCache {
+    /// Creates a new instance of [`FeedCache`] rooted at `root`, which is created on the first
+    /// slice cached if missing.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            max_size: None,
+            size: OnceCell::new(),
+        }
+    }
+
+    /// Limits the total size of the cached slices to `bytes`, unlimited by default.
+    ///
+    /// Once the limit is exceeded, the least recently used slices are deleted until the cache is
+    /// back under 90% of the limit. The size is computed by scanning the cache on the first slice
+    /// cached, so the slices cached by other processes sharing the directory afterwards are only
+    /// accounted for on the next eviction.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Returns the root directory of the cache.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the path of the slice of `exchange` starting at the minute of `slice`, replayed
+    /// with `filters`, whether it was cached or not.
+    pub fn path(&self, exchange: Exchange, filters: &[Filter], slice: DateTime<Utc>) -> PathBuf {
+        self.slice_path(exchange, &filters_json(filters), slice)
+    }
+
+    /// Deletes every cached slice.
+    pub async fn clear(&self) -> Result<()> {
+        let size = self.lock_size().await?;
+        match tokio::fs::remove_dir_all(self.root.join("feeds")).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        if let Some(mut size) = size {
+            *size = 0;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn slice_path(
+        &self,
+        exchange: Exchange,
+        filters_json: &str,
+        slice: DateTime<Utc>,
+    ) -> PathBuf {
+        let hash = Sha256::digest(filters_json.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        self.root
+            .join("feeds")
+            .join(exchange.to_string())
+            .join(hash)
+            .join(format!("{}.json.gz", slice.format("%Y/%m/%d/%H/%M")))
+    }
+
+    /// Returns the compressed slice at `path`, if cached, marking it as recently used.
+    pub(crate) async fn get(&self, path: &Path) -> Result<Option<Bytes>> {
+        let data = match tokio::fs::read(path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if self.max_size.is_some() {
+            let path = path.to_path_buf();
+            let touched = tokio::task::spawn_blocking(move || {
+                std::fs::File::options()
+                    .append(true)
+                    .open(path)?
+                    .set_modified(SystemTime::now())
+            })
+            .await
+            .expect("touching a file doesn't panic");
+            if let Err(e) = touched {
+                log::debug!("Failed to mark cached slice as used: {}", e);
+            }
+        }
+        Ok(Some(data.into()))
+    }
+
+    /// Stores the slice `data` at `path`, compressing it if the API served it uncompressed, and
+    /// evicts the least recently used slices if the cache grew over its maximum size.
+    pub(crate) async fn put(&self, path: &Path, data: &Bytes) -> Result<()> {
+        static UNCONFIRMED: AtomicU64 = AtomicU64::new(0);
+
+        let data = if codec::is_gzip(data) {
+            data.clone()
+        } else {
+            let mut compressed = vec![];
+            GzipEncoder::new(&data[..])
+                .read_to_end(&mut compressed)
+                .await?;
+            compressed.into()
+        };
+
+        let size = self.lock_size().await?;
+        let directory = path.parent().expect("slice paths have a parent");
+        tokio::fs::create_dir_all(directory).await?;
+        // Written aside and renamed so that a slice is never read half written, even by another
+        // process sharing the cache.
+        let unconfirmed = directory.join(format!(
+            "{}.{}.{}.unconfirmed",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            std::process::id(),
+            UNCONFIRMED.fetch_add(1, Ordering::Relaxed)
+        ));
+        tokio::fs::write(&unconfirmed, &data).await?;
+        tokio::fs::rename(&unconfirmed, path).await?;
+
+        if let (Some(mut size), Some(max_size)) = (size, self.max_size) {
+            *size += data.len() as u64;
+            if *size > max_size {
+                let root = self.root.join("feeds");
+                *size = tokio::task::spawn_blocking(move || evict(&root, max_size / 10 * 9))
+                    .await
+                    .expect("evicting slices doesn't panic")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Locks the total size of the cache, scanning it on first use, `None` if unlimited.
+    async fn lock_size(&self) -> Result<Option<tokio::sync::MutexGuard<'_, u64>>> {
+        if self.max_size.is_none() {
+            return Ok(None);
+        }
+        let size = self
+            .size
+            .get_or_try_init(|| async {
+                let root = self.root.join("feeds");
+                let files = tokio::task::spawn_blocking(move || scan(&root))
+                    .await
+                    .expect("scanning slices doesn't panic")?;
+                Ok::<_, crate::Error>(Mutex::new(files.iter().map(|file| file.1).sum()))
+            })
+            .await?;
+        Ok(Some(size.lock().await))
+    }
+}
+
+/// Returns the slices under `directory` with their last use and size.
+fn scan(directory: &Path) -> io::Result<Vec<(SystemTime, u64, PathBuf)>> {
+    let mut files = vec![];
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = match std::fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                directories.push(entry.path());
+            } else if entry.file_name().to_string_lossy().ends_with(".json.gz") {
+                files.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Deletes the least recently used slices under `directory` until their total size is at most
+/// `target`, returning the size left.
+fn evict(directory: &Path, target: u64) -> io::Result<u64> {
+    let mut files = scan(directory)?;
+    files.sort();
+    let mut size = files.iter().map(|file| file.1).sum::<u64>();
+    for (_, len, path) in files {
+        if size <= target {
+            break;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => size -= len,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => size -= len,
+            Err(e) => return Err(e),
+        }
+        // Removes the directory of the hour once empty, failing otherwise.
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::remove_dir(parent);
+        }
+    }
+    log::debug!("Evicted slices from {:?}, {} bytes left", directory, size);
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    #[test]
+    fn test_feed_cache_path() {
+        let cache = FeedCache::new("/cache");
+        let slice = Utc.with_ymd_and_hms(2019, 6, 1, 3, 7, 42).unwrap();
+        assert_eq!(
+            cache.path(Exchange::Bitmex, &[], slice),
+            Path::new("/cache/feeds/bitmex/4f53cda18c2baa0c0354bb5f9a3ecbe5ed12ab4d8e11ba873c2f11161202b945/2019/06/01/03/07.json.gz")
+        );
+
+        // Equivalent filters share their slices.
+        let filters = [
+            Filter::channel("trade").symbols(["XBTUSD"]),
+            Filter::channel("trade").symbols(["XBTUSD"]),
+        ];
+        assert_eq!(
+            cache.path(Exchange::Bitmex, &filters, slice),
+            Path::new("/cache/feeds/bitmex/344565a28e99e233a29205050587032c5fe9635db429272bceacdf9abcf78a3f/2019/06/01/03/07.json.gz")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_feed_cache_eviction() {
+        let root = std::env::temp_dir().join(format!("tardis-feed-cache-{}", std::process::id()));
+        let cache = FeedCache::new(&root).max_size(100);
+        let start = Utc.with_ymd_and_hms(2019, 6, 1, 0, 0, 0).unwrap();
+        let paths = (0..3)
+            .map(|minute| cache.path(Exchange::Bitmex, &[], start + Duration::minutes(minute)))
+            .collect::<Vec<_>>();
+
+        // Slices of 40 bytes once compressed, stored as is.
+        let slice = Bytes::from([0x1f, 0x8b].repeat(20));
+        cache.put(&paths[0], &slice).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        cache.put(&paths[1], &slice).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        // Using the first slice makes the second one the least recently used.
+        assert_eq!(cache.get(&paths[0]).await.unwrap(), Some(slice.clone()));
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        cache.put(&paths[2], &slice).await.unwrap();
+
+        assert!(paths[0].exists());
+        assert!(!paths[1].exists());
+        assert!(paths[2].exists());
+        assert_eq!(cache.get(&paths[1]).await.unwrap(), None);
+
+        cache.clear().await.unwrap();
+        assert!(!paths[0].exists());
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+}
@@ -0,0 +1,417 @@
+//! Replays of the raw messages of the exchanges through the
+//! [data feeds API](https://docs.tardis.dev/api/http#data-feeds-exchange) of Tardis, without a
+//! Tardis Machine Server.
+//!
+//! The API serves the messages in slices of one minute. Each line of a slice is the local
+//! timestamp of a message followed by a space and the message as received from the exchange, eg.
+//! `2019-06-01T00:00:00.1234567Z {"table":"trade",...}`, while an empty line marks a
+//! disconnection from the exchange.
+//!
+//! ```no_run
+//! use chrono::{TimeZone, Utc};
+//! use futures_util::{pin_mut, StreamExt};
+//! use tardis_rs::{feeds::FeedMessage, Client, Exchange, Filter};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> tardis_rs::Result<()> {
+//! let client = Client::new(std::env::var("TARDIS_API_KEY").unwrap());
+//! let stream = client.replay_data_feed(
+//!     Exchange::Bitmex,
+//!     Utc.with_ymd_and_hms(2019, 6, 1, 0, 0, 0).unwrap(),
+//!     Utc.with_ymd_and_hms(2019, 6, 1, 0, 10, 0).unwrap(),
+//!     vec![Filter::channel("trade").symbols(["XBTUSD"])],
+//! );
+//! pin_mut!(stream);
+//!
+//! while let Some(message) = stream.next().await {
+//!     if let FeedMessage::Message { local_timestamp, payload } = message? {
+//!         println!("{} {}", local_timestamp, String::from_utf8_lossy(&payload));
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::Bytes;
+use chrono::{DateTime, Duration, DurationRound, SecondsFormat, Utc};
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use memchr::memchr;
+use reqwest::header::ACCEPT_ENCODING;
+use serde::de::DeserializeOwned;
+use tokio::io::AsyncReadExt;
+
+use crate::{client::error_for_status, codec, log, Client, Error, Exchange, Filter, Result};
+
+mod cache;
+
+pub use cache::*;
+
+/// Number of slices fetched ahead of the one being read by [`Client::replay_data_feed`].
+const PREFETCH_SLICES: usize = 4;
+
+/// A line of a slice of a data feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedMessage {
+    /// A message received from the exchange.
+    Message {
+        /// When Tardis received the message
+        local_timestamp: DateTime<Utc>,
+
+        /// The message as received from the exchange, usually JSON
+        payload: Bytes,
+    },
+
+    /// The connection of Tardis to the exchange dropped, so messages may be missing until it
+    /// reconnected.
+    Disconnect,
+}
+
+impl FeedMessage {
+    /// Returns when Tardis received the message, `None` for a [`FeedMessage::Disconnect`].
+    pub fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Message {
+                local_timestamp, ..
+            } => Some(*local_timestamp),
+            Self::Disconnect => None,
+        }
+    }
+
+    /// Deserializes the JSON payload of the message, `None` for a [`FeedMessage::Disconnect`].
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Option<serde_json::Result<T>> {
+        match self {
+            Self::Message { payload, .. } => Some(serde_json::from_slice(payload)),
+            Self::Disconnect => None,
+        }
+    }
+}
+
+/// Parses a slice of a data feed, either gzip compressed as served by the API or not.
+pub async fn parse_slice(data: Bytes) -> Result<Vec<FeedMessage>> {
+    let data = if codec::is_gzip(&data) {
+        let mut decompressed = vec![];
+        codec::decompress(&data[..])
+            .await?
+            .read_to_end(&mut decompressed)
+            .await?;
+        Bytes::from(decompressed)
+    } else {
+        data
+    };
+
+    let mut messages = vec![];
+    let mut start = 0;
+    while start < data.len() {
+        let end = memchr(b'\n', &data[start..]).map_or(data.len(), |i| start + i);
+        messages.push(parse_line(&data, start, end)?);
+        start = end + 1;
+    }
+    Ok(messages)
+}
+
+/// Parses the line of `data` between `start` and `end`, sharing the buffer for the payload.
+fn parse_line(data: &Bytes, start: usize, end: usize) -> Result<FeedMessage> {
+    let line = &data[start..end];
+    if line.is_empty() {
+        return Ok(FeedMessage::Disconnect);
+    }
+
+    let separator = memchr(b' ', line);
+    let local_timestamp = separator
+        .and_then(|i| std::str::from_utf8(&line[..i]).ok())
+        .and_then(|timestamp| timestamp.parse::<DateTime<Utc>>().ok());
+    match (separator, local_timestamp) {
+        (Some(separator), Some(local_timestamp)) => Ok(FeedMessage::Message {
+            local_timestamp,
+            payload: data.slice(start + separator + 1..end),
+        }),
+        _ => Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "malformed data feed line: {}",
+                String::from_utf8_lossy(line)
+                    .chars()
+                    .take(100)
+                    .collect::<String>()
+            ),
+        ))),
+    }
+}
+
+/// Merges the filters of the same channel and sorts them and their symbols, as the Node.js client
+/// does, so that equivalent filters share their slices in a [`FeedCache`].
+fn optimize_filters(filters: &[Filter]) -> Vec<Filter> {
+    let mut optimized: Vec<Filter> = vec![];
+    for filter in filters {
+        match optimized.iter_mut().find(|f| f.channel == filter.channel) {
+            Some(existing) => match (&mut existing.symbols, &filter.symbols) {
+                (Some(symbols), Some(more)) => symbols.extend(more.iter().cloned()),
+                _ => existing.symbols = None,
+            },
+            None => optimized.push(filter.clone()),
+        }
+    }
+    optimized.sort_by(|a, b| a.channel.cmp(&b.channel));
+    for symbols in optimized.iter_mut().filter_map(|f| f.symbols.as_mut()) {
+        symbols.sort();
+        symbols.dedup();
+    }
+    optimized
+}
+
+/// Returns the JSON of the optimized `filters`, sent to the API and hashed by the cache.
+fn filters_json(filters: &[Filter]) -> String {
+    serde_json::to_string(&optimize_filters(filters)).expect("filters serialize to JSON")
+}
+
+impl Client {
+    /// Returns the messages of the slice of `exchange` starting `offset` minutes after `from`,
+    /// for the channels and symbols of `filters`, or all of them if empty.
+    /// See <https://docs.tardis.dev/api/http#data-feeds-exchange>
+    pub async fn data_feed_slice(
+        &self,
+        exchange: Exchange,
+        from: DateTime<Utc>,
+        offset: u32,
+        filters: &[Filter],
+    ) -> Result<Vec<FeedMessage>> {
+        self.fetch_slice(exchange, from, offset, &filters_json(filters))
+            .await
+    }
+
+    /// Replays the messages of `exchange` received between `from` (inclusive) and `to`
+    /// (exclusive), for the channels and symbols of `filters`, or all of them if empty.
+    ///
+    /// The slices are fetched a few minutes ahead of the messages being read, in order, or read
+    /// from the [`FeedCache`] of the client.
+    pub fn replay_data_feed(
+        &self,
+        exchange: Exchange,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        filters: Vec<Filter>,
+    ) -> impl Stream<Item = Result<FeedMessage>> + '_ {
+        let start = from
+            .duration_trunc(Duration::minutes(1))
+            .expect("a minute fits any date");
+        let filters = filters_json(&filters);
+
+        stream::iter((0..).take_while(move |offset| start + Duration::minutes(*offset) < to))
+            .map(move |offset| {
+                let filters = filters.clone();
+                async move {
+                    self.fetch_slice(exchange, start, offset as u32, &filters)
+                        .await
+                }
+            })
+            .buffered(PREFETCH_SLICES)
+            .map_ok(|messages| stream::iter(messages).map(Ok))
+            .try_flatten()
+            .try_filter(move |message| {
+                let in_range = message
+                    .local_timestamp()
+                    .is_none_or(|timestamp| from <= timestamp && timestamp < to);
+                async move { in_range }
+            })
+    }
+
+    async fn fetch_slice(
+        &self,
+        exchange: Exchange,
+        from: DateTime<Utc>,
+        offset: u32,
+        filters: &str,
+    ) -> Result<Vec<FeedMessage>> {
+        let cache = self.feed_cache.as_ref().map(|cache| {
+            let slice = from + Duration::minutes(offset.into());
+            (cache, cache.slice_path(exchange, filters, slice))
+        });
+        if let Some((cache, path)) = &cache {
+            if let Some(data) = cache.get(path).await? {
+                return parse_slice(data).await;
+            }
+        }
+
+        let mut url = format!(
+            "{}/data-feeds/{}?from={}&offset={}",
+            self.base_url,
+            exchange,
+            from.to_rfc3339_opts(SecondsFormat::Millis, true),
+            offset
+        );
+        if filters != "[]" {
+            url.push_str("&filters=");
+            url.push_str(&urlencoding::encode(filters));
+        }
+
+        let response = self
+            .send(|| {
+                self.client
+                    .get(&url)
+                    .bearer_auth(&self.api_key)
+                    .header(ACCEPT_ENCODING, "gzip")
+            })
+            .await?;
+        let data = error_for_status(response).await?.bytes().await?;
+        let messages = parse_slice(data.clone()).await?;
+
+        if let Some((cache, path)) = &cache {
+            if let Err(e) = cache.put(path, &data).await {
+                log::warn!("Failed to cache slice {:?}: {}", path, e);
+            }
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_compression::tokio::bufread::GzipEncoder;
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::test_server::{serve, Reply, TestServer};
+
+    /// Serves the slice of index `offset` of `slices` gzip compressed.
+    async fn serve_slices(slices: Vec<&'static str>) -> TestServer {
+        let mut bodies = vec![];
+        for slice in slices {
+            let mut body = vec![];
+            GzipEncoder::new(slice.as_bytes())
+                .read_to_end(&mut body)
+                .await
+                .unwrap();
+            bodies.push(body);
+        }
+
+        serve(move |request| {
+            let offset = request
+                .path()
+                .split("offset=")
+                .nth(1)
+                .and_then(|offset| offset.split('&').next()?.parse::<usize>().ok())
+                .unwrap();
+            Some(Reply::ok(bodies[offset].clone()))
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_parse_slice() {
+        let slice = "2019-06-01T00:00:00.1234567Z {\"table\":\"trade\"}\n\n\
+            2019-06-01T00:00:01.0000000Z {\"table\":\"quote\"}\n";
+        let messages = parse_slice(Bytes::from_static(slice.as_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0].local_timestamp(),
+            Some(
+                Utc.with_ymd_and_hms(2019, 6, 1, 0, 0, 0).unwrap()
+                    + Duration::nanoseconds(123456700)
+            )
+        );
+        assert_eq!(
+            messages[0]
+                .deserialize::<serde_json::Value>()
+                .unwrap()
+                .unwrap()["table"],
+            "trade"
+        );
+        assert_eq!(messages[1], FeedMessage::Disconnect);
+
+        let mut compressed = vec![];
+        GzipEncoder::new(slice.as_bytes())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        assert_eq!(parse_slice(compressed.into()).await.unwrap(), messages);
+
+        assert!(parse_slice(Bytes::new()).await.unwrap().is_empty());
+        assert!(matches!(
+            parse_slice(Bytes::from_static(b"{\"table\":\"trade\"}\n")).await,
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidData
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replay_data_feed() {
+        let server = serve_slices(vec![
+            "2019-06-01T00:00:10.0000000Z {\"id\":1}\n2019-06-01T00:00:40.0000000Z {\"id\":2}\n",
+            "2019-06-01T00:01:10.0000000Z {\"id\":3}\n\n",
+            "2019-06-01T00:02:10.0000000Z {\"id\":4}\n2019-06-01T00:02:40.0000000Z {\"id\":5}\n",
+        ])
+        .await;
+        let client = Client::builder("key")
+            .base_url(&server.url)
+            .build()
+            .unwrap();
+
+        let from = Utc.with_ymd_and_hms(2019, 6, 1, 0, 0, 30).unwrap();
+        let to = Utc.with_ymd_and_hms(2019, 6, 1, 0, 2, 30).unwrap();
+        let messages = client
+            .replay_data_feed(
+                Exchange::Bitmex,
+                from,
+                to,
+                vec![Filter::channel("trade").symbols(["XBTUSD"])],
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let ids = messages
+            .iter()
+            .map(|message| {
+                message
+                    .deserialize::<serde_json::Value>()
+                    .map(|value| value.unwrap()["id"].as_u64().unwrap())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [Some(2), Some(3), None, Some(4)]);
+
+        let mut requests = server.paths();
+        requests.sort();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[0],
+            "/data-feeds/bitmex?from=2019-06-01T00:00:00.000Z&offset=0&filters=%5B%7B%22channel%22%3A%22trade%22%2C%22symbols%22%3A%5B%22XBTUSD%22%5D%7D%5D"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_data_feed_cache() {
+        let server = serve_slices(vec![
+            "2019-06-01T00:00:10.0000000Z {\"id\":1}\n",
+            "2019-06-01T00:01:10.0000000Z {\"id\":2}\n",
+        ])
+        .await;
+        let root = std::env::temp_dir().join(format!("tardis-feeds-{}", std::process::id()));
+        let client = Client::builder("key")
+            .base_url(&server.url)
+            .feed_cache(FeedCache::new(&root))
+            .build()
+            .unwrap();
+
+        let from = Utc.with_ymd_and_hms(2019, 6, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2019, 6, 1, 0, 2, 0).unwrap();
+        let filters = vec![Filter::channel("trade")];
+        let replay = || {
+            client
+                .replay_data_feed(Exchange::Bitmex, from, to, filters.clone())
+                .try_collect::<Vec<_>>()
+        };
+        let messages = replay().await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(server.requests().len(), 2);
+
+        // Replayed again from the cache, in the layout of the Node.js client.
+        assert_eq!(replay().await.unwrap(), messages);
+        assert_eq!(server.requests().len(), 2);
+        let cache = FeedCache::new(&root);
+        assert!(cache
+            .path(Exchange::Bitmex, &filters, from + Duration::minutes(1))
+            .exists());
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+}
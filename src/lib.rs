@@ -28,7 +28,7 @@
 //! main.rs
 //!
 //! ```ignore
-//! use tardis_rs::{Exchange, machine::{Client, Message}};
+//! use tardis_rs::{Exchange, machine::{Client, DataType, IntervalUnit, Message}};
 //! use chrono::NaiveDate;
 //!
 //! #[tokio::main]
@@ -41,7 +41,7 @@
 //!         symbols: Some(vec!["BTCUSDT".to_string()]),
 //!         from: NaiveDate::from_ymd_opt(2022, 10, 1).unwrap(),
 //!         to: NaiveDate::from_ymd_opt(2022, 10, 2).unwrap(),
-//!         data_types: vec!["trade_bar_60m".to_string()],
+//!         data_types: vec![DataType::TradeBar { interval: 60, unit: IntervalUnit::Minutes }],
 //!         with_disconnect_messages: None,
 //!     }])
 //!     .await
@@ -60,18 +60,57 @@
 //! To avoid compiling unused dependencies, tardis-rs gates certain features, some of
 //! which are disabled by default:
 //!
-//! | Feature    | Description                                                                                 |
-//! |------------|---------------------------------------------------------------------------------------------|
-//! | machine    | Enables the client for [Tardis Machine Server](https://docs.tardis.dev/api/tardis-machine). |
+//! | Feature    | Description                                                                                              |
+//! |------------|----------------------------------------------------------------------------------------------------------|
+//! | machine    | Enables the client for [Tardis Machine Server](https://docs.tardis.dev/api/tardis-machine).              |
+//! | test-util  | Enables the golden corpus harness and an in-process mock machine server for deterministic tests.         |
+//! | decimal    | Adds variants of the `machine` messages with [rust_decimal](https://docs.rs/rust_decimal) prices.        |
+//! | simd-json  | Parses the `machine` stream messages with [simd-json](https://docs.rs/simd-json) instead of serde_json.  |
+//! | tracing    | Logs connection events through [tracing](https://docs.rs/tracing), enabled by default.                   |
+//! | rustls-tls | Uses [rustls](https://docs.rs/rustls) for HTTPS and `wss://`, enabled by default.                        |
+//! | native-tls | Uses the TLS library of the platform (OpenSSL on Linux) for HTTPS and `wss://` instead.                  |
+//! | socks      | Supports SOCKS5 proxies for the HTTP client, see `ClientBuilder::proxy`.                                 |
+//! | arrow      | Converts normalized messages to [Arrow](https://docs.rs/arrow-array) record batches, see `arrow`.        |
+//! | parquet    | Writes datasets and normalized messages to Parquet files, see `sink::ParquetSink`.                       |
+//!
+//! One of the TLS features is required, the build fails without either of them. rustls is used
+//! when both are enabled, so to use OpenSSL instead, disable the default features and enable
+//! `native-tls`:
+//!
+//! ```toml
+//! tardis-rs = { version = "0.1", default-features = false, features = ["machine", "native-tls", "tracing"] }
+//! ```
 
 #![forbid(unsafe_code)]
-#![deny(private_in_public, unreachable_pub)]
+#![deny(private_interfaces, private_bounds, unreachable_pub)]
 #![warn(rustdoc::broken_intra_doc_links)]
 #![warn(missing_docs)]
 
+#[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+compile_error!(
+    "tardis-rs requires a TLS backend, enable either the `rustls-tls` or the `native-tls` feature"
+);
+
+#[cfg(feature = "arrow")]
+#[cfg_attr(not(feature = "parquet"), allow(dead_code))]
+pub mod arrow;
 mod client;
+pub mod codec;
+pub mod datasets;
+#[cfg_attr(not(feature = "machine"), allow(dead_code))]
+mod de;
+pub mod feeds;
+mod log;
 pub mod machine;
 mod models;
+mod rate_limit;
+pub mod recording;
+mod restart;
+pub mod sink;
+#[cfg(test)]
+mod test_server;
 
 pub use client::*;
 pub use models::*;
+pub use rate_limit::*;
+pub use restart::*;
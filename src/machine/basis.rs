@@ -0,0 +1,221 @@
+//! Basis between a derivative and its underlying spot market.
+
+use async_stream::stream;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    currency::QuoteNormalizer,
+    quotes::{InstrumentKey, PriceKind, QuoteTracker},
+    Message, Result,
+};
+
+/// How the relative basis is annualized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Annualization {
+    /// Over the time remaining until the expiry of a dated future.
+    Expiry(DateTime<Utc>),
+
+    /// Over a fixed period, eg. the funding interval of a perpetual.
+    Period(Duration),
+}
+
+/// The basis of a derivative at a point in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Basis {
+    /// Price of the derivative used
+    pub derivative_price: f64,
+
+    /// Price of the spot market used
+    pub spot_price: f64,
+
+    /// Derivative price minus spot price
+    pub absolute: f64,
+
+    /// Absolute basis relative to the spot price
+    pub relative: f64,
+
+    /// Relative basis annualized, `None` once a dated future expired
+    pub annualized: Option<f64>,
+
+    /// Arrival timestamp of the update that triggered the computation
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// Computes the [`Basis`] between a derivative (usually a perpetual) and a spot market every time
+/// either of them is updated.
+#[derive(Debug, Clone)]
+pub struct BasisCalculator {
+    derivative: InstrumentKey,
+    spot: InstrumentKey,
+    derivative_price: PriceKind,
+    spot_price: PriceKind,
+    annualization: Annualization,
+    max_staleness: Option<Duration>,
+    quotes: QuoteTracker,
+}
+
+impl BasisCalculator {
+    /// Creates a new instance of [`BasisCalculator`] using the mark price of the derivative, the
+    /// mid price of the spot market and annualizing over the usual 8 hours funding interval.
+    pub fn new(derivative: InstrumentKey, spot: InstrumentKey) -> Self {
+        Self {
+            derivative,
+            spot,
+            derivative_price: PriceKind::Mark,
+            spot_price: PriceKind::Mid,
+            annualization: Annualization::Period(Duration::hours(8)),
+            max_staleness: None,
+            quotes: QuoteTracker::new(),
+        }
+    }
+
+    /// Sets which prices of the derivative and spot market are used.
+    pub fn prices(mut self, derivative: PriceKind, spot: PriceKind) -> Self {
+        self.derivative_price = derivative;
+        self.spot_price = spot;
+        self
+    }
+
+    /// Sets how the relative basis is annualized.
+    pub fn annualization(mut self, annualization: Annualization) -> Self {
+        self.annualization = annualization;
+        self
+    }
+
+    /// Sets the maximum age of either price, older prices make the basis unavailable.
+    pub fn max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+
+    /// Normalizes both prices into their equivalent quote currency, eg. to compute the basis of a
+    /// USD margined future against a USDT quoted spot market.
+    pub fn quote_normalizer(mut self, normalizer: QuoteNormalizer) -> Self {
+        self.quotes = self.quotes.normalizer(normalizer);
+        self
+    }
+
+    /// Updates the prices with a message, returning the new basis if the message updated either
+    /// the derivative or the spot market.
+    pub fn update(&mut self, message: &Message) -> Option<Basis> {
+        let updated = self.quotes.update(message)?;
+        if updated != self.derivative && updated != self.spot {
+            return None;
+        }
+
+        let now = message.local_timestamp()?;
+        let derivative = self.quotes.get(&self.derivative)?;
+        let spot = self.quotes.get(&self.spot)?;
+        if let Some(max_staleness) = self.max_staleness {
+            if derivative.is_stale(now, max_staleness) || spot.is_stale(now, max_staleness) {
+                return None;
+            }
+        }
+
+        let derivative_price = derivative.price(self.derivative_price)?;
+        let spot_price = spot.price(self.spot_price)?;
+        let absolute = derivative_price - spot_price;
+        let relative = absolute / spot_price;
+
+        let period = match self.annualization {
+            Annualization::Expiry(expiry) => expiry - now,
+            Annualization::Period(period) => period,
+        };
+        let annualized = (period > Duration::zero()).then(|| {
+            relative * Duration::days(365).num_milliseconds() as f64
+                / period.num_milliseconds() as f64
+        });
+
+        Some(Basis {
+            derivative_price,
+            spot_price,
+            absolute,
+            relative,
+            annualized,
+            local_timestamp: now,
+        })
+    }
+}
+
+/// Derives a stream of [`Basis`] out of the given stream, while errors are passed through.
+pub fn basis<S>(messages: S, mut calculator: BasisCalculator) -> impl Stream<Item = Result<Basis>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    stream! {
+        futures_util::pin_mut!(messages);
+
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(msg) => {
+                    if let Some(basis) = calculator.update(&msg) {
+                        yield Ok(basis);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{
+        machine::{BookLevel, BookSnapshot, DerivativeTicker},
+        Exchange,
+    };
+
+    #[test]
+    fn test_perpetual_basis() {
+        let timestamp = Utc.timestamp_millis_opt(0).unwrap();
+        let mut calculator = BasisCalculator::new(
+            InstrumentKey::new(Exchange::BinanceFutures, "btcusdt"),
+            InstrumentKey::new(Exchange::Binance, "btcusdt"),
+        )
+        .annualization(Annualization::Period(Duration::days(365)));
+
+        assert!(calculator
+            .update(&Message::DerivativeTicker(Box::new(DerivativeTicker {
+                symbol: "btcusdt".into(),
+                exchange: Exchange::BinanceFutures,
+                last_price: Some(101.0),
+                open_interest: None,
+                funding_rate: None,
+                index_price: None,
+                mark_price: Some(102.0),
+                timestamp,
+                local_timestamp: timestamp,
+            })))
+            .is_none());
+
+        let basis = calculator
+            .update(&Message::BookSnapshot(Box::new(BookSnapshot {
+                symbol: "btcusdt".into(),
+                exchange: Exchange::Binance,
+                name: "book_snapshot_1_0ms".to_string(),
+                depth: 1,
+                interval: 0,
+                bids: vec![BookLevel {
+                    price: 99.0,
+                    amount: 1.0,
+                }],
+                asks: vec![BookLevel {
+                    price: 101.0,
+                    amount: 1.0,
+                }],
+                timestamp,
+                local_timestamp: timestamp,
+            })))
+            .unwrap();
+
+        assert_eq!(basis.absolute, 2.0);
+        assert_eq!(basis.relative, 0.02);
+        assert_eq!(basis.annualized, Some(0.02));
+    }
+}
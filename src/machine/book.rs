@@ -0,0 +1,577 @@
+//! Utilities for working with the order book data returned by Tardis Machine Server.
+
+use std::{
+    borrow::Borrow,
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, HashMap},
+};
+
+use async_stream::stream;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use super::{BookChange, BookLevel, BookSnapshot, Message, Result, Symbol};
+use crate::Exchange;
+
+/// Tolerance used when assigning a price to a bucket, so that prices sitting exactly on a bucket
+/// boundary are not pushed to the neighbouring bucket by floating point noise.
+const EPSILON: f64 = 1e-9;
+
+/// Side of the order book.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookSide {
+    /// The bid (buy) side.
+    Bid,
+
+    /// The ask (sell) side.
+    Ask,
+}
+
+/// How price levels are grouped together by [`aggregate`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bucketing {
+    /// Buckets of a fixed absolute price width.
+    Price(f64),
+
+    /// Buckets spanning a multiple of the instrument's tick size, eg. 10 ticks.
+    Ticks {
+        /// The price increment of the instrument.
+        tick_size: f64,
+        /// The number of ticks in a single bucket.
+        ticks: u32,
+    },
+
+    /// Buckets spanning a number of basis points of a reference price (usually the mid price).
+    Bps {
+        /// The width of a single bucket in basis points.
+        bps: f64,
+        /// The price the basis points are relative to.
+        reference: f64,
+    },
+}
+
+impl Bucketing {
+    /// Returns the absolute price width of a single bucket.
+    pub fn width(&self) -> f64 {
+        match *self {
+            Bucketing::Price(width) => width,
+            Bucketing::Ticks { tick_size, ticks } => tick_size * ticks as f64,
+            Bucketing::Bps { bps, reference } => reference * bps / 10_000.0,
+        }
+    }
+}
+
+/// A compact view of both sides of the book with levels grouped into price buckets.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Ladder {
+    /// Bid buckets, best (highest) price first.
+    pub bids: Vec<BookLevel>,
+
+    /// Ask buckets, best (lowest) price first.
+    pub asks: Vec<BookLevel>,
+}
+
+impl Ladder {
+    /// Builds a [`Ladder`] out of the levels of a [`BookSnapshot`].
+    pub fn from_snapshot(snapshot: &BookSnapshot, bucketing: Bucketing) -> Self {
+        Self {
+            bids: aggregate(&snapshot.bids, BookSide::Bid, bucketing),
+            asks: aggregate(&snapshot.asks, BookSide::Ask, bucketing),
+        }
+    }
+}
+
+/// Aggregates the given levels of one side of the book into price buckets, summing the amount of
+/// every level falling into the same bucket.
+///
+/// Bid prices are rounded down and ask prices are rounded up to the bucket boundary, so a bucket
+/// never advertises a better price than the levels it contains. Levels with a zero amount are
+/// ignored and the result is ordered from the best price outwards.
+pub fn aggregate(levels: &[BookLevel], side: BookSide, bucketing: Bucketing) -> Vec<BookLevel> {
+    let width = bucketing.width();
+    if width.is_nan() || width <= 0.0 {
+        return vec![];
+    }
+
+    let mut buckets = BTreeMap::<i64, f64>::new();
+    for level in levels.iter().filter(|level| level.amount > 0.0) {
+        let index = match side {
+            BookSide::Bid => (level.price / width + EPSILON).floor(),
+            BookSide::Ask => (level.price / width - EPSILON).ceil(),
+        } as i64;
+
+        *buckets.entry(index).or_default() += level.amount;
+    }
+
+    let to_level = |(index, amount): (i64, f64)| BookLevel {
+        price: index as f64 * width,
+        amount,
+    };
+
+    match side {
+        BookSide::Bid => buckets.into_iter().rev().map(to_level).collect(),
+        BookSide::Ask => buckets.into_iter().map(to_level).collect(),
+    }
+}
+
+/// The liquidity available on one side of the book up to a given notional.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SideDepth {
+    /// The quantity that can be filled before the notional is exhausted.
+    pub amount: f64,
+
+    /// The notional actually consumed, lower than requested when the book is too thin.
+    pub notional: f64,
+
+    /// The price of the last level touched, `None` when the side is empty.
+    pub worst_price: Option<f64>,
+}
+
+/// Walks the given levels of one side of the book from the touch, its best price, outwards and
+/// sums the quantity available until `notional` (price * amount) is consumed, partially filling
+/// the last level touched.
+///
+/// As no order rests between the mid price and the touch, this is the quantity available within
+/// `notional` of the mid price.
+pub fn depth_from_touch<I>(levels: I, notional: f64) -> SideDepth
+where
+    I: IntoIterator,
+    I::Item: Borrow<BookLevel>,
+{
+    let mut depth = SideDepth::default();
+
+    for level in levels.into_iter() {
+        let level = level.borrow();
+        if level.amount <= 0.0 {
+            continue;
+        }
+        let remaining = notional - depth.notional;
+        if remaining <= 0.0 {
+            break;
+        }
+
+        let level_notional = level.price * level.amount;
+        let (amount, consumed) = if level_notional > remaining {
+            (remaining / level.price, remaining)
+        } else {
+            (level.amount, level_notional)
+        };
+
+        depth.amount += amount;
+        depth.notional += consumed;
+        depth.worst_price = Some(level.price);
+    }
+
+    depth
+}
+
+/// Notional based depth of both sides of the book at a point in time, each side measured from its
+/// touch outwards, see [`depth_from_touch`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotionalDepth {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// The requested notional per side
+    pub notional: f64,
+
+    /// The mid price of the book, `None` when either side is empty
+    pub mid_price: Option<f64>,
+
+    /// Liquidity available on the bid side
+    pub bids: SideDepth,
+
+    /// Liquidity available on the ask side
+    pub asks: SideDepth,
+
+    /// Timestamp of the book the depth was computed from
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp of the book the depth was computed from
+    pub local_timestamp: DateTime<Utc>,
+}
+
+impl NotionalDepth {
+    /// Computes the notional depth of a [`BookSnapshot`].
+    pub fn from_snapshot(snapshot: &BookSnapshot, notional: f64) -> Self {
+        let mid_price = match (snapshot.bids.first(), snapshot.asks.first()) {
+            (Some(bid), Some(ask)) => Some((bid.price + ask.price) / 2.0),
+            _ => None,
+        };
+
+        Self {
+            symbol: snapshot.symbol.clone(),
+            exchange: snapshot.exchange,
+            notional,
+            mid_price,
+            bids: depth_from_touch(&snapshot.bids, notional),
+            asks: depth_from_touch(&snapshot.asks, notional),
+            timestamp: snapshot.timestamp,
+            local_timestamp: snapshot.local_timestamp,
+        }
+    }
+
+    /// Computes the notional depth of a local [`OrderBook`], `None` until a change was applied
+    /// after its first snapshot.
+    pub fn from_book(book: &OrderBook, notional: f64) -> Option<Self> {
+        Some(Self {
+            symbol: book.symbol.clone()?,
+            exchange: book.exchange?,
+            notional,
+            mid_price: book.mid_price(),
+            bids: depth_from_touch(book.bids(), notional),
+            asks: depth_from_touch(book.asks(), notional),
+            timestamp: book.timestamp?,
+            local_timestamp: book.local_timestamp?,
+        })
+    }
+}
+
+/// Derives a stream of [`NotionalDepth`] out of the `book_snapshot` messages of the given stream,
+/// and of the local [`OrderBook`] of every instrument maintained out of its `book_change`
+/// messages, yielded after each change once the book received its snapshot. Other messages are
+/// skipped while errors are passed through.
+pub fn notional_depths<S>(messages: S, notional: f64) -> impl Stream<Item = Result<NotionalDepth>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    stream! {
+        futures_util::pin_mut!(messages);
+        let mut books = HashMap::<(Exchange, Symbol), OrderBook>::new();
+
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(Message::BookSnapshot(snapshot)) => {
+                    yield Ok(NotionalDepth::from_snapshot(&snapshot, notional));
+                }
+                Ok(Message::BookChange(change)) => {
+                    let book = books
+                        .entry((change.exchange, change.symbol.clone()))
+                        .or_default();
+                    book.update(&change);
+                    if let Some(depth) = NotionalDepth::from_book(book, notional) {
+                        yield Ok(depth);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+/// A price usable as the key of a [`BTreeMap`], ordered with [`f64::total_cmp`].
+#[derive(Debug, Copy, Clone)]
+struct Price(f64);
+
+impl PartialEq for Price {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A local L2 order book maintained out of `book_change` messages.
+///
+/// A `book_change` flagged as a snapshot replaces the whole book, or extends it when it follows
+/// another snapshot as some exchanges send their snapshot over several messages. Updates received
+/// before the first snapshot are ignored, and levels updated with a zero amount are removed.
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    bids: BTreeMap<Reverse<Price>, f64>,
+    asks: BTreeMap<Price, f64>,
+    received_snapshot: bool,
+    last_was_snapshot: bool,
+    symbol: Option<Symbol>,
+    exchange: Option<Exchange>,
+    timestamp: Option<DateTime<Utc>>,
+    local_timestamp: Option<DateTime<Utc>>,
+}
+
+impl OrderBook {
+    /// Creates a new instance of an empty [`OrderBook`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies a `book_change` to the book.
+    pub fn update(&mut self, change: &BookChange) {
+        if change.is_snapshot {
+            if !self.last_was_snapshot {
+                self.bids.clear();
+                self.asks.clear();
+            }
+            self.received_snapshot = true;
+        }
+        self.last_was_snapshot = change.is_snapshot;
+        if !self.received_snapshot {
+            return;
+        }
+
+        for level in &change.bids {
+            update_level(&mut self.bids, Reverse(Price(level.price)), level.amount);
+        }
+        for level in &change.asks {
+            update_level(&mut self.asks, Price(level.price), level.amount);
+        }
+        if self.symbol.as_ref() != Some(&change.symbol) {
+            self.symbol = Some(change.symbol.clone());
+        }
+        self.exchange = Some(change.exchange);
+        self.timestamp = Some(change.timestamp);
+        self.local_timestamp = Some(change.local_timestamp);
+    }
+
+    /// Returns the bid levels, best (highest) price first.
+    pub fn bids(&self) -> impl Iterator<Item = BookLevel> + '_ {
+        self.bids
+            .iter()
+            .map(|(Reverse(price), amount)| to_level(price, amount))
+    }
+
+    /// Returns the ask levels, best (lowest) price first.
+    pub fn asks(&self) -> impl Iterator<Item = BookLevel> + '_ {
+        self.asks
+            .iter()
+            .map(|(price, amount)| to_level(price, amount))
+    }
+
+    /// Returns the best bid, `None` when the bid side is empty.
+    pub fn best_bid(&self) -> Option<BookLevel> {
+        self.bids().next()
+    }
+
+    /// Returns the best ask, `None` when the ask side is empty.
+    pub fn best_ask(&self) -> Option<BookLevel> {
+        self.asks().next()
+    }
+
+    /// Returns the mid price of the book, `None` when either side is empty.
+    pub fn mid_price(&self) -> Option<f64> {
+        Some((self.best_bid()?.price + self.best_ask()?.price) / 2.0)
+    }
+
+    /// Returns the difference between the best ask and bid prices, `None` when either side is
+    /// empty.
+    pub fn spread(&self) -> Option<f64> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    /// Returns the top `levels` bids and asks, best price first, as a [`Ladder`] of single
+    /// price levels.
+    pub fn depth(&self, levels: usize) -> Ladder {
+        Ladder {
+            bids: self.bids().take(levels).collect(),
+            asks: self.asks().take(levels).collect(),
+        }
+    }
+
+    /// Returns `true` once a snapshot was received, before which updates are ignored.
+    pub fn has_snapshot(&self) -> bool {
+        self.received_snapshot
+    }
+
+    /// Returns the symbol of the last change applied.
+    pub fn symbol(&self) -> Option<&Symbol> {
+        self.symbol.as_ref()
+    }
+
+    /// Returns the exchange of the last change applied.
+    pub fn exchange(&self) -> Option<Exchange> {
+        self.exchange
+    }
+
+    /// Returns the exchange timestamp of the last change applied.
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.timestamp
+    }
+
+    /// Returns the arrival timestamp of the last change applied.
+    pub fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.local_timestamp
+    }
+}
+
+fn update_level<K: Ord>(side: &mut BTreeMap<K, f64>, price: K, amount: f64) {
+    if amount > 0.0 {
+        side.insert(price, amount);
+    } else {
+        side.remove(&price);
+    }
+}
+
+fn to_level(price: &Price, amount: &f64) -> BookLevel {
+    BookLevel {
+        price: price.0,
+        amount: *amount,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: f64, amount: f64) -> BookLevel {
+        BookLevel { price, amount }
+    }
+
+    #[test]
+    fn test_aggregate_bids_round_down() {
+        let levels = [
+            level(100.5, 1.0),
+            level(100.0, 2.0),
+            level(99.5, 3.0),
+            level(99.0, 0.0),
+        ];
+
+        let buckets = aggregate(&levels, BookSide::Bid, Bucketing::Price(1.0));
+
+        assert_eq!(buckets, vec![level(100.0, 3.0), level(99.0, 3.0)]);
+    }
+
+    #[test]
+    fn test_aggregate_asks_round_up() {
+        let levels = [level(100.5, 1.0), level(101.0, 2.0), level(101.5, 3.0)];
+
+        let buckets = aggregate(
+            &levels,
+            BookSide::Ask,
+            Bucketing::Ticks {
+                tick_size: 0.5,
+                ticks: 2,
+            },
+        );
+
+        assert_eq!(buckets, vec![level(101.0, 3.0), level(102.0, 3.0)]);
+    }
+
+    #[test]
+    fn test_bps_width() {
+        let bucketing = Bucketing::Bps {
+            bps: 5.0,
+            reference: 20_000.0,
+        };
+
+        assert_eq!(bucketing.width(), 10.0);
+    }
+
+    #[test]
+    fn test_depth_from_touch() {
+        let levels = [level(100.0, 1.0), level(99.0, 2.0), level(98.0, 5.0)];
+
+        let depth = depth_from_touch(levels, 199.0);
+
+        assert_eq!(depth.amount, 2.0);
+        assert_eq!(depth.notional, 199.0);
+        assert_eq!(depth.worst_price, Some(99.0));
+
+        let depth = depth_from_touch(levels, 10_000.0);
+
+        assert_eq!(depth.amount, 8.0);
+        assert_eq!(depth.notional, 788.0);
+        assert_eq!(depth.worst_price, Some(98.0));
+    }
+
+    fn change(is_snapshot: bool, bids: &[BookLevel], asks: &[BookLevel]) -> BookChange {
+        serde_json::from_value(serde_json::json!({
+            "symbol": "BTCUSDT",
+            "exchange": "bybit",
+            "isSnapshot": is_snapshot,
+            "bids": bids,
+            "asks": asks,
+            "timestamp": "2022-10-01T00:00:00.100Z",
+            "localTimestamp": "2022-10-01T00:00:00.104Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_order_book() {
+        let mut book = OrderBook::new();
+
+        book.update(&change(false, &[level(100.0, 1.0)], &[]));
+        assert!(!book.has_snapshot());
+        assert_eq!(book.best_bid(), None);
+
+        book.update(&change(
+            true,
+            &[level(99.0, 2.0), level(100.0, 1.0)],
+            &[level(102.0, 1.0)],
+        ));
+        book.update(&change(true, &[], &[level(101.0, 3.0)]));
+        assert_eq!(book.best_bid(), Some(level(100.0, 1.0)));
+        assert_eq!(book.best_ask(), Some(level(101.0, 3.0)));
+        assert_eq!(book.mid_price(), Some(100.5));
+        assert_eq!(book.spread(), Some(1.0));
+
+        book.update(&change(false, &[level(100.0, 0.0)], &[level(101.5, 2.0)]));
+        assert_eq!(
+            book.depth(2),
+            Ladder {
+                bids: vec![level(99.0, 2.0)],
+                asks: vec![level(101.0, 3.0), level(101.5, 2.0)],
+            }
+        );
+
+        // A snapshot after updates replaces the book.
+        book.update(&change(true, &[level(98.0, 1.0)], &[level(99.0, 1.0)]));
+        assert_eq!(book.bids().count(), 1);
+        assert_eq!(book.asks().collect::<Vec<_>>(), vec![level(99.0, 1.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_notional_depths_from_book_changes() {
+        let messages = [
+            change(false, &[level(100.0, 1.0)], &[]),
+            change(
+                true,
+                &[level(100.0, 1.0), level(99.0, 2.0)],
+                &[level(101.0, 1.0), level(102.0, 3.0)],
+            ),
+            change(false, &[level(100.0, 0.0)], &[level(101.0, 0.5)]),
+        ]
+        .map(|change| Ok(Message::BookChange(Box::new(change))));
+
+        let depths = notional_depths(futures_util::stream::iter(messages), 150.0)
+            .map(|depth| depth.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        // Nothing is yielded before the snapshot.
+        assert_eq!(depths.len(), 2);
+        assert_eq!(depths[0].symbol, "BTCUSDT");
+        assert_eq!(depths[0].exchange, Exchange::Bybit);
+        assert_eq!(depths[0].mid_price, Some(100.5));
+        assert_eq!(depths[0].bids.amount, 1.0 + 50.0 / 99.0);
+        assert_eq!(depths[0].asks.worst_price, Some(102.0));
+
+        assert_eq!(depths[1].mid_price, Some(100.0));
+        assert_eq!(depths[1].bids.amount, 150.0 / 99.0);
+        assert_eq!(depths[1].bids.worst_price, Some(99.0));
+        assert_eq!(depths[1].asks.amount, 0.5 + 99.5 / 102.0);
+        assert_eq!(
+            depths[1].local_timestamp,
+            "2022-10-01T00:00:00.104Z".parse::<DateTime<Utc>>().unwrap()
+        );
+    }
+}
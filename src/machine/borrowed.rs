@@ -0,0 +1,345 @@
+//! Borrowed variants of the normalized messages, decoded straight from the payload of a websocket
+//! frame without allocating for their strings.
+//!
+//! Decode them from a [`RawMessage`] with [`RawMessage::message_ref`], or have the [`Client`]
+//! decode every frame with [`Client::replay_normalized_with`] and
+//! [`Client::stream_normalized_with`].
+//!
+//! [`Client`]: super::Client
+//! [`Client::replay_normalized_with`]: super::Client::replay_normalized_with
+//! [`Client::stream_normalized_with`]: super::Client::stream_normalized_with
+
+use std::{borrow::Cow, fmt, marker::PhantomData};
+
+use chrono::{DateTime, Utc};
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_json::{Map, Value};
+
+use super::{
+    models::{message_of_kind, Key},
+    BookChange, BookLevels, BookTicker, Message, RawMessage, Symbol, Trade, TradeSide,
+};
+use crate::Exchange;
+
+/// A normalized message borrowing its strings from the payload it was decoded from.
+///
+/// Only the types streamed at a high frequency have a borrowed variant, the others are decoded
+/// into an owned [`Message`].
+#[derive(Debug, Clone)]
+pub enum MessageRef<'a> {
+    /// A [`Trade`].
+    Trade(TradeRef<'a>),
+
+    /// A [`BookChange`].
+    BookChange(BookChangeRef<'a>),
+
+    /// A [`BookTicker`].
+    BookTicker(BookTickerRef<'a>),
+
+    /// Any other message.
+    Other(Message),
+}
+
+impl MessageRef<'_> {
+    /// Returns the `type` of the message, eg. `trade`.
+    pub fn kind(&self) -> &str {
+        match self {
+            MessageRef::Trade(_) => "trade",
+            MessageRef::BookChange(_) => "book_change",
+            MessageRef::BookTicker(_) => "book_ticker",
+            MessageRef::Other(msg) => msg.kind(),
+        }
+    }
+
+    /// Returns the exchange of the message, `None` for an unknown message without an exchange
+    /// known to this crate.
+    pub fn exchange(&self) -> Option<Exchange> {
+        match self {
+            MessageRef::Trade(msg) => Some(msg.exchange),
+            MessageRef::BookChange(msg) => Some(msg.exchange),
+            MessageRef::BookTicker(msg) => Some(msg.exchange),
+            MessageRef::Other(msg) => msg.exchange(),
+        }
+    }
+
+    /// Returns the symbol of the message, `None` for messages not specific to an instrument.
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            MessageRef::Trade(msg) => Some(&msg.symbol),
+            MessageRef::BookChange(msg) => Some(&msg.symbol),
+            MessageRef::BookTicker(msg) => Some(&msg.symbol),
+            MessageRef::Other(msg) => msg.symbol().map(Symbol::as_str),
+        }
+    }
+
+    /// Returns the arrival timestamp of the message, `None` for an unknown message without one.
+    pub fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            MessageRef::Trade(msg) => Some(msg.local_timestamp),
+            MessageRef::BookChange(msg) => Some(msg.local_timestamp),
+            MessageRef::BookTicker(msg) => Some(msg.local_timestamp),
+            MessageRef::Other(msg) => msg.local_timestamp(),
+        }
+    }
+
+    /// Converts the message into an owned [`Message`].
+    pub fn into_owned(self) -> Message {
+        match self {
+            MessageRef::Trade(msg) => Message::Trade(msg.into_owned()),
+            MessageRef::BookChange(msg) => Message::BookChange(Box::new(msg.into_owned())),
+            MessageRef::BookTicker(msg) => Message::BookTicker(Box::new(msg.into_owned())),
+            MessageRef::Other(msg) => msg,
+        }
+    }
+}
+
+/// A [`Trade`] borrowing its strings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeRef<'a> {
+    /// Instrument symbol as provided by exchange
+    #[serde(borrow)]
+    pub symbol: Cow<'a, str>,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Trade id if provided by exchange
+    #[serde(borrow, default, deserialize_with = "option_str")]
+    pub id: Option<Cow<'a, str>>,
+
+    /// Trade price as provided by exchange
+    #[serde(deserialize_with = "crate::de::f64")]
+    pub price: f64,
+
+    /// Trade amount as provided by exchange
+    #[serde(deserialize_with = "crate::de::f64")]
+    pub amount: f64,
+
+    /// Liquidity taker side (aggressor)
+    pub side: TradeSide,
+
+    /// Trade timestamp provided by exchange (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+impl TradeRef<'_> {
+    /// Converts the trade into an owned [`Trade`].
+    pub fn into_owned(self) -> Trade {
+        Trade {
+            symbol: Symbol::intern(&self.symbol),
+            exchange: self.exchange,
+            id: self.id.map(Cow::into_owned),
+            price: self.price,
+            amount: self.amount,
+            side: self.side,
+            timestamp: self.timestamp,
+            local_timestamp: self.local_timestamp,
+        }
+    }
+}
+
+/// A [`BookChange`] borrowing its symbol.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookChangeRef<'a> {
+    /// Instrument symbol as provided by exchange
+    #[serde(borrow)]
+    pub symbol: Cow<'a, str>,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// If true marks initial order book snapshot
+    pub is_snapshot: bool,
+
+    /// Updated bids price-amount levels
+    pub bids: BookLevels,
+
+    /// Updated asks price-amount levels
+    pub asks: BookLevels,
+
+    /// Order book update timestamp if provided by exchange,
+    /// otherwise equals to localTimestamp, (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+impl BookChangeRef<'_> {
+    /// Converts the change into an owned [`BookChange`].
+    pub fn into_owned(self) -> BookChange {
+        BookChange {
+            symbol: Symbol::intern(&self.symbol),
+            exchange: self.exchange,
+            is_snapshot: self.is_snapshot,
+            bids: self.bids,
+            asks: self.asks,
+            timestamp: self.timestamp,
+            local_timestamp: self.local_timestamp,
+        }
+    }
+}
+
+/// A [`BookTicker`] borrowing its symbol.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookTickerRef<'a> {
+    /// Instrument symbol as provided by exchange
+    #[serde(borrow)]
+    pub symbol: Cow<'a, str>,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Best ask amount, if the ask side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub ask_amount: Option<f64>,
+
+    /// Best ask price, if the ask side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub ask_price: Option<f64>,
+
+    /// Best bid price, if the bid side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub bid_price: Option<f64>,
+
+    /// Best bid amount, if the bid side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub bid_amount: Option<f64>,
+
+    /// Message timestamp provided by exchange (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+impl BookTickerRef<'_> {
+    /// Converts the ticker into an owned [`BookTicker`].
+    pub fn into_owned(self) -> BookTicker {
+        BookTicker {
+            symbol: Symbol::intern(&self.symbol),
+            exchange: self.exchange,
+            ask_amount: self.ask_amount,
+            ask_price: self.ask_price,
+            bid_price: self.bid_price,
+            bid_amount: self.bid_amount,
+            timestamp: self.timestamp,
+            local_timestamp: self.local_timestamp,
+        }
+    }
+}
+
+impl RawMessage {
+    /// Decodes the payload into a normalized message borrowing its strings from the payload.
+    pub fn message_ref(&self) -> serde_json::Result<MessageRef<'_>> {
+        serde_json::from_slice(self.payload())
+    }
+}
+
+/// Deserializes an optional string, borrowed from the input when possible.
+fn option_str<'de: 'a, 'a, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Cow<'a, str>>, D::Error> {
+    Ok(Option::<Key<'de>>::deserialize(deserializer)?.map(|Key(value)| value))
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for MessageRef<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(KindVisitor(PhantomData))
+    }
+}
+
+/// A message decoded from its fields once its `type` is known.
+pub(super) trait OfKind<'de>: Sized {
+    fn of_kind<D: Deserializer<'de>>(kind: &str, fields: D) -> Result<Self, D::Error>;
+}
+
+impl<'de> OfKind<'de> for MessageRef<'de> {
+    fn of_kind<D: Deserializer<'de>>(kind: &str, fields: D) -> Result<Self, D::Error> {
+        Ok(match kind {
+            "trade" => MessageRef::Trade(TradeRef::deserialize(fields)?),
+            "book_change" => MessageRef::BookChange(BookChangeRef::deserialize(fields)?),
+            "book_ticker" => MessageRef::BookTicker(BookTickerRef::deserialize(fields)?),
+            _ => MessageRef::Other(message_of_kind(kind, fields)?),
+        })
+    }
+}
+
+/// Same as the visitor of [`Message`], for the alternative message types.
+pub(super) struct KindVisitor<T>(pub(super) PhantomData<T>);
+
+impl<'de, T: OfKind<'de>> Visitor<'de> for KindVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a message with a `type` field")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let Some(Key(key)) = map.next_key()? else {
+            return Err(de::Error::missing_field("type"));
+        };
+
+        if key == "type" {
+            let Key(kind) = map.next_value()?;
+            return T::of_kind(&kind, MapAccessDeserializer::new(map));
+        }
+
+        // The buffered fields can't be borrowed from, but are rare.
+        let mut fields = Map::new();
+        fields.insert(key.into_owned(), map.next_value()?);
+        while let Some((key, value)) = map.next_entry()? {
+            fields.insert(key, value);
+        }
+        let kind = match fields.remove("type") {
+            Some(Value::String(kind)) => kind,
+            Some(_) => return Err(de::Error::custom("`type` is not a string")),
+            None => return Err(de::Error::missing_field("type")),
+        };
+        T::of_kind(&kind, Value::Object(fields)).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_ref() {
+        let raw = RawMessage::new(
+            r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a\"b","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#,
+        );
+        let MessageRef::Trade(trade) = raw.message_ref().unwrap() else {
+            panic!("not a trade");
+        };
+        assert!(matches!(trade.symbol, Cow::Borrowed("XBTUSD")));
+        assert_eq!(trade.id.as_deref(), Some("a\"b"));
+        assert_eq!(trade.clone().into_owned().price, 7996.0);
+
+        for line in include_str!("../../fixtures/golden/corpus.ndjson").lines() {
+            let message = serde_json::from_str::<MessageRef>(line).unwrap();
+            let expected = serde_json::from_str::<Message>(line).unwrap();
+            assert_eq!(message.kind(), expected.kind());
+            assert_eq!(message.symbol(), expected.symbol().map(Symbol::as_str));
+            assert_eq!(
+                serde_json::to_value(message.into_owned()).unwrap(),
+                serde_json::to_value(expected).unwrap()
+            );
+        }
+    }
+}
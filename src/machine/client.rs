@@ -1,17 +1,49 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
 
-use crate::machine::StreamNormalizedRequestOptions;
+use crate::{codec, log, machine::StreamNormalizedRequestOptions, Failure, RestartPolicy};
 use async_stream::stream;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use futures_util::{stream::SplitSink, SinkExt, Stream, StreamExt};
-use serde::de::DeserializeOwned;
-use tokio::net::TcpStream;
+use serde_json::value::RawValue;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    sync::{mpsc, watch, Semaphore},
+    time::Instant,
+};
 use tokio_tungstenite::{
-    connect_async,
-    tungstenite::{self, protocol::frame::coding::CloseCode},
-    MaybeTlsStream, WebSocketStream,
+    client_async_with_config,
+    tungstenite::{
+        self,
+        client::IntoClientRequest,
+        error::UrlError,
+        handshake::client::Response,
+        http::{
+            header::{AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SEC_WEBSOCKET_EXTENSIONS},
+            HeaderMap, HeaderName, HeaderValue, StatusCode,
+        },
+        protocol::{frame::coding::CloseCode, CloseFrame, WebSocketConfig},
+    },
+    WebSocketStream,
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    deflate::{self, Inflate},
+    metrics::MetricsObserver,
+    split::{split_by_type, TypedStreams},
+    stats::{StreamStats, SubscriptionKey, SubscriptionStats},
+    tls, Message, MessageRef, OptionsError, Prefilter, Proxy, RawMessage,
+    ReplayNormalizedRequestOptions, ReplayRawRequestOptions, StreamRawRequestOptions,
+    SymbolInterner,
 };
-
-use super::{Message, ReplayNormalizedRequestOptions};
 
 /// A helper Result type.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -23,9 +55,40 @@ pub enum Error {
     #[error("Options cannot be empty")]
     EmptyOptions,
 
+    /// The error when the request options are invalid.
+    #[error("Invalid options: {0}")]
+    InvalidOptions(#[from] OptionsError),
+
+    /// The error when the URL of a request would be longer than the
+    /// [`ClientBuilder::max_url_length`], eg. as hundreds of symbols were requested. Split the
+    /// symbols across several requests, and merge the replays with
+    /// [`merge_ordered`](super::ordering::merge_ordered).
+    #[error("Options too large: the request URL is {length} bytes long, the maximum is {max}")]
+    OptionsTooLarge {
+        /// The length of the URL
+        length: usize,
+
+        /// The configured maximum length
+        max: usize,
+    },
+
+    /// The error when the URL of the server can't be turned into the URL of its HTTP API, see
+    /// [`ClientBuilder::http_url`].
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+
+    /// The error when the URL of a proxy is invalid or unsupported.
+    #[error("Invalid proxy: {0}")]
+    InvalidProxy(String),
+
+    /// The error when the HTTP client of [`Client::replay_normalized_http`] couldn't be created,
+    /// eg. for a SOCKS5 proxy without the `socks` feature.
+    #[error("Failed to create the HTTP client: {0}")]
+    HttpClient(String),
+
     /// The error when failed to connect to Tardis' websocket connection.
     #[error("Failed to connect: {0}")]
-    ConnectFailed(#[from] tungstenite::Error),
+    ConnectFailed(Box<tungstenite::Error>),
 
     /// The error when WS connection to the machine server got rejected.
     #[error("Connection rejected: {reason}")]
@@ -36,6 +99,22 @@ pub enum Error {
         reason: String,
     },
 
+    /// The error when connecting or waiting for the next message took longer than configured.
+    #[error("{kind:?} timed out after {timeout:?}")]
+    Timeout {
+        /// What timed out.
+        kind: TimeoutKind,
+        /// The configured timeout.
+        timeout: Duration,
+    },
+
+    /// The error when no message was delivered for the [`ClientBuilder::stale_timeout`].
+    #[error("No message received for {timeout:?}")]
+    Stale {
+        /// The configured timeout.
+        timeout: Duration,
+    },
+
     /// The error where the websocket connection was closed unexpectedly by Tardis.
     #[error("Connection closed: {reason}")]
     ConnectionClosed {
@@ -43,47 +122,780 @@ pub enum Error {
         reason: String,
     },
 
+    /// The error sent by the machine server before closing the connection, eg. for an invalid
+    /// data type or a symbol that isn't available.
+    #[error("Tardis Machine Server error {code}: {message}")]
+    ServerError {
+        /// Error code
+        code: u64,
+
+        /// Error message
+        message: String,
+    },
+
+    /// The error of a request to the HTTP API of the machine server, see
+    /// [`Client::replay_normalized_http`].
+    #[error("HTTP request failed: {0}")]
+    Http(crate::Error),
+
     /// The error that could happen when deserializing the response from Tardis.
     #[error("Failed to deserialize message: {0}")]
     Deserialization(#[from] serde_json::Error),
 }
 
+impl Error {
+    /// Turns the failure to deserialize `payload` into an [`Error::ServerError`] if the payload
+    /// is an error sent by the machine server, or an [`Error::Deserialization`] otherwise.
+    fn from_payload(payload: &[u8], e: serde_json::Error) -> Self {
+        Self::server_error(payload).unwrap_or(Error::Deserialization(e))
+    }
+
+    /// Returns the [`Error::ServerError`] sent as `payload`, if it is one.
+    fn server_error(payload: &[u8]) -> Option<Self> {
+        #[derive(serde::Deserialize)]
+        struct ServerError {
+            code: u64,
+            message: String,
+        }
+
+        let ServerError { code, message } = serde_json::from_slice(payload).ok()?;
+        Some(Error::ServerError { code, message })
+    }
+}
+
+impl From<crate::Error> for Error {
+    fn from(e: crate::Error) -> Self {
+        match e {
+            crate::Error::Deserialization(e) => Error::Deserialization(e),
+            e => Error::Http(e),
+        }
+    }
+}
+
+/// What timed out, see [`Error::Timeout`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TimeoutKind {
+    /// Connecting, up to the end of the websocket handshake, see
+    /// [`ClientBuilder::connect_timeout`].
+    Connect,
+
+    /// Waiting for the next message, see [`ClientBuilder::read_timeout`].
+    Read,
+}
+
+/// The HTTP response of the websocket handshake with Tardis Machine Server.
+#[derive(Debug, Clone)]
+pub struct Handshake {
+    /// The status code of the response, `101 Switching Protocols` on success
+    pub status: StatusCode,
+
+    /// The headers of the response
+    pub headers: HeaderMap,
+}
+
+impl Handshake {
+    /// Returns the value of a header of the response, if present and valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+
+    /// Returns the `Server` header, identifying the server software and usually its version.
+    pub fn server(&self) -> Option<&str> {
+        self.header("server")
+    }
+}
+
+/// The stream of messages returned by the [`Client`], along with the metadata of its connection.
+pub struct MessageStream<T = Message> {
+    handshake: Handshake,
+    shutdown: CancellationToken,
+    pause: PauseHandle,
+    inner: Pin<Box<dyn Stream<Item = Result<T>> + Send>>,
+}
+
+/// Pauses and resumes the consumption of a [`MessageStream`] from anywhere, eg. while the storage
+/// the messages are written to is unavailable. Returned by [`MessageStream::pause_handle`].
+///
+/// A paused stream stops reading from its connection, so that the server is held back by TCP
+/// backpressure rather than the messages piling up in memory. The read and stale timeouts don't
+/// run while the stream is paused, but the server may still drop a connection paused for too
+/// long.
+#[derive(Debug, Clone)]
+pub struct PauseHandle {
+    paused: Arc<watch::Sender<bool>>,
+}
+
+impl PauseHandle {
+    fn new() -> Self {
+        Self {
+            paused: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Pauses the stream once the message being received, if any, is delivered.
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// Resumes the stream.
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    /// Returns `true` if the stream is paused.
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    fn subscribe(&self) -> watch::Receiver<bool> {
+        self.paused.subscribe()
+    }
+
+    /// Waits until the stream is resumed, returning `false` if it was shut down meanwhile.
+    async fn resumed(paused: &mut watch::Receiver<bool>, shutdown: &CancellationToken) -> bool {
+        if !*paused.borrow_and_update() {
+            return true;
+        }
+
+        log::debug!("Stream paused");
+        tokio::select! {
+            _ = shutdown.cancelled() => false,
+            resumed = paused.wait_for(|paused| !paused) => {
+                log::debug!("Stream resumed");
+                resumed.is_ok()
+            }
+        }
+    }
+}
+
+impl<T> MessageStream<T> {
+    /// Returns the handshake response of the connection, eg. to check the version of the server.
+    pub fn handshake(&self) -> &Handshake {
+        &self.handshake
+    }
+
+    /// Returns the token shutting down the stream when cancelled, eg. to stop it from another
+    /// task.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Shuts the stream down: the connection is closed with a close frame, its background task
+    /// stops and the stream ends without error.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Returns the handle pausing and resuming the stream, eg. from another task.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// Groups the messages into batches of up to `max_size` messages, so that consumers writing
+    /// them to a database or file can amortize the cost per message.
+    ///
+    /// A batch is yielded once full, or `max_delay` after its first message arrived so that quiet
+    /// streams don't hold messages back. Errors are yielded as they happen, after the batch of
+    /// the messages received before them.
+    pub fn batched(self, max_size: usize, max_delay: Duration) -> MessageStream<Vec<T>>
+    where
+        T: Send + 'static,
+    {
+        let MessageStream {
+            handshake,
+            shutdown,
+            pause,
+            mut inner,
+        } = self;
+        let max_size = max_size.max(1);
+
+        let batches = stream! {
+            let mut batch = Vec::with_capacity(max_size);
+            let mut deadline = None;
+            loop {
+                let next = match deadline {
+                    Some(deadline) => tokio::time::timeout_at(deadline, inner.next()).await,
+                    None => Ok(inner.next().await),
+                };
+                match next {
+                    Ok(Some(Ok(msg))) => {
+                        if batch.is_empty() {
+                            deadline = Some(Instant::now() + max_delay);
+                        }
+                        batch.push(msg);
+                        if batch.len() < max_size {
+                            continue;
+                        }
+                    }
+                    Ok(Some(Err(e))) => {
+                        if !batch.is_empty() {
+                            yield Ok(std::mem::replace(&mut batch, Vec::with_capacity(max_size)));
+                        }
+                        deadline = None;
+                        yield Err(e);
+                        continue;
+                    }
+                    Ok(None) => {
+                        if !batch.is_empty() {
+                            yield Ok(batch);
+                        }
+                        break;
+                    }
+                    // The deadline of the batch passed.
+                    Err(_) => {}
+                }
+
+                deadline = None;
+                yield Ok(std::mem::replace(&mut batch, Vec::with_capacity(max_size)));
+            }
+        };
+
+        MessageStream {
+            handshake,
+            shutdown,
+            pause,
+            inner: Box::pin(batches),
+        }
+    }
+
+    /// Ends the stream once `count` messages were delivered, shutting the connection down with a
+    /// close frame as [`MessageStream::shutdown`] does rather than just dropping it. Errors don't
+    /// count as messages.
+    pub fn take_messages(self, count: u64) -> MessageStream<T>
+    where
+        T: Send + 'static,
+    {
+        let MessageStream {
+            handshake,
+            shutdown,
+            pause,
+            mut inner,
+        } = self;
+
+        let cancel = shutdown.clone();
+        let messages = stream! {
+            let mut remaining = count;
+            while remaining > 0 {
+                let Some(msg) = inner.next().await else {
+                    break;
+                };
+                remaining -= u64::from(msg.is_ok());
+                yield msg;
+            }
+            cancel.cancel();
+        };
+
+        MessageStream {
+            handshake,
+            shutdown,
+            pause,
+            inner: Box::pin(messages),
+        }
+    }
+
+    /// Ends the stream at the wall-clock `deadline`, shutting the connection down with a close
+    /// frame as [`MessageStream::shutdown`] does rather than just dropping it. A message received
+    /// past the deadline isn't delivered.
+    pub fn take_until(self, deadline: DateTime<Utc>) -> MessageStream<T>
+    where
+        T: Send + 'static,
+    {
+        let MessageStream {
+            handshake,
+            shutdown,
+            pause,
+            mut inner,
+        } = self;
+
+        let cancel = shutdown.clone();
+        let messages = stream! {
+            let remaining = (deadline - Utc::now()).to_std().unwrap_or_default();
+            let deadline = Instant::now() + remaining;
+            loop {
+                let msg = tokio::select! {
+                    biased;
+                    _ = tokio::time::sleep_until(deadline) => break,
+                    msg = inner.next() => msg,
+                };
+                match msg {
+                    Some(msg) => yield msg,
+                    None => break,
+                }
+            }
+            cancel.cancel();
+        };
+
+        MessageStream {
+            handshake,
+            shutdown,
+            pause,
+            inner: Box::pin(messages),
+        }
+    }
+}
+
+impl MessageStream {
+    /// Splits the stream into one stream per message type, see [`split_by_type`].
+    pub fn split_by_type(self) -> TypedStreams {
+        split_by_type(self)
+    }
+}
+
+impl<T> Stream for MessageStream<T> {
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl<T> std::fmt::Debug for MessageStream<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageStream")
+            .field("handshake", &self.handshake)
+            .finish_non_exhaustive()
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::ConnectFailed(Box::new(e))
+    }
+}
+
+/// The default of [`ClientBuilder::max_url_length`].
+const DEFAULT_MAX_URL_LENGTH: usize = 8 * 1024;
+
 /// The client for connecting to [Tardis Machine Server](https://docs.tardis.dev/api/tardis-machine).
+#[derive(Debug, Clone)]
 pub struct Client {
     url: String,
+    http_url: Option<String>,
+    max_url_length: usize,
+    with_disconnect_messages: Option<bool>,
+    timeout_interval_ms: Option<u64>,
+    reconnect: Option<RestartPolicy>,
+    disconnect_policy: DisconnectPolicy,
+    connection: ConnectionConfig,
+}
+
+/// What the [`Client`] does with the [`Message::Disconnect`] of a real-time stream, sent when the
+/// machine server lost its connection to an exchange.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum DisconnectPolicy {
+    /// Delivers the message like any other.
+    #[default]
+    Deliver,
+
+    /// Delivers the message, then restarts the subscription of the stream, so that it starts
+    /// over with fresh book snapshots instead of leaving every consumer to resynchronize its
+    /// books. Every exchange is streamed over a connection of its own, so that only the
+    /// subscription of the exchange that disconnected is restarted.
+    Resubscribe,
+}
+
+/// How the [`Client`] keeps its websocket connections alive and detects dead ones.
+///
+/// By default a ping is sent every 10 seconds, without checking that anything comes back.
+/// Servers behind aggressive load balancers may need a shorter interval, and
+/// [`KeepAlive::max_missed`] turns a silent connection into an error, so that it can be
+/// reconnected:
+///
+/// ```
+/// use std::time::Duration;
+/// use tardis_rs::machine::KeepAlive;
+///
+/// let keep_alive = KeepAlive::interval(Duration::from_secs(5)).max_missed(3);
+/// assert_eq!(keep_alive.idle_timeout(), Some(Duration::from_secs(15)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeepAlive {
+    interval: Option<Duration>,
+    payload: Vec<u8>,
+    max_missed: Option<u32>,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self::interval(Duration::from_secs(10))
+    }
+}
+
+impl KeepAlive {
+    /// Sends a ping every `interval`.
+    pub fn interval(interval: Duration) -> Self {
+        Self {
+            interval: Some(interval),
+            payload: vec![],
+            max_missed: None,
+        }
+    }
+
+    /// Never sends pings. Pings of the server are still answered.
+    pub fn disabled() -> Self {
+        Self {
+            interval: None,
+            payload: vec![],
+            max_missed: None,
+        }
+    }
+
+    /// Sets the payload of the pings, empty by default.
+    pub fn payload(mut self, payload: impl Into<Vec<u8>>) -> Self {
+        self.payload = payload.into();
+        self
+    }
+
+    /// Considers the connection dead once nothing, not even a pong, was received for
+    /// `max_missed` intervals in a row. The stream then yields [`Error::ConnectionClosed`].
+    pub fn max_missed(mut self, max_missed: u32) -> Self {
+        self.max_missed = Some(max_missed.max(1));
+        self
+    }
+
+    /// Returns the time without receiving anything after which the connection is considered
+    /// dead, if checked.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.interval?.checked_mul(self.max_missed?)
+    }
+}
+
+/// The settings of the websocket connections opened by the [`Client`].
+#[derive(Debug, Clone)]
+struct ConnectionConfig {
+    keep_alive: KeepAlive,
+    restart_policy: RestartPolicy,
+    websocket_config: Option<WebSocketConfig>,
+    shutdown: CancellationToken,
+    metrics: Option<Arc<dyn MetricsObserver>>,
+    stats: Option<Arc<StreamStats>>,
+    compression: bool,
+    headers: HeaderMap,
+    proxy: Option<Proxy>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    stale_timeout: Option<Duration>,
+    prefilter: Option<Arc<Prefilter>>,
+    pause: PauseHandle,
+    http_client: std::result::Result<reqwest::Client, String>,
+}
+
+impl ConnectionConfig {
+    /// Returns the settings of a new stream, which can be shut down and paused on its own.
+    fn for_stream(&self) -> Self {
+        Self {
+            shutdown: self.shutdown.child_token(),
+            pause: PauseHandle::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Same as [`ConnectionConfig::for_stream`], for a stream of raw exchange messages, which the
+    /// prefilter doesn't apply to.
+    fn for_raw_stream(&self) -> Self {
+        Self {
+            prefilter: None,
+            ..self.for_stream()
+        }
+    }
+
+    /// Creates the HTTP client of [`Client::replay_normalized_http`], with the proxy and the
+    /// connect timeout of the connections.
+    fn http_client(&self) -> std::result::Result<reqwest::Client, String> {
+        let mut client = crate::client::http_client_builder();
+        if let Some(proxy) = &self.proxy {
+            client = client.proxy(proxy.http_proxy().map_err(|e| e.to_string())?);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+        client.build().map_err(|e| e.to_string())
+    }
+}
+
+/// Builds a [`Client`] with defaults applied to every request it makes.
+///
+/// ```ignore
+/// let client = Client::builder("ws://localhost:8001")
+///     .with_disconnect_messages(true)
+///     .timeout_interval_ms(10_000)
+///     .restart_policy(RestartPolicy::default().max_attempts(5))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    client: Client,
+}
+
+impl ClientBuilder {
+    /// Sets `with_disconnect_messages` for every request whose options leave it unset.
+    pub fn with_disconnect_messages(mut self, with_disconnect_messages: bool) -> Self {
+        self.client.with_disconnect_messages = Some(with_disconnect_messages);
+        self
+    }
+
+    /// Sets `timeout_interval_ms` for every real-time request whose options leave it unset.
+    pub fn timeout_interval_ms(mut self, timeout_interval_ms: u64) -> Self {
+        self.client.timeout_interval_ms = Some(timeout_interval_ms);
+        self
+    }
+
+    /// Sets the interval at which ping frames are sent, 10 seconds by default.
+    pub fn heartbeat_interval(mut self, heartbeat_interval: Duration) -> Self {
+        self.client.connection.keep_alive.interval = Some(heartbeat_interval);
+        self
+    }
+
+    /// Sets how connections are kept alive and checked, see [`KeepAlive`].
+    pub fn keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.client.connection.keep_alive = keep_alive;
+        self
+    }
+
+    /// Sets the policy retrying to connect to the server, [`RestartPolicy::never`] by default. Its
+    /// [`RetryPolicy`](crate::RetryPolicy) decides which connection failures are retried.
+    pub fn restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.client.connection.restart_policy = restart_policy;
+        self
+    }
+
+    /// Makes the streams returned by [`Client::stream_normalized`] and
+    /// [`Client::replay_normalized`] reconnect transparently when their connection drops, waiting
+    /// between attempts as decided by `policy`. Replays resume where they left off. The stream only
+    /// yields the error that ended the last connection once the attempts are exhausted.
+    ///
+    /// The attempts count is reset as soon as a reconnected stream delivers a message. Each
+    /// attempt connects once, the [`ClientBuilder::restart_policy`] only retrying the first
+    /// connection of a stream.
+    pub fn reconnect(mut self, policy: RestartPolicy) -> Self {
+        self.client.reconnect = Some(policy);
+        self
+    }
+
+    /// Sets what [`Client::stream_normalized`] does with disconnect messages,
+    /// [`DisconnectPolicy::Deliver`] by default. With [`DisconnectPolicy::Resubscribe`], the
+    /// disconnect messages are requested whatever `with_disconnect_messages` is set to, and the
+    /// restarts are paced by the [`ClientBuilder::reconnect`] policy, or the default
+    /// [`RestartPolicy`] if there is none.
+    pub fn disconnect_policy(mut self, policy: DisconnectPolicy) -> Self {
+        self.client.disconnect_policy = policy;
+        self
+    }
+
+    /// Sets the buffer and message size limits of the websocket connections.
+    pub fn websocket_config(mut self, websocket_config: WebSocketConfig) -> Self {
+        self.client.connection.websocket_config = Some(websocket_config);
+        self
+    }
+
+    /// Shuts down every stream of the client once `token` is cancelled, see
+    /// [`MessageStream::shutdown`].
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.client.connection.shutdown = token;
+        self
+    }
+
+    /// Reports the traffic of every connection of the client to `observer`, see
+    /// [`MetricsObserver`].
+    pub fn metrics(mut self, observer: Arc<dyn MetricsObserver>) -> Self {
+        self.client.connection.metrics = Some(observer);
+        self
+    }
+
+    /// Tracks the statistics of the normalized messages received by every stream of the client
+    /// per subscription, read with [`Client::stats`]. Disabled by default, as every message then
+    /// goes through a shared lock.
+    pub fn track_stats(mut self, track_stats: bool) -> Self {
+        self.client.connection.stats = track_stats.then(|| Arc::new(StreamStats::new()));
+        self
+    }
+
+    /// Adds a header to the upgrade request of every connection, eg. the `Authorization` expected
+    /// by an authenticating reverse proxy in front of the server. Replaces any value previously
+    /// set for the header.
+    ///
+    /// The values of the `Authorization`, `Proxy-Authorization` and `Cookie` headers are marked
+    /// as sensitive, so that they are not printed along with the client.
+    pub fn header(mut self, name: HeaderName, mut value: HeaderValue) -> Self {
+        if [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE].contains(&name) {
+            value.set_sensitive(true);
+        }
+        self.client.connection.headers.insert(name, value);
+        self
+    }
+
+    /// Adds the headers to the upgrade request of every connection, see [`ClientBuilder::header`].
+    pub fn headers(self, headers: HeaderMap) -> Self {
+        headers
+            .into_iter()
+            .filter_map(|(name, value)| Some((name?, value)))
+            .fold(self, |builder, (name, value)| builder.header(name, value))
+    }
+
+    /// Fails every attempt to connect that doesn't complete the websocket handshake within
+    /// `timeout` with [`Error::Timeout`]. Attempts that time out are retried by the
+    /// [`ClientBuilder::restart_policy`]. Unlimited by default.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.client.connection.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Ends a connection with [`Error::Timeout`] once no message was received for `timeout`,
+    /// which [`ClientBuilder::reconnect`] then reconnects. Unlike [`KeepAlive::max_missed`], pongs
+    /// and other control frames don't count as messages. Unlimited by default.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.client.connection.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Watches the streams of the client for silent hangs: once no message was delivered for
+    /// `timeout`, the stream yields [`Error::Stale`] and keeps waiting, yielding it again after
+    /// every further `timeout` of silence. When the client was built with
+    /// [`ClientBuilder::reconnect`], the stale connection is reconnected instead.
+    ///
+    /// Unlike [`ClientBuilder::read_timeout`], a stale stream is left open, so that the consumer
+    /// decides what to do about it, eg. alert or [`MessageStream::shutdown`]. Disabled by default.
+    pub fn stale_timeout(mut self, timeout: Duration) -> Self {
+        self.client.connection.stale_timeout = Some(timeout);
+        self
+    }
+
+    /// Skips the normalized messages rejected by `prefilter` before deserializing them, which is
+    /// much cheaper than filtering the deserialized messages. Doesn't apply to raw exchange
+    /// messages.
+    pub fn prefilter(mut self, prefilter: Prefilter) -> Self {
+        self.client.connection.prefilter = Some(Arc::new(prefilter));
+        self
+    }
+
+    /// Tunnels every connection through `proxy`.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.client.connection.proxy = Some(proxy);
+        self
+    }
+
+    /// Offers the server to compress the messages it sends with the permessage-deflate websocket
+    /// extension, which Tardis Machine Server supports. Disabled by default, as compressing costs
+    /// CPU on both ends, it greatly reduces the bandwidth of verbose data such as `book_change`
+    /// when the server is remote.
+    pub fn compression(mut self, compression: bool) -> Self {
+        self.client.connection.compression = compression;
+        self
+    }
+
+    /// Sets the URL of the HTTP API of the machine server, eg. `http://localhost:8000`, used by
+    /// [`Client::replay_normalized_http`]. Defaults to the websocket URL with the matching
+    /// `http` or `https` scheme and the port below, as the server listens for websockets on the
+    /// port next to its HTTP port.
+    pub fn http_url(mut self, http_url: impl ToString) -> Self {
+        self.client.http_url = Some(http_url.to_string());
+        self
+    }
+
+    /// Sets the maximum length of the URLs of the requests, whose options are passed in the query
+    /// string, 8 KiB by default as a common limit of servers and proxies. Requests whose URL
+    /// would be longer fail with [`Error::OptionsTooLarge`] instead of being rejected by the
+    /// server or a proxy in between.
+    pub fn max_url_length(mut self, max_url_length: usize) -> Self {
+        self.client.max_url_length = max_url_length;
+        self
+    }
+
+    /// Creates the [`Client`].
+    pub fn build(mut self) -> Client {
+        self.client.connection.http_client = self.client.connection.http_client();
+        self.client
+    }
 }
 
 impl Client {
     /// Creates a new instance of [`Client`].
     pub fn new(url: impl ToString) -> Self {
+        Self::builder(url).build()
+    }
+
+    /// Returns the client with the default settings, whose HTTP client is created by
+    /// [`ClientBuilder::build`].
+    fn defaults(url: impl ToString) -> Self {
         Self {
             url: url.to_string(),
+            http_url: None,
+            max_url_length: DEFAULT_MAX_URL_LENGTH,
+            with_disconnect_messages: None,
+            timeout_interval_ms: None,
+            reconnect: None,
+            disconnect_policy: DisconnectPolicy::Deliver,
+            connection: ConnectionConfig {
+                keep_alive: KeepAlive::default(),
+                restart_policy: RestartPolicy::never(),
+                websocket_config: None,
+                shutdown: CancellationToken::new(),
+                metrics: None,
+                stats: None,
+                compression: false,
+                headers: HeaderMap::new(),
+                proxy: None,
+                connect_timeout: None,
+                read_timeout: None,
+                stale_timeout: None,
+                prefilter: None,
+                pause: PauseHandle::new(),
+                http_client: Err("not created".to_string()),
+            },
+        }
+    }
+
+    /// Creates a [`ClientBuilder`] to configure the defaults of a [`Client`].
+    pub fn builder(url: impl ToString) -> ClientBuilder {
+        ClientBuilder {
+            client: Self::defaults(url),
         }
     }
 
+    /// Returns a snapshot of the statistics of every subscription of the streams of the client,
+    /// and its clones, that a normalized message was received for. Empty unless the client was
+    /// built with [`ClientBuilder::track_stats`].
+    pub fn stats(&self) -> HashMap<SubscriptionKey, SubscriptionStats> {
+        self.connection
+            .stats
+            .as_ref()
+            .map(|stats| stats.snapshot())
+            .unwrap_or_default()
+    }
+
     /// Replays [normalized](https://docs.tardis.dev/api/tardis-machine#normalized-data-types)
     /// historical market data for [data types](https://docs.tardis.dev/api/tardis-machine#replay-normalized-options-1)
     /// specified in options. See [supported data types](https://docs.tardis.dev/api/tardis-machine#normalized-data-types)
     /// which include normalized [trade](https://docs.tardis.dev/api/tardis-machine#trade),
     /// [order book change](https://docs.tardis.dev/api/tardis-machine#book_change),
     /// [customizable order book snapshots](https://docs.tardis.dev/api/tardis-machine#book_snapshot_-number_of_levels-_-snapshot_interval-time_unit), etc.
+    ///
+    /// When the client was built with [`ClientBuilder::reconnect`], a replay whose connection
+    /// drops is resumed from the arrival timestamp of the last message received, without
+    /// delivering any message twice.
     pub async fn replay_normalized(
         &self,
         options: Vec<ReplayNormalizedRequestOptions>,
-    ) -> Result<impl Stream<Item = Result<Message>>> {
-        if options.len() == 0 {
-            return Err(Error::EmptyOptions);
-        }
-
-        let options = serde_json::to_string(&options)?;
-        let url = format!(
-            "{}/ws-replay-normalized?options={}",
-            &self.url,
-            urlencoding::encode(&options)
-        );
-
-        tracing::info!("[replay_normalized] url to tardis {}", url);
-        websocket_conn(&url).await
+    ) -> Result<MessageStream> {
+        let url = self.replay_normalized_url(options.clone())?;
+        log::info!("[replay_normalized] url to tardis {}", url);
+        let config = self.connection.for_stream();
+        let messages = websocket_conn(&url, &config).await?;
+
+        Ok(match &self.reconnect {
+            Some(policy) => {
+                let cursor = ReplayCursor {
+                    client: self.clone(),
+                    options,
+                    last_timestamp: None,
+                    delivered_at_last: 0,
+                    resuming: false,
+                    skip: 0,
+                };
+                reconnecting(cursor, config, policy.clone(), messages)
+            }
+            None => messages,
+        })
     }
 
     /// Streams [normalized](https://docs.tardis.dev/api/tardis-machine#normalized-data-types)
@@ -100,131 +912,2391 @@ impl Client {
     /// Provides consolidated real-time market data streaming functionality with options as
     /// an array - provides single consolidated real-time data stream for all exchanges specified
     /// in options array.
+    ///
+    /// The connection to the machine server itself is only re-established when the client was
+    /// built with [`ClientBuilder::reconnect`], or with [`DisconnectPolicy::Resubscribe`].
     pub async fn stream_normalized(
         &self,
         options: Vec<StreamNormalizedRequestOptions>,
-    ) -> Result<impl Stream<Item = Result<Message>>> {
-        if options.len() == 0 {
-            return Err(Error::EmptyOptions);
+    ) -> Result<MessageStream> {
+        let config = self.connection.for_stream();
+        if self.disconnect_policy == DisconnectPolicy::Resubscribe {
+            return self.stream_resubscribing(options, config).await;
         }
 
-        let options = serde_json::to_string(&options)?;
-        let url = format!(
-            "{}/ws-stream-normalized?options={}",
-            &self.url,
-            urlencoding::encode(&options)
-        );
+        let url = self.stream_normalized_url(options)?;
+        log::info!("[stream_normalized] url to tardis {}", url);
+        let messages = websocket_conn(&url, &config).await?;
 
-        tracing::info!("[stream_normalized] url to tardis {}", url);
-        websocket_conn(&url).await
+        Ok(match &self.reconnect {
+            Some(policy) => reconnecting(SameUrl(url), config, policy.clone(), messages),
+            None => messages,
+        })
     }
-}
 
-async fn websocket_conn<T>(url: &str) -> Result<impl Stream<Item = Result<T>>>
-where
-    T: DeserializeOwned,
-{
-    let (ws_stream, ws_resp) = connect_async(url).await?;
+    /// Streams every exchange of `options` over a connection of its own, so that a disconnect
+    /// message only restarts the subscription of the exchange that disconnected, see
+    /// [`DisconnectPolicy::Resubscribe`].
+    async fn stream_resubscribing(
+        &self,
+        options: Vec<StreamNormalizedRequestOptions>,
+        config: ConnectionConfig,
+    ) -> Result<MessageStream> {
+        let mut exchanges: Vec<Vec<StreamNormalizedRequestOptions>> = vec![];
+        for mut option in options {
+            option.with_disconnect_messages = Some(true);
+            match exchanges
+                .iter_mut()
+                .find(|group| group[0].exchange == option.exchange)
+            {
+                Some(group) => group.push(option),
+                None => exchanges.push(vec![option]),
+            }
+        }
 
-    // Return the error response if the status code is not 101.
-    // (meaning the HTTP connection is not being upgraded to a WS connection)
-    if ws_resp.status() != tungstenite::http::StatusCode::SWITCHING_PROTOCOLS {
-        return match ws_resp.body() {
-            Some(resp) => Err(Error::ConnectRejected {
-                status: ws_resp.status(),
-                reason: String::from_utf8_lossy(resp).to_string(),
-            }),
-            None => Err(Error::ConnectRejected {
-                status: ws_resp.status(),
-                reason: "Unknown reason".to_string(),
-            }),
+        let policy = self.reconnect.clone().unwrap_or_default();
+        let mut streams = vec![];
+        for options in exchanges {
+            let url = self.stream_normalized_url(options)?;
+            log::info!("[stream_normalized] url to tardis {}", url);
+            let messages = websocket_conn(&url, &config).await?;
+            streams.push(reconnecting(
+                Resubscribe(url),
+                config.clone(),
+                policy.clone(),
+                messages,
+            ));
+        }
+
+        let Some(handshake) = streams.first().map(|stream| stream.handshake.clone()) else {
+            return Err(Error::EmptyOptions);
         };
+        Ok(MessageStream {
+            handshake,
+            shutdown: config.shutdown.clone(),
+            pause: config.pause.clone(),
+            inner: Box::pin(futures_util::stream::select_all(
+                streams.into_iter().map(|stream| stream.inner),
+            )),
+        })
     }
 
-    Ok(stream! {
-        let (writer, mut reader) = ws_stream.split();
-        tokio::spawn(heartbeat(writer));
+    /// Same as [`Client::replay_normalized`], handing every message to `decode` as a
+    /// [`MessageRef`] borrowed from its websocket frame, and yielding what `decode` returns,
+    /// skipping `None`. Avoids allocating a [`Message`] when only some of its fields are used,
+    /// eg. the prices of trades.
+    ///
+    /// The replay isn't resumed when its connection drops, even when the client was built with
+    /// [`ClientBuilder::reconnect`].
+    pub async fn replay_normalized_with<T, F>(
+        &self,
+        options: Vec<ReplayNormalizedRequestOptions>,
+        decode: F,
+    ) -> Result<MessageStream<T>>
+    where
+        T: Send + 'static,
+        F: FnMut(MessageRef<'_>) -> Option<T> + Send + 'static,
+    {
+        let messages = self.replay_normalized_raw(options).await?;
+        Ok(decode_with(messages, decode))
+    }
 
-        loop {
-            match reader.next().await {
-                Some(msg) => {
-                    let msg = msg?;
-                    match msg {
-                        tungstenite::Message::Frame(_)
-                        | tungstenite::Message::Binary(_)
-                        | tungstenite::Message::Pong(_) => {}
-                        tungstenite::Message::Ping(_) => {
-                            tracing::debug!("Received PING frame");
-                            // ws_stream
-                            //     .send(tungstenite::Message::Pong(vec![]))
-                            //     .await
-                            //     .ok();
-                        }
-                        tungstenite::Message::Close(frame) => {
-                            if let Some(frame) = frame {
-                                if frame.code != CloseCode::Normal {
-                                    tracing::error!(
-                                        "Connection closed abnormally: {}",
-                                        frame.reason
-                                    );
-                                    yield Err(Error::ConnectionClosed { reason: frame.reason.to_string() })
-                                }
-                                tracing::debug!("Connection closed normally: {}", frame.reason);
-                            }
-                            break;
-                        }
-                        tungstenite::Message::Text(msg) => {
-                            tracing::debug!("Received websocket message: {}", msg);
-                            yield Ok(serde_json::from_str::<T>(&msg)?);
-                        }
+    /// Same as [`Client::stream_normalized`], handing every message to `decode` as a
+    /// [`MessageRef`] borrowed from its websocket frame, see [`Client::replay_normalized_with`].
+    pub async fn stream_normalized_with<T, F>(
+        &self,
+        options: Vec<StreamNormalizedRequestOptions>,
+        decode: F,
+    ) -> Result<MessageStream<T>>
+    where
+        T: Send + 'static,
+        F: FnMut(MessageRef<'_>) -> Option<T> + Send + 'static,
+    {
+        let messages = self.stream_normalized_raw(options).await?;
+        Ok(decode_with(messages, decode))
+    }
+
+    /// Same as [`Client::replay_normalized`], yielding the normalized messages as the JSON
+    /// payloads received from the machine server, eg. to relay them or to parse them into types
+    /// of your own with [`RawMessage::deserialize`].
+    ///
+    /// The replay isn't resumed when its connection drops, even when the client was built with
+    /// [`ClientBuilder::reconnect`].
+    pub async fn replay_normalized_raw(
+        &self,
+        options: Vec<ReplayNormalizedRequestOptions>,
+    ) -> Result<MessageStream<RawMessage>> {
+        let url = self.replay_normalized_url(options)?;
+        log::info!("[replay_normalized_raw] url to tardis {}", url);
+        websocket_conn(&url, &self.connection.for_stream()).await
+    }
+
+    /// Same as [`Client::stream_normalized`], yielding the normalized messages as the JSON
+    /// payloads received from the machine server, see [`Client::replay_normalized_raw`].
+    pub async fn stream_normalized_raw(
+        &self,
+        options: Vec<StreamNormalizedRequestOptions>,
+    ) -> Result<MessageStream<RawMessage>> {
+        let url = self.stream_normalized_url(options)?;
+        log::info!("[stream_normalized_raw] url to tardis {}", url);
+        let config = self.connection.for_stream();
+        let messages = websocket_conn(&url, &config).await?;
+
+        Ok(match &self.reconnect {
+            Some(policy) => reconnecting(SameUrl(url), config, policy.clone(), messages),
+            None => messages,
+        })
+    }
+
+    /// Same as [`Client::replay_normalized`], over the HTTP API of the machine server, which
+    /// serves the replay as newline-delimited JSON, eg. where websockets are blocked. See
+    /// [`ClientBuilder::http_url`] for the URL it is requested from.
+    ///
+    /// The headers, the proxy and the connect timeout of the client apply to the request, but the
+    /// other websocket settings don't, and the replay isn't resumed when its connection drops.
+    pub async fn replay_normalized_http(
+        &self,
+        options: Vec<ReplayNormalizedRequestOptions>,
+    ) -> Result<MessageStream> {
+        let url = self.options_url(
+            &self.http_url()?,
+            "replay-normalized",
+            &self.replay_normalized_options(options)?,
+        )?;
+        log::info!("[replay_normalized_http] url to tardis {}", url);
+
+        let client = self
+            .connection
+            .http_client
+            .as_ref()
+            .map_err(|reason| Error::HttpClient(reason.clone()))?;
+        let response = client
+            .get(&url)
+            .headers(self.connection.headers.clone())
+            .send()
+            .await
+            .map_err(crate::Error::from)?;
+
+        let handshake = Handshake {
+            status: response.status(),
+            headers: response.headers().clone(),
+        };
+        if !response.status().is_success() {
+            let body = response.bytes().await.map_err(crate::Error::from)?;
+            return Err(
+                Error::server_error(&body).unwrap_or_else(|| Error::ConnectRejected {
+                    status: handshake.status,
+                    reason: String::from_utf8_lossy(&body).into_owned(),
+                }),
+            );
+        }
+
+        let lines = codec::decode_response::<Box<RawValue>>(response).await?;
+        let prefilter = self.connection.prefilter.clone();
+        let stats = self.connection.stats.clone();
+        let shutdown = self.connection.shutdown.child_token();
+        let cancelled = shutdown.clone();
+        let pause = PauseHandle::new();
+        let mut paused = pause.subscribe();
+        let messages = stream! {
+            futures_util::pin_mut!(lines);
+            let mut symbols = SymbolInterner::default();
+            loop {
+                if !PauseHandle::resumed(&mut paused, &cancelled).await {
+                    break;
+                }
+                let line = tokio::select! {
+                    _ = cancelled.cancelled() => break,
+                    line = lines.next() => line,
+                };
+                let Some(line) = line else {
+                    break;
+                };
+                let payload = match line {
+                    Ok(line) => Bytes::from(Box::<str>::from(line).into_string()),
+                    Err(e) => {
+                        yield Err(e.into());
+                        continue;
                     }
+                };
+                if prefilter.as_ref().is_some_and(|prefilter| !prefilter.matches(&payload)) {
+                    continue;
+                }
+                let msg = symbols.scope(|| Message::from_payload(payload));
+                if let (Some(stats), Ok(msg)) = (&stats, &msg) {
+                    stats.record(msg);
+                }
+                yield msg;
+            }
+        };
+
+        Ok(MessageStream {
+            handshake,
+            shutdown,
+            pause,
+            inner: Box::pin(messages),
+        })
+    }
+
+    /// Replays the raw messages of an exchange, as they were received from its real-time
+    /// WebSocket API, for the channels selected by the filters of `options`. The messages are
+    /// left to the caller to parse, see [`RawMessage::deserialize`].
+    pub async fn replay_raw(
+        &self,
+        options: ReplayRawRequestOptions,
+    ) -> Result<MessageStream<RawMessage>> {
+        let url = self.options_url(&self.url, "ws-replay", &serde_json::to_string(&options)?)?;
+        log::info!("[replay_raw] url to tardis {}", url);
+        websocket_conn(&url, &self.connection.for_raw_stream()).await
+    }
+
+    /// Streams the raw messages of an exchange in real-time, for the channels selected by the
+    /// filters of `options`. The messages are left to the caller to parse, see
+    /// [`RawMessage::deserialize`].
+    ///
+    /// The connection to the machine server is re-established when the client was built with
+    /// [`ClientBuilder::reconnect`].
+    pub async fn stream_raw(
+        &self,
+        mut options: StreamRawRequestOptions,
+    ) -> Result<MessageStream<RawMessage>> {
+        options.timeout_interval_ms = options.timeout_interval_ms.or(self.timeout_interval_ms);
+        let url = self.options_url(&self.url, "ws-stream", &serde_json::to_string(&options)?)?;
+        log::info!("[stream_raw] url to tardis {}", url);
+        let config = self.connection.for_raw_stream();
+        let messages = websocket_conn(&url, &config).await?;
+
+        Ok(match &self.reconnect {
+            Some(policy) => reconnecting(SameUrl(url), config, policy.clone(), messages),
+            None => messages,
+        })
+    }
+
+    /// Same as [`Client::replay_normalized`], splitting the replay period into days that are
+    /// replayed over up to `concurrency` connections at once, which is much faster for long
+    /// periods. The days are re-merged in order, so the messages are delivered in the same order
+    /// as by a single connection.
+    ///
+    /// Days being replayed ahead of the one currently consumed buffer up to 4096 messages each.
+    /// The returned stream has the handshake of the connection of the first day, and shutting it
+    /// down shuts down every connection.
+    pub async fn replay_normalized_parallel(
+        &self,
+        options: Vec<ReplayNormalizedRequestOptions>,
+        concurrency: usize,
+    ) -> Result<MessageStream> {
+        for option in &options {
+            option.validate()?;
+        }
+        let mut days = split_by_day(&options).into_iter();
+        let first_day = days.next().ok_or(Error::EmptyOptions)?;
+
+        let shutdown = self.connection.shutdown.child_token();
+        let mut client = self.clone();
+        client.connection.shutdown = shutdown.clone();
+
+        // Connecting to the first day eagerly surfaces connection errors right away.
+        let first = client.replay_normalized(first_day).await?;
+        let handshake = first.handshake.clone();
+
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+        let (days_tx, mut days_rx) = mpsc::unbounded_channel();
+        let driver_shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            // The first day is already connected, the others connect once they get a permit.
+            let mut first = Some(first);
+            for day in std::iter::once(None).chain(days.map(Some)) {
+                // Permits are taken in order, so the day being consumed always has its connection.
+                let Ok(permit) = semaphore.clone().acquire_owned().await else {
+                    break;
+                };
+                if driver_shutdown.is_cancelled() {
+                    break;
                 }
-                None => {
-                    tracing::error!("Connection closed unexpectedly");
-                    yield Err(Error::ConnectionClosed { reason: "Unknown reason".to_string() });
+                let (messages_tx, messages_rx) = mpsc::channel(PARALLEL_REPLAY_BUFFER);
+                if days_tx.send(messages_rx).is_err() {
                     break;
                 }
+
+                let client = client.clone();
+                let first = first.take();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    let messages = match (day, first) {
+                        (Some(day), _) => client.replay_normalized(day).await,
+                        (None, first) => first.ok_or(Error::EmptyOptions),
+                    };
+                    match messages {
+                        Ok(mut messages) => {
+                            while let Some(msg) = messages.next().await {
+                                if messages_tx.send(msg).await.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            messages_tx.send(Err(e)).await.ok();
+                        }
+                    }
+                });
+            }
+        });
+
+        // Pausing holds back the days replayed ahead through their bounded buffers.
+        let pause = PauseHandle::new();
+        let mut paused = pause.subscribe();
+        let cancelled = shutdown.clone();
+        let messages = stream! {
+            while let Some(mut day) = days_rx.recv().await {
+                while let Some(msg) = day.recv().await {
+                    yield msg;
+                    if !PauseHandle::resumed(&mut paused, &cancelled).await {
+                        return;
+                    }
+                }
+            }
+        };
+
+        Ok(MessageStream {
+            handshake,
+            shutdown,
+            pause,
+            inner: Box::pin(messages),
+        })
+    }
+
+    /// Returns the URL of a replay-normalized request, with the defaults of the client applied,
+    /// failing if any of the options is invalid.
+    fn replay_normalized_url(
+        &self,
+        options: Vec<ReplayNormalizedRequestOptions>,
+    ) -> Result<String> {
+        self.options_url(
+            &self.url,
+            "ws-replay-normalized",
+            &self.replay_normalized_options(options)?,
+        )
+    }
+
+    /// Returns the JSON options of a replay-normalized request, with the defaults of the client
+    /// applied, failing if any of the options is invalid.
+    fn replay_normalized_options(
+        &self,
+        options: Vec<ReplayNormalizedRequestOptions>,
+    ) -> Result<String> {
+        if options.is_empty() {
+            return Err(Error::EmptyOptions);
+        }
+
+        let options = options
+            .into_iter()
+            .map(|mut option| {
+                option.validate()?;
+                option.with_disconnect_messages = option
+                    .with_disconnect_messages
+                    .or(self.with_disconnect_messages);
+                Ok(option)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(serde_json::to_string(&options)?)
+    }
+
+    /// Returns the URL of the `path` endpoint of `base` requested with the given JSON options,
+    /// failing if it is longer than the [`ClientBuilder::max_url_length`].
+    fn options_url(&self, base: &str, path: &str, options: &str) -> Result<String> {
+        let url = format!("{}/{}?options={}", base, path, urlencoding::encode(options));
+        if url.len() > self.max_url_length {
+            return Err(Error::OptionsTooLarge {
+                length: url.len(),
+                max: self.max_url_length,
+            });
+        }
+        Ok(url)
+    }
+
+    /// Returns the base URL of the HTTP API of the machine server, see [`ClientBuilder::http_url`].
+    fn http_url(&self) -> Result<String> {
+        if let Some(http_url) = &self.http_url {
+            return Ok(http_url.trim_end_matches('/').to_string());
+        }
+
+        let invalid = || Error::InvalidUrl(self.url.clone());
+        let mut url = reqwest::Url::parse(&self.url).map_err(|_| invalid())?;
+        let scheme = match url.scheme() {
+            "ws" => "http",
+            "wss" => "https",
+            _ => return Err(invalid()),
+        };
+        let port = url.port().map(|port| port.saturating_sub(1));
+        url.set_scheme(scheme).map_err(|_| invalid())?;
+        url.set_port(port).map_err(|_| invalid())?;
+        Ok(url.as_str().trim_end_matches('/').to_string())
+    }
+
+    /// Returns the URL of a stream-normalized request, with the defaults of the client applied,
+    /// failing if any of the options is invalid.
+    fn stream_normalized_url(
+        &self,
+        options: Vec<StreamNormalizedRequestOptions>,
+    ) -> Result<String> {
+        if options.is_empty() {
+            return Err(Error::EmptyOptions);
+        }
+
+        let options = options
+            .into_iter()
+            .map(|mut option| {
+                option.validate()?;
+                option.with_disconnect_messages = option
+                    .with_disconnect_messages
+                    .or(self.with_disconnect_messages);
+                option.timeout_interval_ms =
+                    option.timeout_interval_ms.or(self.timeout_interval_ms);
+                Ok(option)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.options_url(
+            &self.url,
+            "ws-stream-normalized",
+            &serde_json::to_string(&options)?,
+        )
+    }
+}
+
+/// How many messages each day replayed ahead by [`Client::replay_normalized_parallel`] buffers.
+const PARALLEL_REPLAY_BUFFER: usize = 4096;
+
+/// Splits the replay periods of `options` into UTC days, returning the options of every day that
+/// has anything to replay, in order.
+fn split_by_day(
+    options: &[ReplayNormalizedRequestOptions],
+) -> Vec<Vec<ReplayNormalizedRequestOptions>> {
+    let (Some(from), Some(to)) = (
+        options.iter().map(|option| option.from).min(),
+        options.iter().map(|option| option.to).max(),
+    ) else {
+        return vec![];
+    };
+
+    let mut days = vec![];
+    let mut start = from;
+    while start < to {
+        let end = (start.date_naive() + chrono::Days::new(1))
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time")
+            .and_utc()
+            .min(to);
+        let day = options
+            .iter()
+            .filter(|option| option.from < end && option.to > start)
+            .map(|option| ReplayNormalizedRequestOptions {
+                from: option.from.max(start),
+                to: option.to.min(end),
+                ..option.clone()
+            })
+            .collect::<Vec<_>>();
+        if !day.is_empty() {
+            days.push(day);
+        }
+        start = end;
+    }
+    days
+}
+
+/// A message decoded from the payload of a text frame.
+trait FromPayload: Sized + Send + 'static {
+    fn from_payload(payload: Bytes) -> Result<Self>;
+
+    /// Returns the arrival timestamp of the message, if it is known without parsing it.
+    fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    /// Returns the normalized message, if it was parsed into one.
+    fn as_message(&self) -> Option<&Message> {
+        None
+    }
+}
+
+impl FromPayload for Message {
+    #[cfg(not(feature = "simd-json"))]
+    fn from_payload(payload: Bytes) -> Result<Self> {
+        serde_json::from_slice(&payload).map_err(|e| Error::from_payload(&payload, e))
+    }
+
+    #[cfg(feature = "simd-json")]
+    fn from_payload(payload: Bytes) -> Result<Self> {
+        // simd-json parses in place, so it gets a buffer of its own, the payload being kept intact
+        // to report the errors.
+        let mut buf = payload.to_vec();
+        simd_json::serde::from_slice(&mut buf)
+            .map_err(|e| Error::from_payload(&payload, serde::de::Error::custom(e)))
+    }
+
+    fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        Message::local_timestamp(self)
+    }
+
+    fn as_message(&self) -> Option<&Message> {
+        Some(self)
+    }
+}
+
+impl FromPayload for RawMessage {
+    fn from_payload(payload: Bytes) -> Result<Self> {
+        Ok(RawMessage::from(payload))
+    }
+}
+
+async fn websocket_conn<T: FromPayload>(
+    url: &str,
+    config: &ConnectionConfig,
+) -> Result<MessageStream<T>> {
+    let (handshake, frames) = websocket_frames(url, config).await?;
+    log::debug!(
+        "Connected to {}, server: {}",
+        url,
+        handshake.server().unwrap_or("unknown")
+    );
+
+    let metrics = config.metrics.clone();
+    if let Some(metrics) = &metrics {
+        metrics.on_connect(url);
+    }
+
+    let stats = config.stats.clone();
+    let stale_timeout = config.stale_timeout;
+    let prefilter = config.prefilter.clone();
+    let mut paused = config.pause.subscribe();
+    let shutdown = config.shutdown.clone();
+    let url = url.to_owned();
+    let messages = stream! {
+        futures_util::pin_mut!(frames);
+        let mut symbols = SymbolInterner::default();
+
+        loop {
+            if !PauseHandle::resumed(&mut paused, &shutdown).await {
+                break;
+            }
+            let payload = match stale_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, frames.next()).await {
+                    Ok(payload) => payload,
+                    Err(_) => {
+                        log::warn!("No message received from {} for {:?}", url, timeout);
+                        yield Err(Error::Stale { timeout });
+                        continue;
+                    }
+                },
+                None => frames.next().await,
+            };
+            let Some(payload) = payload else {
+                break;
+            };
+            let payload = match payload {
+                Ok(payload) => payload,
+                Err(e) => {
+                    yield Err(e);
+                    continue;
+                }
+            };
+            if let Some(metrics) = &metrics {
+                metrics.on_message(payload.len());
+            }
+            if prefilter.as_ref().is_some_and(|prefilter| !prefilter.matches(&payload)) {
+                continue;
+            }
+
+            let msg = symbols.scope(|| T::from_payload(payload));
+            if let Some(metrics) = &metrics {
+                match &msg {
+                    Ok(msg) => {
+                        if let Some(local_timestamp) = msg.local_timestamp() {
+                            metrics.on_lag(Utc::now() - local_timestamp);
+                        }
+                    }
+                    Err(Error::Deserialization(e)) => metrics.on_deserialization_error(e),
+                    Err(_) => {}
+                }
             }
+            if let (Some(stats), Ok(msg)) = (&stats, &msg) {
+                if let Some(msg) = msg.as_message() {
+                    stats.record(msg);
+                }
+            }
+            yield msg;
         }
+    };
+
+    Ok(MessageStream {
+        handshake,
+        shutdown: config.shutdown.clone(),
+        pause: config.pause.clone(),
+        inner: Box::pin(messages),
     })
 }
 
-async fn heartbeat(
-    mut sender: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Message>,
-) {
-    // create an interval.
-    let mut interval = tokio::time::interval(Duration::from_secs(10));
+/// Decodes the normalized messages of a raw stream with `decode`, see
+/// [`Client::replay_normalized_with`].
+fn decode_with<T, F>(messages: MessageStream<RawMessage>, mut decode: F) -> MessageStream<T>
+where
+    T: Send + 'static,
+    F: FnMut(MessageRef<'_>) -> Option<T> + Send + 'static,
+{
+    let MessageStream {
+        handshake,
+        shutdown,
+        pause,
+        inner,
+    } = messages;
+    let inner = inner.filter_map(move |msg| {
+        let msg = match msg {
+            Ok(raw) => match raw.message_ref() {
+                Ok(msg) => decode(msg).map(Ok),
+                Err(e) => Some(Err(Error::from_payload(raw.payload(), e))),
+            },
+            Err(e) => Some(Err(e)),
+        };
+        std::future::ready(msg)
+    });
+
+    MessageStream {
+        handshake,
+        shutdown,
+        pause,
+        inner: Box::pin(inner),
+    }
+}
 
-    loop {
-        // wait for the interval to arrive.
-        interval.tick().await;
+/// Decides where a dropped connection picks up again.
+trait Resume<T>: Send + 'static {
+    /// Records a received message, returning `false` if it was already delivered before the
+    /// connection dropped.
+    fn record(&mut self, message: &T) -> bool;
+
+    /// Returns the URL to reconnect to, or `None` if there is nothing left to receive.
+    fn url(&mut self, closed_normally: bool) -> Result<Option<String>>;
+
+    /// Returns `true` if the connection should be restarted once `message` is delivered.
+    fn restarts_after(&self, message: &T) -> bool {
+        let _ = message;
+        false
+    }
+}
+
+/// Reconnects to the same URL, as a real-time stream has no position to resume from.
+struct SameUrl(String);
+
+impl<T> Resume<T> for SameUrl {
+    fn record(&mut self, _: &T) -> bool {
+        true
+    }
+
+    fn url(&mut self, _: bool) -> Result<Option<String>> {
+        Ok(Some(self.0.clone()))
+    }
+}
+
+/// Reconnects to the same URL, restarting the connection after each disconnect message so that
+/// the server subscribes to the exchange again.
+struct Resubscribe(String);
+
+impl Resume<Message> for Resubscribe {
+    fn record(&mut self, _: &Message) -> bool {
+        true
+    }
+
+    fn url(&mut self, _: bool) -> Result<Option<String>> {
+        Ok(Some(self.0.clone()))
+    }
+
+    fn restarts_after(&self, message: &Message) -> bool {
+        matches!(message, Message::Disconnect(_))
+    }
+}
+
+/// Resumes a replay from the arrival timestamp of the last message received, skipping the
+/// messages sharing that timestamp which were already delivered.
+///
+/// Messages are only skipped while a resumed connection catches up with the resume point, so
+/// that a replay delivering arrival timestamps out of order loses nothing.
+struct ReplayCursor {
+    client: Client,
+    options: Vec<ReplayNormalizedRequestOptions>,
+    last_timestamp: Option<DateTime<Utc>>,
+    delivered_at_last: usize,
+    resuming: bool,
+    skip: usize,
+}
+
+impl Resume<Message> for ReplayCursor {
+    fn record(&mut self, message: &Message) -> bool {
+        // An unknown message without a timestamp can't be placed, so it is delivered as is.
+        let Some(timestamp) = message.local_timestamp() else {
+            return true;
+        };
+        let timestamp = Some(timestamp);
+        if self.resuming {
+            if timestamp < self.last_timestamp
+                || (timestamp == self.last_timestamp && self.skip > 0)
+            {
+                self.skip = self.skip.saturating_sub(1);
+                return false;
+            }
+            self.resuming = false;
+            self.skip = 0;
+        }
+
+        // The cursor follows the latest arrival timestamp, as the resumed replay starts there.
+        if timestamp == self.last_timestamp {
+            self.delivered_at_last += 1;
+        } else if timestamp > self.last_timestamp {
+            self.last_timestamp = timestamp;
+            self.delivered_at_last = 1;
+        }
+        true
+    }
+
+    fn url(&mut self, closed_normally: bool) -> Result<Option<String>> {
+        // The server closes the connection normally once the replay is complete.
+        if closed_normally {
+            return Ok(None);
+        }
+
+        let options = match self.last_timestamp {
+            Some(last_timestamp) => self
+                .options
+                .iter()
+                .filter(|option| option.to > last_timestamp)
+                .map(|option| ReplayNormalizedRequestOptions {
+                    from: option.from.max(last_timestamp),
+                    ..option.clone()
+                })
+                .collect::<Vec<_>>(),
+            None => self.options.clone(),
+        };
+        if options.is_empty() {
+            return Ok(None);
+        }
+
+        self.resuming = self.last_timestamp.is_some();
+        self.skip = self.delivered_at_last;
+        self.client.replay_normalized_url(options).map(Some)
+    }
+}
+
+/// Wraps the stream of an established connection so that it reconnects whenever the connection
+/// drops, until `policy` gives up. Deserialization errors don't end a connection and are passed
+/// through as is, while server errors end the stream.
+fn reconnecting<T>(
+    mut resume: impl Resume<T>,
+    config: ConnectionConfig,
+    policy: RestartPolicy,
+    messages: MessageStream<T>,
+) -> MessageStream<T>
+where
+    T: FromPayload,
+{
+    let handshake = messages.handshake.clone();
+    let shutdown = config.shutdown.clone();
+    let pause = config.pause.clone();
+    // The attempts are paced by `policy` alone, rather than each retrying on its own as well.
+    let config = ConnectionConfig {
+        restart_policy: RestartPolicy::never(),
+        ..config
+    };
+
+    let messages = stream! {
+        let mut messages = Some(messages);
+        let mut attempt = 0;
+        let mut last_error = None;
+
+        loop {
+            let mut closed_normally = false;
+            if let Some(mut connection) = messages.take() {
+                closed_normally = true;
+                while let Some(msg) = connection.next().await {
+                    match msg {
+                        Ok(msg) => {
+                            attempt = 0;
+                            let restart = resume.restarts_after(&msg);
+                            if resume.record(&msg) {
+                                yield Ok(msg);
+                            }
+                            if restart {
+                                log::info!("Exchange disconnected, resubscribing");
+                                closed_normally = false;
+                                break;
+                            }
+                        }
+                        Err(e @ Error::Deserialization(_)) => yield Err(e),
+                        Err(e @ Error::ServerError { .. }) => {
+                            // Connecting again would be refused for the same reason.
+                            yield Err(e);
+                            return;
+                        }
+                        Err(e) => {
+                            closed_normally = false;
+                            last_error = Some(e);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            if config.shutdown.is_cancelled() {
+                break;
+            }
+            let url = match resume.url(closed_normally) {
+                Ok(Some(url)) => url,
+                Ok(None) => break,
+                Err(e) => {
+                    yield Err(e);
+                    break;
+                }
+            };
+            let Some(delay) = policy.delay(attempt) else {
+                log::error!("Giving up reconnecting to {} after {} attempts", url, attempt);
+                yield Err(last_error.take().unwrap_or(Error::ConnectionClosed {
+                    reason: "Connection ended".to_string(),
+                }));
+                break;
+            };
+            attempt += 1;
+            log::warn!("Reconnecting to {} in {:?} (attempt {})", url, delay, attempt);
+            if let Some(metrics) = &config.metrics {
+                metrics.on_reconnect(attempt);
+            }
+            tokio::select! {
+                _ = config.shutdown.cancelled() => break,
+                _ = tokio::time::sleep(delay) => {}
+            }
+
+            match websocket_conn(&url, &config).await {
+                Ok(connection) => {
+                    messages = Some(connection);
+                    last_error = None;
+                }
+                Err(e) => {
+                    log::warn!("Failed to reconnect to {}: {}", url, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+    };
+
+    MessageStream {
+        handshake,
+        shutdown,
+        pause,
+        inner: Box::pin(messages),
+    }
+}
+
+/// Connects to the given URL, returning the payload of every text frame received. The payloads
+/// take over the buffer of the frame, so no copy is made.
+async fn websocket_frames(
+    url: &str,
+    config: &ConnectionConfig,
+) -> Result<(Handshake, impl Stream<Item = Result<Bytes>> + Send)> {
+    let (ws_stream, ws_resp) = config
+        .restart_policy
+        .retry(
+            || async {
+                let Some(timeout) = config.connect_timeout else {
+                    return Ok(connect(url, config).await?);
+                };
+                match tokio::time::timeout(timeout, connect(url, config)).await {
+                    Ok(connection) => Ok(connection?),
+                    Err(_) => Err(Error::Timeout {
+                        kind: TimeoutKind::Connect,
+                        timeout,
+                    }),
+                }
+            },
+            |e| {
+                log::warn!("Failed to connect to {}: {}", url, e);
+                config.restart_policy.is_retryable(&Failure::Connect(e))
+            },
+        )
+        .await?;
+
+    // Return the error response if the status code is not 101.
+    // (meaning the HTTP connection is not being upgraded to a WS connection)
+    if ws_resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return match ws_resp.body() {
+            Some(resp) => Err(Error::ConnectRejected {
+                status: ws_resp.status(),
+                reason: String::from_utf8_lossy(resp).to_string(),
+            }),
+            None => Err(Error::ConnectRejected {
+                status: ws_resp.status(),
+                reason: "Unknown reason".to_string(),
+            }),
+        };
+    }
+
+    let handshake = Handshake {
+        status: ws_resp.status(),
+        headers: ws_resp.headers().clone(),
+    };
+
+    let keep_alive = config.keep_alive.clone();
+    let idle_timeout = keep_alive.idle_timeout();
+    let shutdown = config.shutdown.clone();
+    let pause = config.pause.clone();
+    let read_timeout = config.read_timeout;
+    Ok((
+        handshake,
+        stream! {
+            let (writer, mut reader) = ws_stream.split();
+            // The writer stops once `outgoing` is dropped along with this stream, or on shutdown.
+            let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+            tokio::spawn(write_loop(writer, outgoing_rx, keep_alive, shutdown.clone()));
+
+            let mut paused = pause.subscribe();
+            let mut read_deadline = read_timeout.map(|timeout| Instant::now() + timeout);
+            loop {
+                // The read timeout restarts after a pause, even one the stream wasn't polled in.
+                if paused.has_changed().unwrap_or(false) {
+                    if !PauseHandle::resumed(&mut paused, &shutdown).await {
+                        log::debug!("Connection shut down");
+                        break;
+                    }
+                    read_deadline = read_timeout.map(|timeout| Instant::now() + timeout);
+                }
+                let msg = tokio::select! {
+                    biased;
+                    _ = shutdown.cancelled() => {
+                        log::debug!("Connection shut down");
+                        break;
+                    }
+                    _ = sleep_until(read_deadline) => {
+                        let timeout = read_timeout.unwrap_or_default();
+                        log::error!("No message received for {:?}, closing the connection", timeout);
+                        yield Err(Error::Timeout { kind: TimeoutKind::Read, timeout });
+                        break;
+                    }
+                    msg = next_frame(&mut reader, idle_timeout) => msg,
+                };
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(idle_timeout) => {
+                        log::error!("Nothing received for {:?}, closing the connection", idle_timeout);
+                        yield Err(Error::ConnectionClosed { reason: format!("Nothing received for {:?}", idle_timeout) });
+                        break;
+                    }
+                };
+
+                match msg {
+                    Some(msg) => {
+                        let msg = msg?;
+                        match msg {
+                            tungstenite::Message::Frame(_)
+                            | tungstenite::Message::Binary(_)
+                            | tungstenite::Message::Pong(_) => {}
+                            tungstenite::Message::Ping(payload) => {
+                                log::debug!("Received PING frame");
+                                outgoing.send(tungstenite::Message::Pong(payload)).ok();
+                            }
+                            tungstenite::Message::Close(frame) => {
+                                if let Some(frame) = frame {
+                                    if frame.code != CloseCode::Normal {
+                                        log::error!(
+                                            "Connection closed abnormally: {}",
+                                            frame.reason
+                                        );
+                                        yield Err(Error::ConnectionClosed { reason: frame.reason.to_string() })
+                                    }
+                                    log::debug!("Connection closed normally: {}", frame.reason);
+                                }
+                                break;
+                            }
+                            tungstenite::Message::Text(msg) => {
+                                log::debug!("Received websocket message: {}", msg);
+                                read_deadline = read_timeout.map(|timeout| Instant::now() + timeout);
+                                yield Ok(Bytes::from(msg));
+                            }
+                        }
+                    }
+                    None => {
+                        log::error!("Connection closed unexpectedly");
+                        yield Err(Error::ConnectionClosed { reason: "Unknown reason".to_string() });
+                        break;
+                    }
+                }
+            }
+        },
+    ))
+}
+
+/// The connection a websocket runs over, plain or encrypted, possibly inflating what it reads.
+trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+type Connection = WebSocketStream<Box<dyn Io>>;
+
+/// Opens a websocket connection to the given URL.
+async fn connect(
+    url: &str,
+    config: &ConnectionConfig,
+) -> std::result::Result<(Connection, Response), tungstenite::Error> {
+    let mut request = url.into_client_request()?;
+    request.headers_mut().extend(config.headers.clone());
+    if config.compression {
+        request.headers_mut().insert(
+            SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static(deflate::EXTENSION),
+        );
+    }
+
+    let uri = request.uri();
+    let tls = match uri.scheme_str() {
+        Some("ws") => false,
+        Some("wss") => true,
+        _ => return Err(tungstenite::Error::Url(UrlError::UnsupportedUrlScheme)),
+    };
+    let host = uri
+        .host()
+        .ok_or(tungstenite::Error::Url(UrlError::NoHostName))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+
+    let stream = match &config.proxy {
+        Some(proxy) => proxy.connect(&host, port).await?,
+        None => TcpStream::connect((host.as_str(), port)).await?,
+    };
+    let stream: Box<dyn Io> = match tls {
+        true => Box::new(tls::connect(&host, stream).await?),
+        false => Box::new(stream),
+    };
+    let stream: Box<dyn Io> = match config.compression {
+        true => Box::new(Inflate::new(stream)),
+        false => stream,
+    };
+
+    client_async_with_config(request, stream, config.websocket_config).await
+}
+
+/// Sleeps until `deadline`, forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
 
-        // create a copy of the retries count.
-        let mut count = 3;
-        // the duration to wait before each retry.
-        let mut interval = tokio::time::interval(Duration::from_secs(1));
+/// Reads the next frame, failing with `idle_timeout` if nothing was received in time.
+async fn next_frame<S: Stream + Unpin>(
+    reader: &mut S,
+    idle_timeout: Option<Duration>,
+) -> std::result::Result<Option<S::Item>, Duration> {
+    match idle_timeout {
+        Some(idle_timeout) => tokio::time::timeout(idle_timeout, reader.next())
+            .await
+            .map_err(|_| idle_timeout),
+        None => Ok(reader.next().await),
+    }
+}
 
-        // keep trying until we run out of count.
-        while count > 0 {
-            interval.tick().await;
+/// Writes the frames queued by the reader, eg. pongs, to the connection and sends the pings of
+/// `keep_alive`, until the reader goes away or the connection fails. On shutdown, the connection
+/// is closed with a close frame.
+async fn write_loop(
+    mut sender: SplitSink<Connection, tungstenite::Message>,
+    mut outgoing: mpsc::UnboundedReceiver<tungstenite::Message>,
+    keep_alive: KeepAlive,
+    shutdown: CancellationToken,
+) {
+    let mut interval = keep_alive.interval.map(tokio::time::interval);
+    if let Some(interval) = &mut interval {
+        // the first tick completes immediately, there is no need to ping right after connecting.
+        interval.tick().await;
+    }
 
-            // send native ping frame.
-            let _ = sender.send(tungstenite::Message::Ping(vec![]));
+    loop {
+        let ping = async {
+            match &mut interval {
+                Some(interval) => interval.tick().await,
+                None => std::future::pending().await,
+            }
+        };
+        let msg = tokio::select! {
+            // Checked first, as the reader also stops on shutdown, closing `outgoing`.
+            biased;
+            _ = shutdown.cancelled() => {
+                let close = CloseFrame {
+                    code: CloseCode::Normal,
+                    reason: "Shutdown".into(),
+                };
+                sender.send(tungstenite::Message::Close(Some(close))).await.ok();
+                break;
+            }
+            msg = outgoing.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = ping => tungstenite::Message::Ping(keep_alive.payload.clone()),
+        };
 
-            count -= 1;
+        if let Err(e) = sender.send(msg).await {
+            log::warn!("Failed to write to the connection: {}", e);
+            break;
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Exchange;
+    use crate::{
+        machine::{DataType, Filter, IntervalUnit},
+        test_server::{serve_reply, Reply},
+        Exchange,
+    };
     use chrono::{TimeZone, Utc};
     use futures_util::pin_mut;
     use tracing_test::traced_test;
 
+    use std::sync::{Arc, Mutex};
+
     use super::*;
 
+    /// Serves a single websocket connection sending the given text frames, returning its URL.
+    async fn serve(frames: Vec<&'static str>) -> String {
+        serve_connections(vec![frames]).await.0
+    }
+
+    /// Serves one websocket connection per entry of `connections`, each sending its text frames
+    /// and closing, returning the URL and the requested URIs. A `!drop` frame drops the
+    /// connection without closing it, and a `!hold` frame keeps it open without sending anything.
+    /// Further connections are refused.
+    async fn serve_connections(
+        connections: Vec<Vec<&'static str>>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let uris = requests.clone();
+        tokio::spawn(async move {
+            for frames in connections {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_hdr_async(tcp, Handshake(uris.clone()))
+                    .await
+                    .unwrap();
+                // Held connections don't keep the next ones from being accepted.
+                tokio::spawn(async move {
+                    for frame in frames {
+                        if frame == "!drop" {
+                            return;
+                        }
+                        if frame == "!hold" {
+                            let (_, mut reader) = ws.split();
+                            while reader.next().await.is_some() {}
+                            return;
+                        }
+                        ws.send(tungstenite::Message::Text(frame.to_string()))
+                            .await
+                            .unwrap();
+                    }
+                    ws.close(None).await.ok();
+                });
+            }
+        });
+
+        (url, requests)
+    }
+
+    /// Accepts a websocket handshake as Tardis Machine does, recording the requested URI.
+    struct Handshake(Arc<Mutex<Vec<String>>>);
+
+    impl tungstenite::handshake::server::Callback for Handshake {
+        fn on_request(
+            self,
+            request: &tungstenite::handshake::server::Request,
+            mut response: tungstenite::handshake::server::Response,
+        ) -> std::result::Result<
+            tungstenite::handshake::server::Response,
+            tungstenite::handshake::server::ErrorResponse,
+        > {
+            self.0.lock().unwrap().push(request.uri().to_string());
+            response
+                .headers_mut()
+                .insert("server", "tardis-machine/3.35.0".parse().unwrap());
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn test_builder_defaults() {
+        let client = Client::builder("ws://localhost:8001")
+            .with_disconnect_messages(true)
+            .timeout_interval_ms(5_000)
+            .build();
+
+        let url = client
+            .stream_normalized_url(vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: Some(false),
+                timeout_interval_ms: None,
+            }])
+            .unwrap();
+        let url = urlencoding::decode(&url).unwrap();
+
+        assert!(url.contains(r#""withDisconnectMessages":false"#));
+        assert!(url.contains(r#""timeoutIntervalMS":5000"#));
+    }
+
+    #[test]
+    fn test_max_url_length() {
+        let options = |symbols: usize| {
+            vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Binance,
+                symbols: Some((0..symbols).map(|i| format!("SYMBOL{}USDT", i)).collect()),
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+                timeout_interval_ms: None,
+            }]
+        };
+
+        let client = Client::new("ws://localhost:8001");
+        assert!(client.stream_normalized_url(options(10)).is_ok());
+        assert!(matches!(
+            client.stream_normalized_url(options(500)),
+            Err(Error::OptionsTooLarge { max: 8192, .. })
+        ));
+
+        let client = Client::builder("ws://localhost:8001")
+            .max_url_length(64 * 1024)
+            .build();
+        assert!(client.stream_normalized_url(options(500)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_handshake() {
+        let url = serve(vec![r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#]).await;
+
+        let stream = websocket_conn::<Message>(&url, &Client::new(&url).connection)
+            .await
+            .unwrap();
+        assert_eq!(stream.handshake().status, StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(stream.handshake().server(), Some("tardis-machine/3.35.0"));
+
+        let messages = stream.collect::<Vec<_>>().await;
+        assert!(matches!(messages[..], [Ok(Message::Disconnect(_))]));
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.send(tungstenite::Message::Ping(b"server".to_vec()))
+                .await
+                .unwrap();
+
+            let (mut pinged, mut ponged) = (false, false);
+            while !(pinged && ponged) {
+                match ws.next().await.unwrap().unwrap() {
+                    tungstenite::Message::Ping(_) => pinged = true,
+                    tungstenite::Message::Pong(payload) => ponged = payload == b"server",
+                    _ => {}
+                }
+            }
+            ws.send(tungstenite::Message::Text(
+                r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#
+                    .to_string(),
+            ))
+            .await
+            .unwrap();
+            ws.close(None).await.ok();
+        });
+
+        let client = Client::builder(&url)
+            .heartbeat_interval(Duration::from_millis(10))
+            .build();
+        let stream = websocket_conn::<Message>(&url, &client.connection)
+            .await
+            .unwrap();
+        let messages = tokio::time::timeout(Duration::from_secs(5), stream.collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert!(matches!(messages[..], [Ok(Message::Disconnect(_))]));
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_idle_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            // never reads, so the pings of the client are never answered
+            let _ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let client = Client::builder(&url)
+            .keep_alive(KeepAlive::interval(Duration::from_millis(10)).max_missed(2))
+            .build();
+        let stream = websocket_conn::<Message>(&url, &client.connection)
+            .await
+            .unwrap();
+        let messages = tokio::time::timeout(Duration::from_secs(1), stream.collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert!(matches!(
+            messages[..],
+            [Err(Error::ConnectionClosed { .. })]
+        ));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)]
+    async fn test_headers() {
+        use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut headers = HeaderMap::new();
+            let mut ws =
+                tokio_tungstenite::accept_hdr_async(tcp, |req: &Request, resp: Response| {
+                    headers = req.headers().clone();
+                    Ok(resp)
+                })
+                .await
+                .unwrap();
+            ws.close(None).await.ok();
+            headers
+        });
+
+        let mut extra = HeaderMap::new();
+        extra.insert("x-tenant", HeaderValue::from_static("desk-1"));
+        let client = Client::builder(&url)
+            .header(AUTHORIZATION, HeaderValue::from_static("Bearer secret"))
+            .headers(extra)
+            .build();
+        assert!(!format!("{:?}", client).contains("secret"));
+        client
+            .replay_raw(ReplayRawRequestOptions {
+                exchange: Exchange::Bitmex,
+                filters: None,
+                from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
+                to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
+            })
+            .await
+            .unwrap();
+
+        let headers = server.await.unwrap();
+        assert_eq!(headers["authorization"], "Bearer secret");
+        assert_eq!(headers["x-tenant"], "desk-1");
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        const DISCONNECT: &str = r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#;
+        let options = || {
+            vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: Some(true),
+                timeout_interval_ms: None,
+            }]
+        };
+
+        let url = serve(vec![DISCONNECT, "!hold"]).await;
+        let messages = Client::builder(&url)
+            .read_timeout(Duration::from_millis(100))
+            .build()
+            .stream_normalized(options())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(
+            messages[..],
+            [
+                Ok(Message::Disconnect(_)),
+                Err(Error::Timeout {
+                    kind: TimeoutKind::Read,
+                    ..
+                }),
+            ]
+        ));
+
+        // Accepted by the OS, but never upgraded.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let result = Client::builder(&url)
+            .connect_timeout(Duration::from_millis(100))
+            .build()
+            .stream_normalized(options())
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::Timeout {
+                kind: TimeoutKind::Connect,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replay_raw() {
+        let (url, requests) = serve_connections(vec![vec![
+            r#"{"table":"trade","action":"insert","data":[]}"#,
+        ]])
+        .await;
+
+        let messages = Client::new(&url)
+            .replay_raw(ReplayRawRequestOptions {
+                exchange: Exchange::Bitmex,
+                filters: Some(vec![Filter::channel("trade").symbols(["XBTUSD"])]),
+                from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
+                to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
+            })
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        let raw = messages[0].as_ref().unwrap();
+        let value = raw.deserialize::<serde_json::Value>().unwrap();
+        assert_eq!(value["table"], "trade");
+
+        let request = requests.lock().unwrap()[0].clone();
+        let request = urlencoding::decode(&request).unwrap();
+        assert!(request.starts_with("/ws-replay?options="), "{}", request);
+        assert!(
+            request.contains(r#""filters":[{"channel":"trade","symbols":["XBTUSD"]}]"#),
+            "{}",
+            request
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        use flate2::{Compress, Compression, FlushCompress};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let long = format!(r#"{{"data":"{}"}}"#, "x".repeat(300));
+        let messages = [r#"{"id":1}"#.to_string(), long, r#"{"id":3}"#.to_string()];
+
+        let sent = messages.clone();
+        let server = tokio::spawn(async move {
+            let (mut tcp, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(tcp.read_u8().await.unwrap());
+            }
+            let request = String::from_utf8(request).unwrap();
+            let header = |name: &str| {
+                request.lines().find_map(|line| {
+                    let (key, value) = line.split_once(": ")?;
+                    key.eq_ignore_ascii_case(name).then(|| value.to_string())
+                })
+            };
+            let accept = tungstenite::handshake::derive_accept_key(
+                header("sec-websocket-key").unwrap().as_bytes(),
+            );
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+                 Sec-WebSocket-Accept: {}\r\nSec-WebSocket-Extensions: permessage-deflate\r\n\r\n",
+                accept
+            );
+            tcp.write_all(response.as_bytes()).await.unwrap();
+
+            // The compressor keeps its context between messages, as allowed by default.
+            let mut compress = Compress::new(Compression::default(), false);
+            let mut deflate = |message: &str| {
+                let mut out = Vec::with_capacity(1024);
+                compress
+                    .compress_vec(message.as_bytes(), &mut out, FlushCompress::Sync)
+                    .unwrap();
+                out.truncate(out.len() - 4);
+                out
+            };
+            let frame = |first: u8, payload: &[u8]| {
+                let mut frame = vec![first, payload.len() as u8];
+                frame.extend_from_slice(payload);
+                frame
+            };
+
+            let first = deflate(&sent[0]);
+            tcp.write_all(&frame(0xc1, &first)).await.unwrap();
+            // Compressed, fragmented over a text frame and a continuation frame.
+            let second = deflate(&sent[1]);
+            let (start, end) = second.split_at(second.len() / 2);
+            tcp.write_all(&frame(0x41, start)).await.unwrap();
+            tcp.write_all(&frame(0x80, end)).await.unwrap();
+            tcp.write_all(&frame(0x81, sent[2].as_bytes()))
+                .await
+                .unwrap();
+            tcp.write_all(&frame(0x88, &[0x03, 0xe8])).await.unwrap();
+
+            header("sec-websocket-extensions")
+        });
+
+        let received = Client::builder(&url)
+            .compression(true)
+            .build()
+            .replay_raw(ReplayRawRequestOptions {
+                exchange: Exchange::Bitmex,
+                filters: None,
+                from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
+                to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
+            })
+            .await
+            .unwrap()
+            .map(|msg| String::from_utf8(msg.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(received, messages);
+        assert_eq!(server.await.unwrap().as_deref(), Some("permessage-deflate"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_raw() {
+        let (url, requests) = serve_connections(vec![vec![r#"{"e":"trade"}"#]]).await;
+
+        let client = Client::builder(&url).timeout_interval_ms(5_000).build();
+        let messages = client
+            .stream_raw(StreamRawRequestOptions {
+                exchange: Exchange::Binance,
+                filters: Some(vec![Filter::channel("trade").symbols(["btcusdt"])]),
+                timeout_interval_ms: None,
+            })
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(&**messages[0].as_ref().unwrap(), br#"{"e":"trade"}"#);
+
+        let request = requests.lock().unwrap()[0].clone();
+        let request = urlencoding::decode(&request).unwrap();
+        assert!(request.starts_with("/ws-stream?options="), "{}", request);
+        assert!(
+            request.contains(r#""timeoutIntervalMS":5000"#),
+            "{}",
+            request
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (closed, closed_rx) = tokio::sync::oneshot::channel();
+
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.send(tungstenite::Message::Text(
+                r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#
+                    .to_string(),
+            ))
+            .await
+            .unwrap();
+            while let Some(Ok(msg)) = ws.next().await {
+                if let tungstenite::Message::Close(frame) = msg {
+                    closed.send(frame.map(|frame| frame.code)).ok();
+                    break;
+                }
+            }
+        });
+
+        let token = CancellationToken::new();
+        let client = Client::builder(&url)
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)))
+            .cancellation_token(token.clone())
+            .build();
+        let mut stream = client
+            .stream_normalized(vec![StreamNormalizedRequestOptions::builder(
+                Exchange::Bybit,
+            )
+            .data_type(DataType::Trade)
+            .build()])
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            stream.next().await,
+            Some(Ok(Message::Disconnect(_)))
+        ));
+        token.cancel();
+        assert!(stream.next().await.is_none());
+        assert_eq!(closed_rx.await.unwrap(), Some(CloseCode::Normal));
+    }
+
+    #[test]
+    fn test_split_by_day() {
+        let at = |day, hour| Utc.with_ymd_and_hms(2022, 10, day, hour, 0, 0).unwrap();
+        let option = |from, to| ReplayNormalizedRequestOptions {
+            exchange: Exchange::Bybit,
+            symbols: None,
+            from,
+            to,
+            data_types: vec![DataType::Trade],
+            with_disconnect_messages: None,
+        };
+
+        let days = split_by_day(&[option(at(1, 12), at(3, 6)), option(at(2, 0), at(2, 1))]);
+        let periods = days
+            .iter()
+            .map(|day| {
+                day.iter()
+                    .map(|option| (option.from, option.to))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            periods,
+            vec![
+                vec![(at(1, 12), at(2, 0))],
+                vec![(at(2, 0), at(3, 0)), (at(2, 0), at(2, 1))],
+                vec![(at(3, 0), at(3, 6))],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)]
+    async fn test_parallel_replay() {
+        use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        // Every connection replays two disconnect messages at the start of its period.
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut uri = String::new();
+                    let mut ws = tokio_tungstenite::accept_hdr_async(
+                        tcp,
+                        |req: &Request, resp: Response| {
+                            uri = urlencoding::decode(&req.uri().to_string())
+                                .unwrap()
+                                .into_owned();
+                            Ok(resp)
+                        },
+                    )
+                    .await
+                    .unwrap();
+                    let from = &uri[uri.find(r#""from":""#).unwrap() + 8..][..20];
+                    for _ in 0..2 {
+                        let msg = format!(
+                            r#"{{"type":"disconnect","exchange":"bybit","localTimestamp":"{}"}}"#,
+                            from
+                        );
+                        ws.send(tungstenite::Message::Text(msg)).await.unwrap();
+                    }
+                    ws.close(None).await.ok();
+                });
+            }
+        });
+
+        let messages = Client::new(&url)
+            .replay_normalized_parallel(
+                vec![ReplayNormalizedRequestOptions {
+                    exchange: Exchange::Bybit,
+                    symbols: None,
+                    from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
+                    to: Utc.with_ymd_and_hms(2022, 10, 6, 0, 0, 0).unwrap(),
+                    data_types: vec![DataType::Trade],
+                    with_disconnect_messages: None,
+                }],
+                3,
+            )
+            .await
+            .unwrap()
+            .map(|msg| msg.unwrap().local_timestamp().unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        let days = (1..=5)
+            .flat_map(|day| [Utc.with_ymd_and_hms(2022, 10, day, 0, 0, 0).unwrap(); 2])
+            .collect::<Vec<_>>();
+        assert_eq!(messages, days);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        const DISCONNECT: &str = r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#;
+        let (url, _) = serve_connections(vec![vec![DISCONNECT], vec!["{}", DISCONNECT]]).await;
+
+        let client = Client::builder(&url)
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(2))
+            .build();
+        let messages = client
+            .stream_normalized(vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: Some(true),
+                timeout_interval_ms: None,
+            }])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(matches!(
+            messages[..],
+            [
+                Ok(Message::Disconnect(_)),
+                Err(Error::Deserialization(_)),
+                Ok(Message::Disconnect(_)),
+                Err(Error::ConnectFailed(_)),
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_server_error() {
+        const ERROR: &str = r#"{"code":100,"message":"Invalid data type: 'trades'"}"#;
+        let (url, requests) = serve_connections(vec![vec!["{}", ERROR], vec![ERROR]]).await;
+
+        let client = Client::builder(&url)
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(2))
+            .build();
+        let messages = client
+            .stream_normalized(vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+                timeout_interval_ms: None,
+            }])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], Err(Error::Deserialization(_))));
+        assert!(matches!(
+            &messages[1],
+            Err(Error::ServerError { code: 100, message }) if message == "Invalid data type: 'trades'"
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_policy_resubscribe() {
+        const TRADE: &str = r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#;
+        const DISCONNECT: &str = r#"{"type":"disconnect","exchange":"bitmex","localTimestamp":"2019-10-23T10:32:50.000Z"}"#;
+        let (url, requests) = serve_connections(vec![vec![DISCONNECT, "!hold"], vec![TRADE]]).await;
+
+        let client = Client::builder(&url)
+            .disconnect_policy(DisconnectPolicy::Resubscribe)
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(1))
+            .build();
+        let messages = client
+            .stream_normalized(vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bitmex,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+                timeout_interval_ms: None,
+            }])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(matches!(
+            messages[..],
+            [
+                Ok(Message::Disconnect(_)),
+                Ok(Message::Trade(_)),
+                Err(Error::ConnectFailed(_)),
+            ]
+        ));
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|uri| urlencoding::decode(uri)
+            .unwrap()
+            .contains(r#""withDisconnectMessages":true"#)));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_policy_resubscribe_exchange() {
+        const TRADE: &str = r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#;
+        const DISCONNECT: &str = r#"{"type":"disconnect","exchange":"bitmex","localTimestamp":"2019-10-23T10:32:50.000Z"}"#;
+        let (url, requests) = serve_connections(vec![
+            vec![DISCONNECT, "!hold"],
+            vec!["!hold"],
+            vec![TRADE, "!hold"],
+        ])
+        .await;
+
+        let client = Client::builder(&url)
+            .disconnect_policy(DisconnectPolicy::Resubscribe)
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)))
+            .build();
+        let options = |exchange| StreamNormalizedRequestOptions {
+            exchange,
+            symbols: None,
+            data_types: vec![DataType::Trade],
+            with_disconnect_messages: None,
+            timeout_interval_ms: None,
+        };
+        let messages = client
+            .stream_normalized(vec![options(Exchange::Bitmex), options(Exchange::Deribit)])
+            .await
+            .unwrap()
+            .take(2)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(matches!(
+            messages[..],
+            [Ok(Message::Disconnect(_)), Ok(Message::Trade(_))]
+        ));
+        let requests = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|uri| urlencoding::decode(uri).unwrap().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].contains("deribit") && !requests[1].contains("bitmex"));
+        // Only the exchange that disconnected is subscribed to again.
+        assert!(requests[2].contains("bitmex") && !requests[2].contains("deribit"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_normalized_with() {
+        const TRADE: &str = r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#;
+        const DISCONNECT: &str = r#"{"type":"disconnect","exchange":"bitmex","localTimestamp":"2019-10-23T10:32:50.000Z"}"#;
+        let url = serve(vec![TRADE, DISCONNECT, "{}", TRADE]).await;
+
+        let prices = Client::new(&url)
+            .stream_normalized_with(
+                vec![StreamNormalizedRequestOptions {
+                    exchange: Exchange::Bitmex,
+                    symbols: None,
+                    data_types: vec![DataType::Trade],
+                    with_disconnect_messages: Some(true),
+                    timeout_interval_ms: None,
+                }],
+                |msg| match msg {
+                    MessageRef::Trade(trade) => Some(trade.price),
+                    _ => None,
+                },
+            )
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(
+            prices[..],
+            [Ok(7996.0), Err(Error::Deserialization(_)), Ok(7996.0)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_normalized_raw() {
+        const TRADE: &str = r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#;
+        let url = serve(vec![TRADE, "{}"]).await;
+
+        let messages = Client::new(&url)
+            .replay_normalized_raw(vec![ReplayNormalizedRequestOptions {
+                exchange: Exchange::Bitmex,
+                symbols: None,
+                from: Utc.with_ymd_and_hms(2019, 10, 23, 0, 0, 0).unwrap(),
+                to: Utc.with_ymd_and_hms(2019, 10, 24, 0, 0, 0).unwrap(),
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+            }])
+            .await
+            .unwrap()
+            .map(|msg| msg.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            messages,
+            vec![RawMessage::new(TRADE), RawMessage::new("{}")]
+        );
+        assert!(matches!(messages[0].deserialize(), Ok(Message::Trade(_))));
+    }
+
+    #[tokio::test]
+    async fn test_normalized_http() {
+        const TRADE: &str = r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#;
+        let options = || {
+            vec![ReplayNormalizedRequestOptions {
+                exchange: Exchange::Bitmex,
+                symbols: None,
+                from: Utc.with_ymd_and_hms(2019, 10, 23, 0, 0, 0).unwrap(),
+                to: Utc.with_ymd_and_hms(2019, 10, 24, 0, 0, 0).unwrap(),
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+            }]
+        };
+
+        let server = serve_reply(
+            Reply::ok(format!("{}\n{{}}\n{}\n", TRADE, TRADE))
+                .header("content-type", "application/x-json-stream"),
+        )
+        .await;
+        let messages = Client::builder("ws://localhost:8001")
+            .http_url(&server.url)
+            .build()
+            .replay_normalized_http(options())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(
+            messages[..],
+            [
+                Ok(Message::Trade(_)),
+                Err(Error::Deserialization(_)),
+                Ok(Message::Trade(_))
+            ]
+        ));
+        assert!(server.requests()[0]
+            .line()
+            .starts_with("GET /replay-normalized?options="));
+
+        let server = serve_reply(Reply::new(
+            "400 Bad Request",
+            r#"{"code":100,"message":"Invalid data type"}"#,
+        ))
+        .await;
+        let result = Client::builder("ws://localhost:8001")
+            .http_url(&server.url)
+            .build()
+            .replay_normalized_http(options())
+            .await;
+        assert!(matches!(result, Err(Error::ServerError { code: 100, .. })));
+
+        assert_eq!(
+            Client::new("ws://localhost:8001").http_url().unwrap(),
+            "http://localhost:8000"
+        );
+        assert_eq!(
+            Client::new("wss://machine.internal/").http_url().unwrap(),
+            "https://machine.internal"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_normalized_http_proxy() {
+        let proxy = serve_reply(Reply::ok("{}\n")).await;
+        let messages = Client::builder("ws://localhost:8001")
+            .http_url("http://machine.invalid:8000")
+            .proxy(Proxy::new(&proxy.url).unwrap().basic_auth("user", "secret"))
+            .build()
+            .replay_normalized_http(vec![ReplayNormalizedRequestOptions {
+                exchange: Exchange::Bitmex,
+                symbols: None,
+                from: Utc.with_ymd_and_hms(2019, 10, 23, 0, 0, 0).unwrap(),
+                to: Utc.with_ymd_and_hms(2019, 10, 24, 0, 0, 0).unwrap(),
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+            }])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(messages.len(), 1);
+
+        // The request was sent to the proxy, with the full URL of the machine server.
+        let request = &proxy.requests()[0];
+        assert!(request
+            .path()
+            .starts_with("http://machine.invalid:8000/replay-normalized?options="));
+        assert_eq!(
+            request.header("proxy-authorization"),
+            Some("Basic dXNlcjpzZWNyZXQ=")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prefilter() {
+        const XBTUSD: &str = r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#;
+        const ETHUSD: &str = r#"{"type":"trade","symbol":"ETHUSD","exchange":"bitmex","id":"b","price":180,"amount":10,"side":"buy","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#;
+        let url = serve(vec![XBTUSD, ETHUSD, XBTUSD]).await;
+
+        let messages = Client::builder(&url)
+            .prefilter(Prefilter::new().symbols(["XBTUSD"]))
+            .build()
+            .stream_normalized(vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bitmex,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+                timeout_interval_ms: None,
+            }])
+            .await
+            .unwrap()
+            .map(|msg| msg.unwrap().symbol().unwrap().to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(messages, vec!["XBTUSD", "XBTUSD"]);
+    }
+
+    #[tokio::test]
+    async fn test_batched() {
+        const DISCONNECT: &str = r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#;
+        let options = || {
+            vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: Some(true),
+                timeout_interval_ms: None,
+            }]
+        };
+        let sizes = |batches: Vec<Result<Vec<Message>>>| {
+            batches
+                .into_iter()
+                .map(|batch| batch.map(|batch| batch.len()).map_err(|_| ()))
+                .collect::<Vec<_>>()
+        };
+
+        let url = serve(vec![DISCONNECT, DISCONNECT, DISCONNECT, "{}", DISCONNECT]).await;
+        let batches = Client::new(&url)
+            .stream_normalized(options())
+            .await
+            .unwrap()
+            .batched(2, Duration::from_secs(60))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(sizes(batches), vec![Ok(2), Ok(1), Err(()), Ok(1)]);
+
+        // A partial batch is yielded once its delay passed.
+        let url = serve(vec![DISCONNECT, "!hold"]).await;
+        let batches = Client::new(&url)
+            .stream_normalized(options())
+            .await
+            .unwrap()
+            .batched(10, Duration::from_millis(50))
+            .take(1)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(sizes(batches), vec![Ok(1)]);
+    }
+
+    #[tokio::test]
+    async fn test_take_messages() {
+        const TRADE: &str = r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#;
+        let options = || {
+            vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bitmex,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+                timeout_interval_ms: None,
+            }]
+        };
+
+        let url = serve(vec![TRADE, "{}", TRADE, TRADE, "!hold"]).await;
+        let messages = Client::new(&url)
+            .stream_normalized(options())
+            .await
+            .unwrap()
+            .take_messages(2)
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(
+            messages[..],
+            [
+                Ok(Message::Trade(_)),
+                Err(Error::Deserialization(_)),
+                Ok(Message::Trade(_))
+            ]
+        ));
+
+        let url = serve(vec![TRADE, "!hold"]).await;
+        let messages = Client::new(&url)
+            .stream_normalized(options())
+            .await
+            .unwrap();
+        let token = messages.cancellation_token();
+        let messages = messages
+            .take_until(Utc::now() + chrono::Duration::milliseconds(100))
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(messages[..], [Ok(Message::Trade(_))]));
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_stale_timeout() {
+        const DISCONNECT: &str = r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#;
+        let options = || {
+            vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: Some(true),
+                timeout_interval_ms: None,
+            }]
+        };
+
+        // The stale stream is left open.
+        let url = serve(vec![DISCONNECT, "!hold"]).await;
+        let messages = Client::builder(&url)
+            .stale_timeout(Duration::from_millis(50))
+            .build()
+            .stream_normalized(options())
+            .await
+            .unwrap()
+            .take(3)
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(
+            messages[..],
+            [
+                Ok(Message::Disconnect(_)),
+                Err(Error::Stale { .. }),
+                Err(Error::Stale { .. }),
+            ]
+        ));
+
+        // The stale connection is replaced.
+        let (url, _) = serve_connections(vec![vec![DISCONNECT, "!hold"], vec![DISCONNECT]]).await;
+        let messages = Client::builder(&url)
+            .stale_timeout(Duration::from_millis(50))
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(1))
+            .build()
+            .stream_normalized(options())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(
+            messages[..],
+            [
+                Ok(Message::Disconnect(_)),
+                Ok(Message::Disconnect(_)),
+                Err(Error::ConnectFailed(_)),
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pause() {
+        const DISCONNECT: &str = r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#;
+        let url = serve(vec![DISCONNECT, "!hold"]).await;
+        let mut messages = Client::builder(&url)
+            .read_timeout(Duration::from_millis(100))
+            .build()
+            .stream_normalized(vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: Some(true),
+                timeout_interval_ms: None,
+            }])
+            .await
+            .unwrap();
+        let pause = messages.pause_handle();
+        assert!(matches!(
+            messages.next().await,
+            Some(Ok(Message::Disconnect(_)))
+        ));
+
+        // The read timeout doesn't run while paused, and restarts on resume.
+        pause.pause();
+        assert!(pause.is_paused());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        pause.resume();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), messages.next())
+                .await
+                .is_err()
+        );
+        assert!(matches!(
+            messages.next().await,
+            Some(Err(Error::Timeout {
+                kind: TimeoutKind::Read,
+                ..
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        const DISCONNECT: &str = r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#;
+        let (url, _) = serve_connections(vec![vec![DISCONNECT], vec!["{}", DISCONNECT]]).await;
+
+        let metrics = Arc::new(crate::machine::metrics::StreamMetrics::new());
+        let client = Client::builder(&url)
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(2))
+            .metrics(metrics.clone())
+            .build();
+        client
+            .stream_normalized(vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: Some(true),
+                timeout_interval_ms: None,
+            }])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(metrics.connections(), 2);
+        assert_eq!(metrics.messages(), 3);
+        assert_eq!(metrics.bytes(), 2 * DISCONNECT.len() as u64 + 2);
+        assert_eq!(metrics.deserialization_errors(), 1);
+        assert_eq!(metrics.reconnects(), 3);
+        assert!(metrics.lag() > chrono::Duration::days(365));
+    }
+
+    #[tokio::test]
+    async fn test_track_stats() {
+        const TRADE: &str = r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#;
+        let url = serve(vec![TRADE, TRADE]).await;
+        let client = Client::builder(&url).track_stats(true).build();
+        assert!(client.stats().is_empty());
+        client
+            .stream_normalized(vec![StreamNormalizedRequestOptions {
+                exchange: Exchange::Bitmex,
+                symbols: None,
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+                timeout_interval_ms: None,
+            }])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        let stats = client.stats();
+        let trades = &stats[&SubscriptionKey {
+            exchange: Exchange::Bitmex,
+            symbol: "XBTUSD".into(),
+            kind: "trade",
+        }];
+        assert_eq!(trades.messages, 2);
+        assert_eq!(trades.gaps, 0);
+    }
+
+    #[tokio::test]
+    async fn test_resume_replay() {
+        const FIRST: &str = r#"{"type":"trade","symbol":"BTCUSDT","exchange":"bybit","id":"1","price":1.0,"amount":1.0,"side":"buy","timestamp":"2022-10-01T00:00:00.000Z","localTimestamp":"2022-10-01T00:00:00.000Z"}"#;
+        const SECOND: &str = r#"{"type":"trade","symbol":"BTCUSDT","exchange":"bybit","id":"2","price":1.0,"amount":1.0,"side":"buy","timestamp":"2022-10-01T00:00:01.000Z","localTimestamp":"2022-10-01T00:00:01.000Z"}"#;
+        const THIRD: &str = r#"{"type":"trade","symbol":"BTCUSDT","exchange":"bybit","id":"3","price":1.0,"amount":1.0,"side":"buy","timestamp":"2022-10-01T00:00:01.000Z","localTimestamp":"2022-10-01T00:00:01.000Z"}"#;
+        let (url, requests) = serve_connections(vec![
+            vec![FIRST, SECOND, "!drop"],
+            vec![SECOND, THIRD],
+            vec!["unused"],
+        ])
+        .await;
+
+        let client = Client::builder(&url)
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)))
+            .build();
+        let messages = client
+            .replay_normalized(vec![ReplayNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
+                to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+            }])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        let ids = messages
+            .into_iter()
+            .map(|msg| match msg {
+                Ok(Message::Trade(trade)) => trade.id.unwrap(),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2", "3"]);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let resumed = urlencoding::decode(&requests[1]).unwrap();
+        assert!(
+            resumed.contains(r#""from":"2022-10-01T00:00:01Z""#),
+            "{}",
+            resumed
+        );
+    }
+
+    #[test]
+    fn test_from_payload() {
+        for line in include_str!("../../fixtures/golden/corpus.ndjson").lines() {
+            let expected = serde_json::from_str::<Message>(line).unwrap();
+            let message = <Message as FromPayload>::from_payload(Bytes::from(line)).unwrap();
+            assert_eq!(
+                serde_json::to_value(message).unwrap(),
+                serde_json::to_value(expected).unwrap(),
+                "{}",
+                line
+            );
+        }
+
+        let error = r#"{"code":100,"message":"Invalid data type: 'trades'"}"#;
+        assert!(matches!(
+            <Message as FromPayload>::from_payload(Bytes::from(error)),
+            Err(Error::ServerError { code: 100, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replay_out_of_order() {
+        const FIRST: &str = r#"{"type":"trade","symbol":"BTCUSDT","exchange":"bybit","id":"1","price":1.0,"amount":1.0,"side":"buy","timestamp":"2022-10-01T00:00:01.000Z","localTimestamp":"2022-10-01T00:00:01.000Z"}"#;
+        const SECOND: &str = r#"{"type":"trade","symbol":"BTCUSDT","exchange":"bybit","id":"2","price":1.0,"amount":1.0,"side":"buy","timestamp":"2022-10-01T00:00:00.000Z","localTimestamp":"2022-10-01T00:00:00.000Z"}"#;
+        const THIRD: &str = r#"{"type":"trade","symbol":"BTCUSDT","exchange":"bybit","id":"3","price":1.0,"amount":1.0,"side":"buy","timestamp":"2022-10-01T00:00:01.000Z","localTimestamp":"2022-10-01T00:00:01.000Z"}"#;
+        let (url, requests) = serve_connections(vec![vec![FIRST, SECOND, THIRD]]).await;
+
+        let client = Client::builder(&url)
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)))
+            .build();
+        let messages = client
+            .replay_normalized(vec![ReplayNormalizedRequestOptions {
+                exchange: Exchange::Bybit,
+                symbols: None,
+                from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
+                to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
+                data_types: vec![DataType::Trade],
+                with_disconnect_messages: None,
+            }])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        // Nothing is skipped as long as the connection doesn't drop.
+        let ids = messages
+            .into_iter()
+            .map(|msg| match msg {
+                Ok(Message::Trade(trade)) => trade.id.unwrap(),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2", "3"]);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
     #[tokio::test]
     #[traced_test]
     async fn test_replay_normalized_trade() {
@@ -236,7 +3308,7 @@ mod tests {
                 symbols: Some(vec!["BTCUSDT".to_string()]),
                 from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
                 to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
-                data_types: vec!["trade".to_string()],
+                data_types: vec![DataType::Trade],
                 with_disconnect_messages: None,
             }])
             .await
@@ -269,7 +3341,7 @@ mod tests {
                 symbols: Some(vec!["BTCUSDT".to_string()]),
                 from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
                 to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
-                data_types: vec!["book_change".to_string()],
+                data_types: vec![DataType::BookChange],
                 with_disconnect_messages: None,
             }])
             .await
@@ -302,7 +3374,7 @@ mod tests {
                 symbols: Some(vec!["BTCUSDT".to_string()]),
                 from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
                 to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
-                data_types: vec!["derivative_ticker".to_string()],
+                data_types: vec![DataType::DerivativeTicker],
                 with_disconnect_messages: None,
             }])
             .await
@@ -335,7 +3407,11 @@ mod tests {
                 symbols: Some(vec!["BTCUSDT".to_string()]),
                 from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
                 to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
-                data_types: vec!["book_snapshot_2_50ms".to_string()],
+                data_types: vec![DataType::BookSnapshot {
+                    depth: 2,
+                    interval: 50,
+                    unit: IntervalUnit::Milliseconds,
+                }],
                 with_disconnect_messages: None,
             }])
             .await
@@ -368,7 +3444,10 @@ mod tests {
                 symbols: Some(vec!["BTCUSDT".to_string()]),
                 from: Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap(),
                 to: Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap(),
-                data_types: vec!["trade_bar_60m".to_string()],
+                data_types: vec![DataType::TradeBar {
+                    interval: 60,
+                    unit: IntervalUnit::Minutes,
+                }],
                 with_disconnect_messages: None,
             }])
             .await
@@ -399,7 +3478,7 @@ mod tests {
             .stream_normalized(vec![StreamNormalizedRequestOptions {
                 exchange: Exchange::Binance,
                 symbols: Some(vec!["BTCUSDT".to_string()]),
-                data_types: vec!["trade".to_string()],
+                data_types: vec![DataType::Trade],
                 with_disconnect_messages: None,
                 timeout_interval_ms: None,
             }])
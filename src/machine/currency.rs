@@ -0,0 +1,373 @@
+//! Currency handling for analytics consolidating multiple instruments, eg. treating stablecoin
+//! quoted markets as USD markets.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    quotes::{InstrumentKey, PriceKind, QuoteTracker},
+    Message,
+};
+
+/// Quote currencies recognized by [`split_symbol`], longest first so that eg. `FDUSD` is matched
+/// before `USD`.
+const QUOTE_CURRENCIES: &[&str] = &[
+    "FDUSD", "USDT", "USDC", "BUSD", "TUSD", "USDP", "DAI", "USD", "EUR", "GBP", "JPY", "KRW",
+    "TRY", "BTC", "ETH", "BNB",
+];
+
+/// The base and quote currency of a spot instrument.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CurrencyPair {
+    /// The currency being priced, eg. BTC.
+    pub base: String,
+
+    /// The currency the price is expressed in, eg. USDT.
+    pub quote: String,
+}
+
+/// Splits a symbol such as `BTCUSDT`, `btc-usd` or `ETH/BTC` into its currencies.
+///
+/// Symbols without a separator are split on a known quote currency suffix, `None` is returned when
+/// no known quote currency is found. The contract markers of derivative symbols, `PERPETUAL`,
+/// `PERP`, `SWAP` and expiry dates, are ignored along with what follows them, eg. the strike of an
+/// option: `BTC-USDT-SWAP` and `BTCUSDT_231229` are BTC/USDT, while `BTC-PERPETUAL` and
+/// `BTC-29DEC23-40000-C` have no quote currency.
+pub fn split_symbol(symbol: &str) -> Option<CurrencyPair> {
+    let symbol = symbol.to_uppercase();
+    let parts = symbol
+        .split(['-', '/', '_'])
+        .take_while(|part| !is_contract_marker(part))
+        .collect::<Vec<_>>();
+
+    match parts[..] {
+        [symbol] => QUOTE_CURRENCIES.iter().find_map(|quote| {
+            let base = symbol.strip_suffix(quote)?;
+            (!base.is_empty()).then(|| CurrencyPair {
+                base: base.to_string(),
+                quote: quote.to_string(),
+            })
+        }),
+        [base, quote, ..] => (!base.is_empty() && !quote.is_empty()).then(|| CurrencyPair {
+            base: base.to_string(),
+            quote: quote.to_string(),
+        }),
+        [] => None,
+    }
+}
+
+/// Returns whether a part of an uppercase symbol marks a derivative contract: a perpetual or swap,
+/// or an expiry date such as `231229`, `20231229` or `29DEC23`.
+fn is_contract_marker(part: &str) -> bool {
+    if matches!(part, "PERPETUAL" | "PERP" | "SWAP") {
+        return true;
+    }
+    if part.len() >= 4 && part.bytes().all(|b| b.is_ascii_digit()) {
+        return true;
+    }
+
+    // A day of one or two digits, a month of three letters and a year of two digits.
+    let day = part.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &part.as_bytes()[day..];
+    (1..=2).contains(&day)
+        && rest.len() == 5
+        && rest[..3].iter().all(u8::is_ascii_alphabetic)
+        && rest[3..].iter().all(u8::is_ascii_digit)
+}
+
+/// An instrument's currencies after normalization by a [`QuoteNormalizer`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NormalizedPair {
+    /// The normalized currencies, eg. BTC/USD for BTCUSDT.
+    pub pair: CurrencyPair,
+
+    /// The factor converting prices of the instrument into the normalized quote currency.
+    pub factor: f64,
+}
+
+/// Treats configured quote currencies (usually stablecoins) as equivalent to another currency
+/// (usually USD), optionally applying a conversion factor.
+///
+/// ```ignore
+/// let normalizer = QuoteNormalizer::stablecoins()
+///     .pair_factor(InstrumentKey::new(Exchange::Binance, "btcusdt"), 0.9995);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct QuoteNormalizer {
+    equivalents: HashMap<String, (String, f64)>,
+    pair_factors: HashMap<InstrumentKey, f64>,
+}
+
+impl QuoteNormalizer {
+    /// Creates a new instance of [`QuoteNormalizer`] without any equivalence.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a [`QuoteNormalizer`] treating USDT, USDC and BUSD as USD at par.
+    pub fn stablecoins() -> Self {
+        Self::new()
+            .equivalent("USDT", "USD", 1.0)
+            .equivalent("USDC", "USD", 1.0)
+            .equivalent("BUSD", "USD", 1.0)
+    }
+
+    /// Treats `currency` as `target`, converting prices with `factor`.
+    pub fn equivalent(mut self, currency: &str, target: &str, factor: f64) -> Self {
+        self.equivalents
+            .insert(currency.to_uppercase(), (target.to_uppercase(), factor));
+        self
+    }
+
+    /// Overrides the conversion factor of a single instrument, eg. with the observed USDT/USD
+    /// rate on that exchange.
+    pub fn pair_factor(mut self, instrument: InstrumentKey, factor: f64) -> Self {
+        self.pair_factors.insert(instrument, factor);
+        self
+    }
+
+    /// Returns the currency `currency` is treated as and the factor converting between them.
+    pub fn equivalent_of(&self, currency: &str) -> Option<(&str, f64)> {
+        self.equivalents
+            .get(&currency.to_uppercase())
+            .map(|(target, factor)| (target.as_str(), *factor))
+    }
+
+    /// Returns the normalized currencies of an instrument, `None` if its symbol cannot be split.
+    pub fn normalize(&self, instrument: &InstrumentKey) -> Option<NormalizedPair> {
+        let CurrencyPair { base, quote } = split_symbol(&instrument.symbol)?;
+
+        let (quote, factor) = match self.equivalents.get(&quote) {
+            Some((target, factor)) => (target.clone(), *factor),
+            None => (quote, 1.0),
+        };
+        let factor = self.pair_factors.get(instrument).copied().unwrap_or(factor);
+
+        Some(NormalizedPair {
+            pair: CurrencyPair { base, quote },
+            factor,
+        })
+    }
+
+    /// Returns the factor converting prices of the instrument into its normalized quote currency,
+    /// `1.0` for instruments that are not normalized.
+    pub fn factor(&self, instrument: &InstrumentKey) -> f64 {
+        if let Some(factor) = self.pair_factors.get(instrument) {
+            return *factor;
+        }
+
+        self.normalize(instrument)
+            .map_or(1.0, |normalized| normalized.factor)
+    }
+
+    /// Returns `true` if both instruments trade the same normalized pair, eg. BTCUSDT on one
+    /// exchange and BTC-USD on another.
+    pub fn same_pair(&self, a: &InstrumentKey, b: &InstrumentKey) -> bool {
+        match (self.normalize(a), self.normalize(b)) {
+            (Some(a), Some(b)) => a.pair == b.pair,
+            _ => false,
+        }
+    }
+}
+
+/// Converts prices and notionals of derived analytics into a single reference currency, using the
+/// prices of configured index instruments.
+///
+/// ```ignore
+/// let mut converter = CurrencyConverter::new("USD")
+///     .index("BTC", InstrumentKey::new(Exchange::Deribit, "BTC-PERPETUAL"))
+///     .index("ETH", InstrumentKey::new(Exchange::Deribit, "ETH-PERPETUAL"));
+///
+/// converter.update(&message);
+/// let notional = converter.convert(1.5, "ETH");
+/// ```
+#[derive(Debug, Clone)]
+pub struct CurrencyConverter {
+    reference: String,
+    indexes: HashMap<String, (InstrumentKey, PriceKind)>,
+    normalizer: QuoteNormalizer,
+    max_staleness: Option<Duration>,
+    quotes: QuoteTracker,
+    now: Option<DateTime<Utc>>,
+}
+
+impl CurrencyConverter {
+    /// Creates a new instance of [`CurrencyConverter`] converting into `reference`.
+    pub fn new(reference: &str) -> Self {
+        Self {
+            reference: reference.to_uppercase(),
+            indexes: HashMap::new(),
+            normalizer: QuoteNormalizer::new(),
+            max_staleness: None,
+            quotes: QuoteTracker::new(),
+            now: None,
+        }
+    }
+
+    /// Uses the index price of `instrument` (usually from its `derivative_ticker`) as the rate of
+    /// `currency` in the reference currency.
+    pub fn index(self, currency: &str, instrument: InstrumentKey) -> Self {
+        self.index_with(currency, instrument, PriceKind::Index)
+    }
+
+    /// Uses the given price of `instrument` as the rate of `currency` in the reference currency,
+    /// eg. the mid price of a spot market.
+    pub fn index_with(
+        mut self,
+        currency: &str,
+        instrument: InstrumentKey,
+        price: PriceKind,
+    ) -> Self {
+        self.indexes
+            .insert(currency.to_uppercase(), (instrument, price));
+        self
+    }
+
+    /// Resolves currencies without an index through their equivalent, eg. USDT as USD.
+    pub fn normalizer(mut self, normalizer: QuoteNormalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Sets the maximum age of an index price, older prices make conversions unavailable.
+    pub fn max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+
+    /// Returns the reference currency.
+    pub fn reference(&self) -> &str {
+        &self.reference
+    }
+
+    /// Updates the index prices with a message.
+    pub fn update(&mut self, message: &Message) {
+        self.now = message.local_timestamp().or(self.now);
+        self.quotes.update(message);
+    }
+
+    /// Returns the value of one unit of `currency` in the reference currency.
+    pub fn rate(&self, currency: &str) -> Option<f64> {
+        let currency = currency.to_uppercase();
+        if currency == self.reference {
+            return Some(1.0);
+        }
+
+        if self.indexes.contains_key(&currency) {
+            return self.index_rate(&currency);
+        }
+
+        let (equivalent, factor) = self.normalizer.equivalent_of(&currency)?;
+        if equivalent == self.reference {
+            return Some(factor);
+        }
+        Some(self.index_rate(equivalent)? * factor)
+    }
+
+    fn index_rate(&self, currency: &str) -> Option<f64> {
+        let (instrument, price) = self.indexes.get(currency)?;
+        let quote = self.quotes.get(instrument)?;
+        if let (Some(max_staleness), Some(now)) = (self.max_staleness, self.now) {
+            if quote.is_stale(now, max_staleness) {
+                return None;
+            }
+        }
+        quote.price(*price)
+    }
+
+    /// Converts an amount of `currency` into the reference currency.
+    pub fn convert(&self, amount: f64, currency: &str) -> Option<f64> {
+        Some(amount * self.rate(currency)?)
+    }
+
+    /// Converts a price (or notional) of an instrument, expressed in its quote currency, into the
+    /// reference currency.
+    pub fn convert_price(&self, instrument: &InstrumentKey, price: f64) -> Option<f64> {
+        self.convert(price, &split_symbol(&instrument.symbol)?.quote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{machine::DerivativeTicker, Exchange};
+
+    #[test]
+    fn test_split_symbol() {
+        let pair = |base: &str, quote: &str| {
+            Some(CurrencyPair {
+                base: base.to_string(),
+                quote: quote.to_string(),
+            })
+        };
+
+        assert_eq!(split_symbol("btcusdt"), pair("BTC", "USDT"));
+        assert_eq!(split_symbol("BTC-USD"), pair("BTC", "USD"));
+        assert_eq!(split_symbol("ETH/BTC"), pair("ETH", "BTC"));
+        assert_eq!(split_symbol("USDT"), None);
+        assert_eq!(split_symbol("btcfdusd"), pair("BTC", "FDUSD"));
+
+        // The contract markers aren't currencies.
+        assert_eq!(split_symbol("BTC-PERPETUAL"), None);
+        assert_eq!(split_symbol("BTC-USDT-SWAP"), pair("BTC", "USDT"));
+        assert_eq!(split_symbol("ETH-USD-231229"), pair("ETH", "USD"));
+        assert_eq!(split_symbol("BTCUSDT_231229"), pair("BTC", "USDT"));
+        assert_eq!(split_symbol("ETHUSDT-PERP"), pair("ETH", "USDT"));
+        assert_eq!(split_symbol("BTC-29DEC23"), None);
+        assert_eq!(split_symbol("BTC-29DEC23-40000-C"), None);
+        assert_eq!(split_symbol("SOL_USDC-5JAN24"), pair("SOL", "USDC"));
+        assert_eq!(split_symbol("-USD"), None);
+
+        assert!(QUOTE_CURRENCIES
+            .windows(2)
+            .all(|pair| pair[0].len() >= pair[1].len()));
+    }
+
+    #[test]
+    fn test_stablecoins() {
+        let binance = InstrumentKey::new(Exchange::Binance, "btcusdt");
+        let coinbase = InstrumentKey::new(Exchange::Coinbase, "BTC-USD");
+        let normalizer = QuoteNormalizer::stablecoins().pair_factor(binance.clone(), 0.999);
+
+        assert!(normalizer.same_pair(&binance, &coinbase));
+        assert_eq!(normalizer.factor(&binance), 0.999);
+        assert_eq!(normalizer.factor(&coinbase), 1.0);
+        assert!(!QuoteNormalizer::new().same_pair(&binance, &coinbase));
+    }
+
+    #[test]
+    fn test_converter() {
+        let timestamp = Utc.timestamp_millis_opt(0).unwrap();
+        let mut converter = CurrencyConverter::new("usd")
+            .index(
+                "BTC",
+                InstrumentKey::new(Exchange::Deribit, "BTC-PERPETUAL"),
+            )
+            .normalizer(QuoteNormalizer::stablecoins().equivalent("USDD", "BTC", 0.5));
+
+        assert_eq!(converter.convert(2.0, "BTC"), None);
+
+        converter.update(&Message::DerivativeTicker(Box::new(DerivativeTicker {
+            symbol: "BTC-PERPETUAL".into(),
+            exchange: Exchange::Deribit,
+            last_price: Some(20_010.0),
+            open_interest: None,
+            funding_rate: None,
+            index_price: Some(20_000.0),
+            mark_price: Some(20_005.0),
+            timestamp,
+            local_timestamp: timestamp,
+        })));
+
+        let ethbtc = InstrumentKey::new(Exchange::Binance, "ethbtc");
+        assert_eq!(converter.convert(2.0, "BTC"), Some(40_000.0));
+        assert_eq!(converter.convert_price(&ethbtc, 0.05), Some(1_000.0));
+        assert_eq!(converter.convert(10.0, "USDT"), Some(10.0));
+        assert_eq!(converter.convert(1.0, "USDD"), Some(10_000.0));
+        assert_eq!(converter.convert(1.0, "ETH"), None);
+    }
+}
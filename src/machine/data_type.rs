@@ -0,0 +1,241 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A [normalized data type](https://docs.tardis.dev/api/tardis-machine#normalized-data-types)
+/// that can be requested from Tardis Machine Server.
+///
+/// Data types are formatted and parsed the way the server names them, eg.
+/// `DataType::TradeBar { interval: 60, unit: IntervalUnit::Minutes }` is `trade_bar_60m`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DataType {
+    /// Individual trades, `trade`.
+    Trade,
+
+    /// Incremental order book updates, `book_change`.
+    BookChange,
+
+    /// Derivative instrument tickers, `derivative_ticker`.
+    DerivativeTicker,
+
+    /// Liquidations of derivative positions, `liquidation`.
+    Liquidation,
+
+    /// Best bid and ask changes, `book_ticker`.
+    BookTicker,
+
+    /// Option instrument summaries, `option_summary`.
+    OptionSummary,
+
+    /// Trades aggregated into bars, eg. `trade_bar_10s` or `trade_bar_100ticks`.
+    TradeBar {
+        /// Size of the bars, in `unit`
+        interval: u64,
+
+        /// Unit of `interval`
+        unit: IntervalUnit,
+    },
+
+    /// Order book snapshots of the top `depth` levels, eg. `book_snapshot_10_100ms`. An interval
+    /// of zero produces a snapshot on every change of the top levels.
+    BookSnapshot {
+        /// Number of levels of each side
+        depth: u32,
+
+        /// Time between snapshots, in `unit`
+        interval: u64,
+
+        /// Unit of `interval`, one of the time units
+        unit: IntervalUnit,
+    },
+}
+
+/// The unit of the interval of [`DataType::TradeBar`] and [`DataType::BookSnapshot`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IntervalUnit {
+    /// Milliseconds, `ms`.
+    Milliseconds,
+
+    /// Seconds, `s`.
+    Seconds,
+
+    /// Minutes, `m`.
+    Minutes,
+
+    /// Number of trades, `ticks`. Only valid for trade bars.
+    Ticks,
+
+    /// Traded volume, `vol`. Only valid for trade bars.
+    Volume,
+}
+
+impl IntervalUnit {
+    /// Returns the suffix of the unit used in data type names.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntervalUnit::Milliseconds => "ms",
+            IntervalUnit::Seconds => "s",
+            IntervalUnit::Minutes => "m",
+            IntervalUnit::Ticks => "ticks",
+            IntervalUnit::Volume => "vol",
+        }
+    }
+
+    /// Returns `true` for the units measuring time.
+    pub fn is_time(&self) -> bool {
+        matches!(
+            self,
+            IntervalUnit::Milliseconds | IntervalUnit::Seconds | IntervalUnit::Minutes
+        )
+    }
+}
+
+impl fmt::Display for IntervalUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Trade => f.write_str("trade"),
+            DataType::BookChange => f.write_str("book_change"),
+            DataType::DerivativeTicker => f.write_str("derivative_ticker"),
+            DataType::Liquidation => f.write_str("liquidation"),
+            DataType::BookTicker => f.write_str("book_ticker"),
+            DataType::OptionSummary => f.write_str("option_summary"),
+            DataType::TradeBar { interval, unit } => write!(f, "trade_bar_{}{}", interval, unit),
+            DataType::BookSnapshot {
+                depth,
+                interval,
+                unit,
+            } => write!(f, "book_snapshot_{}_{}{}", depth, interval, unit),
+        }
+    }
+}
+
+/// The error returned when parsing an unknown or malformed [`DataType`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid data type: {0}")]
+pub struct ParseDataTypeError(String);
+
+impl FromStr for DataType {
+    type Err = ParseDataTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseDataTypeError(s.to_string());
+
+        match s {
+            "trade" => return Ok(DataType::Trade),
+            "book_change" => return Ok(DataType::BookChange),
+            "derivative_ticker" => return Ok(DataType::DerivativeTicker),
+            "liquidation" => return Ok(DataType::Liquidation),
+            "book_ticker" => return Ok(DataType::BookTicker),
+            "option_summary" => return Ok(DataType::OptionSummary),
+            _ => {}
+        }
+
+        if let Some(interval) = s.strip_prefix("trade_bar_") {
+            let (interval, unit) = parse_interval(interval).ok_or_else(invalid)?;
+            return Ok(DataType::TradeBar { interval, unit });
+        }
+
+        if let Some(snapshot) = s.strip_prefix("book_snapshot_") {
+            let (depth, interval) = snapshot.split_once('_').ok_or_else(invalid)?;
+            let depth = depth.parse().map_err(|_| invalid())?;
+            let (interval, unit) = parse_interval(interval)
+                .filter(|(_, unit)| unit.is_time())
+                .ok_or_else(invalid)?;
+            return Ok(DataType::BookSnapshot {
+                depth,
+                interval,
+                unit,
+            });
+        }
+
+        Err(invalid())
+    }
+}
+
+/// Parses an interval such as `100ms` into its value and unit.
+fn parse_interval(s: &str) -> Option<(u64, IntervalUnit)> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (interval, unit) = s.split_at(split);
+    let unit = match unit {
+        "ms" => IntervalUnit::Milliseconds,
+        "s" => IntervalUnit::Seconds,
+        "m" => IntervalUnit::Minutes,
+        "ticks" => IntervalUnit::Ticks,
+        "vol" => IntervalUnit::Volume,
+        _ => return None,
+    };
+    Some((interval.parse().ok()?, unit))
+}
+
+impl Serialize for DataType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DataType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for name in [
+            "trade",
+            "book_change",
+            "derivative_ticker",
+            "liquidation",
+            "book_ticker",
+            "option_summary",
+            "trade_bar_60m",
+            "trade_bar_100ticks",
+            "trade_bar_5000vol",
+            "book_snapshot_2_50ms",
+            "book_snapshot_25_0ms",
+        ] {
+            let data_type = name.parse::<DataType>().unwrap();
+            assert_eq!(data_type.to_string(), name);
+            assert_eq!(
+                serde_json::to_string(&data_type).unwrap(),
+                format!("\"{}\"", name)
+            );
+        }
+
+        assert_eq!(
+            "book_snapshot_10_1s".parse(),
+            Ok(DataType::BookSnapshot {
+                depth: 10,
+                interval: 1,
+                unit: IntervalUnit::Seconds
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        for name in [
+            "trades",
+            "trade_bar_",
+            "trade_bar_m",
+            "trade_bar_10h",
+            "book_snapshot_10",
+            "book_snapshot_10_100ticks",
+        ] {
+            assert!(name.parse::<DataType>().is_err(), "{}", name);
+        }
+    }
+}
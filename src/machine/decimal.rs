@@ -0,0 +1,365 @@
+//! Variants of the normalized messages keeping their prices and amounts as [`Decimal`]s, for
+//! consumers such as order management systems that can't afford the rounding of `f64`.
+//!
+//! Decode them from a [`RawMessage`](super::RawMessage) with
+//! [`RawMessage::deserialize`](super::RawMessage::deserialize). JSON numbers are read as written
+//! by the server as long as they have up to 17 significant digits, numbers given as strings are
+//! read exactly.
+
+use std::marker::PhantomData;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::{
+    borrowed::{KindVisitor, OfKind},
+    models::message_of_kind,
+    Message, Symbol, TradeSide,
+};
+use crate::Exchange;
+
+/// A normalized message with its prices and amounts as [`Decimal`]s.
+///
+/// Only the types carrying prices and amounts traded on have such a variant, the others are
+/// decoded into a [`Message`].
+#[derive(Debug, Clone)]
+pub enum MessageDecimal {
+    /// A [`Trade`](super::Trade).
+    Trade(TradeDecimal),
+
+    /// A [`BookChange`](super::BookChange).
+    BookChange(Box<BookChangeDecimal>),
+
+    /// A [`BookSnapshot`](super::BookSnapshot).
+    BookSnapshot(Box<BookSnapshotDecimal>),
+
+    /// A [`TradeBar`](super::TradeBar).
+    TradeBar(Box<TradeBarDecimal>),
+
+    /// A [`BookTicker`](super::BookTicker).
+    BookTicker(Box<BookTickerDecimal>),
+
+    /// Any other message.
+    Other(Message),
+}
+
+impl MessageDecimal {
+    /// Returns the `type` of the message, eg. `trade`.
+    pub fn kind(&self) -> &str {
+        match self {
+            MessageDecimal::Trade(_) => "trade",
+            MessageDecimal::BookChange(_) => "book_change",
+            MessageDecimal::BookSnapshot(_) => "book_snapshot",
+            MessageDecimal::TradeBar(_) => "trade_bar",
+            MessageDecimal::BookTicker(_) => "book_ticker",
+            MessageDecimal::Other(msg) => msg.kind(),
+        }
+    }
+
+    /// Returns the exchange of the message, `None` for an unknown message without an exchange
+    /// known to this crate.
+    pub fn exchange(&self) -> Option<Exchange> {
+        match self {
+            MessageDecimal::Trade(msg) => Some(msg.exchange),
+            MessageDecimal::BookChange(msg) => Some(msg.exchange),
+            MessageDecimal::BookSnapshot(msg) => Some(msg.exchange),
+            MessageDecimal::TradeBar(msg) => Some(msg.exchange),
+            MessageDecimal::BookTicker(msg) => Some(msg.exchange),
+            MessageDecimal::Other(msg) => msg.exchange(),
+        }
+    }
+
+    /// Returns the message arrival timestamp, `None` for an unknown message without one.
+    pub fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            MessageDecimal::Trade(msg) => Some(msg.local_timestamp),
+            MessageDecimal::BookChange(msg) => Some(msg.local_timestamp),
+            MessageDecimal::BookSnapshot(msg) => Some(msg.local_timestamp),
+            MessageDecimal::TradeBar(msg) => Some(msg.local_timestamp),
+            MessageDecimal::BookTicker(msg) => Some(msg.local_timestamp),
+            MessageDecimal::Other(msg) => msg.local_timestamp(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageDecimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(KindVisitor(PhantomData))
+    }
+}
+
+impl<'de> OfKind<'de> for MessageDecimal {
+    fn of_kind<D: Deserializer<'de>>(kind: &str, fields: D) -> Result<Self, D::Error> {
+        Ok(match kind {
+            "trade" => MessageDecimal::Trade(TradeDecimal::deserialize(fields)?),
+            "book_change" => {
+                MessageDecimal::BookChange(Box::new(BookChangeDecimal::deserialize(fields)?))
+            }
+            "book_snapshot" => {
+                MessageDecimal::BookSnapshot(Box::new(BookSnapshotDecimal::deserialize(fields)?))
+            }
+            "trade_bar" => {
+                MessageDecimal::TradeBar(Box::new(TradeBarDecimal::deserialize(fields)?))
+            }
+            "book_ticker" => {
+                MessageDecimal::BookTicker(Box::new(BookTickerDecimal::deserialize(fields)?))
+            }
+            _ => MessageDecimal::Other(message_of_kind(kind, fields)?),
+        })
+    }
+}
+
+/// A [`Trade`](super::Trade) with its price and amount as [`Decimal`]s.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeDecimal {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Trade id if provided by exchange
+    pub id: Option<String>,
+
+    /// Trade price as provided by exchange
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub price: Decimal,
+
+    /// Trade amount as provided by exchange
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub amount: Decimal,
+
+    /// Liquidity taker side (aggressor)
+    pub side: TradeSide,
+
+    /// Trade timestamp provided by exchange
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// A [`BookLevel`](super::BookLevel) with its price and amount as [`Decimal`]s.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookLevelDecimal {
+    /// The desired price of the order
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub price: Decimal,
+
+    /// The quantity of the order
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub amount: Decimal,
+}
+
+/// A [`BookChange`](super::BookChange) with its levels as [`BookLevelDecimal`]s.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookChangeDecimal {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// If true marks initial order book snapshot
+    pub is_snapshot: bool,
+
+    /// Updated bids price-amount levels
+    pub bids: Vec<BookLevelDecimal>,
+
+    /// Updated asks price-amount levels
+    pub asks: Vec<BookLevelDecimal>,
+
+    /// Order book update timestamp if provided by exchange, otherwise equals to localTimestamp
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// A [`BookSnapshot`](super::BookSnapshot) with its levels as [`BookLevelDecimal`]s.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookSnapshotDecimal {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Name with format book_snapshot_{depth}_{interval}{time_unit}
+    pub name: String,
+
+    /// Requested number of levels (top bids/asks)
+    pub depth: u64,
+
+    /// Requested snapshot interval in milliseconds
+    pub interval: u64,
+
+    /// Top "depth" bids price-amount levels
+    pub bids: Vec<BookLevelDecimal>,
+
+    /// Top "depth" asks price-amount levels
+    pub asks: Vec<BookLevelDecimal>,
+
+    /// Snapshot timestamp based on last book_change message processed timestamp adjusted to
+    /// snapshot interval
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp that triggered snapshot
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// A [`TradeBar`](super::TradeBar) with its prices and volumes as [`Decimal`]s.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeBarDecimal {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// name with format trade_bar_{interval}
+    pub name: String,
+
+    /// requested trade bar interval
+    pub interval: u64,
+
+    /// open price
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub open: Decimal,
+
+    /// high price
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub high: Decimal,
+
+    /// low price
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub low: Decimal,
+
+    /// close price
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub close: Decimal,
+
+    /// total volume traded in given interval
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub volume: Decimal,
+
+    /// buy volume traded in given interval
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub buy_volume: Decimal,
+
+    /// sell volume traded in given interval
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub sell_volume: Decimal,
+
+    /// trades count in given interval
+    pub trades: u64,
+
+    /// volume weighted average price
+    #[serde(deserialize_with = "crate::de::decimal")]
+    pub vwap: Decimal,
+
+    /// timestamp of first trade for given bar
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub open_timestamp: DateTime<Utc>,
+
+    /// timestamp of last trade for given bar
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub close_timestamp: DateTime<Utc>,
+
+    /// end of interval period timestamp
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// message arrival timestamp that triggered given bar computation
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// A [`BookTicker`](super::BookTicker) with its prices and amounts as [`Decimal`]s.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookTickerDecimal {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Best ask amount, if the ask side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_decimal")]
+    pub ask_amount: Option<Decimal>,
+
+    /// Best ask price, if the ask side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_decimal")]
+    pub ask_price: Option<Decimal>,
+
+    /// Best bid price, if the bid side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_decimal")]
+    pub bid_price: Option<Decimal>,
+
+    /// Best bid amount, if the bid side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_decimal")]
+    pub bid_amount: Option<Decimal>,
+
+    /// Message timestamp provided by exchange
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_message_decimal() {
+        let trade = serde_json::from_str::<MessageDecimal>(
+            r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":0.1,"amount":"1e-8","side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#,
+        )
+        .unwrap();
+        let MessageDecimal::Trade(trade) = trade else {
+            panic!("not a trade");
+        };
+        assert_eq!(trade.price, Decimal::from_str("0.1").unwrap());
+        assert_eq!(trade.amount, Decimal::from_str("0.00000001").unwrap());
+
+        let ticker = serde_json::from_str::<MessageDecimal>(
+            r#"{"type":"book_ticker","symbol":"XBTUSD","exchange":"bitmex","askAmount":"19310.50","askPrice":null,"bidPrice":7996,"timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#,
+        )
+        .unwrap();
+        let MessageDecimal::BookTicker(ticker) = ticker else {
+            panic!("not a book ticker");
+        };
+        assert_eq!(
+            ticker.ask_amount,
+            Some(Decimal::from_str("19310.50").unwrap())
+        );
+        assert_eq!(ticker.ask_price, None);
+        assert_eq!(ticker.bid_price, Some(Decimal::from(7996)));
+        assert_eq!(ticker.bid_amount, None);
+
+        for line in include_str!("../../fixtures/golden/corpus.ndjson").lines() {
+            let message = serde_json::from_str::<MessageDecimal>(line).unwrap();
+            let expected = serde_json::from_str::<Message>(line).unwrap();
+            assert_eq!(message.kind(), expected.kind());
+            assert_eq!(message.exchange(), expected.exchange());
+            assert_eq!(message.local_timestamp(), expected.local_timestamp());
+        }
+    }
+}
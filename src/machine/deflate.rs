@@ -0,0 +1,236 @@
+//! Client side support of the [permessage-deflate](https://www.rfc-editor.org/rfc/rfc7692)
+//! websocket extension, which tungstenite doesn't implement.
+//!
+//! [`Inflate`] sits between the connection and tungstenite and rewrites every compressed frame
+//! received into an uncompressed one, so tungstenite never sees a compressed frame. Messages sent
+//! by the client are left uncompressed, which the extension allows.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, BufMut, BytesMut};
+use flate2::{Decompress, FlushDecompress, Status};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The `Sec-WebSocket-Extensions` header value offering the extension to the server.
+pub(super) const EXTENSION: &str = "permessage-deflate";
+
+/// The bytes ending every compressed message, which the sender strips.
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// The RSV1 bit of the first byte of a frame, set on the first frame of a compressed message.
+const RSV1: u8 = 0x40;
+
+/// Inflates the compressed frames read from a connection.
+///
+/// The bytes of the HTTP handshake response are passed through as is, and the frames that
+/// follow are only rewritten when they are part of a compressed message.
+pub(super) struct Inflate<S> {
+    inner: S,
+    input: BytesMut,
+    output: BytesMut,
+    handshake_done: bool,
+    in_compressed_message: bool,
+    decompress: Decompress,
+}
+
+impl<S> Inflate<S> {
+    /// Wraps a connection whose handshake is yet to be made.
+    pub(super) fn new(inner: S) -> Self {
+        Self {
+            inner,
+            input: BytesMut::with_capacity(8 * 1024),
+            output: BytesMut::new(),
+            handshake_done: false,
+            in_compressed_message: false,
+            decompress: Decompress::new(false),
+        }
+    }
+
+    /// Moves what can be processed from `input` to `output`, returning `false` if more input is
+    /// needed.
+    fn process(&mut self) -> io::Result<bool> {
+        if !self.handshake_done {
+            return Ok(self.pass_handshake());
+        }
+
+        let Some((header_len, payload_len, mask)) = parse_header(&self.input) else {
+            return Ok(false);
+        };
+        let frame_len = header_len + payload_len;
+        if self.input.len() < frame_len {
+            self.input.reserve(frame_len - self.input.len());
+            return Ok(false);
+        }
+
+        let first = self.input[0];
+        let opcode = first & 0x0f;
+        let is_final = first & 0x80 != 0;
+        let is_data = opcode <= 0x2;
+        if is_data && opcode != 0 && first & RSV1 != 0 {
+            self.in_compressed_message = true;
+        }
+
+        let mut frame = self.input.split_to(frame_len);
+        if !(is_data && self.in_compressed_message) {
+            self.output.extend_from_slice(&frame);
+            return Ok(true);
+        }
+
+        frame.advance(header_len);
+        if let Some(mask) = mask {
+            for (i, byte) in frame.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+        let mut payload = Vec::with_capacity(frame.len() * 4);
+        self.inflate(&frame, &mut payload)?;
+        if is_final {
+            self.inflate(&TRAILER, &mut payload)?;
+            self.in_compressed_message = false;
+        }
+
+        write_header(&mut self.output, first & !RSV1, payload.len());
+        self.output.extend_from_slice(&payload);
+        Ok(true)
+    }
+
+    /// Passes the bytes of the handshake response through, up to the blank line ending it.
+    fn pass_handshake(&mut self) -> bool {
+        if let Some(end) = self.input.windows(4).position(|w| w == b"\r\n\r\n") {
+            self.output.extend_from_slice(&self.input.split_to(end + 4));
+            self.handshake_done = true;
+            return true;
+        }
+
+        // Keeps the bytes that could be the start of the blank line.
+        let pass = self.input.len().saturating_sub(3);
+        self.output.extend_from_slice(&self.input.split_to(pass));
+        pass > 0
+    }
+
+    fn inflate(&mut self, mut input: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(output.capacity().max(1024));
+            }
+            let total_in = self.decompress.total_in();
+            let total_out = self.decompress.total_out();
+            let status = self
+                .decompress
+                .decompress_vec(input, output, FlushDecompress::Sync)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            input = &input[(self.decompress.total_in() - total_in) as usize..];
+            let progressed = self.decompress.total_out() > total_out;
+
+            match status {
+                Status::StreamEnd => return Ok(()),
+                _ if input.is_empty() && output.len() < output.capacity() => return Ok(()),
+                Status::BufError if !progressed => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Truncated compressed message",
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Parses the header of a frame, returning its length, the payload length and masking key.
+fn parse_header(buf: &[u8]) -> Option<(usize, usize, Option<[u8; 4]>)> {
+    let second = *buf.get(1)?;
+    let (mut header_len, payload_len) = match second & 0x7f {
+        126 => (
+            4,
+            u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as usize,
+        ),
+        127 => (
+            10,
+            u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?) as usize,
+        ),
+        len => (2, len as usize),
+    };
+
+    let mask = if second & 0x80 != 0 {
+        let mask = buf.get(header_len..header_len + 4)?.try_into().ok()?;
+        header_len += 4;
+        Some(mask)
+    } else {
+        None
+    };
+    Some((header_len, payload_len, mask))
+}
+
+/// Writes the header of an unmasked frame.
+fn write_header(buf: &mut BytesMut, first: u8, payload_len: usize) {
+    buf.put_u8(first);
+    match payload_len {
+        0..=125 => buf.put_u8(payload_len as u8),
+        126..=0xffff => {
+            buf.put_u8(126);
+            buf.put_u16(payload_len as u16);
+        }
+        _ => {
+            buf.put_u8(127);
+            buf.put_u64(payload_len as u64);
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Inflate<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if !this.output.is_empty() {
+                let len = this.output.len().min(buf.remaining());
+                buf.put_slice(&this.output.split_to(len));
+                return Poll::Ready(Ok(()));
+            }
+            if this.process()? {
+                continue;
+            }
+
+            this.input.reserve(4 * 1024);
+            let read = std::task::ready!(tokio_util::io::poll_read_buf(
+                Pin::new(&mut this.inner),
+                cx,
+                &mut this.input
+            ))?;
+            if read == 0 {
+                if this.input.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+                // Leaves the truncated frame for tungstenite to report.
+                let rest = this.input.split();
+                this.output.extend_from_slice(&rest);
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Inflate<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,207 @@
+//! Utilities for detecting gaps in the `book_change` messages of a stream, which would otherwise
+//! silently corrupt the order books rebuilt out of them.
+
+use std::collections::HashMap;
+
+use async_stream::stream;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt};
+
+use super::{Message, Result, Symbol};
+use crate::{log, Exchange};
+
+/// What is wrong with the `book_change` messages of an instrument, see [`DataGap`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GapKind {
+    /// Incremental updates arrived without a preceding snapshot, eg. at the start of the stream
+    /// or after a disconnect. The updates can't be applied until the next snapshot.
+    MissingSnapshot,
+
+    /// A snapshot arrived after incremental updates without a disconnect in between, so updates
+    /// may have been lost before it.
+    UnexpectedSnapshot,
+}
+
+/// A gap detected in the `book_change` messages of an instrument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataGap {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// What is wrong with the messages
+    pub kind: GapKind,
+
+    /// Arrival timestamp of the message revealing the gap
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// A message of a stream that went through [`validate_books`].
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub enum Validated {
+    Message(Message),
+    Gap(DataGap),
+}
+
+/// Where the book of an instrument stands.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BookState {
+    /// No snapshot was received since the start of the stream or the last disconnect.
+    Unsynced,
+
+    /// A [`GapKind::MissingSnapshot`] was reported, waiting for the next snapshot.
+    Gapped,
+
+    /// The last message was a snapshot, which may be followed by more snapshot messages.
+    Snapshot,
+
+    /// Incremental updates are being applied.
+    Updating,
+}
+
+/// Tracks the `book_change` messages of every instrument to detect the gaps in them.
+#[derive(Debug, Default)]
+pub struct BookValidator {
+    books: HashMap<(Exchange, Symbol), BookState>,
+}
+
+impl BookValidator {
+    /// Creates a new instance of [`BookValidator`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a message, returning the gap it reveals, if any. A missing snapshot is only
+    /// reported once until the next snapshot of the instrument.
+    pub fn push(&mut self, message: &Message) -> Option<DataGap> {
+        let change = match message {
+            Message::BookChange(change) => change,
+            Message::Disconnect(disconnect) => {
+                for ((exchange, _), state) in self.books.iter_mut() {
+                    if *exchange == disconnect.exchange {
+                        *state = BookState::Unsynced;
+                    }
+                }
+                return None;
+            }
+            _ => return None,
+        };
+
+        let state = self
+            .books
+            .entry((change.exchange, change.symbol.clone()))
+            .or_insert(BookState::Unsynced);
+        let kind = match (*state, change.is_snapshot) {
+            (BookState::Updating, true) => Some(GapKind::UnexpectedSnapshot),
+            (BookState::Unsynced, false) => Some(GapKind::MissingSnapshot),
+            _ => None,
+        };
+        *state = match (*state, change.is_snapshot) {
+            (_, true) => BookState::Snapshot,
+            (BookState::Unsynced | BookState::Gapped, false) => BookState::Gapped,
+            (_, false) => BookState::Updating,
+        };
+
+        let kind = kind?;
+        log::warn!(
+            "{:?} in the book of {} on {}",
+            kind,
+            change.symbol,
+            change.exchange
+        );
+        Some(DataGap {
+            exchange: change.exchange,
+            symbol: change.symbol.clone(),
+            kind,
+            local_timestamp: change.local_timestamp,
+        })
+    }
+}
+
+/// Checks the `book_change` messages of the stream with a [`BookValidator`], yielding a
+/// [`Validated::Gap`] right before the message revealing it. Errors are passed through.
+pub fn validate_books<S>(messages: S) -> impl Stream<Item = Result<Validated>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    stream! {
+        futures_util::pin_mut!(messages);
+        let mut validator = BookValidator::new();
+
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(msg) => {
+                    if let Some(gap) = validator.push(&msg) {
+                        yield Ok(Validated::Gap(gap));
+                    }
+                    yield Ok(Validated::Message(msg));
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(symbol: &str, is_snapshot: bool) -> Message {
+        Message::BookChange(
+            serde_json::from_value(serde_json::json!({
+                "symbol": symbol,
+                "exchange": "bybit",
+                "isSnapshot": is_snapshot,
+                "bids": [],
+                "asks": [],
+                "timestamp": "2022-10-01T00:00:00.100Z",
+                "localTimestamp": "2022-10-01T00:00:00.104Z",
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn disconnect() -> Message {
+        serde_json::from_str(
+            r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:01.000Z"}"#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_validate_books() {
+        let messages = vec![
+            change("BTCUSDT", false),
+            change("BTCUSDT", false),
+            change("BTCUSDT", true),
+            change("BTCUSDT", true),
+            change("ETHUSDT", true),
+            change("BTCUSDT", false),
+            change("BTCUSDT", true),
+            disconnect(),
+            change("ETHUSDT", true),
+            change("BTCUSDT", false),
+        ];
+
+        let gaps = validate_books(futures_util::stream::iter(messages.into_iter().map(Ok)))
+            .filter_map(|msg| async move {
+                match msg.unwrap() {
+                    Validated::Gap(gap) => Some((gap.symbol.to_string(), gap.kind)),
+                    Validated::Message(_) => None,
+                }
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            gaps,
+            vec![
+                ("BTCUSDT".to_string(), GapKind::MissingSnapshot),
+                ("BTCUSDT".to_string(), GapKind::UnexpectedSnapshot),
+                ("BTCUSDT".to_string(), GapKind::MissingSnapshot),
+            ]
+        );
+    }
+}
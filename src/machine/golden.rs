@@ -0,0 +1,356 @@
+//! A harness replaying a fixture corpus of normalized messages through the parsing, order book,
+//! trade and trade bar pipelines of this crate and comparing the results against golden outputs.
+//!
+//! The crate bundles its own corpus ([`CORPUS`]) and the outputs it is expected to produce
+//! ([`GOLDEN`]). Code extending the crate can register additional [`GoldenStage`]s, record their
+//! outputs once with [`Harness::record`] and later assert that nothing changed with
+//! [`Harness::verify`].
+//!
+//! ```ignore
+//! use tardis_rs::machine::golden::{Harness, CORPUS, GOLDEN};
+//!
+//! Harness::default().verify(CORPUS, GOLDEN).unwrap();
+//! ```
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    book::{Bucketing, Ladder, NotionalDepth, OrderBook},
+    trades::{TradeBarBuilder, TradeClusterer},
+    DataType, IntervalUnit, Message, Symbol,
+};
+use crate::Exchange;
+
+/// The bundled corpus of normalized messages, one JSON message per line.
+pub const CORPUS: &str = include_str!("../../fixtures/golden/corpus.ndjson");
+
+/// The outputs the default [`Harness`] produces for [`CORPUS`], one [`GoldenRecord`] per line.
+pub const GOLDEN: &str = include_str!("../../fixtures/golden/golden.ndjson");
+
+/// The error that could happen while running the golden harness.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The error when a line of the corpus or golden file could not be parsed.
+    #[error("Failed to parse line {line}: {source}")]
+    Parse {
+        /// The line number, starting at 1.
+        line: usize,
+        /// The underlying error.
+        source: serde_json::Error,
+    },
+
+    /// The error when an output differs from the golden output.
+    #[error("Output {index} differs, expected {expected:?} but got {actual:?}")]
+    Mismatch {
+        /// The index of the first differing output.
+        index: usize,
+        /// The golden output, `None` if the harness produced more outputs than expected.
+        expected: Option<GoldenRecord>,
+        /// The actual output, `None` if the harness produced fewer outputs than expected.
+        actual: Option<GoldenRecord>,
+    },
+}
+
+/// A single output of a [`GoldenStage`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoldenRecord {
+    /// The name of the stage that produced the output.
+    pub stage: String,
+
+    /// The output itself.
+    pub output: Value,
+}
+
+/// A step of the pipeline whose outputs are checked by the [`Harness`].
+pub trait GoldenStage {
+    /// The name the outputs of the stage are recorded under.
+    fn name(&self) -> &str;
+
+    /// Processes a message of the corpus, returning the outputs it produced.
+    fn process(&mut self, message: &Message) -> Vec<Value>;
+
+    /// Returns the outputs still pending once the corpus ends.
+    fn finish(&mut self) -> Vec<Value> {
+        vec![]
+    }
+}
+
+/// Re-serializes every parsed message, catching changes in the models.
+#[derive(Debug, Default)]
+pub struct ParseStage;
+
+impl GoldenStage for ParseStage {
+    fn name(&self) -> &str {
+        "parse"
+    }
+
+    fn process(&mut self, message: &Message) -> Vec<Value> {
+        vec![to_value(message)]
+    }
+}
+
+/// Aggregates book snapshots into a [`Ladder`].
+#[derive(Debug)]
+pub struct LadderStage(pub Bucketing);
+
+impl GoldenStage for LadderStage {
+    fn name(&self) -> &str {
+        "ladder"
+    }
+
+    fn process(&mut self, message: &Message) -> Vec<Value> {
+        match message {
+            Message::BookSnapshot(snapshot) => {
+                vec![to_value(&Ladder::from_snapshot(snapshot, self.0))]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Maintains an [`OrderBook`] per instrument out of the book changes, emitting its top levels as
+/// a [`Ladder`] after each change once the book received its snapshot.
+#[derive(Debug)]
+pub struct OrderBookStage {
+    levels: usize,
+    books: HashMap<(Exchange, Symbol), OrderBook>,
+}
+
+impl OrderBookStage {
+    /// Creates a stage emitting the top `levels` of each side of the books.
+    pub fn new(levels: usize) -> Self {
+        Self {
+            levels,
+            books: HashMap::new(),
+        }
+    }
+}
+
+impl GoldenStage for OrderBookStage {
+    fn name(&self) -> &str {
+        "order_book"
+    }
+
+    fn process(&mut self, message: &Message) -> Vec<Value> {
+        match message {
+            Message::BookChange(change) => {
+                let book = self
+                    .books
+                    .entry((change.exchange, change.symbol.clone()))
+                    .or_default();
+                book.update(change);
+                if book.has_snapshot() {
+                    vec![to_value(&book.depth(self.levels))]
+                } else {
+                    vec![]
+                }
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Computes the [`NotionalDepth`] of book snapshots.
+#[derive(Debug)]
+pub struct NotionalDepthStage(pub f64);
+
+impl GoldenStage for NotionalDepthStage {
+    fn name(&self) -> &str {
+        "notional_depth"
+    }
+
+    fn process(&mut self, message: &Message) -> Vec<Value> {
+        match message {
+            Message::BookSnapshot(snapshot) => {
+                vec![to_value(&NotionalDepth::from_snapshot(snapshot, self.0))]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Merges trades into aggregate trades with a [`TradeClusterer`].
+#[derive(Debug, Default)]
+pub struct ClusterStage(TradeClusterer);
+
+impl GoldenStage for ClusterStage {
+    fn name(&self) -> &str {
+        "cluster_trades"
+    }
+
+    fn process(&mut self, message: &Message) -> Vec<Value> {
+        match message {
+            Message::Trade(trade) => self.0.push(trade.clone()).iter().map(to_value).collect(),
+            _ => vec![],
+        }
+    }
+
+    fn finish(&mut self) -> Vec<Value> {
+        self.0.flush().iter().map(to_value).collect()
+    }
+}
+
+/// Aggregates trades into bars with a [`TradeBarBuilder`], the time bars being completed by the
+/// arrival of any later message.
+#[derive(Debug)]
+pub struct BarsStage(TradeBarBuilder);
+
+impl BarsStage {
+    /// Creates a stage computing the bars of `data_type`, eg. `trade_bar_1s`. Returns `None` if it
+    /// isn't a [`DataType::TradeBar`] of a positive interval.
+    pub fn new(data_type: DataType) -> Option<Self> {
+        TradeBarBuilder::new(data_type).map(Self)
+    }
+}
+
+impl GoldenStage for BarsStage {
+    fn name(&self) -> &str {
+        "trade_bars"
+    }
+
+    fn process(&mut self, message: &Message) -> Vec<Value> {
+        let mut bars = message
+            .local_timestamp()
+            .map(|timestamp| self.0.advance(timestamp))
+            .unwrap_or_default();
+        if let Message::Trade(trade) = message {
+            bars.extend(self.0.push(trade));
+        }
+        bars.iter().map(to_value).collect()
+    }
+
+    fn finish(&mut self) -> Vec<Value> {
+        self.0.flush().iter().map(to_value).collect()
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).expect("golden outputs must be serializable")
+}
+
+/// Runs a corpus through a list of [`GoldenStage`]s.
+pub struct Harness {
+    stages: Vec<Box<dyn GoldenStage>>,
+}
+
+impl Default for Harness {
+    /// Creates a [`Harness`] with the stages [`GOLDEN`] was recorded with.
+    fn default() -> Self {
+        Self::new()
+            .stage(ParseStage)
+            .stage(LadderStage(Bucketing::Price(1.0)))
+            .stage(OrderBookStage::new(5))
+            .stage(NotionalDepthStage(50_000.0))
+            .stage(ClusterStage::default())
+            .stage(
+                BarsStage::new(DataType::TradeBar {
+                    interval: 1,
+                    unit: IntervalUnit::Seconds,
+                })
+                .expect("positive interval"),
+            )
+    }
+}
+
+impl Harness {
+    /// Creates a new instance of [`Harness`] without any stage.
+    pub fn new() -> Self {
+        Self { stages: vec![] }
+    }
+
+    /// Adds a stage to the pipeline.
+    pub fn stage(mut self, stage: impl GoldenStage + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Runs every message of the corpus through the stages, returning their outputs in order.
+    pub fn run(&mut self, corpus: &str) -> Result<Vec<GoldenRecord>, Error> {
+        let mut records = vec![];
+
+        for (line, message) in parse_lines::<Message>(corpus) {
+            let message = message.map_err(|source| Error::Parse { line, source })?;
+
+            for stage in self.stages.iter_mut() {
+                let outputs = stage.process(&message);
+                records.extend(outputs.into_iter().map(|output| GoldenRecord {
+                    stage: stage.name().to_string(),
+                    output,
+                }));
+            }
+        }
+
+        for stage in self.stages.iter_mut() {
+            let outputs = stage.finish();
+            records.extend(outputs.into_iter().map(|output| GoldenRecord {
+                stage: stage.name().to_string(),
+                output,
+            }));
+        }
+
+        Ok(records)
+    }
+
+    /// Runs the corpus and returns the outputs formatted as a golden file.
+    pub fn record(&mut self, corpus: &str) -> Result<String, Error> {
+        let mut golden = String::new();
+        for record in self.run(corpus)? {
+            golden.push_str(&to_value(&record).to_string());
+            golden.push('\n');
+        }
+        Ok(golden)
+    }
+
+    /// Runs the corpus and compares the outputs against the given golden file.
+    pub fn verify(&mut self, corpus: &str, golden: &str) -> Result<(), Error> {
+        let actual = self.run(corpus)?;
+        let expected = parse_lines::<GoldenRecord>(golden)
+            .map(|(line, record)| record.map_err(|source| Error::Parse { line, source }))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for index in 0..actual.len().max(expected.len()) {
+            if actual.get(index) != expected.get(index) {
+                return Err(Error::Mismatch {
+                    index,
+                    expected: expected.get(index).cloned(),
+                    actual: actual.get(index).cloned(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_lines<T: serde::de::DeserializeOwned>(
+    input: &str,
+) -> impl Iterator<Item = (usize, serde_json::Result<T>)> + '_ {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| (index + 1, serde_json::from_str(line)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_corpus() {
+        Harness::default().verify(CORPUS, GOLDEN).unwrap();
+    }
+
+    #[test]
+    fn test_detects_mismatch() {
+        let golden = GOLDEN.replacen("19310.5", "19310.25", 1);
+
+        assert!(matches!(
+            Harness::default().verify(CORPUS, &golden),
+            Err(Error::Mismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,227 @@
+//! Utilities for measuring how far behind real time a stream is running, eg. to tell whether a
+//! `stream_normalized` consumer keeps up with the feed.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use async_stream::stream;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{Stream, StreamExt};
+
+use super::{Message, Result};
+
+/// The latencies of a message, see [`measure_latency`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Latency {
+    /// Time elapsed between the arrival of the message at the machine server, its
+    /// `local_timestamp`, and its receipt by the consumer
+    pub receive: Duration,
+
+    /// Time elapsed between the exchange timestamp of the message and its arrival at the machine
+    /// server, if the message has an exchange timestamp
+    pub exchange: Option<Duration>,
+}
+
+impl Latency {
+    /// Returns the latencies of a message received at `received_at`, `None` for an unknown
+    /// message without an arrival timestamp.
+    pub fn of(message: &Message, received_at: DateTime<Utc>) -> Option<Self> {
+        let local_timestamp = message.local_timestamp()?;
+        Some(Self {
+            receive: received_at - local_timestamp,
+            exchange: message
+                .timestamp()
+                .map(|timestamp| local_timestamp - timestamp),
+        })
+    }
+}
+
+/// A message annotated with its latencies by [`measure_latency`].
+#[derive(Debug, Clone)]
+pub struct Measured {
+    /// The message
+    pub message: Message,
+
+    /// When the message was received by the consumer
+    pub received_at: DateTime<Utc>,
+
+    /// The latencies of the message, `None` for an unknown message without an arrival timestamp
+    pub latency: Option<Latency>,
+}
+
+/// The latencies of the last messages of a stream, to compute their percentiles.
+#[derive(Debug)]
+struct Window {
+    capacity: usize,
+    samples: VecDeque<Duration>,
+}
+
+impl Window {
+    fn push(&mut self, sample: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut samples = self.samples.iter().copied().collect::<Vec<_>>();
+        if samples.is_empty() {
+            return None;
+        }
+        let last = samples.len() - 1;
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * last as f64).round() as usize;
+        let (_, sample, _) = samples.select_nth_unstable(rank);
+        Some(*sample)
+    }
+}
+
+/// Keeps the latencies of the last `window` messages measured by [`measure_latency`] to compute
+/// rolling percentiles, eg. to report the p99 receive latency every minute.
+///
+/// ```
+/// use std::sync::Arc;
+/// use tardis_rs::machine::latency::LatencyTracker;
+///
+/// let tracker = Arc::new(LatencyTracker::new(10_000));
+/// assert_eq!(tracker.receive_percentile(99.0), None);
+/// ```
+#[derive(Debug)]
+pub struct LatencyTracker {
+    receive: Mutex<Window>,
+    exchange: Mutex<Window>,
+}
+
+impl LatencyTracker {
+    /// Creates a new instance of [`LatencyTracker`] keeping the latencies of the last `window`
+    /// messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "window must not be empty");
+
+        let window = || {
+            Mutex::new(Window {
+                capacity: window,
+                samples: VecDeque::with_capacity(window),
+            })
+        };
+        Self {
+            receive: window(),
+            exchange: window(),
+        }
+    }
+
+    /// Records the latencies of a message.
+    pub fn record(&self, latency: &Latency) {
+        self.receive.lock().unwrap().push(latency.receive);
+        if let Some(exchange) = latency.exchange {
+            self.exchange.lock().unwrap().push(exchange);
+        }
+    }
+
+    /// Returns the given percentile, between 0 and 100, of the receive latencies in the window,
+    /// `None` if no message was recorded.
+    pub fn receive_percentile(&self, percentile: f64) -> Option<Duration> {
+        self.receive.lock().unwrap().percentile(percentile)
+    }
+
+    /// Returns the given percentile, between 0 and 100, of the exchange latencies in the window,
+    /// `None` if no message with an exchange timestamp was recorded.
+    pub fn exchange_percentile(&self, percentile: f64) -> Option<Duration> {
+        self.exchange.lock().unwrap().percentile(percentile)
+    }
+}
+
+/// Annotates the messages of the stream with their [`Latency`], measured as they are received,
+/// and records it in `tracker`. Errors are passed through.
+///
+/// Only meaningful for real-time streams, as replayed messages arrived long ago.
+pub fn measure_latency<S>(
+    messages: S,
+    tracker: Arc<LatencyTracker>,
+) -> impl Stream<Item = Result<Measured>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    stream! {
+        futures_util::pin_mut!(messages);
+
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(message) => {
+                    let received_at = Utc::now();
+                    let latency = Latency::of(&message, received_at);
+                    if let Some(latency) = &latency {
+                        tracker.record(latency);
+                    }
+                    yield Ok(Measured { message, received_at, latency });
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(timestamp: &str, local_timestamp: &str) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "type": "trade",
+            "symbol": "BTCUSDT",
+            "exchange": "bybit",
+            "id": "1",
+            "price": 19310.5,
+            "amount": 0.1,
+            "side": "buy",
+            "timestamp": timestamp,
+            "localTimestamp": local_timestamp,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_measure_latency() {
+        let latency = Latency::of(
+            &trade("2022-10-01T00:00:00.100Z", "2022-10-01T00:00:00.104Z"),
+            "2022-10-01T00:00:00.154Z".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(latency.receive, Duration::milliseconds(50));
+        assert_eq!(latency.exchange, Some(Duration::milliseconds(4)));
+
+        let tracker = Arc::new(LatencyTracker::new(3));
+        let messages = (1..=4).map(|millis| {
+            Ok(trade(
+                "2022-10-01T00:00:00.000Z",
+                &format!("2022-10-01T00:00:00.00{}Z", millis),
+            ))
+        });
+        let measured = measure_latency(futures_util::stream::iter(messages), tracker.clone())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(measured.len(), 4);
+        assert!(measured[0].as_ref().unwrap().latency.unwrap().receive > Duration::days(365));
+
+        // The first message fell out of the window.
+        assert_eq!(
+            tracker.exchange_percentile(0.0),
+            Some(Duration::milliseconds(2))
+        );
+        assert_eq!(
+            tracker.exchange_percentile(50.0),
+            Some(Duration::milliseconds(3))
+        );
+        assert_eq!(
+            tracker.exchange_percentile(100.0),
+            Some(Duration::milliseconds(4))
+        );
+        assert!(tracker.receive_percentile(99.0).is_some());
+    }
+}
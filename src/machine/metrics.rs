@@ -0,0 +1,132 @@
+//! Hooks observing the traffic of the connections opened by a [`Client`](super::Client).
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicI64, AtomicU64, Ordering},
+};
+
+use chrono::Duration;
+
+/// Receives the events of the connections of a [`Client`](super::Client), to feed them into a
+/// monitoring system. Set with [`ClientBuilder::metrics`](super::ClientBuilder::metrics).
+///
+/// Every method does nothing by default, and is called on the task consuming the stream, so it
+/// should be cheap, eg. increment a counter. [`StreamMetrics`] is an implementation keeping
+/// counters in memory.
+pub trait MetricsObserver: Send + Sync + 'static {
+    /// Called once a connection to `url` is established.
+    fn on_connect(&self, url: &str) {
+        let _ = url;
+    }
+
+    /// Called for every message received, with the size of its payload in bytes.
+    fn on_message(&self, bytes: usize) {
+        let _ = bytes;
+    }
+
+    /// Called for every message that failed to be deserialized.
+    fn on_deserialization_error(&self, error: &serde_json::Error) {
+        let _ = error;
+    }
+
+    /// Called before every attempt to reconnect, starting at 1 for the first attempt.
+    fn on_reconnect(&self, attempt: u32) {
+        let _ = attempt;
+    }
+
+    /// Called for every normalized message received, with the time elapsed since its
+    /// `local_timestamp`. Only meaningful for real-time streams, as replayed messages arrived
+    /// long ago.
+    fn on_lag(&self, lag: Duration) {
+        let _ = lag;
+    }
+}
+
+impl fmt::Debug for dyn MetricsObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MetricsObserver")
+    }
+}
+
+/// A [`MetricsObserver`] counting the events it observes.
+///
+/// ```
+/// use std::sync::Arc;
+/// use tardis_rs::machine::{metrics::StreamMetrics, Client};
+///
+/// let metrics = Arc::new(StreamMetrics::new());
+/// let client = Client::builder("ws://localhost:8001")
+///     .metrics(metrics.clone())
+///     .build();
+/// assert_eq!(metrics.messages(), 0);
+/// ```
+#[derive(Debug, Default)]
+pub struct StreamMetrics {
+    connections: AtomicU64,
+    messages: AtomicU64,
+    bytes: AtomicU64,
+    deserialization_errors: AtomicU64,
+    reconnects: AtomicU64,
+    lag_micros: AtomicI64,
+}
+
+impl StreamMetrics {
+    /// Creates a new instance of [`StreamMetrics`] with every counter at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of connections established.
+    pub fn connections(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of messages received.
+    pub fn messages(&self) -> u64 {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total size of the payloads received, in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of messages that failed to be deserialized.
+    pub fn deserialization_errors(&self) -> u64 {
+        self.deserialization_errors.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of attempts to reconnect.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    /// Returns the lag of the last normalized message received.
+    pub fn lag(&self) -> Duration {
+        Duration::microseconds(self.lag_micros.load(Ordering::Relaxed))
+    }
+}
+
+impl MetricsObserver for StreamMetrics {
+    fn on_connect(&self, _: &str) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_message(&self, bytes: usize) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn on_deserialization_error(&self, _: &serde_json::Error) {
+        self.deserialization_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_reconnect(&self, _: u32) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_lag(&self, lag: Duration) {
+        let micros = lag.num_microseconds().unwrap_or(i64::MAX);
+        self.lag_micros.store(micros, Ordering::Relaxed);
+    }
+}
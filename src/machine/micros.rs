@@ -0,0 +1,339 @@
+//! Variants of the normalized messages keeping their timestamps as microseconds since the Unix
+//! epoch, for hot paths where converting every timestamp into a [`DateTime`] is wasted work, eg.
+//! when the timestamps are only compared or bucketed.
+//!
+//! Decode them from a [`RawMessage`](super::RawMessage) with
+//! [`RawMessage::deserialize`](super::RawMessage::deserialize), and convert the timestamps with
+//! [`to_datetime`] and [`from_datetime`] where needed.
+
+use std::marker::PhantomData;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer};
+
+use super::{
+    borrowed::{KindVisitor, OfKind},
+    models::message_of_kind,
+    BookChange, BookLevels, BookTicker, Message, Symbol, Trade, TradeSide,
+};
+use crate::Exchange;
+
+/// Converts microseconds since the Unix epoch into a [`DateTime`].
+///
+/// # Panics
+///
+/// Panics if `micros` is out of the range of [`DateTime`], which is hundreds of thousands of
+/// years away.
+pub fn to_datetime(micros: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_micros(micros).expect("timestamp out of range")
+}
+
+/// Converts a [`DateTime`] into microseconds since the Unix epoch, truncating the nanoseconds.
+pub fn from_datetime(timestamp: DateTime<Utc>) -> i64 {
+    timestamp.timestamp_micros()
+}
+
+/// A normalized message with its timestamps as microseconds since the Unix epoch.
+///
+/// Only the types streamed at a high frequency have such a variant, the others are decoded into
+/// a [`Message`].
+#[derive(Debug, Clone)]
+pub enum MessageMicros {
+    /// A [`Trade`].
+    Trade(TradeMicros),
+
+    /// A [`BookChange`].
+    BookChange(Box<BookChangeMicros>),
+
+    /// A [`BookTicker`].
+    BookTicker(Box<BookTickerMicros>),
+
+    /// Any other message.
+    Other(Message),
+}
+
+impl MessageMicros {
+    /// Returns the `type` of the message, eg. `trade`.
+    pub fn kind(&self) -> &str {
+        match self {
+            MessageMicros::Trade(_) => "trade",
+            MessageMicros::BookChange(_) => "book_change",
+            MessageMicros::BookTicker(_) => "book_ticker",
+            MessageMicros::Other(msg) => msg.kind(),
+        }
+    }
+
+    /// Returns the exchange of the message, `None` for an unknown message without an exchange
+    /// known to this crate.
+    pub fn exchange(&self) -> Option<Exchange> {
+        match self {
+            MessageMicros::Trade(msg) => Some(msg.exchange),
+            MessageMicros::BookChange(msg) => Some(msg.exchange),
+            MessageMicros::BookTicker(msg) => Some(msg.exchange),
+            MessageMicros::Other(msg) => msg.exchange(),
+        }
+    }
+
+    /// Returns the arrival timestamp of the message, in microseconds since the Unix epoch, `None`
+    /// for an unknown message without one.
+    pub fn local_timestamp(&self) -> Option<i64> {
+        match self {
+            MessageMicros::Trade(msg) => Some(msg.local_timestamp),
+            MessageMicros::BookChange(msg) => Some(msg.local_timestamp),
+            MessageMicros::BookTicker(msg) => Some(msg.local_timestamp),
+            MessageMicros::Other(msg) => msg.local_timestamp().map(from_datetime),
+        }
+    }
+}
+
+impl From<MessageMicros> for Message {
+    fn from(message: MessageMicros) -> Self {
+        match message {
+            MessageMicros::Trade(msg) => Message::Trade(msg.into()),
+            MessageMicros::BookChange(msg) => Message::BookChange(Box::new((*msg).into())),
+            MessageMicros::BookTicker(msg) => Message::BookTicker(Box::new((*msg).into())),
+            MessageMicros::Other(msg) => msg,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageMicros {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(KindVisitor(PhantomData))
+    }
+}
+
+impl<'de> OfKind<'de> for MessageMicros {
+    fn of_kind<D: Deserializer<'de>>(kind: &str, fields: D) -> Result<Self, D::Error> {
+        Ok(match kind {
+            "trade" => MessageMicros::Trade(TradeMicros::deserialize(fields)?),
+            "book_change" => {
+                MessageMicros::BookChange(Box::new(BookChangeMicros::deserialize(fields)?))
+            }
+            "book_ticker" => {
+                MessageMicros::BookTicker(Box::new(BookTickerMicros::deserialize(fields)?))
+            }
+            _ => MessageMicros::Other(message_of_kind(kind, fields)?),
+        })
+    }
+}
+
+/// A [`Trade`] with its timestamps as microseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeMicros {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Trade id if provided by exchange
+    pub id: Option<String>,
+
+    /// Trade price as provided by exchange
+    #[serde(deserialize_with = "crate::de::f64")]
+    pub price: f64,
+
+    /// Trade amount as provided by exchange
+    #[serde(deserialize_with = "crate::de::f64")]
+    pub amount: f64,
+
+    /// Liquidity taker side (aggressor)
+    pub side: TradeSide,
+
+    /// Trade timestamp provided by exchange
+    #[serde(deserialize_with = "crate::de::timestamp_micros")]
+    pub timestamp: i64,
+
+    /// Message arrival timestamp
+    #[serde(deserialize_with = "crate::de::timestamp_micros")]
+    pub local_timestamp: i64,
+}
+
+impl From<Trade> for TradeMicros {
+    fn from(trade: Trade) -> Self {
+        Self {
+            symbol: trade.symbol,
+            exchange: trade.exchange,
+            id: trade.id,
+            price: trade.price,
+            amount: trade.amount,
+            side: trade.side,
+            timestamp: from_datetime(trade.timestamp),
+            local_timestamp: from_datetime(trade.local_timestamp),
+        }
+    }
+}
+
+impl From<TradeMicros> for Trade {
+    fn from(trade: TradeMicros) -> Self {
+        Self {
+            symbol: trade.symbol,
+            exchange: trade.exchange,
+            id: trade.id,
+            price: trade.price,
+            amount: trade.amount,
+            side: trade.side,
+            timestamp: to_datetime(trade.timestamp),
+            local_timestamp: to_datetime(trade.local_timestamp),
+        }
+    }
+}
+
+/// A [`BookChange`] with its timestamps as microseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookChangeMicros {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// If true marks initial order book snapshot
+    pub is_snapshot: bool,
+
+    /// Updated bids price-amount levels
+    pub bids: BookLevels,
+
+    /// Updated asks price-amount levels
+    pub asks: BookLevels,
+
+    /// Order book update timestamp if provided by exchange, otherwise equals to localTimestamp
+    #[serde(deserialize_with = "crate::de::timestamp_micros")]
+    pub timestamp: i64,
+
+    /// Message arrival timestamp
+    #[serde(deserialize_with = "crate::de::timestamp_micros")]
+    pub local_timestamp: i64,
+}
+
+impl From<BookChange> for BookChangeMicros {
+    fn from(change: BookChange) -> Self {
+        Self {
+            symbol: change.symbol,
+            exchange: change.exchange,
+            is_snapshot: change.is_snapshot,
+            bids: change.bids,
+            asks: change.asks,
+            timestamp: from_datetime(change.timestamp),
+            local_timestamp: from_datetime(change.local_timestamp),
+        }
+    }
+}
+
+impl From<BookChangeMicros> for BookChange {
+    fn from(change: BookChangeMicros) -> Self {
+        Self {
+            symbol: change.symbol,
+            exchange: change.exchange,
+            is_snapshot: change.is_snapshot,
+            bids: change.bids,
+            asks: change.asks,
+            timestamp: to_datetime(change.timestamp),
+            local_timestamp: to_datetime(change.local_timestamp),
+        }
+    }
+}
+
+/// A [`BookTicker`] with its timestamps as microseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookTickerMicros {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Best ask amount, if the ask side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub ask_amount: Option<f64>,
+
+    /// Best ask price, if the ask side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub ask_price: Option<f64>,
+
+    /// Best bid price, if the bid side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub bid_price: Option<f64>,
+
+    /// Best bid amount, if the bid side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub bid_amount: Option<f64>,
+
+    /// Message timestamp provided by exchange
+    #[serde(deserialize_with = "crate::de::timestamp_micros")]
+    pub timestamp: i64,
+
+    /// Message arrival timestamp
+    #[serde(deserialize_with = "crate::de::timestamp_micros")]
+    pub local_timestamp: i64,
+}
+
+impl From<BookTicker> for BookTickerMicros {
+    fn from(ticker: BookTicker) -> Self {
+        Self {
+            symbol: ticker.symbol,
+            exchange: ticker.exchange,
+            ask_amount: ticker.ask_amount,
+            ask_price: ticker.ask_price,
+            bid_price: ticker.bid_price,
+            bid_amount: ticker.bid_amount,
+            timestamp: from_datetime(ticker.timestamp),
+            local_timestamp: from_datetime(ticker.local_timestamp),
+        }
+    }
+}
+
+impl From<BookTickerMicros> for BookTicker {
+    fn from(ticker: BookTickerMicros) -> Self {
+        Self {
+            symbol: ticker.symbol,
+            exchange: ticker.exchange,
+            ask_amount: ticker.ask_amount,
+            ask_price: ticker.ask_price,
+            bid_price: ticker.bid_price,
+            bid_amount: ticker.bid_amount,
+            timestamp: to_datetime(ticker.timestamp),
+            local_timestamp: to_datetime(ticker.local_timestamp),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_micros() {
+        let trade = serde_json::from_str::<MessageMicros>(
+            r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669123Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#,
+        )
+        .unwrap();
+        let MessageMicros::Trade(trade) = trade else {
+            panic!("not a trade");
+        };
+        assert_eq!(trade.timestamp, 1_571_826_769_669_123);
+        assert_eq!(trade.local_timestamp, 1_571_826_769_740_000);
+        assert_eq!(
+            to_datetime(trade.timestamp).to_string(),
+            "2019-10-23 10:32:49.669123 UTC"
+        );
+
+        for line in include_str!("../../fixtures/golden/corpus.ndjson").lines() {
+            let message = serde_json::from_str::<MessageMicros>(line).unwrap();
+            let expected = serde_json::from_str::<Message>(line).unwrap();
+            assert_eq!(message.kind(), expected.kind());
+            assert_eq!(
+                message.local_timestamp(),
+                expected.local_timestamp().map(from_datetime)
+            );
+            assert_eq!(
+                serde_json::to_value(Message::from(message)).unwrap(),
+                serde_json::to_value(expected).unwrap()
+            );
+        }
+    }
+}
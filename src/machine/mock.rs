@@ -0,0 +1,266 @@
+//! An in-process mock of Tardis Machine Server, serving scripted websocket connections so that
+//! code consuming the streams of a [`Client`](super::Client) can be tested deterministically
+//! without a live server.
+//!
+//! ```
+//! use futures_util::StreamExt;
+//! use tardis_rs::{
+//!     machine::{mock::{MockFrame, MockServer}, Client, DataType, StreamNormalizedRequestOptions},
+//!     Exchange,
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let server = MockServer::builder()
+//!     .connection([
+//!         MockFrame::text(r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#),
+//!         MockFrame::error(100, "Invalid data type"),
+//!     ])
+//!     .start()
+//!     .await;
+//!
+//! let messages = Client::new(server.url())
+//!     .stream_normalized(vec![StreamNormalizedRequestOptions::builder(Exchange::Bybit)
+//!         .data_type(DataType::Trade)
+//!         .build()])
+//!     .await
+//!     .unwrap()
+//!     .collect::<Vec<_>>()
+//!     .await;
+//! assert_eq!(messages.len(), 2);
+//! assert_eq!(server.requests().len(), 1);
+//! # }
+//! ```
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{Request, Response},
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Message as Frame,
+};
+
+use super::Message;
+
+/// A step of the script of a connection served by a [`MockServer`].
+#[derive(Debug, Clone)]
+pub enum MockFrame {
+    /// Sends a text frame, eg. a normalized message.
+    Text(String),
+
+    /// Waits before the next step.
+    Delay(Duration),
+
+    /// Sends a close frame with the given code and reason and ends the connection.
+    Close(u16, String),
+
+    /// Drops the connection without closing it, as a crashed server or a broken network would.
+    Drop,
+
+    /// Keeps the connection open without sending anything, until the client closes it.
+    Hold,
+}
+
+impl MockFrame {
+    /// Sends a text frame.
+    pub fn text(text: impl ToString) -> Self {
+        MockFrame::Text(text.to_string())
+    }
+
+    /// Sends a normalized message.
+    pub fn message(message: &Message) -> Self {
+        MockFrame::Text(serde_json::to_string(message).expect("message can be serialized"))
+    }
+
+    /// Sends the payload the server answers invalid options with, which the client yields as
+    /// [`Error::ServerError`](super::Error::ServerError).
+    pub fn error(code: u64, message: impl AsRef<str>) -> Self {
+        MockFrame::Text(
+            serde_json::json!({ "code": code, "message": message.as_ref() }).to_string(),
+        )
+    }
+}
+
+/// Builds a [`MockServer`] out of the scripts of the connections it serves.
+#[derive(Debug, Default)]
+pub struct MockServerBuilder {
+    connections: Vec<Vec<MockFrame>>,
+}
+
+impl MockServerBuilder {
+    /// Adds a connection, served once the previous ones were, going through `frames` and then
+    /// closing it normally unless the script ended it otherwise.
+    pub fn connection(mut self, frames: impl IntoIterator<Item = MockFrame>) -> Self {
+        self.connections.push(frames.into_iter().collect());
+        self
+    }
+
+    /// Starts listening on a local port. Connections past the scripted ones are refused.
+    pub async fn start(self) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind the mock server");
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let task = tokio::spawn(serve(listener, self.connections, requests.clone()));
+        MockServer {
+            url,
+            requests,
+            task,
+        }
+    }
+}
+
+/// An in-process mock of Tardis Machine Server, see the [module](self) documentation. Stops
+/// serving once dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Creates a [`MockServerBuilder`].
+    pub fn builder() -> MockServerBuilder {
+        MockServerBuilder::default()
+    }
+
+    /// Returns the URL to build the [`Client`](super::Client) with, eg. `ws://127.0.0.1:41234`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the URIs requested by the connections so far, eg.
+    /// `/ws-stream-normalized?options=...`, to check the options sent by the client.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// The handshake callback returns the error response of tungstenite, which is large.
+#[allow(clippy::result_large_err)]
+async fn serve(
+    listener: TcpListener,
+    connections: Vec<Vec<MockFrame>>,
+    requests: Arc<Mutex<Vec<String>>>,
+) {
+    for frames in connections {
+        let Ok((tcp, _)) = listener.accept().await else {
+            return;
+        };
+        let requests = requests.clone();
+        let callback = move |req: &Request, resp: Response| {
+            requests.lock().unwrap().push(req.uri().to_string());
+            Ok(resp)
+        };
+        let Ok(mut ws) = tokio_tungstenite::accept_hdr_async(tcp, callback).await else {
+            continue;
+        };
+
+        let mut close = Some(CloseFrame {
+            code: CloseCode::Normal,
+            reason: "".into(),
+        });
+        for frame in frames {
+            match frame {
+                MockFrame::Text(text) => {
+                    if ws.send(Frame::Text(text)).await.is_err() {
+                        close = None;
+                        break;
+                    }
+                }
+                MockFrame::Delay(delay) => tokio::time::sleep(delay).await,
+                MockFrame::Close(code, reason) => {
+                    close = Some(CloseFrame {
+                        code: code.into(),
+                        reason: reason.into(),
+                    });
+                    break;
+                }
+                MockFrame::Drop => {
+                    close = None;
+                    break;
+                }
+                MockFrame::Hold => {
+                    while let Some(Ok(frame)) = ws.next().await {
+                        if frame.is_close() {
+                            break;
+                        }
+                    }
+                    close = None;
+                    break;
+                }
+            }
+        }
+        if let Some(close) = close {
+            ws.close(Some(close)).await.ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        machine::{Client, DataType, Error, StreamNormalizedRequestOptions},
+        Exchange, RestartPolicy,
+    };
+
+    const DISCONNECT: &str =
+        r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.000Z"}"#;
+
+    #[tokio::test]
+    async fn test_mock_server() {
+        let disconnect = serde_json::from_str::<Message>(DISCONNECT).unwrap();
+        let server = MockServer::builder()
+            .connection([
+                MockFrame::message(&disconnect),
+                MockFrame::Delay(Duration::from_millis(10)),
+                MockFrame::Drop,
+            ])
+            .connection([
+                MockFrame::text(DISCONNECT),
+                MockFrame::error(100, "Invalid"),
+            ])
+            .start()
+            .await;
+
+        let messages = Client::builder(server.url())
+            .reconnect(RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(1))
+            .build()
+            .stream_normalized(vec![StreamNormalizedRequestOptions::builder(
+                Exchange::Bybit,
+            )
+            .data_type(DataType::Trade)
+            .build()])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(
+            messages[..],
+            [
+                Ok(Message::Disconnect(_)),
+                Ok(Message::Disconnect(_)),
+                Err(Error::ServerError { code: 100, .. }),
+            ]
+        ));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("/ws-stream-normalized?options="));
+    }
+}
@@ -2,8 +2,41 @@
 
 //! The API Client and types specific to [Tardis Machine Server](https://docs.tardis.dev/api/tardis-machine).
 
+pub mod basis;
+pub mod book;
+mod borrowed;
 mod client;
+pub mod currency;
+mod data_type;
+#[cfg(feature = "decimal")]
+pub mod decimal;
+mod deflate;
+pub mod gaps;
+#[cfg(feature = "test-util")]
+pub mod golden;
+pub mod latency;
+pub mod metrics;
+pub mod micros;
+#[cfg(feature = "test-util")]
+pub mod mock;
 mod models;
+pub mod ordering;
+pub mod pacing;
+mod prefilter;
+mod proxy;
+pub mod quotes;
+pub mod session;
+pub mod split;
+pub mod stats;
+mod symbol;
+pub mod synthetic;
+mod tls;
+pub mod trades;
 
+pub use borrowed::*;
 pub use client::*;
+pub use data_type::*;
 pub use models::*;
+pub use prefilter::*;
+pub use proxy::*;
+pub use symbol::*;
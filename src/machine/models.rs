@@ -1,6 +1,20 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use super::{DataType, Symbol};
 use crate::Exchange;
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+pub use crate::Filter;
+use bytes::Bytes;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{
+    de::{self, value::MapAccessDeserializer, DeserializeOwned, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_json::{Map, Value};
+use smallvec::SmallVec;
 
 /// The options that can be specified for calling Tardis Machine Server's replay-normalized.
 #[derive(Debug, Clone, Serialize)]
@@ -23,7 +37,7 @@ pub struct ReplayNormalizedRequestOptions {
 
     /// Array of normalized [data types](https://docs.tardis.dev/api/tardis-machine#normalized-data-types)
     /// for which real-time data will be provided.
-    pub data_types: Vec<String>,
+    pub data_types: Vec<DataType>,
 
     /// When set to true, sends also disconnect messages that mark events when real-time WebSocket
     /// connection that was used to collect the historical data got disconnected.
@@ -32,6 +46,178 @@ pub struct ReplayNormalizedRequestOptions {
     pub with_disconnect_messages: Option<bool>,
 }
 
+impl ReplayNormalizedRequestOptions {
+    /// Checks the options for the mistakes the server would reject them for, which the
+    /// [`Client`](super::Client) does before connecting.
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if self.from >= self.to {
+            return Err(OptionsError::InvalidDateRange {
+                from: self.from,
+                to: self.to,
+            });
+        }
+        if self.from > Utc::now() {
+            return Err(OptionsError::FutureDateRange { from: self.from });
+        }
+        validate(&self.symbols, &self.data_types)
+    }
+
+    /// Creates a [`ReplayNormalizedRequestOptionsBuilder`] for replaying data of `exchange`.
+    ///
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use tardis_rs::{machine::{DataType, ReplayNormalizedRequestOptions}, Exchange};
+    ///
+    /// let options = ReplayNormalizedRequestOptions::builder(Exchange::Bybit)
+    ///     .symbols(vec!["BTCUSDT".to_string()])
+    ///     .from(Utc.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap())
+    ///     .to(Utc.with_ymd_and_hms(2022, 10, 2, 0, 0, 0).unwrap())
+    ///     .data_types([DataType::Trade])
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(options.data_types, vec![DataType::Trade]);
+    /// ```
+    pub fn builder(exchange: Exchange) -> ReplayNormalizedRequestOptionsBuilder {
+        ReplayNormalizedRequestOptionsBuilder {
+            exchange,
+            symbols: None,
+            from: None,
+            to: None,
+            data_types: vec![],
+            with_disconnect_messages: None,
+        }
+    }
+}
+
+/// The error when request options are invalid.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum OptionsError {
+    /// A required option was not set.
+    #[error("Missing option `{0}`")]
+    Missing(&'static str),
+
+    /// The replay period doesn't end after it starts.
+    #[error("Replay period is empty: from {from} to {to}")]
+    InvalidDateRange {
+        /// The start of the replay period.
+        from: DateTime<Utc>,
+        /// The end of the replay period.
+        to: DateTime<Utc>,
+    },
+
+    /// The replay period starts in the future, so there is no historical data to replay yet.
+    #[error("Replay period starts in the future: {from}")]
+    FutureDateRange {
+        /// The start of the replay period.
+        from: DateTime<Utc>,
+    },
+
+    /// No data type was requested.
+    #[error("No data types requested")]
+    EmptyDataTypes,
+
+    /// The symbols were set to an empty list, which the server rejects. Leave them unset to
+    /// request every symbol of the exchange.
+    #[error("Symbols cannot be empty, leave them unset to request every symbol")]
+    EmptySymbols,
+
+    /// The data type can't be produced by the server, eg. book snapshots of zero levels or with
+    /// an interval in ticks.
+    #[error("Unsupported data type `{0}`")]
+    UnsupportedDataType(DataType),
+}
+
+/// Checks the options shared by replays and real-time streams.
+fn validate(symbols: &Option<Vec<String>>, data_types: &[DataType]) -> Result<(), OptionsError> {
+    if symbols.as_ref().is_some_and(Vec::is_empty) {
+        return Err(OptionsError::EmptySymbols);
+    }
+    if data_types.is_empty() {
+        return Err(OptionsError::EmptyDataTypes);
+    }
+
+    for data_type in data_types {
+        let supported = match *data_type {
+            DataType::TradeBar { interval, .. } => interval > 0,
+            DataType::BookSnapshot { depth, unit, .. } => depth > 0 && unit.is_time(),
+            _ => true,
+        };
+        if !supported {
+            return Err(OptionsError::UnsupportedDataType(*data_type));
+        }
+    }
+    Ok(())
+}
+
+/// Builds [`ReplayNormalizedRequestOptions`], checking the options on [`build`](Self::build).
+#[derive(Debug, Clone)]
+pub struct ReplayNormalizedRequestOptionsBuilder {
+    exchange: Exchange,
+    symbols: Option<Vec<String>>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    data_types: Vec<DataType>,
+    with_disconnect_messages: Option<bool>,
+}
+
+impl ReplayNormalizedRequestOptionsBuilder {
+    /// Sets the symbols to replay, all symbols of the exchange by default.
+    pub fn symbols(mut self, symbols: Vec<String>) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Same as [`symbols`](Self::symbols), collecting the symbols from an iterator.
+    pub fn symbols_iter(self, symbols: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.symbols(symbols.into_iter().map(Into::into).collect())
+    }
+
+    /// Sets the start of the replay period, required.
+    pub fn from(mut self, from: DateTime<Utc>) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// Sets the end of the replay period, required.
+    pub fn to(mut self, to: DateTime<Utc>) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Adds data types to replay.
+    pub fn data_types(mut self, data_types: impl IntoIterator<Item = DataType>) -> Self {
+        self.data_types.extend(data_types);
+        self
+    }
+
+    /// Adds a data type to replay.
+    pub fn data_type(mut self, data_type: DataType) -> Self {
+        self.data_types.push(data_type);
+        self
+    }
+
+    /// Sets whether to also replay disconnect messages.
+    pub fn with_disconnect_messages(mut self, with_disconnect_messages: bool) -> Self {
+        self.with_disconnect_messages = Some(with_disconnect_messages);
+        self
+    }
+
+    /// Creates the [`ReplayNormalizedRequestOptions`], failing if the replay period is missing or
+    /// the options are invalid, see [`ReplayNormalizedRequestOptions::validate`].
+    pub fn build(self) -> Result<ReplayNormalizedRequestOptions, OptionsError> {
+        let options = ReplayNormalizedRequestOptions {
+            exchange: self.exchange,
+            symbols: self.symbols,
+            from: self.from.ok_or(OptionsError::Missing("from"))?,
+            to: self.to.ok_or(OptionsError::Missing("to"))?,
+            data_types: self.data_types,
+            with_disconnect_messages: self.with_disconnect_messages,
+        };
+        options.validate()?;
+        Ok(options)
+    }
+}
+
 /// The options that can be specified for calling Tardis Machine Server's stream-normalized.
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -46,7 +232,7 @@ pub struct StreamNormalizedRequestOptions {
 
     /// Array of normalized [data types](https://docs.tardis.dev/api/tardis-machine#normalized-data-types)
     /// for which real-time data will be provided.
-    pub data_types: Vec<String>,
+    pub data_types: Vec<DataType>,
 
     /// When set to true, sends disconnect messages anytime underlying exchange real-time WebSocket
     /// connection(s) gets disconnected.
@@ -61,21 +247,301 @@ pub struct StreamNormalizedRequestOptions {
     pub timeout_interval_ms: Option<u64>,
 }
 
+impl StreamNormalizedRequestOptions {
+    /// Checks the options for the mistakes the server would reject them for, which the
+    /// [`Client`](super::Client) does before connecting.
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        validate(&self.symbols, &self.data_types)
+    }
+
+    /// Creates a [`StreamNormalizedRequestOptionsBuilder`] for streaming data of `exchange`.
+    ///
+    /// ```
+    /// use tardis_rs::{machine::{DataType, StreamNormalizedRequestOptions}, Exchange};
+    ///
+    /// let options = StreamNormalizedRequestOptions::builder(Exchange::Binance)
+    ///     .symbols_iter(["btcusdt", "ethusdt"])
+    ///     .data_type(DataType::Trade)
+    ///     .build();
+    /// assert_eq!(options.symbols.unwrap().len(), 2);
+    /// ```
+    pub fn builder(exchange: Exchange) -> StreamNormalizedRequestOptionsBuilder {
+        StreamNormalizedRequestOptionsBuilder {
+            options: StreamNormalizedRequestOptions {
+                exchange,
+                symbols: None,
+                data_types: vec![],
+                with_disconnect_messages: None,
+                timeout_interval_ms: None,
+            },
+        }
+    }
+}
+
+/// Builds [`StreamNormalizedRequestOptions`]. Options left unset fall back to the defaults of the
+/// [`Client`](super::Client), then of the server: no disconnect messages and no timeout.
+#[derive(Debug, Clone)]
+pub struct StreamNormalizedRequestOptionsBuilder {
+    options: StreamNormalizedRequestOptions,
+}
+
+impl StreamNormalizedRequestOptionsBuilder {
+    /// Sets the symbols to stream, all symbols of the exchange by default.
+    pub fn symbols(mut self, symbols: Vec<String>) -> Self {
+        self.options.symbols = Some(symbols);
+        self
+    }
+
+    /// Same as [`symbols`](Self::symbols), collecting the symbols from an iterator.
+    pub fn symbols_iter(self, symbols: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.symbols(symbols.into_iter().map(Into::into).collect())
+    }
+
+    /// Adds data types to stream.
+    pub fn data_types(mut self, data_types: impl IntoIterator<Item = DataType>) -> Self {
+        self.options.data_types.extend(data_types);
+        self
+    }
+
+    /// Adds a data type to stream.
+    pub fn data_type(mut self, data_type: DataType) -> Self {
+        self.options.data_types.push(data_type);
+        self
+    }
+
+    /// Sets whether to also send disconnect messages.
+    pub fn with_disconnect_messages(mut self, with_disconnect_messages: bool) -> Self {
+        self.options.with_disconnect_messages = Some(with_disconnect_messages);
+        self
+    }
+
+    /// Sets the time in milliseconds after which the connections to the exchanges are restarted
+    /// if no message has been received.
+    pub fn timeout_interval_ms(mut self, timeout_interval_ms: u64) -> Self {
+        self.options.timeout_interval_ms = Some(timeout_interval_ms);
+        self
+    }
+
+    /// Creates the [`StreamNormalizedRequestOptions`].
+    pub fn build(self) -> StreamNormalizedRequestOptions {
+        self.options
+    }
+}
+
+/// The options that can be specified for calling Tardis Machine Server's replay of raw exchange
+/// messages.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayRawRequestOptions {
+    /// Requested [`Exchange`].
+    pub exchange: Exchange,
+
+    /// Optional channels and symbols to replay, all channels when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filters: Option<Vec<Filter>>,
+
+    /// Replay period start date (UTC) in a ISO 8601 format, e.g., 2019-04-01
+    pub from: DateTime<Utc>,
+
+    /// Replay period end date (UTC) in a ISO 8601 format, e.g., 2019-04-02
+    pub to: DateTime<Utc>,
+}
+
+/// The options that can be specified for calling Tardis Machine Server's stream of raw exchange
+/// messages.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamRawRequestOptions {
+    /// Requested [`Exchange`].
+    pub exchange: Exchange,
+
+    /// Optional channels and symbols to stream, all channels when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filters: Option<Vec<Filter>>,
+
+    /// Specifies time in milliseconds after which connection to real-time exchanges' WebSocket API
+    /// is restarted if no message has been received.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "timeoutIntervalMS")]
+    pub timeout_interval_ms: Option<u64>,
+}
+
 /// The possible type of message returned from Tardis Machine Server.
+///
+/// Messages of a type unknown to this crate, eg. added to a newer version of the server, are
+/// deserialized into [`Message::Unknown`] instead of failing.
 #[allow(missing_docs)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum Message {
     Trade(Trade),
-    BookChange(BookChange),
-    DerivativeTicker(DerivativeTicker),
-    BookSnapshot(BookSnapshot),
-    TradeBar(TradeBar),
+    BookChange(Box<BookChange>),
+    DerivativeTicker(Box<DerivativeTicker>),
+    BookSnapshot(Box<BookSnapshot>),
+    TradeBar(Box<TradeBar>),
     Disconnect(Disconnect),
+    Liquidation(Liquidation),
+    BookTicker(Box<BookTicker>),
+    OptionSummary(Box<OptionSummary>),
+    #[serde(untagged)]
+    Unknown(Box<UnknownMessage>),
+}
+
+impl Message {
+    /// Returns the type of the message, as found in its `type` field, or `unknown` for
+    /// [`Message::Unknown`] whose type is returned by [`UnknownMessage::kind`].
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::Trade(_) => "trade",
+            Message::BookChange(_) => "book_change",
+            Message::DerivativeTicker(_) => "derivative_ticker",
+            Message::BookSnapshot(_) => "book_snapshot",
+            Message::TradeBar(_) => "trade_bar",
+            Message::Disconnect(_) => "disconnect",
+            Message::Liquidation(_) => "liquidation",
+            Message::BookTicker(_) => "book_ticker",
+            Message::OptionSummary(_) => "option_summary",
+            Message::Unknown(_) => "unknown",
+        }
+    }
+
+    /// Returns the exchange the message originates from, `None` for a [`Message::Unknown`] without
+    /// an exchange known to this crate.
+    pub fn exchange(&self) -> Option<Exchange> {
+        match self {
+            Message::Trade(msg) => Some(msg.exchange),
+            Message::BookChange(msg) => Some(msg.exchange),
+            Message::DerivativeTicker(msg) => Some(msg.exchange),
+            Message::BookSnapshot(msg) => Some(msg.exchange),
+            Message::TradeBar(msg) => Some(msg.exchange),
+            Message::Disconnect(msg) => Some(msg.exchange),
+            Message::Liquidation(msg) => Some(msg.exchange),
+            Message::BookTicker(msg) => Some(msg.exchange),
+            Message::OptionSummary(msg) => Some(msg.exchange),
+            Message::Unknown(msg) => msg.exchange,
+        }
+    }
+
+    /// Returns the instrument symbol of the message, `None` for messages not tied to a symbol and
+    /// for [`Message::Unknown`].
+    pub fn symbol(&self) -> Option<&Symbol> {
+        match self {
+            Message::Trade(msg) => Some(&msg.symbol),
+            Message::BookChange(msg) => Some(&msg.symbol),
+            Message::DerivativeTicker(msg) => Some(&msg.symbol),
+            Message::BookSnapshot(msg) => Some(&msg.symbol),
+            Message::TradeBar(msg) => Some(&msg.symbol),
+            Message::Disconnect(_) => None,
+            Message::Liquidation(msg) => Some(&msg.symbol),
+            Message::BookTicker(msg) => Some(&msg.symbol),
+            Message::OptionSummary(msg) => Some(&msg.symbol),
+            Message::Unknown(_) => None,
+        }
+    }
+
+    /// Returns the message arrival timestamp, `None` for a [`Message::Unknown`] without one.
+    pub fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Message::Trade(msg) => Some(msg.local_timestamp),
+            Message::BookChange(msg) => Some(msg.local_timestamp),
+            Message::DerivativeTicker(msg) => Some(msg.local_timestamp),
+            Message::BookSnapshot(msg) => Some(msg.local_timestamp),
+            Message::TradeBar(msg) => Some(msg.local_timestamp),
+            Message::Disconnect(msg) => Some(msg.local_timestamp),
+            Message::Liquidation(msg) => Some(msg.local_timestamp),
+            Message::BookTicker(msg) => Some(msg.local_timestamp),
+            Message::OptionSummary(msg) => Some(msg.local_timestamp),
+            Message::Unknown(msg) => msg.local_timestamp,
+        }
+    }
+
+    /// Returns the timestamp provided by the exchange, `None` for [`Message::Disconnect`] and
+    /// [`Message::Unknown`].
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Message::Trade(msg) => Some(msg.timestamp),
+            Message::BookChange(msg) => Some(msg.timestamp),
+            Message::DerivativeTicker(msg) => Some(msg.timestamp),
+            Message::BookSnapshot(msg) => Some(msg.timestamp),
+            Message::TradeBar(msg) => Some(msg.timestamp),
+            Message::Disconnect(_) => None,
+            Message::Liquidation(msg) => Some(msg.timestamp),
+            Message::BookTicker(msg) => Some(msg.timestamp),
+            Message::OptionSummary(msg) => Some(msg.timestamp),
+            Message::Unknown(_) => None,
+        }
+    }
+
+    pub(crate) fn local_timestamp_mut(&mut self) -> Option<&mut DateTime<Utc>> {
+        match self {
+            Message::Trade(msg) => Some(&mut msg.local_timestamp),
+            Message::BookChange(msg) => Some(&mut msg.local_timestamp),
+            Message::DerivativeTicker(msg) => Some(&mut msg.local_timestamp),
+            Message::BookSnapshot(msg) => Some(&mut msg.local_timestamp),
+            Message::TradeBar(msg) => Some(&mut msg.local_timestamp),
+            Message::Disconnect(msg) => Some(&mut msg.local_timestamp),
+            Message::Liquidation(msg) => Some(&mut msg.local_timestamp),
+            Message::BookTicker(msg) => Some(&mut msg.local_timestamp),
+            Message::OptionSummary(msg) => Some(&mut msg.local_timestamp),
+            Message::Unknown(msg) => msg.local_timestamp.as_mut(),
+        }
+    }
+}
+
+/// A message kept as the raw JSON payload received from Tardis Machine Server.
+///
+/// The payload shares the buffer of the websocket frame it was received in, so recording or
+/// relaying it doesn't copy it and cloning it is cheap.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RawMessage(Bytes);
+
+impl RawMessage {
+    /// Creates a new instance of [`RawMessage`] out of a JSON payload.
+    pub fn new(payload: impl Into<Bytes>) -> Self {
+        Self(payload.into())
+    }
+
+    /// Returns the JSON payload.
+    pub fn payload(&self) -> &Bytes {
+        &self.0
+    }
+
+    /// Returns the JSON payload, consuming the message.
+    pub fn into_payload(self) -> Bytes {
+        self.0
+    }
+
+    /// Deserializes the payload, eg. into a [`Message`].
+    pub fn deserialize<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_slice(&self.0)
+    }
+}
+
+impl Deref for RawMessage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Serialize for RawMessage {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let payload = std::str::from_utf8(&self.0).map_err(serde::ser::Error::custom)?;
+        serde_json::from_str::<&serde_json::value::RawValue>(payload)
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl From<Bytes> for RawMessage {
+    fn from(payload: Bytes) -> Self {
+        Self(payload)
+    }
 }
 
 /// Side of the trade.
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TradeSide {
     /// Buy order.
@@ -93,7 +559,7 @@ pub enum TradeSide {
 #[serde(rename_all = "camelCase")]
 pub struct Trade {
     /// Instrument symbol as provided by exchange
-    pub symbol: String,
+    pub symbol: Symbol,
 
     /// Exchange ID
     pub exchange: Exchange,
@@ -102,18 +568,55 @@ pub struct Trade {
     pub id: Option<String>,
 
     /// Trade price as provided by exchange
+    #[serde(deserialize_with = "crate::de::f64")]
     pub price: f64,
 
     /// Trade amount as provided by exchange
+    #[serde(deserialize_with = "crate::de::f64")]
     pub amount: f64,
 
     /// Liquidity taker side (aggressor)
     pub side: TradeSide,
 
     /// Trade timestamp provided by exchange (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub timestamp: DateTime<Utc>,
 
     /// Message arrival timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// Liquidation of a position of a derivative instrument.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Liquidation {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Liquidation id if provided by exchange
+    pub id: Option<String>,
+
+    /// Liquidation price as provided by exchange
+    #[serde(deserialize_with = "crate::de::f64")]
+    pub price: f64,
+
+    /// Liquidation amount as provided by exchange
+    #[serde(deserialize_with = "crate::de::f64")]
+    pub amount: f64,
+
+    /// Liquidation side, `buy` when a short position was liquidated and `sell` for a long one
+    pub side: TradeSide,
+
+    /// Liquidation timestamp provided by exchange (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub local_timestamp: DateTime<Utc>,
 }
 
@@ -124,7 +627,7 @@ pub struct Trade {
 #[serde(rename_all = "camelCase")]
 pub struct BookChange {
     /// Instrument symbol as provided by exchange
-    pub symbol: String,
+    pub symbol: Symbol,
 
     /// Exchange ID
     pub exchange: Exchange,
@@ -133,16 +636,18 @@ pub struct BookChange {
     pub is_snapshot: bool,
 
     /// Updated bids price-amount levels
-    pub bids: Vec<BookLevel>,
+    pub bids: BookLevels,
 
     /// Updated asks price-amount levels
-    pub asks: Vec<BookLevel>,
+    pub asks: BookLevels,
 
     /// Order book update timestamp if provided by exchange,
     /// otherwise equals to localTimestamp, (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub timestamp: DateTime<Utc>,
 
     /// Message arrival timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub local_timestamp: DateTime<Utc>,
 }
 
@@ -151,44 +656,272 @@ pub struct BookChange {
 #[serde(rename_all = "camelCase")]
 pub struct DerivativeTicker {
     /// Instrument symbol as provided by exchange
-    pub symbol: String,
+    pub symbol: Symbol,
 
     /// Exchange ID
     pub exchange: Exchange,
 
     /// Last instrument price if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
     pub last_price: Option<f64>,
 
     /// Last open interest if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
     pub open_interest: Option<f64>,
 
     /// Last funding rate if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
     pub funding_rate: Option<f64>,
 
     /// Last index price if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
     pub index_price: Option<f64>,
 
     /// Last mark price if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
     pub mark_price: Option<f64>,
 
     /// Message timestamp provided by exchange (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub timestamp: DateTime<Utc>,
 
     /// Message arrival timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub local_timestamp: DateTime<Utc>,
 }
 
-/// A particular level in the order book.
+/// Best bid and ask of an instrument, sent on every change of the top of the order book.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct BookTicker {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Best ask amount, if the ask side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub ask_amount: Option<f64>,
+
+    /// Best ask price, if the ask side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub ask_price: Option<f64>,
+
+    /// Best bid price, if the bid side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub bid_price: Option<f64>,
+
+    /// Best bid amount, if the bid side isn't empty
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub bid_amount: Option<f64>,
+
+    /// Message timestamp provided by exchange (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// Type of an option.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionType {
+    /// Put option.
+    Put,
+
+    /// Call option.
+    Call,
+}
+
+/// Summary of an option instrument, with its best bid and ask, implied volatilities and greeks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptionSummary {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Type of the option
+    pub option_type: OptionType,
+
+    /// Strike price of the option
+    #[serde(deserialize_with = "crate::de::f64")]
+    pub strike_price: f64,
+
+    /// Expiration date of the option (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub expiration_date: DateTime<Utc>,
+
+    /// Best bid price if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub best_bid_price: Option<f64>,
+
+    /// Best bid amount if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub best_bid_amount: Option<f64>,
+
+    /// Implied volatility of the best bid if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    #[serde(rename = "bestBidIV")]
+    pub best_bid_iv: Option<f64>,
+
+    /// Best ask price if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub best_ask_price: Option<f64>,
+
+    /// Best ask amount if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub best_ask_amount: Option<f64>,
+
+    /// Implied volatility of the best ask if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    #[serde(rename = "bestAskIV")]
+    pub best_ask_iv: Option<f64>,
+
+    /// Last traded price if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub last_price: Option<f64>,
+
+    /// Open interest if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub open_interest: Option<f64>,
+
+    /// Mark price if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub mark_price: Option<f64>,
+
+    /// Implied volatility of the mark price if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    #[serde(rename = "markIV")]
+    pub mark_iv: Option<f64>,
+
+    /// Delta if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub delta: Option<f64>,
+
+    /// Gamma if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub gamma: Option<f64>,
+
+    /// Vega if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub vega: Option<f64>,
+
+    /// Theta if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub theta: Option<f64>,
+
+    /// Rho if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub rho: Option<f64>,
+
+    /// Price of the underlying if provided by exchange
+    #[serde(default, deserialize_with = "crate::de::option_f64")]
+    pub underlying_price: Option<f64>,
+
+    /// Index of the underlying, eg. `BTC-USD`
+    pub underlying_index: String,
+
+    /// Message timestamp provided by exchange (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// A particular level in the order book.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct BookLevel {
     /// The desired price of the order.
+    #[serde(deserialize_with = "crate::de::f64")]
     pub price: f64,
 
     /// The quantity of the order.
+    #[serde(deserialize_with = "crate::de::f64")]
     pub amount: f64,
 }
 
+/// The number of levels a [`BookLevels`] stores without allocating.
+pub const INLINE_BOOK_LEVELS: usize = 4;
+
+/// The price levels of one side of a [`BookChange`].
+///
+/// Most incremental updates only touch a handful of levels, up to [`INLINE_BOOK_LEVELS`] of them
+/// are stored inline to avoid an allocation per update. Dereferences to a slice of [`BookLevel`]s.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BookLevels(SmallVec<[BookLevel; INLINE_BOOK_LEVELS]>);
+
+impl BookLevels {
+    /// Creates an empty instance of [`BookLevels`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a level.
+    pub fn push(&mut self, level: BookLevel) {
+        self.0.push(level);
+    }
+
+    /// Converts the levels into a [`Vec`], without copying if they were already spilled to the
+    /// heap.
+    pub fn into_vec(self) -> Vec<BookLevel> {
+        self.0.into_vec()
+    }
+}
+
+impl Deref for BookLevels {
+    type Target = [BookLevel];
+
+    fn deref(&self) -> &[BookLevel] {
+        &self.0
+    }
+}
+
+impl DerefMut for BookLevels {
+    fn deref_mut(&mut self) -> &mut [BookLevel] {
+        &mut self.0
+    }
+}
+
+impl From<Vec<BookLevel>> for BookLevels {
+    fn from(levels: Vec<BookLevel>) -> Self {
+        Self(SmallVec::from_vec(levels))
+    }
+}
+
+impl FromIterator<BookLevel> for BookLevels {
+    fn from_iter<I: IntoIterator<Item = BookLevel>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for BookLevels {
+    type Item = BookLevel;
+    type IntoIter = smallvec::IntoIter<[BookLevel; INLINE_BOOK_LEVELS]>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a BookLevels {
+    type Item = &'a BookLevel;
+    type IntoIter = std::slice::Iter<'a, BookLevel>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
 /// Order book snapshot for selected number_of_levels (top bids and asks), snapshot_interval and time_unit.
 /// When snapshot_interval is set to 0 , snapshots are taken anytime order book state within specified
 /// levels has changed, otherwise snapshots are taken anytime snapshot_interval time has passed and
@@ -198,7 +931,7 @@ pub struct BookLevel {
 #[serde(rename_all = "camelCase")]
 pub struct BookSnapshot {
     /// Instrument symbol as provided by exchange
-    pub symbol: String,
+    pub symbol: Symbol,
 
     /// Exchange ID
     pub exchange: Exchange,
@@ -219,9 +952,11 @@ pub struct BookSnapshot {
     pub asks: Vec<BookLevel>,
 
     /// Snapshot timestamp based on last book_change message processed timestamp adjusted to snapshot interval
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub timestamp: DateTime<Utc>,
 
     /// Message arrival timestamp that triggered snapshot (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub local_timestamp: DateTime<Utc>,
 }
 
@@ -242,7 +977,7 @@ pub enum TradeBarKind {
 #[serde(rename_all = "camelCase")]
 pub struct TradeBar {
     /// Instrument symbol as provided by exchange
-    pub symbol: String,
+    pub symbol: Symbol,
 
     /// Exchange ID
     pub exchange: Exchange,
@@ -254,42 +989,54 @@ pub struct TradeBar {
     pub interval: u64,
 
     /// open price
+    #[serde(deserialize_with = "crate::de::f64")]
     pub open: f64,
 
     /// high price
+    #[serde(deserialize_with = "crate::de::f64")]
     pub high: f64,
 
     /// low price
+    #[serde(deserialize_with = "crate::de::f64")]
     pub low: f64,
 
     /// close price
+    #[serde(deserialize_with = "crate::de::f64")]
     pub close: f64,
 
     /// total volume traded in given interval
+    #[serde(deserialize_with = "crate::de::f64")]
     pub volume: f64,
 
     /// buy volume traded in given interval
+    #[serde(deserialize_with = "crate::de::f64")]
     pub buy_volume: f64,
 
     /// sell volume traded in given interval
+    #[serde(deserialize_with = "crate::de::f64")]
     pub sell_volume: f64,
 
     /// trades count in given interval
     pub trades: u64,
 
     /// volume weighted average price
+    #[serde(deserialize_with = "crate::de::f64")]
     pub vwap: f64,
 
     /// timestamp of first trade for given bar (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub open_timestamp: DateTime<Utc>,
 
     /// timestamp of last trade for given bar (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub close_timestamp: DateTime<Utc>,
 
     /// end of interval period timestamp (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub timestamp: DateTime<Utc>,
 
     /// message arrival timestamp that triggered given bar computation (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub local_timestamp: DateTime<Utc>,
 }
 
@@ -302,5 +1049,633 @@ pub struct Disconnect {
     pub exchange: Exchange,
 
     /// message arrival timestamp that triggered given bar computation (ISO 8601 format)
+    #[serde(deserialize_with = "crate::de::timestamp")]
     pub local_timestamp: DateTime<Utc>,
 }
+
+/// Message of a type unknown to this crate, kept as its JSON payload.
+///
+/// Serializing it writes the payload back as it was received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownMessage {
+    /// Exchange ID, `None` if missing or unknown to this crate
+    pub exchange: Option<Exchange>,
+
+    /// Message arrival timestamp, `None` if missing or invalid
+    pub local_timestamp: Option<DateTime<Utc>>,
+
+    /// The whole message, including its `type` field
+    pub payload: serde_json::Value,
+}
+
+impl UnknownMessage {
+    /// Returns the type of the message, as found in its `type` field.
+    pub fn kind(&self) -> &str {
+        self.payload["type"].as_str().unwrap_or_default()
+    }
+
+    /// Creates the message out of the fields of a payload of the given type, the fields this
+    /// crate can't make sense of being left in the payload only.
+    fn from_fields(kind: &str, mut fields: Map<String, Value>) -> Self {
+        let exchange = fields
+            .get("exchange")
+            .and_then(|exchange| Exchange::deserialize(exchange).ok());
+        let local_timestamp = fields
+            .get("localTimestamp")
+            .and_then(|timestamp| crate::de::timestamp(timestamp).ok());
+        fields.insert("type".to_string(), Value::String(kind.to_string()));
+
+        Self {
+            exchange,
+            local_timestamp,
+            payload: Value::Object(fields),
+        }
+    }
+}
+
+impl Serialize for UnknownMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.payload.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(MessageVisitor)
+    }
+}
+
+/// Deserializes a [`Message`] in a single pass when its `type` field comes first, as it does in
+/// the messages of Tardis Machine Server, and buffers the fields otherwise.
+struct MessageVisitor;
+
+impl<'de> Visitor<'de> for MessageVisitor {
+    type Value = Message;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a message with a `type` field")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Message, A::Error> {
+        let Some(Key(key)) = map.next_key()? else {
+            return Err(de::Error::missing_field("type"));
+        };
+
+        if key == "type" {
+            let Key(kind) = map.next_value()?;
+            return message_of_kind(&kind, MapAccessDeserializer::new(map));
+        }
+
+        let mut fields = Map::new();
+        fields.insert(key.into_owned(), map.next_value()?);
+        while let Some((key, value)) = map.next_entry()? {
+            fields.insert(key, value);
+        }
+        let kind = match fields.remove("type") {
+            Some(Value::String(kind)) => kind,
+            Some(_) => return Err(de::Error::custom("`type` is not a string")),
+            None => return Err(de::Error::missing_field("type")),
+        };
+        message_of_kind(&kind, Value::Object(fields)).map_err(de::Error::custom)
+    }
+}
+
+/// Deserializes the fields other than `type` of a message of the given type.
+pub(super) fn message_of_kind<'de, D: Deserializer<'de>>(
+    kind: &str,
+    fields: D,
+) -> Result<Message, D::Error> {
+    Ok(match kind {
+        "trade" => Message::Trade(Trade::deserialize(fields)?),
+        "book_change" => Message::BookChange(Box::new(BookChange::deserialize(fields)?)),
+        "derivative_ticker" => {
+            Message::DerivativeTicker(Box::new(DerivativeTicker::deserialize(fields)?))
+        }
+        "book_snapshot" => Message::BookSnapshot(Box::new(BookSnapshot::deserialize(fields)?)),
+        "trade_bar" => Message::TradeBar(Box::new(TradeBar::deserialize(fields)?)),
+        "disconnect" => Message::Disconnect(Disconnect::deserialize(fields)?),
+        "liquidation" => Message::Liquidation(Liquidation::deserialize(fields)?),
+        "book_ticker" => Message::BookTicker(Box::new(BookTicker::deserialize(fields)?)),
+        "option_summary" => Message::OptionSummary(Box::new(OptionSummary::deserialize(fields)?)),
+        _ => {
+            let fields = Map::deserialize(fields)?;
+            Message::Unknown(Box::new(UnknownMessage::from_fields(kind, fields)))
+        }
+    })
+}
+
+/// A string borrowed from the input when possible, for the `type` field and its key.
+pub(super) struct Key<'de>(pub(super) Cow<'de, str>);
+
+impl<'de> Deserialize<'de> for Key<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = Key<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Key<'de>, E> {
+                Ok(Key(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Key<'de>, E> {
+                Ok(Key(Cow::Owned(v.to_string())))
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> Result<Key<'de>, E> {
+                Ok(Key(Cow::Owned(v)))
+            }
+        }
+
+        deserializer.deserialize_str(KeyVisitor)
+    }
+}
+
+/// Formats a timestamp as ISO 8601 with microseconds, the precision Tardis provides.
+fn fmt_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Formats the trailing timestamps shared by every message.
+fn fmt_timestamps(
+    f: &mut fmt::Formatter<'_>,
+    timestamp: &DateTime<Utc>,
+    local_timestamp: &DateTime<Utc>,
+) -> fmt::Result {
+    write!(
+        f,
+        " ts={} local={}",
+        fmt_timestamp(timestamp),
+        fmt_timestamp(local_timestamp)
+    )
+}
+
+/// Formats the best level of a side, `-` if the side is empty.
+fn fmt_best(f: &mut fmt::Formatter<'_>, levels: &[BookLevel]) -> fmt::Result {
+    match levels.first() {
+        Some(level) => write!(f, "{}", level),
+        None => write!(f, "-"),
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::Trade(msg) => msg.fmt(f),
+            Message::BookChange(msg) => msg.fmt(f),
+            Message::DerivativeTicker(msg) => msg.fmt(f),
+            Message::BookSnapshot(msg) => msg.fmt(f),
+            Message::TradeBar(msg) => msg.fmt(f),
+            Message::Disconnect(msg) => msg.fmt(f),
+            Message::Liquidation(msg) => msg.fmt(f),
+            Message::BookTicker(msg) => msg.fmt(f),
+            Message::OptionSummary(msg) => msg.fmt(f),
+            Message::Unknown(msg) => msg.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for TradeSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TradeSide::Buy => "buy",
+            TradeSide::Sell => "sell",
+            TradeSide::Unknown => "unknown",
+        })
+    }
+}
+
+impl fmt::Display for Trade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "trade {} {} {} {}@{}",
+            self.exchange, self.symbol, self.side, self.amount, self.price
+        )?;
+        if let Some(id) = &self.id {
+            write!(f, " id={}", id)?;
+        }
+        fmt_timestamps(f, &self.timestamp, &self.local_timestamp)
+    }
+}
+
+impl fmt::Display for Liquidation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "liquidation {} {} {} {}@{}",
+            self.exchange, self.symbol, self.side, self.amount, self.price
+        )?;
+        if let Some(id) = &self.id {
+            write!(f, " id={}", id)?;
+        }
+        fmt_timestamps(f, &self.timestamp, &self.local_timestamp)
+    }
+}
+
+impl fmt::Display for BookChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "book_change {} {} {} bids={} asks={}",
+            self.exchange,
+            self.symbol,
+            if self.is_snapshot {
+                "snapshot"
+            } else {
+                "update"
+            },
+            self.bids.len(),
+            self.asks.len()
+        )?;
+        fmt_timestamps(f, &self.timestamp, &self.local_timestamp)
+    }
+}
+
+impl fmt::Display for DerivativeTicker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "derivative_ticker {} {}", self.exchange, self.symbol)?;
+        let fields = [
+            ("last", self.last_price),
+            ("mark", self.mark_price),
+            ("index", self.index_price),
+            ("funding", self.funding_rate),
+            ("oi", self.open_interest),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                write!(f, " {}={}", name, value)?;
+            }
+        }
+        fmt_timestamps(f, &self.timestamp, &self.local_timestamp)
+    }
+}
+
+impl fmt::Display for BookTicker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "book_ticker {} {} ", self.exchange, self.symbol)?;
+        match (self.bid_amount, self.bid_price) {
+            (Some(amount), Some(price)) => write!(f, "{}@{}", amount, price)?,
+            _ => f.write_str("-")?,
+        }
+        f.write_str(" / ")?;
+        match (self.ask_amount, self.ask_price) {
+            (Some(amount), Some(price)) => write!(f, "{}@{}", amount, price)?,
+            _ => f.write_str("-")?,
+        }
+        fmt_timestamps(f, &self.timestamp, &self.local_timestamp)
+    }
+}
+
+impl fmt::Display for OptionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OptionType::Put => "put",
+            OptionType::Call => "call",
+        })
+    }
+}
+
+impl fmt::Display for OptionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "option_summary {} {} {} strike={} expiry={}",
+            self.exchange,
+            self.symbol,
+            self.option_type,
+            self.strike_price,
+            fmt_timestamp(&self.expiration_date)
+        )?;
+        let fields = [
+            ("bid_iv", self.best_bid_iv),
+            ("ask_iv", self.best_ask_iv),
+            ("mark_iv", self.mark_iv),
+            ("delta", self.delta),
+            ("underlying", self.underlying_price),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                write!(f, " {}={}", name, value)?;
+            }
+        }
+        fmt_timestamps(f, &self.timestamp, &self.local_timestamp)
+    }
+}
+
+impl fmt::Display for BookLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.amount, self.price)
+    }
+}
+
+impl fmt::Display for BookSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} ", self.name, self.exchange, self.symbol)?;
+        fmt_best(f, &self.bids)?;
+        f.write_str(" / ")?;
+        fmt_best(f, &self.asks)?;
+        fmt_timestamps(f, &self.timestamp, &self.local_timestamp)
+    }
+}
+
+impl fmt::Display for TradeBar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} o={} h={} l={} c={} v={} trades={}",
+            self.name,
+            self.exchange,
+            self.symbol,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume,
+            self.trades
+        )?;
+        fmt_timestamps(f, &self.timestamp, &self.local_timestamp)
+    }
+}
+
+impl fmt::Display for UnknownMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The exchange as received, which may be unknown to this crate.
+        let exchange = self.payload["exchange"].as_str().unwrap_or("-");
+        write!(f, "{} {}", self.kind(), exchange)?;
+        if let Some(local_timestamp) = &self.local_timestamp {
+            write!(f, " local={}", fmt_timestamp(local_timestamp))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Disconnect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "disconnect {} local={}",
+            self.exchange,
+            fmt_timestamp(&self.local_timestamp)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::machine::IntervalUnit;
+
+    #[test]
+    fn test_replay_options_builder() {
+        let day = |day| Utc.with_ymd_and_hms(2022, 10, day, 0, 0, 0).unwrap();
+        let builder = ReplayNormalizedRequestOptions::builder(Exchange::Bybit)
+            .data_type(DataType::Trade)
+            .with_disconnect_messages(true);
+
+        let options = builder.clone().from(day(1)).to(day(2)).build().unwrap();
+        assert_eq!(options.from, day(1));
+        assert_eq!(options.symbols, None);
+        assert_eq!(options.with_disconnect_messages, Some(true));
+
+        assert_eq!(
+            builder.clone().from(day(1)).build().unwrap_err(),
+            OptionsError::Missing("to")
+        );
+        assert_eq!(
+            builder.clone().from(day(2)).to(day(2)).build().unwrap_err(),
+            OptionsError::InvalidDateRange {
+                from: day(2),
+                to: day(2)
+            }
+        );
+
+        let tomorrow = Utc::now() + chrono::Duration::days(1);
+        assert_eq!(
+            builder
+                .clone()
+                .from(tomorrow)
+                .to(tomorrow + chrono::Duration::days(1))
+                .build()
+                .unwrap_err(),
+            OptionsError::FutureDateRange { from: tomorrow }
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .from(day(1))
+                .to(day(2))
+                .symbols(vec![])
+                .build()
+                .unwrap_err(),
+            OptionsError::EmptySymbols
+        );
+        let snapshot = DataType::BookSnapshot {
+            depth: 10,
+            interval: 5,
+            unit: IntervalUnit::Ticks,
+        };
+        assert_eq!(
+            builder
+                .from(day(1))
+                .to(day(2))
+                .data_type(snapshot)
+                .build()
+                .unwrap_err(),
+            OptionsError::UnsupportedDataType(snapshot)
+        );
+        assert_eq!(
+            ReplayNormalizedRequestOptions::builder(Exchange::Bybit)
+                .from(day(1))
+                .to(day(2))
+                .build()
+                .unwrap_err(),
+            OptionsError::EmptyDataTypes
+        );
+    }
+
+    #[test]
+    fn test_stream_options_builder() {
+        let options = StreamNormalizedRequestOptions::builder(Exchange::Binance)
+            .symbols_iter(["btcusdt"])
+            .data_types([DataType::Trade, DataType::BookChange])
+            .timeout_interval_ms(5_000)
+            .build();
+
+        assert_eq!(options.symbols, Some(vec!["btcusdt".to_string()]));
+        assert_eq!(options.data_types.len(), 2);
+        assert_eq!(options.with_disconnect_messages, None);
+        assert_eq!(options.timeout_interval_ms, Some(5_000));
+    }
+
+    #[test]
+    fn test_raw_message() {
+        let line = include_str!("../../fixtures/golden/corpus.ndjson")
+            .lines()
+            .nth(2)
+            .unwrap();
+        let raw = RawMessage::new(line.to_string());
+
+        assert_eq!(&*raw, line.as_bytes());
+        assert!(matches!(
+            raw.deserialize::<Message>().unwrap(),
+            Message::Trade(trade) if trade.id.as_deref() == Some("a2")
+        ));
+        assert_eq!(serde_json::to_string(&raw).unwrap(), line);
+    }
+
+    #[test]
+    fn test_book_levels_inline() {
+        let change = include_str!("../../fixtures/golden/corpus.ndjson")
+            .lines()
+            .map(|line| serde_json::from_str::<Message>(line).unwrap())
+            .find_map(|message| match message {
+                Message::BookChange(change) if !change.is_snapshot => Some(change),
+                _ => None,
+            })
+            .unwrap();
+
+        assert!(!change.bids.0.spilled() && !change.asks.0.spilled());
+        assert_eq!(
+            serde_json::from_value::<BookLevels>(serde_json::to_value(&change.bids).unwrap())
+                .unwrap(),
+            change.bids
+        );
+    }
+
+    #[test]
+    fn test_message_size() {
+        // Large variants are boxed so that moving a message stays cheap, see `benches/message.rs`.
+        assert!(std::mem::size_of::<Message>() <= std::mem::size_of::<Trade>() + 8);
+    }
+
+    #[test]
+    fn test_liquidation() {
+        let json = r#"{"type":"liquidation","symbol":"BTCUSDT","exchange":"binance-futures","id":null,"price":19310.5,"amount":0.25,"side":"sell","timestamp":"2022-10-01T00:00:00.100Z","localTimestamp":"2022-10-01T00:00:00.104Z"}"#;
+        let message = serde_json::from_str::<Message>(json).unwrap();
+
+        let Message::Liquidation(liquidation) = &message else {
+            panic!("expected a liquidation, got {:?}", message);
+        };
+        assert_eq!(liquidation.side, TradeSide::Sell);
+        assert_eq!(liquidation.price, 19310.5);
+        assert_eq!(message.kind(), "liquidation");
+        assert_eq!(
+            message.to_string(),
+            "liquidation binance-futures BTCUSDT sell 0.25@19310.5 ts=2022-10-01T00:00:00.100000Z local=2022-10-01T00:00:00.104000Z"
+        );
+    }
+
+    #[test]
+    fn test_book_ticker() {
+        let json = r#"{"type":"book_ticker","symbol":"BTCUSDT","exchange":"bybit","askAmount":1.5,"askPrice":19311,"bidPrice":19310.5,"bidAmount":"0.5","timestamp":"2022-10-01T00:00:00.100Z","localTimestamp":"2022-10-01T00:00:00.104Z"}"#;
+        let message = serde_json::from_str::<Message>(json).unwrap();
+
+        let Message::BookTicker(ticker) = &message else {
+            panic!("expected a book ticker, got {:?}", message);
+        };
+        assert_eq!(ticker.bid_amount, Some(0.5));
+        assert_eq!(ticker.ask_price, Some(19311.0));
+        assert_eq!(
+            message.to_string(),
+            "book_ticker bybit BTCUSDT 0.5@19310.5 / 1.5@19311 ts=2022-10-01T00:00:00.100000Z local=2022-10-01T00:00:00.104000Z"
+        );
+
+        let json = r#"{"type":"book_ticker","symbol":"BTCUSDT","exchange":"bybit","timestamp":"2022-10-01T00:00:00.100Z","localTimestamp":"2022-10-01T00:00:00.104Z"}"#;
+        let message = serde_json::from_str::<Message>(json).unwrap();
+        assert!(message.to_string().contains(" - / - "));
+    }
+
+    #[test]
+    fn test_option_summary() {
+        let json = r#"{"type":"option_summary","symbol":"BTC-30SEP22-20000-C","exchange":"deribit","optionType":"call","strikePrice":20000,"expirationDate":"2022-09-30T08:00:00.000Z","bestBidPrice":0.0345,"bestBidAmount":12,"bestBidIV":61.2,"bestAskPrice":0.036,"bestAskAmount":7.5,"bestAskIV":63.04,"lastPrice":0.035,"openInterest":1520.3,"markPrice":0.0352,"markIV":62.1,"delta":0.48,"gamma":0.0001,"vega":21.7,"theta":-45.2,"rho":3.1,"underlyingPrice":19650.25,"underlyingIndex":"SYN.BTC-30SEP22","timestamp":"2022-09-20T00:00:00.100Z","localTimestamp":"2022-09-20T00:00:00.104Z"}"#;
+        let message = serde_json::from_str::<Message>(json).unwrap();
+
+        let Message::OptionSummary(summary) = &message else {
+            panic!("expected an option summary, got {:?}", message);
+        };
+        assert_eq!(summary.option_type, OptionType::Call);
+        assert_eq!(summary.best_bid_iv, Some(61.2));
+        assert_eq!(summary.mark_iv, Some(62.1));
+        assert_eq!(summary.rho, Some(3.1));
+        assert_eq!(summary.underlying_index, "SYN.BTC-30SEP22");
+        assert_eq!(
+            message.to_string(),
+            "option_summary deribit BTC-30SEP22-20000-C call strike=20000 expiry=2022-09-30T08:00:00.000000Z bid_iv=61.2 ask_iv=63.04 mark_iv=62.1 delta=0.48 underlying=19650.25 ts=2022-09-20T00:00:00.100000Z local=2022-09-20T00:00:00.104000Z"
+        );
+
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(value["bestAskIV"], 63.04);
+        assert_eq!(value["type"], "option_summary");
+    }
+
+    #[test]
+    fn test_unknown_message() {
+        let json = r#"{"type":"index_change","symbol":"BTCUSDT","exchange":"bybit","bidPrice":19310.5,"localTimestamp":"2022-10-01T00:00:00.104Z"}"#;
+        let message = serde_json::from_str::<Message>(json).unwrap();
+
+        let Message::Unknown(unknown) = &message else {
+            panic!("expected an unknown message, got {:?}", message);
+        };
+        assert_eq!(unknown.kind(), "index_change");
+        assert_eq!(message.kind(), "unknown");
+        assert_eq!(message.exchange(), Some(Exchange::Bybit));
+        assert_eq!(unknown.payload["bidPrice"], 19310.5);
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::from_str::<Value>(json).unwrap()
+        );
+        assert_eq!(
+            message.to_string(),
+            "index_change bybit local=2022-10-01T00:00:00.104000Z"
+        );
+
+        // Known types are recognized wherever their `type` field is.
+        let json = r#"{"exchange":"bybit","localTimestamp":"2022-10-01T00:00:00.104Z","type":"disconnect"}"#;
+        assert!(matches!(
+            serde_json::from_str::<Message>(json).unwrap(),
+            Message::Disconnect(_)
+        ));
+
+        // Only the type is required, the exchange and timestamp being left in the payload when
+        // missing or unknown to this crate.
+        let json =
+            r#"{"type":"index_change","exchange":"new-exchange","localTimestamp":1664582400}"#;
+        let message = serde_json::from_str::<Message>(json).unwrap();
+        let Message::Unknown(unknown) = &message else {
+            panic!("expected an unknown message, got {:?}", message);
+        };
+        assert_eq!(message.exchange(), None);
+        assert_eq!(message.local_timestamp(), None);
+        assert_eq!(unknown.payload["exchange"], "new-exchange");
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::from_str::<Value>(json).unwrap()
+        );
+        assert_eq!(message.to_string(), "index_change new-exchange");
+
+        let message = serde_json::from_str::<Message>(r#"{"type":"index_change"}"#).unwrap();
+        assert_eq!(message.to_string(), "index_change -");
+        assert!(serde_json::from_str::<Message>(r#"{"exchange":"bybit"}"#).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let messages = include_str!("../../fixtures/golden/corpus.ndjson")
+            .lines()
+            .map(|line| serde_json::from_str::<Message>(line).unwrap().to_string())
+            .collect::<Vec<_>>();
+
+        assert!(messages.iter().all(|message| !message.contains('\n')));
+        assert_eq!(
+            messages[2],
+            "trade bybit BTCUSDT buy 0.5@19311 id=a2 ts=2022-10-01T00:00:00.100000Z local=2022-10-01T00:00:00.104000Z"
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Utilities for keeping the messages of a stream ordered by their local timestamp.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use async_stream::stream;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt};
+
+use super::{Message, Result};
+use crate::log;
+
+/// What to do with a message whose `local_timestamp` is older than the one of the message
+/// emitted before it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TimestampPolicy {
+    /// Emit messages as they arrive.
+    #[default]
+    PassThrough,
+
+    /// Overwrite the `local_timestamp` of late messages with the latest timestamp emitted so far.
+    Clamp,
+
+    /// Buffer up to `window` messages and emit them sorted by `local_timestamp`. Messages that are
+    /// still late after going through the buffer are emitted as they are.
+    Reorder {
+        /// The maximum number of messages held back.
+        window: usize,
+    },
+
+    /// Emit late messages as [`Ordered::OutOfOrder`].
+    Flag,
+}
+
+/// A message of a stream that went through [`enforce_monotonic`].
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub enum Ordered {
+    Message(Message),
+    OutOfOrder(Message),
+}
+
+impl Ordered {
+    /// Returns the inner message, regardless of whether it was in order.
+    pub fn into_message(self) -> Message {
+        match self {
+            Ordered::Message(msg) | Ordered::OutOfOrder(msg) => msg,
+        }
+    }
+}
+
+/// A message held back in the reorder buffer, ordered by timestamp then arrival so that messages
+/// sharing a timestamp keep their original order. Messages without a timestamp come first.
+#[derive(Debug)]
+struct Pending {
+    local_timestamp: Option<DateTime<Utc>>,
+    sequence: u64,
+    message: Message,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, as BinaryHeap is a max-heap and we want to pop the oldest message first.
+        (other.local_timestamp, other.sequence).cmp(&(self.local_timestamp, self.sequence))
+    }
+}
+
+/// Applies a [`TimestampPolicy`] to a sequence of messages.
+#[derive(Debug, Default)]
+pub struct TimestampEnforcer {
+    policy: TimestampPolicy,
+    last: Option<DateTime<Utc>>,
+    buffer: BinaryHeap<Pending>,
+    sequence: u64,
+}
+
+impl TimestampEnforcer {
+    /// Creates a new instance of [`TimestampEnforcer`].
+    pub fn new(policy: TimestampPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a message, returning the messages that are ready to be emitted.
+    pub fn push(&mut self, message: Message) -> Vec<Ordered> {
+        match self.policy {
+            TimestampPolicy::Reorder { window } => {
+                self.sequence += 1;
+                self.buffer.push(Pending {
+                    local_timestamp: message.local_timestamp(),
+                    sequence: self.sequence,
+                    message,
+                });
+
+                let mut ready = vec![];
+                while self.buffer.len() > window {
+                    if let Some(pending) = self.buffer.pop() {
+                        ready.push(self.emit(pending.message));
+                    }
+                }
+                ready
+            }
+            _ => vec![self.emit(message)],
+        }
+    }
+
+    /// Returns the messages still held back, to be called once the input ends.
+    pub fn finish(&mut self) -> Vec<Ordered> {
+        let mut ready = vec![];
+        while let Some(pending) = self.buffer.pop() {
+            ready.push(self.emit(pending.message));
+        }
+        ready
+    }
+
+    fn emit(&mut self, mut message: Message) -> Ordered {
+        // Unknown messages without a timestamp can't be out of order.
+        let Some(local_timestamp) = message.local_timestamp() else {
+            return Ordered::Message(message);
+        };
+
+        match self.last {
+            Some(last) if local_timestamp < last => {
+                log::warn!(
+                    "Out of order message: {} is older than {}",
+                    local_timestamp,
+                    last
+                );
+
+                match self.policy {
+                    TimestampPolicy::Clamp => {
+                        if let Some(local_timestamp) = message.local_timestamp_mut() {
+                            *local_timestamp = last;
+                        }
+                        Ordered::Message(message)
+                    }
+                    TimestampPolicy::Flag => Ordered::OutOfOrder(message),
+                    _ => Ordered::Message(message),
+                }
+            }
+            _ => {
+                self.last = Some(local_timestamp);
+                Ordered::Message(message)
+            }
+        }
+    }
+}
+
+/// Applies the given [`TimestampPolicy`] to the messages of the stream, while errors are passed
+/// through.
+pub fn enforce_monotonic<S>(
+    messages: S,
+    policy: TimestampPolicy,
+) -> impl Stream<Item = Result<Ordered>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    stream! {
+        futures_util::pin_mut!(messages);
+        let mut enforcer = TimestampEnforcer::new(policy);
+
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(msg) => {
+                    for ordered in enforcer.push(msg) {
+                        yield Ok(ordered);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+
+        for ordered in enforcer.finish() {
+            yield Ok(ordered);
+        }
+    }
+}
+
+/// Merges streams that are each ordered by `local_timestamp` into a single stream ordered by
+/// `local_timestamp`, eg. the replays of several exchanges opened on separate connections.
+///
+/// Messages sharing a timestamp are emitted in the order of the streams they come from. As the
+/// merge waits for every stream to have a message ready, a stalled stream holds back the others.
+/// Errors are passed through as soon as they are received.
+pub fn merge_ordered<S>(streams: impl IntoIterator<Item = S>) -> impl Stream<Item = Result<Message>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    let mut streams = streams.into_iter().map(Box::pin).collect::<Vec<_>>();
+
+    stream! {
+        let mut heads = BinaryHeap::with_capacity(streams.len());
+
+        for (index, messages) in streams.iter_mut().enumerate() {
+            while let Some(msg) = messages.next().await {
+                match msg {
+                    Ok(message) => {
+                        heads.push(Pending {
+                            local_timestamp: message.local_timestamp(),
+                            sequence: index as u64,
+                            message,
+                        });
+                        break;
+                    }
+                    Err(e) => yield Err(e),
+                }
+            }
+        }
+
+        while let Some(head) = heads.pop() {
+            let index = head.sequence as usize;
+            yield Ok(head.message);
+
+            while let Some(msg) = streams[index].next().await {
+                match msg {
+                    Ok(message) => {
+                        heads.push(Pending {
+                            local_timestamp: message.local_timestamp(),
+                            sequence: index as u64,
+                            message,
+                        });
+                        break;
+                    }
+                    Err(e) => yield Err(e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{machine::Disconnect, Exchange};
+
+    fn message(millis: i64) -> Message {
+        Message::Disconnect(Disconnect {
+            exchange: Exchange::Bybit,
+            local_timestamp: Utc.timestamp_millis_opt(millis).unwrap(),
+        })
+    }
+
+    fn millis(ordered: Vec<Ordered>) -> Vec<(i64, bool)> {
+        ordered
+            .into_iter()
+            .map(|ordered| match ordered {
+                Ordered::Message(msg) => (msg.local_timestamp().unwrap().timestamp_millis(), false),
+                Ordered::OutOfOrder(msg) => {
+                    (msg.local_timestamp().unwrap().timestamp_millis(), true)
+                }
+            })
+            .collect()
+    }
+
+    fn run(policy: TimestampPolicy, input: &[i64]) -> Vec<(i64, bool)> {
+        let mut enforcer = TimestampEnforcer::new(policy);
+        let mut output = vec![];
+        for millis in input {
+            output.extend(enforcer.push(message(*millis)));
+        }
+        output.extend(enforcer.finish());
+        millis(output)
+    }
+
+    #[test]
+    fn test_clamp() {
+        assert_eq!(
+            run(TimestampPolicy::Clamp, &[1, 3, 2, 4]),
+            vec![(1, false), (3, false), (3, false), (4, false)]
+        );
+    }
+
+    #[test]
+    fn test_flag() {
+        assert_eq!(
+            run(TimestampPolicy::Flag, &[1, 3, 2, 4]),
+            vec![(1, false), (3, false), (2, true), (4, false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_ordered() {
+        let stream = |input: &[i64]| {
+            futures_util::stream::iter(
+                input
+                    .iter()
+                    .map(|millis| Ok(message(*millis)))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let merged = merge_ordered([stream(&[1, 4, 4, 7]), stream(&[]), stream(&[0, 4, 9])])
+            .map(|msg| msg.unwrap().local_timestamp().unwrap().timestamp_millis())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(merged, vec![0, 1, 4, 4, 4, 7, 9]);
+    }
+
+    #[test]
+    fn test_reorder() {
+        assert_eq!(
+            run(TimestampPolicy::Reorder { window: 2 }, &[1, 3, 2, 5, 4, 0]),
+            vec![
+                (1, false),
+                (2, false),
+                (3, false),
+                (0, false),
+                (4, false),
+                (5, false)
+            ]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Utilities for delivering a replay at the pace the messages were originally received, eg. to
+//! shadow-test a strategy against historical data in real-time.
+
+use async_stream::stream;
+use futures_util::{Stream, StreamExt};
+use tokio::time::Instant;
+
+use super::{Message, Result};
+
+/// Delays the messages of the stream so that they are delivered `speed` times as fast as they
+/// were received, going by their `local_timestamp`, eg. at real-time speed with a `speed` of 1 or
+/// ten times as fast with 10. Errors are passed through without delay.
+///
+/// The pace is anchored to the first message, so a consumer that falls behind catches up by
+/// receiving the late messages right away. Messages older than the ones before them aren't
+/// delayed.
+///
+/// # Panics
+///
+/// Panics if `speed` isn't positive.
+pub fn pace<S>(messages: S, speed: f64) -> impl Stream<Item = Result<Message>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    assert!(speed > 0.0, "speed must be positive, got {}", speed);
+
+    stream! {
+        futures_util::pin_mut!(messages);
+        let mut anchor = None;
+
+        while let Some(msg) = messages.next().await {
+            // Messages without a timestamp are delivered right away.
+            if let Some(local_timestamp) = msg.as_ref().ok().and_then(Message::local_timestamp) {
+                let (started, first_timestamp) =
+                    *anchor.get_or_insert((Instant::now(), local_timestamp));
+                let elapsed = (local_timestamp - first_timestamp)
+                    .to_std()
+                    .unwrap_or_default()
+                    .div_f64(speed);
+                if let Some(deadline) = started.checked_add(elapsed) {
+                    tokio::time::sleep_until(deadline).await;
+                }
+            }
+            yield msg;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{machine::Disconnect, Exchange};
+
+    fn message(millis: i64) -> Result<Message> {
+        Ok(Message::Disconnect(Disconnect {
+            exchange: Exchange::Bybit,
+            local_timestamp: Utc.timestamp_millis_opt(millis).unwrap(),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_pace() {
+        let messages =
+            futures_util::stream::iter([message(0), message(400), message(100), message(800)]);
+
+        let started = Instant::now();
+        let elapsed = pace(messages, 10.0)
+            .map(|_| started.elapsed())
+            .collect::<Vec<_>>()
+            .await;
+
+        // The late third message isn't delayed, and the last one is due 80ms in, not 800ms.
+        assert!(elapsed[1] >= Duration::from_millis(40));
+        assert!(elapsed[3] >= Duration::from_millis(80));
+        assert!(elapsed[3] < Duration::from_millis(400));
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+
+use memchr::memmem;
+
+/// A cheap filter of the normalized messages, applied to their text before they are deserialized
+/// to skip the messages that aren't needed, eg. of a multi-symbol subscription. Set with
+/// [`ClientBuilder::prefilter`](super::ClientBuilder::prefilter).
+///
+/// The filter scans the text for the `type` and `symbol` fields and compares their values with
+/// the whitelists. Messages that can't be told apart by the scan are kept, eg. the messages
+/// without a symbol, or whose symbol contains escaped characters.
+///
+/// ```
+/// use tardis_rs::machine::Prefilter;
+///
+/// let prefilter = Prefilter::new()
+///     .symbols(["XBTUSD", "ETHUSD"])
+///     .kinds(["trade", "disconnect"]);
+/// assert!(prefilter.matches(br#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex"}"#));
+/// assert!(!prefilter.matches(br#"{"type":"trade","symbol":"SOLUSD","exchange":"bitmex"}"#));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Prefilter {
+    symbols: Option<HashSet<Vec<u8>>>,
+    kinds: Option<HashSet<Vec<u8>>>,
+}
+
+impl Prefilter {
+    /// Creates a new instance of [`Prefilter`] keeping every message.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keeps the messages of the given symbols, as provided by the exchange.
+    pub fn symbols<S: AsRef<str>>(mut self, symbols: impl IntoIterator<Item = S>) -> Self {
+        self.symbols = Some(whitelist(symbols));
+        self
+    }
+
+    /// Only keeps the messages of the given types, eg. `trade` or `book_snapshot`, as returned by
+    /// [`Message::kind`](super::Message::kind). `disconnect` must be listed to keep the disconnect
+    /// messages.
+    pub fn kinds<S: AsRef<str>>(mut self, kinds: impl IntoIterator<Item = S>) -> Self {
+        self.kinds = Some(whitelist(kinds));
+        self
+    }
+
+    /// Returns `true` if the message with the given text should be kept.
+    pub fn matches(&self, payload: &[u8]) -> bool {
+        let rejects = |whitelist: &Option<HashSet<Vec<u8>>>, key: &[u8]| {
+            whitelist.as_ref().is_some_and(|whitelist| {
+                scan(payload, key).is_some_and(|value| !whitelist.contains(value))
+            })
+        };
+        !rejects(&self.kinds, br#""type""#) && !rejects(&self.symbols, br#""symbol""#)
+    }
+}
+
+fn whitelist<S: AsRef<str>>(values: impl IntoIterator<Item = S>) -> HashSet<Vec<u8>> {
+    values
+        .into_iter()
+        .map(|value| value.as_ref().as_bytes().to_vec())
+        .collect()
+}
+
+/// Returns the value of the first field named `key`, if it is a string without escaped
+/// characters.
+fn scan<'a>(payload: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let start = memmem::find(payload, key)? + key.len();
+    let rest = skip_whitespace(&payload[start..]).strip_prefix(b":")?;
+    let rest = skip_whitespace(rest).strip_prefix(b"\"")?;
+    let value = &rest[..memchr::memchr(b'"', rest)?];
+    if value.contains(&b'\\') {
+        return None;
+    }
+    Some(value)
+}
+
+fn skip_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefilter() {
+        let trade = |symbol: &str| {
+            format!(
+                r#"{{"type": "trade", "symbol" : "{}", "exchange":"bitmex","price":7996}}"#,
+                symbol
+            )
+        };
+        let disconnect = br#"{"type":"disconnect","exchange":"bitmex"}"#;
+
+        let prefilter = Prefilter::new().symbols(["XBTUSD"]);
+        assert!(prefilter.matches(trade("XBTUSD").as_bytes()));
+        assert!(!prefilter.matches(trade("ETHUSD").as_bytes()));
+        assert!(prefilter.matches(trade(r"ETH\u0055SD").as_bytes()));
+        assert!(prefilter.matches(disconnect));
+
+        let prefilter = prefilter.kinds(["book_change"]);
+        assert!(!prefilter.matches(trade("XBTUSD").as_bytes()));
+        assert!(!prefilter.matches(disconnect));
+        assert!(prefilter.matches(
+            br#"{"type":"book_change","symbol":"XBTUSD","exchange":"bitmex","bids":[],"asks":[]}"#
+        ));
+
+        assert!(Prefilter::new().matches(b"not json"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Tracking of the latest prices of instruments out of a stream of normalized messages.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{currency::QuoteNormalizer, Message, Symbol};
+use crate::Exchange;
+
+/// Identifies an instrument of an exchange.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct InstrumentKey {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+}
+
+impl InstrumentKey {
+    /// Creates a new instance of [`InstrumentKey`].
+    pub fn new(exchange: Exchange, symbol: impl Into<Symbol>) -> Self {
+        Self {
+            exchange,
+            symbol: symbol.into(),
+        }
+    }
+}
+
+/// Which price of a [`Quote`] to use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceKind {
+    /// Best bid price.
+    Bid,
+
+    /// Best ask price.
+    Ask,
+
+    /// Mid price between the best bid and ask.
+    Mid,
+
+    /// Last traded price.
+    Last,
+
+    /// Mark price of a derivative.
+    Mark,
+
+    /// Index price of a derivative.
+    Index,
+}
+
+/// The latest known prices of an instrument.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quote {
+    /// Best bid price, from the latest book snapshot or book ticker
+    pub bid_price: Option<f64>,
+
+    /// Amount available at the best bid price
+    pub bid_amount: Option<f64>,
+
+    /// Best ask price, from the latest book snapshot or book ticker
+    pub ask_price: Option<f64>,
+
+    /// Amount available at the best ask price
+    pub ask_amount: Option<f64>,
+
+    /// Last traded price, from trades or the derivative ticker
+    pub last_price: Option<f64>,
+
+    /// Last mark price, from the derivative ticker
+    pub mark_price: Option<f64>,
+
+    /// Last index price, from the derivative ticker
+    pub index_price: Option<f64>,
+
+    /// Arrival timestamp of the message that last updated the quote
+    pub local_timestamp: Option<DateTime<Utc>>,
+}
+
+impl Quote {
+    /// Returns the mid price, `None` unless both the best bid and ask are known.
+    pub fn mid_price(&self) -> Option<f64> {
+        Some((self.bid_price? + self.ask_price?) / 2.0)
+    }
+
+    /// Returns the requested price, if known.
+    pub fn price(&self, kind: PriceKind) -> Option<f64> {
+        match kind {
+            PriceKind::Bid => self.bid_price,
+            PriceKind::Ask => self.ask_price,
+            PriceKind::Mid => self.mid_price(),
+            PriceKind::Last => self.last_price,
+            PriceKind::Mark => self.mark_price,
+            PriceKind::Index => self.index_price,
+        }
+    }
+
+    /// Returns `true` if the quote was last updated more than `max_staleness` before `now`.
+    pub fn is_stale(&self, now: DateTime<Utc>, max_staleness: Duration) -> bool {
+        self.local_timestamp
+            .is_none_or(|updated| now - updated > max_staleness)
+    }
+}
+
+/// Keeps the latest [`Quote`] of every instrument seen in a stream of messages.
+///
+/// Quotes are updated from `book_snapshot`, `book_ticker`, `trade` and `derivative_ticker`
+/// messages.
+#[derive(Debug, Clone, Default)]
+pub struct QuoteTracker {
+    quotes: HashMap<InstrumentKey, Quote>,
+    normalizer: Option<QuoteNormalizer>,
+}
+
+impl QuoteTracker {
+    /// Creates a new instance of [`QuoteTracker`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Converts the prices of every instrument into its normalized quote currency, eg. so that
+    /// USDT and USD quoted markets can be compared directly.
+    pub fn normalizer(mut self, normalizer: QuoteNormalizer) -> Self {
+        self.normalizer = Some(normalizer);
+        self
+    }
+
+    /// Updates the quotes with a message, returning the instrument whose quote changed.
+    pub fn update(&mut self, message: &Message) -> Option<InstrumentKey> {
+        let key = InstrumentKey::new(message.exchange()?, message.symbol()?.clone());
+        let factor = self
+            .normalizer
+            .as_ref()
+            .map_or(1.0, |normalizer| normalizer.factor(&key));
+        let convert = |price: f64| price * factor;
+
+        match message {
+            Message::BookSnapshot(snapshot) => {
+                let quote = self.quotes.entry(key.clone()).or_default();
+                quote.bid_price = snapshot.bids.first().map(|level| convert(level.price));
+                quote.bid_amount = snapshot.bids.first().map(|level| level.amount);
+                quote.ask_price = snapshot.asks.first().map(|level| convert(level.price));
+                quote.ask_amount = snapshot.asks.first().map(|level| level.amount);
+                quote.local_timestamp = Some(snapshot.local_timestamp);
+            }
+            Message::BookTicker(ticker) => {
+                let quote = self.quotes.entry(key.clone()).or_default();
+                quote.bid_price = ticker.bid_price.map(convert);
+                quote.bid_amount = ticker.bid_amount;
+                quote.ask_price = ticker.ask_price.map(convert);
+                quote.ask_amount = ticker.ask_amount;
+                quote.local_timestamp = Some(ticker.local_timestamp);
+            }
+            Message::Trade(trade) => {
+                let quote = self.quotes.entry(key.clone()).or_default();
+                quote.last_price = Some(convert(trade.price));
+                quote.local_timestamp = Some(trade.local_timestamp);
+            }
+            Message::DerivativeTicker(ticker) => {
+                let quote = self.quotes.entry(key.clone()).or_default();
+                quote.last_price = ticker.last_price.map(convert).or(quote.last_price);
+                quote.mark_price = ticker.mark_price.map(convert).or(quote.mark_price);
+                quote.index_price = ticker.index_price.map(convert).or(quote.index_price);
+                quote.local_timestamp = Some(ticker.local_timestamp);
+            }
+            _ => return None,
+        }
+
+        Some(key)
+    }
+
+    /// Returns the latest quote of an instrument.
+    pub fn get(&self, key: &InstrumentKey) -> Option<&Quote> {
+        self.quotes.get(key)
+    }
+}
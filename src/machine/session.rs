@@ -0,0 +1,176 @@
+//! Bookkeeping of what a stream of messages actually delivered, eg. to assert on the outcome of a
+//! batch replay once it completed.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use async_stream::stream;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt};
+
+use super::{quotes::InstrumentKey, Error, Message, Result};
+use crate::log;
+
+/// What a session delivered so far, and in total once [`SessionSummary::completed`] is set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionSummary {
+    /// Number of messages received
+    pub messages: u64,
+
+    /// Number of messages received per type, eg. `trade`
+    pub by_type: BTreeMap<&'static str, u64>,
+
+    /// Number of messages received per instrument
+    pub by_instrument: BTreeMap<InstrumentKey, u64>,
+
+    /// Arrival timestamp of the first message
+    pub first_timestamp: Option<DateTime<Utc>>,
+
+    /// Arrival timestamp of the last message
+    pub last_timestamp: Option<DateTime<Utc>>,
+
+    /// Number of `disconnect` messages received
+    pub disconnects: u64,
+
+    /// Number of messages that could not be deserialized
+    pub deserialization_failures: u64,
+
+    /// Number of other errors yielded by the stream
+    pub errors: u64,
+
+    /// Whether the stream ended
+    pub completed: bool,
+}
+
+impl SessionSummary {
+    fn record(&mut self, message: &Result<Message>) {
+        let message = match message {
+            Ok(message) => message,
+            Err(Error::Deserialization(_)) => {
+                self.deserialization_failures += 1;
+                return;
+            }
+            Err(_) => {
+                self.errors += 1;
+                return;
+            }
+        };
+
+        self.messages += 1;
+        *self.by_type.entry(message.kind()).or_default() += 1;
+        if let (Some(exchange), Some(symbol)) = (message.exchange(), message.symbol()) {
+            let key = InstrumentKey::new(exchange, symbol.clone());
+            *self.by_instrument.entry(key).or_default() += 1;
+        }
+        if let Message::Disconnect(_) = message {
+            self.disconnects += 1;
+        }
+
+        if let Some(timestamp) = message.local_timestamp() {
+            self.first_timestamp.get_or_insert(timestamp);
+            self.last_timestamp = Some(timestamp);
+        }
+    }
+}
+
+impl fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} messages from {} instruments",
+            self.messages,
+            self.by_instrument.len()
+        )?;
+        if let (Some(first), Some(last)) = (self.first_timestamp, self.last_timestamp) {
+            write!(f, " between {} and {}", first, last)?;
+        }
+        for (kind, count) in &self.by_type {
+            write!(f, ", {} {}", count, kind)?;
+        }
+        write!(
+            f,
+            ", {} disconnects, {} deserialization failures, {} errors",
+            self.disconnects, self.deserialization_failures, self.errors
+        )?;
+        if !self.completed {
+            write!(f, " (in progress)")?;
+        }
+        Ok(())
+    }
+}
+
+/// A handle on a tracked session, readable while the stream is consumed and after it completed.
+#[derive(Debug, Clone, Default)]
+pub struct SessionHandle {
+    summary: Arc<Mutex<SessionSummary>>,
+}
+
+impl SessionHandle {
+    /// Returns a copy of the current summary of the session.
+    pub fn summary(&self) -> SessionSummary {
+        self.summary.lock().unwrap().clone()
+    }
+
+    /// Returns `true` once the stream ended.
+    pub fn is_completed(&self) -> bool {
+        self.summary.lock().unwrap().completed
+    }
+}
+
+/// Tracks what the given stream delivers, returning the stream along with a [`SessionHandle`] to
+/// read the [`SessionSummary`] from. The summary is logged once the stream ends.
+pub fn track_session<S>(messages: S) -> (impl Stream<Item = Result<Message>>, SessionHandle)
+where
+    S: Stream<Item = Result<Message>>,
+{
+    let handle = SessionHandle::default();
+    let summary = handle.summary.clone();
+
+    let messages = stream! {
+        futures_util::pin_mut!(messages);
+
+        while let Some(msg) = messages.next().await {
+            summary.lock().unwrap().record(&msg);
+            yield msg;
+        }
+
+        let mut summary = summary.lock().unwrap();
+        summary.completed = true;
+        log::info!("Session completed: {}", summary);
+    };
+
+    (messages, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_track_session() {
+        let mut messages = vec![];
+        for line in include_str!("../../fixtures/golden/corpus.ndjson")
+            .lines()
+            .chain(["{}"])
+        {
+            messages.push(serde_json::from_str::<Message>(line).map_err(Error::from));
+        }
+        let (messages, handle) = track_session(stream::iter(messages));
+
+        assert_eq!(messages.count().await, 11);
+
+        let summary = handle.summary();
+        assert!(summary.completed);
+        assert_eq!(summary.messages, 10);
+        assert_eq!(summary.by_type["trade"], 3);
+        assert_eq!(summary.by_instrument.values().sum::<u64>(), 9);
+        assert_eq!(summary.disconnects, 1);
+        assert_eq!(summary.deserialization_failures, 1);
+        assert!(summary.first_timestamp < summary.last_timestamp);
+    }
+}
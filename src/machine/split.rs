@@ -0,0 +1,180 @@
+//! Splitting of a stream of normalized messages into one typed stream per message type.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt};
+use tokio::sync::mpsc;
+
+use super::{
+    BookChange, BookSnapshot, BookTicker, DerivativeTicker, Disconnect, Error, Liquidation,
+    Message, OptionSummary, Result, Trade, TradeBar, UnknownMessage,
+};
+
+/// How many messages of a type are buffered before the split waits for them to be consumed.
+const CAPACITY: usize = 1024;
+
+/// A stream of the messages of a single type, returned by [`split_by_type`].
+#[derive(Debug)]
+pub struct TypedStream<T> {
+    receiver: mpsc::Receiver<T>,
+}
+
+impl<T> Stream for TypedStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// The typed streams of every message type, see [`split_by_type`].
+#[derive(Debug)]
+pub struct TypedStreams {
+    /// The `trade` messages
+    pub trades: TypedStream<Trade>,
+
+    /// The `book_change` messages
+    pub book_changes: TypedStream<BookChange>,
+
+    /// The `derivative_ticker` messages
+    pub derivative_tickers: TypedStream<DerivativeTicker>,
+
+    /// The `book_snapshot` messages
+    pub book_snapshots: TypedStream<BookSnapshot>,
+
+    /// The `trade_bar` messages
+    pub trade_bars: TypedStream<TradeBar>,
+
+    /// The `disconnect` messages
+    pub disconnects: TypedStream<Disconnect>,
+
+    /// The `liquidation` messages
+    pub liquidations: TypedStream<Liquidation>,
+
+    /// The `book_ticker` messages
+    pub book_tickers: TypedStream<BookTicker>,
+
+    /// The `option_summary` messages
+    pub option_summaries: TypedStream<OptionSummary>,
+
+    /// The messages of a type unknown to this crate
+    pub unknowns: TypedStream<UnknownMessage>,
+
+    /// The errors yielded by the stream
+    pub errors: TypedStream<Error>,
+}
+
+fn channel<T>() -> (mpsc::Sender<T>, TypedStream<T>) {
+    let (sender, receiver) = mpsc::channel(CAPACITY);
+    (sender, TypedStream { receiver })
+}
+
+/// Splits a stream of messages into one stream per message type, so that each type can be
+/// consumed on its own without matching on [`Message`].
+///
+/// The messages are moved into their stream as they are, without being cloned, by a task spawned
+/// on the current tokio runtime. Streams that are dropped are skipped, and the task stops once
+/// all of them are dropped or the source ends. As each stream buffers up to 1024 messages, a
+/// stream that is kept but not consumed eventually holds back the others.
+pub fn split_by_type<S>(messages: S) -> TypedStreams
+where
+    S: Stream<Item = Result<Message>> + Send + 'static,
+{
+    let (trades, trades_rx) = channel();
+    let (book_changes, book_changes_rx) = channel();
+    let (derivative_tickers, derivative_tickers_rx) = channel();
+    let (book_snapshots, book_snapshots_rx) = channel();
+    let (trade_bars, trade_bars_rx) = channel();
+    let (disconnects, disconnects_rx) = channel();
+    let (liquidations, liquidations_rx) = channel();
+    let (book_tickers, book_tickers_rx) = channel();
+    let (option_summaries, option_summaries_rx) = channel();
+    let (unknowns, unknowns_rx) = channel();
+    let (errors, errors_rx) = channel();
+
+    tokio::spawn(async move {
+        futures_util::pin_mut!(messages);
+
+        while let Some(msg) = messages.next().await {
+            // Sending only fails when the receiving stream was dropped, which skips the message.
+            let sent = match msg {
+                Ok(Message::Trade(trade)) => trades.send(trade).await.is_ok(),
+                Ok(Message::BookChange(change)) => book_changes.send(*change).await.is_ok(),
+                Ok(Message::DerivativeTicker(ticker)) => {
+                    derivative_tickers.send(*ticker).await.is_ok()
+                }
+                Ok(Message::BookSnapshot(snapshot)) => book_snapshots.send(*snapshot).await.is_ok(),
+                Ok(Message::TradeBar(bar)) => trade_bars.send(*bar).await.is_ok(),
+                Ok(Message::Disconnect(disconnect)) => disconnects.send(disconnect).await.is_ok(),
+                Ok(Message::Liquidation(liquidation)) => {
+                    liquidations.send(liquidation).await.is_ok()
+                }
+                Ok(Message::BookTicker(ticker)) => book_tickers.send(*ticker).await.is_ok(),
+                Ok(Message::OptionSummary(summary)) => {
+                    option_summaries.send(*summary).await.is_ok()
+                }
+                Ok(Message::Unknown(msg)) => unknowns.send(*msg).await.is_ok(),
+                Err(e) => errors.send(e).await.is_ok(),
+            };
+
+            if !sent
+                && trades.is_closed()
+                && book_changes.is_closed()
+                && derivative_tickers.is_closed()
+                && book_snapshots.is_closed()
+                && trade_bars.is_closed()
+                && disconnects.is_closed()
+                && liquidations.is_closed()
+                && book_tickers.is_closed()
+                && option_summaries.is_closed()
+                && unknowns.is_closed()
+                && errors.is_closed()
+            {
+                break;
+            }
+        }
+    });
+
+    TypedStreams {
+        trades: trades_rx,
+        book_changes: book_changes_rx,
+        derivative_tickers: derivative_tickers_rx,
+        book_snapshots: book_snapshots_rx,
+        trade_bars: trade_bars_rx,
+        disconnects: disconnects_rx,
+        liquidations: liquidations_rx,
+        book_tickers: book_tickers_rx,
+        option_summaries: option_summaries_rx,
+        unknowns: unknowns_rx,
+        errors: errors_rx,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_split_by_type() {
+        let mut messages = vec![];
+        for line in include_str!("../../fixtures/golden/corpus.ndjson").lines() {
+            messages.push(serde_json::from_str::<Message>(line).map_err(Error::from));
+        }
+        let expected_trades = messages
+            .iter()
+            .filter(|msg| matches!(msg, Ok(Message::Trade(_))))
+            .count();
+
+        let split = split_by_type(stream::iter(messages));
+        drop(split.book_changes);
+
+        assert_eq!(split.trades.count().await, expected_trades);
+        assert_eq!(split.disconnects.count().await, 1);
+        assert_eq!(split.errors.count().await, 0);
+    }
+}
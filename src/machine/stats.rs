@@ -0,0 +1,148 @@
+//! Statistics of the messages received per subscription, to tell which subscriptions of a
+//! consolidated multi-exchange stream are healthy. Enabled with
+//! [`ClientBuilder::track_stats`](super::ClientBuilder::track_stats) and read with
+//! [`Client::stats`](super::Client::stats).
+
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+
+use super::{gaps::BookValidator, Message, Symbol};
+use crate::Exchange;
+
+/// A subscription the messages are counted for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubscriptionKey {
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Type of the messages, as returned by [`Message::kind`], eg. `trade`
+    pub kind: &'static str,
+}
+
+/// The statistics of a subscription, see [`StreamStats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriptionStats {
+    /// Number of messages received
+    pub messages: u64,
+
+    /// Arrival timestamp of the last message
+    pub last_local_timestamp: DateTime<Utc>,
+
+    /// When the last message was received by the client
+    pub last_received_at: DateTime<Utc>,
+
+    /// Number of gaps in the messages: the disconnects of the exchange since the first message,
+    /// and the gaps detected in `book_change` messages, see [`gaps`](super::gaps)
+    pub gaps: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    subscriptions: HashMap<SubscriptionKey, SubscriptionStats>,
+    books: BookValidator,
+}
+
+/// Tracks the [`SubscriptionStats`] of the normalized messages received by every stream of a
+/// client. Messages not tied to a symbol, such as disconnects, aren't counted themselves.
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    state: Mutex<State>,
+}
+
+impl StreamStats {
+    /// Creates a new instance of [`StreamStats`] without any subscription.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a message received.
+    pub fn record(&self, message: &Message) {
+        let mut state = self.state.lock().unwrap();
+        let gap = state.books.push(message);
+
+        let Some(symbol) = message.symbol() else {
+            if let Message::Disconnect(disconnect) = message {
+                for (key, stats) in state.subscriptions.iter_mut() {
+                    if key.exchange == disconnect.exchange {
+                        stats.gaps += 1;
+                    }
+                }
+            }
+            return;
+        };
+
+        // Messages tied to a symbol are of known types, which always have both.
+        let (Some(exchange), Some(local_timestamp)) =
+            (message.exchange(), message.local_timestamp())
+        else {
+            return;
+        };
+        let key = SubscriptionKey {
+            exchange,
+            symbol: symbol.clone(),
+            kind: message.kind(),
+        };
+        let now = Utc::now();
+        let stats = state
+            .subscriptions
+            .entry(key)
+            .or_insert_with(|| SubscriptionStats {
+                messages: 0,
+                last_local_timestamp: local_timestamp,
+                last_received_at: now,
+                gaps: 0,
+            });
+        stats.messages += 1;
+        stats.last_local_timestamp = local_timestamp;
+        stats.last_received_at = now;
+        stats.gaps += u64::from(gap.is_some());
+    }
+
+    /// Returns a snapshot of the statistics of every subscription a message was received for.
+    pub fn snapshot(&self) -> HashMap<SubscriptionKey, SubscriptionStats> {
+        self.state.lock().unwrap().subscriptions.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_stats() {
+        let stats = StreamStats::new();
+        for line in [
+            r#"{"type":"trade","symbol":"BTCUSDT","exchange":"bybit","id":"1","price":19310.5,"amount":0.1,"side":"buy","timestamp":"2022-10-01T00:00:00.100Z","localTimestamp":"2022-10-01T00:00:00.104Z"}"#,
+            r#"{"type":"trade","symbol":"BTCUSDT","exchange":"bybit","id":"2","price":19311,"amount":0.2,"side":"sell","timestamp":"2022-10-01T00:00:00.200Z","localTimestamp":"2022-10-01T00:00:00.204Z"}"#,
+            r#"{"type":"book_change","symbol":"BTCUSDT","exchange":"bybit","isSnapshot":false,"bids":[],"asks":[],"timestamp":"2022-10-01T00:00:00.300Z","localTimestamp":"2022-10-01T00:00:00.304Z"}"#,
+            r#"{"type":"trade","symbol":"ETHUSD","exchange":"bitmex","id":"3","price":1300,"amount":1,"side":"buy","timestamp":"2022-10-01T00:00:00.400Z","localTimestamp":"2022-10-01T00:00:00.404Z"}"#,
+            r#"{"type":"disconnect","exchange":"bybit","localTimestamp":"2022-10-01T00:00:01.000Z"}"#,
+        ] {
+            stats.record(&serde_json::from_str(line).unwrap());
+        }
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 3);
+        let key = |exchange, symbol: &str, kind| SubscriptionKey {
+            exchange,
+            symbol: Symbol::from(symbol),
+            kind,
+        };
+        let trades = &snapshot[&key(Exchange::Bybit, "BTCUSDT", "trade")];
+        assert_eq!(trades.messages, 2);
+        assert_eq!(
+            trades.last_local_timestamp.to_rfc3339(),
+            "2022-10-01T00:00:00.204+00:00"
+        );
+        assert_eq!(trades.gaps, 1);
+        assert_eq!(
+            snapshot[&key(Exchange::Bybit, "BTCUSDT", "book_change")].gaps,
+            2
+        );
+        assert_eq!(snapshot[&key(Exchange::Bitmex, "ETHUSD", "trade")].gaps, 0);
+    }
+}
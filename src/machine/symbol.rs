@@ -0,0 +1,188 @@
+use std::{borrow::Borrow, cell::RefCell, collections::HashSet, fmt, ops::Deref, sync::Arc};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The most symbols a [`SymbolInterner`] holds, the others being allocated per message.
+const MAX_SYMBOLS: usize = 100_000;
+
+thread_local! {
+    /// The symbols of the [`SymbolInterner`] in scope on the current thread, if any.
+    static SCOPE: RefCell<Option<HashSet<Arc<str>>>> = const { RefCell::new(None) };
+}
+
+/// Interns the symbols of the messages deserialized within [`SymbolInterner::scope`], eg. the
+/// messages of a connection, and is dropped along with them.
+#[derive(Debug, Default)]
+pub(crate) struct SymbolInterner {
+    symbols: HashSet<Arc<str>>,
+}
+
+impl SymbolInterner {
+    /// Runs `f`, the symbols it deserializes being interned by this interner.
+    pub(crate) fn scope<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        /// Takes the symbols back once `f` returns or panics, restoring the outer scope if any.
+        struct Guard<'a> {
+            symbols: &'a mut HashSet<Arc<str>>,
+            outer: Option<HashSet<Arc<str>>>,
+        }
+
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                *self.symbols = SCOPE.replace(self.outer.take()).unwrap_or_default();
+            }
+        }
+
+        let outer = SCOPE.replace(Some(std::mem::take(&mut self.symbols)));
+        let _guard = Guard {
+            symbols: &mut self.symbols,
+            outer,
+        };
+        f()
+    }
+}
+
+/// An instrument symbol, cheap to clone.
+///
+/// The symbols of the messages received by a connection are interned, so that every message of an
+/// instrument shares the same allocation instead of allocating a fresh `String` per message. The
+/// interned symbols are dropped along with the connection.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    /// Returns the symbol for `symbol`, shared with the previous messages of the connection
+    /// deserializing it, if any.
+    pub fn intern(symbol: &str) -> Self {
+        SCOPE.with_borrow_mut(|scope| {
+            let Some(symbols) = scope else {
+                return Self(Arc::from(symbol));
+            };
+            if let Some(interned) = symbols.get(symbol) {
+                return Self(interned.clone());
+            }
+
+            let interned: Arc<str> = Arc::from(symbol);
+            if symbols.len() < MAX_SYMBOLS {
+                symbols.insert(interned.clone());
+            }
+            Self(interned)
+        })
+    }
+
+    /// Returns the symbol as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(symbol: &str) -> Self {
+        Self::intern(symbol)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(symbol: String) -> Self {
+        Self::intern(&symbol)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(symbol: &String) -> Self {
+        Self::intern(symbol)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Symbol;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a symbol")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, symbol: &str) -> Result<Symbol, E> {
+                Ok(Symbol::intern(symbol))
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interned() {
+        let parse = || serde_json::from_str::<Symbol>(r#""BTCUSDT""#).unwrap();
+
+        let mut interner = SymbolInterner::default();
+        let (a, b) = interner.scope(|| (parse(), parse()));
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, "BTCUSDT");
+        assert_eq!(serde_json::to_string(&b).unwrap(), r#""BTCUSDT""#);
+
+        // Shared with the later scopes of the same interner only.
+        assert!(Arc::ptr_eq(&interner.scope(parse).0, &a.0));
+        assert!(!Arc::ptr_eq(
+            &SymbolInterner::default().scope(parse).0,
+            &a.0
+        ));
+        assert!(!Arc::ptr_eq(&parse().0, &a.0));
+    }
+}
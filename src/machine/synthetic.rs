@@ -0,0 +1,430 @@
+//! Synthetic instruments derived from the prices of multiple legs, eg. calendar spreads,
+//! inter-exchange spreads, weighted baskets or cross rates.
+
+use async_stream::stream;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    currency::QuoteNormalizer,
+    quotes::{InstrumentKey, Quote, QuoteTracker},
+    Message, Result,
+};
+
+/// A leg of a [`SyntheticInstrument`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leg {
+    /// The instrument of the leg.
+    pub instrument: InstrumentKey,
+
+    /// The weight of the leg, negative for legs that are sold.
+    pub weight: f64,
+}
+
+/// An instrument whose price is the weighted sum of the prices of its legs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticInstrument {
+    /// The name the synthetic quotes are emitted under.
+    pub name: String,
+
+    /// The legs of the instrument.
+    pub legs: Vec<Leg>,
+
+    /// The maximum age of a leg quote, older quotes make the synthetic price unavailable.
+    pub max_staleness: Option<Duration>,
+}
+
+impl SyntheticInstrument {
+    /// Creates a new instance of [`SyntheticInstrument`] without any leg.
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            legs: vec![],
+            max_staleness: None,
+        }
+    }
+
+    /// Buying `far` and selling `near`, eg. two futures of the same underlying.
+    pub fn calendar_spread(name: impl ToString, far: InstrumentKey, near: InstrumentKey) -> Self {
+        Self::new(name).leg(far, 1.0).leg(near, -1.0)
+    }
+
+    /// Buying `a` and selling `b`, eg. the same instrument listed on two exchanges.
+    pub fn inter_exchange_spread(name: impl ToString, a: InstrumentKey, b: InstrumentKey) -> Self {
+        Self::new(name).leg(a, 1.0).leg(b, -1.0)
+    }
+
+    /// A weighted basket of instruments.
+    pub fn basket(
+        name: impl ToString,
+        legs: impl IntoIterator<Item = (InstrumentKey, f64)>,
+    ) -> Self {
+        legs.into_iter()
+            .fold(Self::new(name), |basket, (instrument, weight)| {
+                basket.leg(instrument, weight)
+            })
+    }
+
+    /// Adds a leg to the instrument.
+    pub fn leg(mut self, instrument: InstrumentKey, weight: f64) -> Self {
+        self.legs.push(Leg { instrument, weight });
+        self
+    }
+
+    /// Sets the maximum age of the leg quotes.
+    pub fn max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+
+    /// Prices the instrument out of the latest leg quotes as of `now`.
+    ///
+    /// The synthetic bid sells every bought leg at its bid and buys back every sold leg at its
+    /// ask (and conversely for the ask), so the synthetic spread accounts for the spread of every
+    /// leg. Returns `None` if no price could be computed.
+    pub fn price(&self, quotes: &QuoteTracker, now: DateTime<Utc>) -> Option<SyntheticQuote> {
+        let legs = self
+            .legs
+            .iter()
+            .map(|leg| {
+                let quote = quotes.get(&leg.instrument)?;
+                match self.max_staleness {
+                    Some(max_staleness) if quote.is_stale(now, max_staleness) => None,
+                    _ => Some((leg.weight, quote)),
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let sum = |price: fn(f64, &Quote) -> Option<f64>| {
+            legs.iter()
+                .map(|(weight, quote)| Some(weight * price(*weight, quote)?))
+                .sum::<Option<f64>>()
+        };
+
+        let quote = SyntheticQuote {
+            name: self.name.clone(),
+            bid_price: sum(|weight, quote| {
+                if weight >= 0.0 {
+                    quote.bid_price
+                } else {
+                    quote.ask_price
+                }
+            }),
+            ask_price: sum(|weight, quote| {
+                if weight >= 0.0 {
+                    quote.ask_price
+                } else {
+                    quote.bid_price
+                }
+            }),
+            last_price: sum(|_, quote| quote.last_price),
+            local_timestamp: now,
+        };
+
+        if quote.bid_price.is_none() && quote.ask_price.is_none() && quote.last_price.is_none() {
+            return None;
+        }
+
+        Some(quote)
+    }
+}
+
+/// A cross rate derived from two instruments quoted in a common currency, eg. ETH/BTC out of
+/// ETH/USD and BTC/USD.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossRate {
+    /// The name the synthetic quotes are emitted under.
+    pub name: String,
+
+    /// The instrument providing the base currency of the cross, eg. ETH/USD.
+    pub base: InstrumentKey,
+
+    /// The instrument providing the quote currency of the cross, eg. BTC/USD.
+    pub quote: InstrumentKey,
+
+    /// The maximum age of either leg quote. As both legs must be fresh as of the latest update,
+    /// this also bounds how far apart in time the two legs can be.
+    pub max_staleness: Duration,
+}
+
+impl CrossRate {
+    /// Creates a new instance of [`CrossRate`].
+    pub fn new(
+        name: impl ToString,
+        base: InstrumentKey,
+        quote: InstrumentKey,
+        max_staleness: Duration,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            base,
+            quote,
+            max_staleness,
+        }
+    }
+
+    /// Prices the cross out of the latest leg quotes as of `now`, returning `None` if either leg
+    /// is stale or no price could be computed.
+    pub fn price(&self, quotes: &QuoteTracker, now: DateTime<Utc>) -> Option<SyntheticQuote> {
+        let base = quotes.get(&self.base)?;
+        let quote = quotes.get(&self.quote)?;
+        if base.is_stale(now, self.max_staleness) || quote.is_stale(now, self.max_staleness) {
+            return None;
+        }
+
+        let divide = |numerator: Option<f64>, denominator: Option<f64>| match denominator? {
+            denominator if denominator > 0.0 => Some(numerator? / denominator),
+            _ => None,
+        };
+
+        let cross = SyntheticQuote {
+            name: self.name.clone(),
+            bid_price: divide(base.bid_price, quote.ask_price),
+            ask_price: divide(base.ask_price, quote.bid_price),
+            last_price: divide(base.last_price, quote.last_price),
+            local_timestamp: now,
+        };
+
+        if cross.bid_price.is_none() && cross.ask_price.is_none() && cross.last_price.is_none() {
+            return None;
+        }
+
+        Some(cross)
+    }
+}
+
+/// The price of a [`SyntheticInstrument`] or [`CrossRate`] at a point in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntheticQuote {
+    /// The name of the synthetic instrument
+    pub name: String,
+
+    /// Synthetic bid price, `None` if a leg is missing the price it needs
+    pub bid_price: Option<f64>,
+
+    /// Synthetic ask price, `None` if a leg is missing the price it needs
+    pub ask_price: Option<f64>,
+
+    /// Weighted sum of the last traded price of the legs
+    pub last_price: Option<f64>,
+
+    /// Arrival timestamp of the leg update that triggered the computation
+    pub local_timestamp: DateTime<Utc>,
+}
+
+/// Re-prices a set of [`SyntheticInstrument`]s and [`CrossRate`]s every time one of their legs is
+/// updated.
+#[derive(Debug, Clone, Default)]
+pub struct SyntheticEngine {
+    quotes: QuoteTracker,
+    instruments: Vec<SyntheticInstrument>,
+    cross_rates: Vec<CrossRate>,
+}
+
+impl From<Vec<SyntheticInstrument>> for SyntheticEngine {
+    fn from(instruments: Vec<SyntheticInstrument>) -> Self {
+        Self::new(instruments)
+    }
+}
+
+impl SyntheticEngine {
+    /// Creates a new instance of [`SyntheticEngine`].
+    pub fn new(instruments: Vec<SyntheticInstrument>) -> Self {
+        Self {
+            instruments,
+            ..Default::default()
+        }
+    }
+
+    /// Normalizes the leg prices into their equivalent quote currency before pricing, eg. to
+    /// spread USDT and USD quoted markets.
+    pub fn quote_normalizer(mut self, normalizer: QuoteNormalizer) -> Self {
+        self.quotes = self.quotes.normalizer(normalizer);
+        self
+    }
+
+    /// Adds a cross rate to price.
+    pub fn cross_rate(mut self, cross_rate: CrossRate) -> Self {
+        self.cross_rates.push(cross_rate);
+        self
+    }
+
+    /// Updates the leg quotes with a message, returning the synthetic quotes affected by it.
+    pub fn update(&mut self, message: &Message) -> Vec<SyntheticQuote> {
+        let (Some(updated), Some(now)) = (self.quotes.update(message), message.local_timestamp())
+        else {
+            return vec![];
+        };
+
+        let instruments = self
+            .instruments
+            .iter()
+            .filter(|instrument| instrument.legs.iter().any(|leg| leg.instrument == updated))
+            .filter_map(|instrument| instrument.price(&self.quotes, now));
+        let cross_rates = self
+            .cross_rates
+            .iter()
+            .filter(|cross| cross.base == updated || cross.quote == updated)
+            .filter_map(|cross| cross.price(&self.quotes, now));
+
+        instruments.chain(cross_rates).collect()
+    }
+}
+
+/// Derives a stream of [`SyntheticQuote`]s out of the leg messages of the given stream, while
+/// errors are passed through.
+pub fn synthesize<S>(
+    messages: S,
+    engine: impl Into<SyntheticEngine>,
+) -> impl Stream<Item = Result<SyntheticQuote>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    stream! {
+        futures_util::pin_mut!(messages);
+        let mut engine = engine.into();
+
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(msg) => {
+                    for quote in engine.update(&msg) {
+                        yield Ok(quote);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{
+        machine::{BookLevel, BookSnapshot},
+        Exchange,
+    };
+
+    fn snapshot(exchange: Exchange, symbol: &str, bid: f64, ask: f64, millis: i64) -> Message {
+        let timestamp = Utc.timestamp_millis_opt(millis).unwrap();
+        Message::BookSnapshot(Box::new(BookSnapshot {
+            symbol: symbol.into(),
+            exchange,
+            name: "book_snapshot_1_0ms".to_string(),
+            depth: 1,
+            interval: 0,
+            bids: vec![BookLevel {
+                price: bid,
+                amount: 1.0,
+            }],
+            asks: vec![BookLevel {
+                price: ask,
+                amount: 1.0,
+            }],
+            timestamp,
+            local_timestamp: timestamp,
+        }))
+    }
+
+    #[test]
+    fn test_calendar_spread() {
+        let far = InstrumentKey::new(Exchange::Deribit, "BTC-30DEC22");
+        let near = InstrumentKey::new(Exchange::Deribit, "BTC-28OCT22");
+        let mut engine = SyntheticEngine::new(vec![SyntheticInstrument::calendar_spread(
+            "BTC Oct/Dec",
+            far,
+            near,
+        )
+        .max_staleness(Duration::seconds(1))]);
+
+        assert!(engine
+            .update(&snapshot(Exchange::Deribit, "BTC-30DEC22", 110.0, 111.0, 0))
+            .is_empty());
+
+        let quotes = engine.update(&snapshot(
+            Exchange::Deribit,
+            "BTC-28OCT22",
+            100.0,
+            100.5,
+            10,
+        ));
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].bid_price, Some(9.5));
+        assert_eq!(quotes[0].ask_price, Some(11.0));
+        assert_eq!(quotes[0].last_price, None);
+
+        // The far leg is now older than the allowed staleness.
+        assert!(engine
+            .update(&snapshot(
+                Exchange::Deribit,
+                "BTC-28OCT22",
+                100.0,
+                100.5,
+                2_000
+            ))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_normalized_inter_exchange_spread() {
+        let binance = InstrumentKey::new(Exchange::Binance, "btcusdt");
+        let coinbase = InstrumentKey::new(Exchange::Coinbase, "BTC-USD");
+        let mut engine = SyntheticEngine::new(vec![SyntheticInstrument::inter_exchange_spread(
+            "BTC Binance/Coinbase",
+            binance.clone(),
+            coinbase,
+        )])
+        .quote_normalizer(QuoteNormalizer::stablecoins().pair_factor(binance, 0.5));
+
+        engine.update(&snapshot(Exchange::Binance, "btcusdt", 200.0, 202.0, 0));
+        let quotes = engine.update(&snapshot(Exchange::Coinbase, "BTC-USD", 99.0, 100.0, 0));
+        assert_eq!(quotes[0].bid_price, Some(0.0));
+        assert_eq!(quotes[0].ask_price, Some(2.0));
+    }
+
+    #[test]
+    fn test_cross_rate() {
+        let mut engine = SyntheticEngine::default().cross_rate(CrossRate::new(
+            "ETH/BTC",
+            InstrumentKey::new(Exchange::Coinbase, "ETH-USD"),
+            InstrumentKey::new(Exchange::Coinbase, "BTC-USD"),
+            Duration::seconds(1),
+        ));
+
+        assert!(engine
+            .update(&snapshot(
+                Exchange::Coinbase,
+                "ETH-USD",
+                1_000.0,
+                1_010.0,
+                0
+            ))
+            .is_empty());
+
+        let quotes = engine.update(&snapshot(
+            Exchange::Coinbase,
+            "BTC-USD",
+            20_000.0,
+            20_200.0,
+            500,
+        ));
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].bid_price, Some(1_000.0 / 20_200.0));
+        assert_eq!(quotes[0].ask_price, Some(1_010.0 / 20_000.0));
+
+        // The ETH-USD leg is now older than the allowed staleness.
+        assert!(engine
+            .update(&snapshot(
+                Exchange::Coinbase,
+                "BTC-USD",
+                20_000.0,
+                20_200.0,
+                1_500
+            ))
+            .is_empty());
+    }
+}
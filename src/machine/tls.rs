@@ -0,0 +1,52 @@
+//! Encryption of the websocket connections to `wss://` URLs, with the TLS backend selected by the
+//! `native-tls` and `rustls-tls` features. rustls is used when both are enabled.
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite;
+
+/// Encrypts a connection with rustls, trusting the native root certificates.
+#[cfg(feature = "rustls-tls")]
+pub(super) async fn connect<S>(
+    host: &str,
+    stream: S,
+) -> Result<impl AsyncRead + AsyncWrite + Send + Unpin, tungstenite::Error>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    use std::{io, sync::Arc};
+    use tokio_rustls::rustls;
+
+    let mut roots = rustls::RootCertStore::empty();
+    let certs = rustls_native_certs::load_native_certs()?;
+    roots.add_parsable_certificates(&certs.into_iter().map(|cert| cert.0).collect::<Vec<_>>());
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let domain = rustls::ServerName::try_from(host)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(domain, stream)
+        .await?)
+}
+
+/// Encrypts a connection with the TLS library of the platform, eg. OpenSSL on Linux.
+#[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+pub(super) async fn connect<S>(
+    host: &str,
+    stream: S,
+) -> Result<impl AsyncRead + AsyncWrite + Send + Unpin, tungstenite::Error>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    use std::io;
+    use tokio_native_tls::native_tls;
+
+    let to_io = |e: native_tls::Error| io::Error::other(e);
+    let connector = native_tls::TlsConnector::new().map_err(to_io)?;
+    Ok(tokio_native_tls::TlsConnector::from(connector)
+        .connect(host, stream)
+        .await
+        .map_err(to_io)?)
+}
@@ -0,0 +1,607 @@
+//! Utilities for working with the trades returned by Tardis Machine Server: merging, deduplicating
+//! and aggregating them into bars.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use async_stream::stream;
+use chrono::{DateTime, TimeZone, Utc};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use super::{DataType, IntervalUnit, Message, Result, Symbol, Trade, TradeBar, TradeSide};
+use crate::{log, Exchange};
+
+/// Consecutive trades sharing the same symbol, timestamp, side and price merged into one, which
+/// usually are the fills of a single aggressive order printed separately by the exchange.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregateTrade {
+    /// Instrument symbol as provided by exchange
+    pub symbol: Symbol,
+
+    /// Exchange ID
+    pub exchange: Exchange,
+
+    /// Trade id of the first trade in the aggregate if provided by exchange
+    pub id: Option<String>,
+
+    /// Trade price as provided by exchange
+    pub price: f64,
+
+    /// Sum of the amounts of the merged trades
+    pub amount: f64,
+
+    /// Liquidity taker side (aggressor)
+    pub side: TradeSide,
+
+    /// Number of trades merged into this aggregate
+    pub count: usize,
+
+    /// Trade timestamp provided by exchange (ISO 8601 format)
+    pub timestamp: DateTime<Utc>,
+
+    /// Message arrival timestamp of the first trade in the aggregate (ISO 8601 format)
+    pub local_timestamp: DateTime<Utc>,
+}
+
+impl AggregateTrade {
+    fn matches(&self, trade: &Trade) -> bool {
+        self.exchange == trade.exchange
+            && self.symbol == trade.symbol
+            && self.timestamp == trade.timestamp
+            && self.side == trade.side
+            && self.price == trade.price
+    }
+}
+
+impl From<Trade> for AggregateTrade {
+    fn from(trade: Trade) -> Self {
+        Self {
+            symbol: trade.symbol,
+            exchange: trade.exchange,
+            id: trade.id,
+            price: trade.price,
+            amount: trade.amount,
+            side: trade.side,
+            count: 1,
+            timestamp: trade.timestamp,
+            local_timestamp: trade.local_timestamp,
+        }
+    }
+}
+
+/// Merges consecutive trades into [`AggregateTrade`]s.
+///
+/// An aggregate is only complete once a trade that doesn't belong to it arrives, so the last
+/// aggregate must be retrieved with [`TradeClusterer::flush`] once the input ends.
+#[derive(Debug, Default)]
+pub struct TradeClusterer {
+    pending: Option<AggregateTrade>,
+}
+
+impl TradeClusterer {
+    /// Creates a new instance of [`TradeClusterer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a trade, returning the previous aggregate if the trade doesn't belong to it.
+    pub fn push(&mut self, trade: Trade) -> Option<AggregateTrade> {
+        match &mut self.pending {
+            Some(pending) if pending.matches(&trade) => {
+                pending.amount += trade.amount;
+                pending.count += 1;
+                None
+            }
+            _ => self.pending.replace(trade.into()),
+        }
+    }
+
+    /// Returns the aggregate currently being built, if any.
+    pub fn flush(&mut self) -> Option<AggregateTrade> {
+        self.pending.take()
+    }
+}
+
+/// Merges the trades of the given stream into [`AggregateTrade`]s, see [`TradeClusterer`].
+/// Other messages are skipped while errors are passed through.
+pub fn cluster_trades<S>(messages: S) -> impl Stream<Item = Result<AggregateTrade>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    stream! {
+        futures_util::pin_mut!(messages);
+        let mut clusterer = TradeClusterer::new();
+
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(Message::Trade(trade)) => {
+                    if let Some(aggregate) = clusterer.push(trade) {
+                        yield Ok(aggregate);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => yield Err(e),
+            }
+        }
+
+        if let Some(aggregate) = clusterer.flush() {
+            yield Ok(aggregate);
+        }
+    }
+}
+
+/// What to do with a trade whose id was already seen recently for the same symbol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Don't track trade ids at all.
+    Ignore,
+
+    /// Drop duplicated trades, remembering the last `window` trade ids per symbol.
+    Drop {
+        /// The number of trade ids remembered per symbol.
+        window: usize,
+    },
+
+    /// Surface duplicated trades as [`Deduplicated::Duplicate`], remembering the last `window`
+    /// trade ids per symbol.
+    Flag {
+        /// The number of trade ids remembered per symbol.
+        window: usize,
+    },
+}
+
+impl Default for DuplicatePolicy {
+    fn default() -> Self {
+        DuplicatePolicy::Drop { window: 1_000 }
+    }
+}
+
+/// A message of a stream that went through [`dedup_trades`].
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub enum Deduplicated {
+    Message(Message),
+    Duplicate(Trade),
+}
+
+#[derive(Debug, Default)]
+struct SeenIds {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+/// Tracks recently seen trade ids per symbol to detect duplicated trades, which are known to
+/// appear around reconnections and in the replays of some exchanges.
+#[derive(Debug, Default)]
+pub struct TradeDeduplicator {
+    default_policy: DuplicatePolicy,
+    policies: HashMap<Exchange, HashMap<Symbol, DuplicatePolicy>>,
+    seen: HashMap<(Exchange, Symbol), SeenIds>,
+}
+
+impl TradeDeduplicator {
+    /// Creates a new instance of [`TradeDeduplicator`] applying `policy` to every symbol.
+    pub fn new(policy: DuplicatePolicy) -> Self {
+        Self {
+            default_policy: policy,
+            ..Default::default()
+        }
+    }
+
+    /// Overrides the policy for a single symbol of an exchange.
+    pub fn with_policy(
+        mut self,
+        exchange: Exchange,
+        symbol: impl Into<Symbol>,
+        policy: DuplicatePolicy,
+    ) -> Self {
+        self.policies
+            .entry(exchange)
+            .or_default()
+            .insert(symbol.into(), policy);
+        self
+    }
+
+    /// Returns the policy applied to the given symbol of an exchange.
+    pub fn policy(&self, exchange: Exchange, symbol: &str) -> DuplicatePolicy {
+        self.policies
+            .get(&exchange)
+            .and_then(|policies| policies.get(symbol))
+            .copied()
+            .unwrap_or(self.default_policy)
+    }
+
+    /// Records the id of the trade, returning `true` if it was already seen recently. Trades
+    /// without an id are never considered duplicates.
+    pub fn check(&mut self, trade: &Trade) -> bool {
+        let window = match self.policy(trade.exchange, &trade.symbol) {
+            DuplicatePolicy::Ignore => return false,
+            DuplicatePolicy::Drop { window } | DuplicatePolicy::Flag { window } => window,
+        };
+        let Some(id) = &trade.id else {
+            return false;
+        };
+
+        let seen = self
+            .seen
+            .entry((trade.exchange, trade.symbol.clone()))
+            .or_default();
+        if seen.ids.contains(id) {
+            return true;
+        }
+
+        seen.ids.insert(id.clone());
+        seen.order.push_back(id.clone());
+        while seen.order.len() > window {
+            if let Some(oldest) = seen.order.pop_front() {
+                seen.ids.remove(&oldest);
+            }
+        }
+
+        false
+    }
+}
+
+/// Detects duplicated trades in the given stream using the policies of `deduplicator`. Other
+/// messages are passed through untouched.
+pub fn dedup_trades<S>(
+    messages: S,
+    mut deduplicator: TradeDeduplicator,
+) -> impl Stream<Item = Result<Deduplicated>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    stream! {
+        futures_util::pin_mut!(messages);
+
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(Message::Trade(trade)) if deduplicator.check(&trade) => {
+                    log::debug!("Duplicated trade {:?} for {}", trade.id, trade.symbol);
+                    if let DuplicatePolicy::Flag { .. } =
+                        deduplicator.policy(trade.exchange, &trade.symbol)
+                    {
+                        yield Ok(Deduplicated::Duplicate(trade));
+                    }
+                }
+                Ok(msg) => yield Ok(Deduplicated::Message(msg)),
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+/// Aggregates trades into [`TradeBar`]s, the way Tardis Machine Server computes the `trade_bar_*`
+/// data types, eg. to build bars from a replay of trades without requesting them.
+///
+/// Time bars cover the intervals aligned on the trade timestamps, and are complete once a later
+/// trade of their symbol arrives or once [`TradeBarBuilder::advance`] moves past their end. Tick
+/// and volume bars are complete with the trade reaching their size.
+#[derive(Debug)]
+pub struct TradeBarBuilder {
+    name: String,
+    unit: IntervalUnit,
+    interval: u64,
+    bars: HashMap<(Exchange, Symbol), PendingBar>,
+}
+
+#[derive(Debug)]
+struct PendingBar {
+    bar: TradeBar,
+    notional: f64,
+    end: Option<DateTime<Utc>>,
+}
+
+impl TradeBarBuilder {
+    /// Creates a new instance of [`TradeBarBuilder`] computing the bars of `data_type`, eg.
+    /// `trade_bar_10s`. Returns `None` if it isn't a [`DataType::TradeBar`] of a positive
+    /// interval.
+    pub fn new(data_type: DataType) -> Option<Self> {
+        let DataType::TradeBar { interval, unit } = data_type else {
+            return None;
+        };
+        let interval = match unit {
+            IntervalUnit::Milliseconds => interval,
+            IntervalUnit::Seconds => interval.checked_mul(1_000)?,
+            IntervalUnit::Minutes => interval.checked_mul(60_000)?,
+            IntervalUnit::Ticks | IntervalUnit::Volume => interval,
+        };
+        (interval > 0).then(|| Self {
+            name: data_type.to_string(),
+            unit,
+            interval,
+            bars: HashMap::new(),
+        })
+    }
+
+    /// Adds a trade, returning the bar of its symbol it completes, if any.
+    pub fn push(&mut self, trade: &Trade) -> Option<TradeBar> {
+        let key = (trade.exchange, trade.symbol.clone());
+        let mut completed = None;
+        if let Some(pending) = self.bars.get(&key) {
+            if pending.end.is_some_and(|end| trade.timestamp >= end) {
+                let mut pending = self.bars.remove(&key).expect("bar found above");
+                pending.bar.local_timestamp = trade.local_timestamp;
+                completed = Some(pending.bar);
+            }
+        }
+
+        let pending = self
+            .bars
+            .entry(key.clone())
+            .or_insert_with(|| PendingBar::open(&self.name, self.unit, self.interval, trade));
+        pending.add(trade);
+
+        let full = match self.unit {
+            IntervalUnit::Ticks => pending.bar.trades >= self.interval,
+            IntervalUnit::Volume => pending.bar.volume >= self.interval as f64,
+            _ => false,
+        };
+        if full {
+            let pending = self.bars.remove(&key).expect("bar inserted above");
+            completed = Some(pending.bar);
+        }
+        completed
+    }
+
+    /// Completes the time bars ending at or before `timestamp`, eg. the arrival time of the last
+    /// message received, so that a bar doesn't wait for the next trade of its symbol. The bars are
+    /// returned in the order of their end.
+    pub fn advance(&mut self, timestamp: DateTime<Utc>) -> Vec<TradeBar> {
+        let ended = self
+            .bars
+            .iter()
+            .filter(|(_, pending)| pending.end.is_some_and(|end| end <= timestamp))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        let mut bars = ended
+            .into_iter()
+            .filter_map(|key| self.bars.remove(&key))
+            .map(|mut pending| {
+                pending.bar.local_timestamp = timestamp;
+                pending.bar
+            })
+            .collect::<Vec<_>>();
+        bars.sort_by(|a, b| (a.timestamp, &a.symbol).cmp(&(b.timestamp, &b.symbol)));
+        bars
+    }
+
+    /// Returns the bars still in progress, eg. once the input ends, in the order of their last
+    /// trade.
+    pub fn flush(&mut self) -> Vec<TradeBar> {
+        let mut bars = self
+            .bars
+            .drain()
+            .map(|(_, pending)| pending.bar)
+            .collect::<Vec<_>>();
+        bars.sort_by(|a, b| (a.close_timestamp, &a.symbol).cmp(&(b.close_timestamp, &b.symbol)));
+        bars
+    }
+}
+
+impl PendingBar {
+    fn open(name: &str, unit: IntervalUnit, interval: u64, trade: &Trade) -> Self {
+        let end = unit.is_time().then(|| {
+            let millis = trade.timestamp.timestamp_millis();
+            let start = millis - millis.rem_euclid(interval as i64);
+            Utc.timestamp_millis_opt(start + interval as i64)
+                .single()
+                .unwrap_or(trade.timestamp)
+        });
+        Self {
+            bar: TradeBar {
+                symbol: trade.symbol.clone(),
+                exchange: trade.exchange,
+                name: name.to_string(),
+                interval,
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                close: trade.price,
+                volume: 0.0,
+                buy_volume: 0.0,
+                sell_volume: 0.0,
+                trades: 0,
+                vwap: trade.price,
+                open_timestamp: trade.timestamp,
+                close_timestamp: trade.timestamp,
+                timestamp: end.unwrap_or(trade.timestamp),
+                local_timestamp: trade.local_timestamp,
+            },
+            notional: 0.0,
+            end,
+        }
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        let bar = &mut self.bar;
+        bar.high = bar.high.max(trade.price);
+        bar.low = bar.low.min(trade.price);
+        bar.close = trade.price;
+        bar.volume += trade.amount;
+        match trade.side {
+            TradeSide::Buy => bar.buy_volume += trade.amount,
+            TradeSide::Sell => bar.sell_volume += trade.amount,
+            TradeSide::Unknown => {}
+        }
+        bar.trades += 1;
+        self.notional += trade.price * trade.amount;
+        if bar.volume > 0.0 {
+            bar.vwap = self.notional / bar.volume;
+        }
+        bar.close_timestamp = trade.timestamp;
+        if self.end.is_none() {
+            bar.timestamp = trade.timestamp;
+        }
+        bar.local_timestamp = trade.local_timestamp;
+    }
+}
+
+/// Aggregates the trades of the given stream into bars, see [`TradeBarBuilder`]. The time bars are
+/// also completed by the arrival of any other message, while the bars in progress are returned
+/// once the stream ends. Errors are passed through.
+pub fn trade_bars<S>(
+    messages: S,
+    mut builder: TradeBarBuilder,
+) -> impl Stream<Item = Result<TradeBar>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    stream! {
+        futures_util::pin_mut!(messages);
+
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(Message::Trade(trade)) => {
+                    for bar in builder.advance(trade.local_timestamp) {
+                        yield Ok(bar);
+                    }
+                    if let Some(bar) = builder.push(&trade) {
+                        yield Ok(bar);
+                    }
+                }
+                Ok(msg) => {
+                    for bar in msg.local_timestamp().map(|now| builder.advance(now)).unwrap_or_default() {
+                        yield Ok(bar);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+        for bar in builder.flush() {
+            yield Ok(bar);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn trade(millis: i64, side: TradeSide, price: f64, amount: f64) -> Trade {
+        let timestamp = Utc.timestamp_millis_opt(millis).unwrap();
+        Trade {
+            symbol: "BTCUSDT".into(),
+            exchange: Exchange::Bybit,
+            id: Some(format!("{}-{}", millis, amount)),
+            price,
+            amount,
+            side,
+            timestamp,
+            local_timestamp: timestamp,
+        }
+    }
+
+    #[test]
+    fn test_cluster_trades() {
+        let mut clusterer = TradeClusterer::new();
+
+        assert_eq!(clusterer.push(trade(1, TradeSide::Buy, 100.0, 1.0)), None);
+        assert_eq!(clusterer.push(trade(1, TradeSide::Buy, 100.0, 2.0)), None);
+
+        let aggregate = clusterer
+            .push(trade(1, TradeSide::Buy, 101.0, 1.0))
+            .unwrap();
+        assert_eq!(aggregate.amount, 3.0);
+        assert_eq!(aggregate.count, 2);
+        assert_eq!(aggregate.id.as_deref(), Some("1-1"));
+
+        let aggregate = clusterer
+            .push(trade(2, TradeSide::Buy, 101.0, 1.0))
+            .unwrap();
+        assert_eq!(aggregate.count, 1);
+
+        assert_eq!(clusterer.flush().unwrap().count, 1);
+        assert_eq!(clusterer.flush(), None);
+    }
+
+    #[test]
+    fn test_dedup_window() {
+        let mut deduplicator = TradeDeduplicator::new(DuplicatePolicy::Drop { window: 2 });
+        let first = trade(1, TradeSide::Buy, 100.0, 1.0);
+
+        assert!(!deduplicator.check(&first));
+        assert!(deduplicator.check(&first));
+
+        assert!(!deduplicator.check(&trade(2, TradeSide::Buy, 100.0, 1.0)));
+        assert!(!deduplicator.check(&trade(3, TradeSide::Buy, 100.0, 1.0)));
+
+        // The first trade id has fallen out of the window.
+        assert!(!deduplicator.check(&first));
+    }
+
+    #[test]
+    fn test_dedup_policy_override() {
+        let mut deduplicator = TradeDeduplicator::default().with_policy(
+            Exchange::Bybit,
+            "BTCUSDT",
+            DuplicatePolicy::Ignore,
+        );
+        let trade = trade(1, TradeSide::Buy, 100.0, 1.0);
+
+        assert!(!deduplicator.check(&trade));
+        assert!(!deduplicator.check(&trade));
+    }
+
+    #[test]
+    fn test_trade_bars() {
+        // The trades and the bar computed by the server in the golden corpus.
+        let corpus = include_str!("../../fixtures/golden/corpus.ndjson")
+            .lines()
+            .map(|line| serde_json::from_str::<Message>(line).unwrap());
+        let (trades, expected) = corpus.fold((vec![], vec![]), |(mut trades, mut bars), msg| {
+            match msg {
+                Message::Trade(trade) => trades.push(trade),
+                Message::TradeBar(bar) => bars.push(*bar),
+                _ => {}
+            }
+            (trades, bars)
+        });
+
+        let data_type = "trade_bar_1s".parse().unwrap();
+        let mut builder = TradeBarBuilder::new(data_type).unwrap();
+        assert!(trades.iter().all(|trade| builder.push(trade).is_none()));
+        let bars = builder.advance(expected[0].local_timestamp);
+        assert_eq!(
+            serde_json::to_value(&bars).unwrap(),
+            serde_json::to_value(&expected).unwrap()
+        );
+        assert!(builder.flush().is_empty());
+
+        assert!(TradeBarBuilder::new(DataType::Trade).is_none());
+    }
+
+    #[test]
+    fn test_tick_bars() {
+        let data_type = DataType::TradeBar {
+            interval: 2,
+            unit: IntervalUnit::Ticks,
+        };
+        let mut builder = TradeBarBuilder::new(data_type).unwrap();
+
+        assert!(builder
+            .push(&trade(1, TradeSide::Buy, 100.0, 1.0))
+            .is_none());
+        let bar = builder
+            .push(&trade(5, TradeSide::Sell, 102.0, 3.0))
+            .unwrap();
+        assert_eq!(bar.name, "trade_bar_2ticks");
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close),
+            (100.0, 102.0, 100.0, 102.0)
+        );
+        assert_eq!((bar.buy_volume, bar.sell_volume, bar.trades), (1.0, 3.0, 2));
+        assert_eq!(bar.vwap, 101.5);
+        assert_eq!(bar.timestamp, Utc.timestamp_millis_opt(5).unwrap());
+
+        // Time doesn't complete tick bars, only the end of the input does.
+        assert!(builder
+            .push(&trade(9, TradeSide::Buy, 101.0, 1.0))
+            .is_none());
+        assert!(builder.advance(Utc::now()).is_empty());
+        assert_eq!(builder.flush().len(), 1);
+    }
+}
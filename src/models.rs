@@ -1,5 +1,8 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::{Error, Result};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 /// The response format for Tardis.dev API.
@@ -17,8 +20,24 @@ pub enum Response<T> {
     Success(T),
 }
 
+impl<T> Response<T> {
+    /// Converts the response into a [`Result`], turning an error response into [`Error::Api`].
+    pub fn into_result(self) -> Result<T> {
+        match self {
+            Response::Success(value) => Ok(value),
+            Response::Error { code, message } => Err(Error::Api { code, message }),
+        }
+    }
+}
+
+impl<T> From<Response<T>> for Result<T> {
+    fn from(response: Response<T>) -> Self {
+        response.into_result()
+    }
+}
+
 #[allow(missing_docs)]
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 /// Supported exchanges on Tardis
 /// Visit <https://api.tardis.dev/v1/exchanges> to get the list of all supported exchanges that
@@ -88,17 +107,203 @@ pub enum Exchange {
     BlockchainCom,
 }
 
-impl ToString for Exchange {
-    fn to_string(&self) -> String {
-        serde_json::to_value(self)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string()
+impl Exchange {
+    /// Every supported exchange.
+    pub const ALL: &'static [Exchange] = &[
+        Self::Bitmex,
+        Self::Deribit,
+        Self::BinanceFutures,
+        Self::BinanceDelivery,
+        Self::BinanceOptions,
+        Self::Binance,
+        Self::Ftx,
+        Self::OkexFutures,
+        Self::OkexOptions,
+        Self::OkexSwap,
+        Self::Okex,
+        Self::HuobiDm,
+        Self::HuobiDmSwap,
+        Self::HuobiDmLinearSwap,
+        Self::Huobi,
+        Self::BitfinexDerivatives,
+        Self::Bitfinex,
+        Self::Coinbase,
+        Self::Cryptofacilities,
+        Self::Kraken,
+        Self::Bitstamp,
+        Self::Gemini,
+        Self::Poloniex,
+        Self::Bybit,
+        Self::BybitSpot,
+        Self::BybitOptions,
+        Self::Phemex,
+        Self::Delta,
+        Self::FtxUs,
+        Self::BinanceUs,
+        Self::GateIoFutures,
+        Self::GateIo,
+        Self::Okcoin,
+        Self::Bitflyer,
+        Self::Hitbtc,
+        Self::Coinflex,
+        Self::BinanceJersey,
+        Self::BinanceDex,
+        Self::Upbit,
+        Self::Ascendex,
+        Self::Dydx,
+        Self::Serum,
+        Self::Mango,
+        Self::HuobiDmPptions,
+        Self::StarAtlas,
+        Self::CryptoCom,
+        Self::CryptoComDerivatives,
+        Self::Kucoin,
+        Self::Bitnomial,
+        Self::WooX,
+        Self::BlockchainCom,
+    ];
+
+    /// Returns every supported exchange.
+    pub fn all() -> &'static [Exchange] {
+        Self::ALL
+    }
+
+    /// Returns the exchange ID used by the Tardis.dev API, eg. `binance-futures`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Bitmex => "bitmex",
+            Self::Deribit => "deribit",
+            Self::BinanceFutures => "binance-futures",
+            Self::BinanceDelivery => "binance-delivery",
+            Self::BinanceOptions => "binance-options",
+            Self::Binance => "binance",
+            Self::Ftx => "ftx",
+            Self::OkexFutures => "okex-futures",
+            Self::OkexOptions => "okex-options",
+            Self::OkexSwap => "okex-swap",
+            Self::Okex => "okex",
+            Self::HuobiDm => "huobi-dm",
+            Self::HuobiDmSwap => "huobi-dm-swap",
+            Self::HuobiDmLinearSwap => "huobi-dm-linear-swap",
+            Self::Huobi => "huobi",
+            Self::BitfinexDerivatives => "bitfinex-derivatives",
+            Self::Bitfinex => "bitfinex",
+            Self::Coinbase => "coinbase",
+            Self::Cryptofacilities => "cryptofacilities",
+            Self::Kraken => "kraken",
+            Self::Bitstamp => "bitstamp",
+            Self::Gemini => "gemini",
+            Self::Poloniex => "poloniex",
+            Self::Bybit => "bybit",
+            Self::BybitSpot => "bybit-spot",
+            Self::BybitOptions => "bybit-options",
+            Self::Phemex => "phemex",
+            Self::Delta => "delta",
+            Self::FtxUs => "ftx-us",
+            Self::BinanceUs => "binance-us",
+            Self::GateIoFutures => "gate-io-futures",
+            Self::GateIo => "gate-io",
+            Self::Okcoin => "okcoin",
+            Self::Bitflyer => "bitflyer",
+            Self::Hitbtc => "hitbtc",
+            Self::Coinflex => "coinflex",
+            Self::BinanceJersey => "binance-jersey",
+            Self::BinanceDex => "binance-dex",
+            Self::Upbit => "upbit",
+            Self::Ascendex => "ascendex",
+            Self::Dydx => "dydx",
+            Self::Serum => "serum",
+            Self::Mango => "mango",
+            Self::HuobiDmPptions => "huobi-dm-pptions",
+            Self::StarAtlas => "star-atlas",
+            Self::CryptoCom => "crypto-com",
+            Self::CryptoComDerivatives => "crypto-com-derivatives",
+            Self::Kucoin => "kucoin",
+            Self::Bitnomial => "bitnomial",
+            Self::WooX => "woo-x",
+            Self::BlockchainCom => "blockchain-com",
+        }
+    }
+
+    /// Returns `true` if the exchange lists derivative instruments (futures, perpetuals or
+    /// options).
+    pub fn is_derivatives(&self) -> bool {
+        matches!(
+            self,
+            Self::Bitmex
+                | Self::Deribit
+                | Self::BinanceFutures
+                | Self::BinanceDelivery
+                | Self::BinanceOptions
+                | Self::Ftx
+                | Self::OkexFutures
+                | Self::OkexOptions
+                | Self::OkexSwap
+                | Self::HuobiDm
+                | Self::HuobiDmSwap
+                | Self::HuobiDmLinearSwap
+                | Self::BitfinexDerivatives
+                | Self::Cryptofacilities
+                | Self::Bybit
+                | Self::BybitOptions
+                | Self::Phemex
+                | Self::Delta
+                | Self::GateIoFutures
+                | Self::Coinflex
+                | Self::Dydx
+                | Self::Mango
+                | Self::HuobiDmPptions
+                | Self::CryptoComDerivatives
+                | Self::Bitnomial
+                | Self::WooX
+        )
+    }
+
+    /// Returns `true` if the exchange lists spot instruments.
+    pub fn is_spot(&self) -> bool {
+        matches!(
+            self,
+            Self::Binance
+                | Self::Ftx
+                | Self::Okex
+                | Self::Huobi
+                | Self::Bitfinex
+                | Self::Coinbase
+                | Self::Kraken
+                | Self::Bitstamp
+                | Self::Gemini
+                | Self::Poloniex
+                | Self::BybitSpot
+                | Self::Phemex
+                | Self::FtxUs
+                | Self::BinanceUs
+                | Self::GateIo
+                | Self::Okcoin
+                | Self::Bitflyer
+                | Self::Hitbtc
+                | Self::Coinflex
+                | Self::BinanceJersey
+                | Self::BinanceDex
+                | Self::Upbit
+                | Self::Ascendex
+                | Self::Serum
+                | Self::Mango
+                | Self::StarAtlas
+                | Self::CryptoCom
+                | Self::Kucoin
+                | Self::WooX
+                | Self::BlockchainCom
+        )
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+impl std::fmt::Display for Exchange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 /// The type of the symbol eg. Spot, Perpetual, Future, Option.
 pub enum SymbolType {
@@ -115,7 +320,7 @@ pub enum SymbolType {
     Option,
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 /// The type of an option symbol eg. Call, Put
 pub enum OptionType {
@@ -126,6 +331,270 @@ pub enum OptionType {
     Put,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The metadata of an exchange supported by Tardis, see <https://docs.tardis.dev/api/http#exchanges>.
+pub struct ExchangeInfo {
+    /// Exchange ID, eg. `bitmex`, which may not have a matching [`Exchange`] yet
+    pub id: String,
+
+    /// Display name of the exchange
+    pub name: String,
+
+    /// Indicates if the data of the exchange is still being collected
+    pub enabled: bool,
+
+    /// Indicates if downloadable CSV datasets are available for the exchange
+    #[serde(default)]
+    pub supports_datasets: bool,
+
+    /// Date in ISO format
+    pub available_since: String,
+
+    /// Date in ISO format, only for exchanges that are no longer collected
+    pub available_to: Option<String>,
+
+    /// Channels of the real-time API of the exchange that were recorded
+    #[serde(default)]
+    pub available_channels: Vec<String>,
+}
+
+impl ExchangeInfo {
+    /// Returns the matching [`Exchange`], `None` if the exchange isn't known to this version of
+    /// the crate.
+    pub fn exchange(&self) -> Option<Exchange> {
+        Exchange::ALL
+            .iter()
+            .copied()
+            .find(|exchange| exchange.as_str() == self.id)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The details of an exchange, see <https://docs.tardis.dev/api/http#exchanges-exchange>.
+pub struct ExchangeDetails {
+    /// Exchange ID, eg. `bitmex`
+    pub id: String,
+
+    /// Display name of the exchange
+    pub name: String,
+
+    /// Indicates if the data of the exchange is still being collected
+    pub enabled: bool,
+
+    /// Date in ISO format
+    pub available_since: String,
+
+    /// Date in ISO format, only for exchanges that are no longer collected
+    pub available_to: Option<String>,
+
+    /// Channels of the real-time API of the exchange that were recorded
+    #[serde(default)]
+    pub available_channels: Vec<String>,
+
+    /// Symbols that were recorded, including the ones no longer traded
+    #[serde(default)]
+    pub available_symbols: Vec<AvailableSymbol>,
+
+    /// Incidents that affected the recorded data, eg. an outage of the exchange
+    #[serde(default)]
+    pub incident_reports: Vec<IncidentReport>,
+
+    /// Availability of the downloadable CSV datasets, if the exchange supports them
+    pub datasets: Option<DatasetsInfo>,
+}
+
+impl ExchangeDetails {
+    /// Returns the recorded symbol with the given ID, eg. `XBTUSD`.
+    pub fn symbol(&self, id: &str) -> Option<&AvailableSymbol> {
+        self.available_symbols
+            .iter()
+            .find(|symbol| symbol.id.eq_ignore_ascii_case(id))
+    }
+
+    /// Returns `true` if the channel was recorded, eg. `orderBookL2`.
+    pub fn has_channel(&self, channel: &str) -> bool {
+        self.available_channels.iter().any(|c| c == channel)
+    }
+
+    /// Returns the incidents overlapping with the given period, to check a replay against.
+    pub fn incidents_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> impl Iterator<Item = &IncidentReport> {
+        self.incident_reports.iter().filter(move |incident| {
+            parse_date(&incident.from).is_none_or(|start| start < to)
+                && parse_date(&incident.to).is_none_or(|end| end > from)
+        })
+    }
+}
+
+/// A symbol recorded for an exchange, see [`ExchangeDetails`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableSymbol {
+    /// Symbol ID as provided by exchange
+    pub id: String,
+
+    /// Type of the symbol, eg. `spot`, `perpetual`, `future`, `option` or `combo`
+    #[serde(rename = "type")]
+    pub symbol_type: String,
+
+    /// Date in ISO format
+    pub available_since: String,
+
+    /// Date in ISO format, only for symbols that are no longer recorded, eg. expired futures
+    pub available_to: Option<String>,
+}
+
+impl AvailableSymbol {
+    /// Returns `true` if the symbol was recorded at `at`. A date that fails to parse is assumed
+    /// not to restrict the availability.
+    pub fn is_available_at(&self, at: DateTime<Utc>) -> bool {
+        parse_date(&self.available_since).is_none_or(|since| since <= at)
+            && self
+                .available_to
+                .as_deref()
+                .and_then(parse_date)
+                .is_none_or(|to| at < to)
+    }
+}
+
+/// An incident that affected the data recorded for an exchange, see [`ExchangeDetails`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncidentReport {
+    /// Date in ISO format
+    pub from: String,
+
+    /// Date in ISO format
+    pub to: String,
+
+    /// Status of the incident, eg. `resolved` or `wontfix`
+    pub status: String,
+
+    /// Description of the incident
+    pub details: String,
+}
+
+/// The availability of the downloadable CSV datasets of an exchange, see [`ExchangeDetails`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetsInfo {
+    /// Formats the datasets are available in, eg. `csv`
+    #[serde(default)]
+    pub formats: Vec<String>,
+
+    /// Date in ISO format
+    pub exported_from: Option<String>,
+
+    /// Date in ISO format
+    pub exported_until: Option<String>,
+
+    /// Symbols that datasets are available for
+    #[serde(default)]
+    pub symbols: Vec<DatasetSymbol>,
+}
+
+/// A symbol that downloadable CSV datasets are available for, see [`DatasetsInfo`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetSymbol {
+    /// Symbol ID as provided by exchange, or a grouped symbol such as `PERPETUALS`
+    pub id: String,
+
+    /// Type of the symbol, eg. `spot` or `perpetual`
+    #[serde(rename = "type")]
+    pub symbol_type: String,
+
+    /// Date in ISO format
+    pub available_since: String,
+
+    /// Date in ISO format
+    pub available_to: Option<String>,
+
+    /// Types of the datasets available for the symbol, eg. `trades`
+    #[serde(default)]
+    pub data_types: Vec<String>,
+}
+
+/// Parses a date in the ISO format returned by the API.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    date.parse().ok()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+/// The filter of the instruments listed by [`Client::instruments`](crate::Client::instruments),
+/// see <https://docs.tardis.dev/api/instruments-metadata-api#instruments-metadata-endpoint>.
+///
+/// Each criterion accepts several values, matching the instruments with any of them.
+pub struct InstrumentFilter {
+    /// Types of the instruments
+    #[serde(rename = "type", skip_serializing_if = "Vec::is_empty")]
+    pub symbol_types: Vec<SymbolType>,
+
+    /// Normalized base currencies, eg. `BTC`
+    #[serde(rename = "baseCurrency", skip_serializing_if = "Vec::is_empty")]
+    pub base_currencies: Vec<String>,
+
+    /// Normalized quote currencies, eg. `USDT`
+    #[serde(rename = "quoteCurrency", skip_serializing_if = "Vec::is_empty")]
+    pub quote_currencies: Vec<String>,
+
+    /// Contract types, eg. `linear_perpetual` or `inverse_future`
+    #[serde(rename = "contractType", skip_serializing_if = "Vec::is_empty")]
+    pub contract_types: Vec<String>,
+
+    /// Whether the instruments can currently be traded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+}
+
+impl InstrumentFilter {
+    /// Creates a filter matching every instrument.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches the instruments of the given type, or any other type added.
+    pub fn symbol_type(mut self, symbol_type: SymbolType) -> Self {
+        self.symbol_types.push(symbol_type);
+        self
+    }
+
+    /// Matches the instruments of the given base currency, or any other base currency added.
+    pub fn base_currency(mut self, currency: impl ToString) -> Self {
+        self.base_currencies.push(currency.to_string());
+        self
+    }
+
+    /// Matches the instruments of the given quote currency, or any other quote currency added.
+    pub fn quote_currency(mut self, currency: impl ToString) -> Self {
+        self.quote_currencies.push(currency.to_string());
+        self
+    }
+
+    /// Matches the instruments of the given contract type, or any other contract type added.
+    pub fn contract_type(mut self, contract_type: impl ToString) -> Self {
+        self.contract_types.push(contract_type.to_string());
+        self
+    }
+
+    /// Matches the instruments that can currently be traded if `active`, or the others.
+    pub fn active(mut self, active: bool) -> Self {
+        self.active = Some(active);
+        self
+    }
+
+    /// Returns `true` if the filter matches every instrument.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 /// The changes info returned by exchanges API. Note that is meant to be accurate and complete only for
@@ -221,3 +690,160 @@ pub struct InstrumentInfo {
     /// changes are done on best effort basis and not always complete.
     pub changes: Option<Vec<InstrumentChanges>>,
 }
+
+/// Selects the raw messages of an exchange channel, optionally only for some symbols, eg. in a
+/// replay of a [data feed](crate::feeds).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Filter {
+    /// Name of the exchange channel, eg. `trade` for BitMEX
+    pub channel: String,
+
+    /// Optional symbols of the channel, all symbols when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbols: Option<Vec<String>>,
+}
+
+impl Filter {
+    /// Creates a [`Filter`] selecting every symbol of `channel`.
+    pub fn channel(channel: impl Into<String>) -> Self {
+        Self {
+            channel: channel.into(),
+            symbols: None,
+        }
+    }
+
+    /// Restricts the filter to the given symbols.
+    pub fn symbols(mut self, symbols: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.symbols = Some(symbols.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the position of `exchange` in [`Exchange::ALL`]. The match has no wildcard arm, so
+    /// that a new variant doesn't compile until it is given a position here, which it must also
+    /// have in [`Exchange::ALL`].
+    fn position(exchange: Exchange) -> usize {
+        match exchange {
+            Exchange::Bitmex => 0,
+            Exchange::Deribit => 1,
+            Exchange::BinanceFutures => 2,
+            Exchange::BinanceDelivery => 3,
+            Exchange::BinanceOptions => 4,
+            Exchange::Binance => 5,
+            Exchange::Ftx => 6,
+            Exchange::OkexFutures => 7,
+            Exchange::OkexOptions => 8,
+            Exchange::OkexSwap => 9,
+            Exchange::Okex => 10,
+            Exchange::HuobiDm => 11,
+            Exchange::HuobiDmSwap => 12,
+            Exchange::HuobiDmLinearSwap => 13,
+            Exchange::Huobi => 14,
+            Exchange::BitfinexDerivatives => 15,
+            Exchange::Bitfinex => 16,
+            Exchange::Coinbase => 17,
+            Exchange::Cryptofacilities => 18,
+            Exchange::Kraken => 19,
+            Exchange::Bitstamp => 20,
+            Exchange::Gemini => 21,
+            Exchange::Poloniex => 22,
+            Exchange::Bybit => 23,
+            Exchange::BybitSpot => 24,
+            Exchange::BybitOptions => 25,
+            Exchange::Phemex => 26,
+            Exchange::Delta => 27,
+            Exchange::FtxUs => 28,
+            Exchange::BinanceUs => 29,
+            Exchange::GateIoFutures => 30,
+            Exchange::GateIo => 31,
+            Exchange::Okcoin => 32,
+            Exchange::Bitflyer => 33,
+            Exchange::Hitbtc => 34,
+            Exchange::Coinflex => 35,
+            Exchange::BinanceJersey => 36,
+            Exchange::BinanceDex => 37,
+            Exchange::Upbit => 38,
+            Exchange::Ascendex => 39,
+            Exchange::Dydx => 40,
+            Exchange::Serum => 41,
+            Exchange::Mango => 42,
+            Exchange::HuobiDmPptions => 43,
+            Exchange::StarAtlas => 44,
+            Exchange::CryptoCom => 45,
+            Exchange::CryptoComDerivatives => 46,
+            Exchange::Kucoin => 47,
+            Exchange::Bitnomial => 48,
+            Exchange::WooX => 49,
+            Exchange::BlockchainCom => 50,
+        }
+    }
+
+    #[test]
+    fn test_exchange_all_is_exhaustive() {
+        let positions = Exchange::ALL
+            .iter()
+            .map(|exchange| position(*exchange))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, (0..Exchange::ALL.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_exchange_ids_match_serde() {
+        for exchange in Exchange::all() {
+            assert_eq!(
+                serde_json::to_value(exchange).unwrap(),
+                exchange.to_string()
+            );
+            assert!(exchange.is_spot() || exchange.is_derivatives());
+        }
+    }
+
+    #[test]
+    fn test_exchange_details() {
+        let details = serde_json::from_str::<ExchangeDetails>(
+            r#"{"id":"bitmex","name":"BitMEX","enabled":true,"availableSince":"2019-03-30T00:00:00.000Z","availableChannels":["trade","orderBookL2"],"availableSymbols":[{"id":"XBTUSD","type":"perpetual","availableSince":"2019-03-30T00:00:00.000Z"},{"id":"XBTZ19","type":"future","availableSince":"2019-06-01T00:00:00.000Z","availableTo":"2019-12-27T12:00:00.000Z"}],"incidentReports":[{"from":"2019-05-22T00:00:00.000Z","to":"2019-05-22T03:00:00.000Z","status":"resolved","details":"Missing data"}],"datasets":{"formats":["csv"],"exportedFrom":"2019-03-30T00:00:00.000Z","exportedUntil":"2023-01-01T00:00:00.000Z","symbols":[{"id":"XBTUSD","type":"perpetual","availableSince":"2019-03-30T00:00:00.000Z","dataTypes":["trades"]}]}}"#,
+        )
+        .unwrap();
+
+        let date = |date: &str| date.parse::<DateTime<Utc>>().unwrap();
+        assert!(details.has_channel("orderBookL2"));
+        assert!(!details.has_channel("quote"));
+        let future = details.symbol("xbtz19").unwrap();
+        assert!(future.is_available_at(date("2019-12-01T00:00:00Z")));
+        assert!(!future.is_available_at(date("2020-01-01T00:00:00Z")));
+        assert!(details.symbol("ETHUSD").is_none());
+        assert_eq!(
+            details
+                .incidents_between(date("2019-05-22T02:00:00Z"), date("2019-05-23T00:00:00Z"))
+                .count(),
+            1
+        );
+        assert_eq!(
+            details
+                .incidents_between(date("2019-05-23T00:00:00Z"), date("2019-05-24T00:00:00Z"))
+                .count(),
+            0
+        );
+        assert_eq!(
+            details.datasets.unwrap().symbols[0].data_types,
+            vec!["trades"]
+        );
+    }
+
+    #[test]
+    fn test_response_into_result() {
+        let error = serde_json::from_str::<Response<InstrumentInfo>>(
+            r#"{"code":100,"message":"Invalid 'symbol' param provided"}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            error.into_result(),
+            Err(Error::Api { code: 100, .. })
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::{
+    sync::{Semaphore, SemaphorePermit},
+    time::Instant,
+};
+
+/// Limits the requests a [`Client`](crate::Client) sends to Tardis, so that bulk jobs, eg.
+/// fetching the metadata of every instrument or downloading months of datasets, stay within the
+/// limits of the API instead of getting rate limited.
+///
+/// Requests are spread by a token bucket refilled at the given rate, allowing short bursts up to
+/// the size of the bucket.
+///
+/// ```
+/// use tardis_rs::{Client, RateLimit};
+///
+/// let client = Client::builder("key")
+///     .rate_limit(RateLimit::per_second(10.0).max_concurrent(4))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    requests_per_second: f64,
+    burst: u32,
+    max_concurrent: Option<usize>,
+}
+
+impl RateLimit {
+    /// Allows `requests` requests per second on average, in bursts of up to one second of
+    /// requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `requests` isn't positive.
+    pub fn per_second(requests: f64) -> Self {
+        assert!(
+            requests > 0.0,
+            "requests per second must be positive, got {}",
+            requests
+        );
+        Self {
+            requests_per_second: requests,
+            burst: requests.ceil() as u32,
+            max_concurrent: None,
+        }
+    }
+
+    /// Sets the number of requests that can be sent at once after a pause, one second of
+    /// requests by default.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Limits the number of requests waiting for their response at the same time, unlimited by
+    /// default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_concurrent` is zero.
+    pub fn max_concurrent(mut self, max_concurrent: usize) -> Self {
+        assert!(max_concurrent > 0, "max_concurrent must not be zero");
+        self.max_concurrent = Some(max_concurrent);
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Enforces a [`RateLimit`] over the requests of a client.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+    concurrency: Option<Semaphore>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                tokens: limit.burst.into(),
+                refilled_at: Instant::now(),
+            }),
+            concurrency: limit.max_concurrent.map(Semaphore::new),
+            limit,
+        }
+    }
+
+    /// Waits until a request can be sent, returning the permit to hold until its response
+    /// arrived when the concurrency is limited.
+    pub(crate) async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let permit = match &self.concurrency {
+            Some(semaphore) => Some(
+                semaphore
+                    .acquire()
+                    .await
+                    .expect("semaphore is never closed"),
+            ),
+            None => None,
+        };
+
+        // Takes a token right away, going into debt if the bucket is empty, so that waiting
+        // requests are served in order.
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = (now - bucket.refilled_at).as_secs_f64() * self.limit.requests_per_second;
+            bucket.tokens = (bucket.tokens + refill).min(self.limit.burst.into()) - 1.0;
+            bucket.refilled_at = now;
+            Duration::from_secs_f64((-bucket.tokens).max(0.0) / self.limit.requests_per_second)
+        };
+        tokio::time::sleep(wait).await;
+
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(RateLimit::per_second(50.0).burst(2));
+        let started = Instant::now();
+        for _ in 0..6 {
+            limiter.acquire().await;
+        }
+        // The first 2 requests are a burst, the next 4 wait 20ms each.
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(75), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+
+        let limiter = Arc::new(RateLimiter::new(
+            RateLimit::per_second(1000.0).max_concurrent(1),
+        ));
+        let permit = limiter.acquire().await;
+        assert!(permit.is_some());
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await.is_some() }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        drop(permit);
+        assert!(waiting.await.unwrap());
+    }
+}
@@ -0,0 +1,286 @@
+//! A versioned file format for recording the messages of a stream to disk.
+//!
+//! A recording is a NDJSON file whose first line is a [`Header`] describing the recording,
+//! followed by one message per line. Recordings made before the header was introduced (version 1)
+//! are plain NDJSON files and are still readable, [`migrate`] rewrites them in the current format.
+
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::FramedRead;
+
+use crate::codec::NdjsonCodec;
+
+/// The error that could happen while writing or reading a recording.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The error that could happen when reading or writing the recording.
+    #[error("Failed to access recording: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The error that could happen when serializing or deserializing a message.
+    #[error("Failed to deserialize message: {0}")]
+    Deserialization(#[from] serde_json::Error),
+
+    /// The error when a recording was written with a newer version of the format.
+    #[error("Unsupported recording version: {version}")]
+    UnsupportedRecording {
+        /// The version found in the header of the recording.
+        version: u32,
+    },
+
+    /// The error of the client, eg. when recording the messages of a replay.
+    #[error(transparent)]
+    Client(#[from] crate::Error),
+}
+
+impl Error {
+    /// Turns the error of decoding a line of the recording into an [`Error::Io`] or an
+    /// [`Error::Deserialization`].
+    fn from_codec(e: crate::Error) -> Self {
+        match e {
+            crate::Error::Io(e) => Error::Io(e),
+            crate::Error::Deserialization(e) => Error::Deserialization(e),
+            e => Error::Client(e),
+        }
+    }
+}
+
+/// The result of writing or reading a recording.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The value of [`Header::format`] identifying a recording.
+pub const FORMAT: &str = "tardis-rs-recording";
+
+/// The version of the format written by this crate.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// The kind of messages stored in a recording.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingKind {
+    /// Normalized messages, eg. `machine::Message`.
+    Normalized,
+
+    /// Raw exchange messages.
+    Raw,
+}
+
+/// The first line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Header {
+    /// Always [`FORMAT`].
+    pub format: String,
+
+    /// The version of the format the recording was written with.
+    pub version: u32,
+
+    /// The kind of messages stored in the recording.
+    pub kind: RecordingKind,
+
+    /// When the recording was created, `None` for recordings migrated from version 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+
+    /// The version of the crate that wrote the recording.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub crate_version: Option<String>,
+}
+
+impl Header {
+    /// Creates the header of a new recording in the current version.
+    pub fn new(kind: RecordingKind) -> Self {
+        Self {
+            format: FORMAT.to_string(),
+            version: CURRENT_VERSION,
+            kind,
+            created_at: Some(Utc::now()),
+            crate_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }
+    }
+
+    /// The header assumed for version 1 recordings, which don't have one.
+    fn legacy(kind: RecordingKind) -> Self {
+        Self {
+            format: FORMAT.to_string(),
+            version: 1,
+            kind,
+            created_at: None,
+            crate_version: None,
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        serde_json::from_str::<Header>(line)
+            .ok()
+            .filter(|header| header.format == FORMAT)
+    }
+}
+
+/// Writes messages to a recording in the current version of the format.
+pub struct RecordingWriter<W> {
+    writer: W,
+}
+
+impl<W> RecordingWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// Creates a new recording, writing its header straight away.
+    pub async fn new(writer: W, kind: RecordingKind) -> Result<Self> {
+        Self::with_header(writer, &Header::new(kind)).await
+    }
+
+    async fn with_header(writer: W, header: &Header) -> Result<Self> {
+        let mut recording = Self { writer };
+        recording.write(header).await?;
+        Ok(recording)
+    }
+
+    /// Appends a message to the recording.
+    pub async fn write<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// Appends an already serialized message to the recording, eg. the payload of a raw exchange
+    /// message. The payload must be a single line of JSON.
+    pub async fn write_raw(&mut self, payload: &[u8]) -> Result<()> {
+        self.writer.write_all(payload).await?;
+        self.writer.write_all(b"\n").await?;
+        Ok(())
+    }
+
+    /// Flushes the buffered messages and returns the underlying writer.
+    pub async fn finish(mut self) -> Result<W> {
+        self.writer.flush().await?;
+        Ok(self.writer)
+    }
+}
+
+/// Opens a recording of any supported version, returning its header and a stream of its messages.
+///
+/// Version 1 recordings have no header, `kind` is used to describe them.
+pub async fn read<T, R>(
+    mut reader: R,
+    kind: RecordingKind,
+) -> Result<(Header, impl Stream<Item = Result<T>>)>
+where
+    T: DeserializeOwned,
+    R: AsyncBufRead + Unpin,
+{
+    let mut first_line = String::new();
+    reader.read_line(&mut first_line).await?;
+
+    let (header, first) = match Header::parse(&first_line) {
+        Some(header) => (header, None),
+        None => (Header::legacy(kind), Some(first_line)),
+    };
+
+    if header.version > CURRENT_VERSION {
+        return Err(Error::UnsupportedRecording {
+            version: header.version,
+        });
+    }
+
+    let first = first
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str::<T>(&line).map_err(Error::from));
+    let messages = stream::iter(first).chain(
+        FramedRead::new(reader, NdjsonCodec::new())
+            .map(|message| message.map_err(Error::from_codec)),
+    );
+
+    Ok((header, messages))
+}
+
+/// Rewrites a recording of any supported version in the current version of the format, returning
+/// the header of the source recording.
+pub async fn migrate<R, W>(reader: R, writer: W, kind: RecordingKind) -> Result<Header>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let (source, messages) = read::<serde_json::Value, _>(reader, kind).await?;
+
+    let mut header = source.clone();
+    header.version = CURRENT_VERSION;
+    if header.crate_version.is_none() {
+        header.crate_version = Some(env!("CARGO_PKG_VERSION").to_string());
+    }
+
+    let mut recording = RecordingWriter::with_header(writer, &header).await?;
+    futures_util::pin_mut!(messages);
+    while let Some(message) = messages.next().await {
+        recording.write(&message?).await?;
+    }
+    recording.finish().await?;
+
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_and_read() {
+        let mut recording = RecordingWriter::new(vec![], RecordingKind::Normalized)
+            .await
+            .unwrap();
+        recording.write(&json!({"id": 1})).await.unwrap();
+        recording.write_raw(br#"{"id":2}"#).await.unwrap();
+        let file = recording.finish().await.unwrap();
+
+        let (header, messages) = read::<Value, _>(file.as_slice(), RecordingKind::Raw)
+            .await
+            .unwrap();
+        let messages = messages.map(|msg| msg.unwrap()).collect::<Vec<_>>().await;
+
+        assert_eq!(header.version, CURRENT_VERSION);
+        assert_eq!(header.kind, RecordingKind::Normalized);
+        assert_eq!(messages, vec![json!({"id": 1}), json!({"id": 2})]);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy() {
+        let legacy: &[u8] = b"{\"id\":1}\n{\"id\":2}\n";
+
+        let mut migrated = vec![];
+        let source = migrate(legacy, &mut migrated, RecordingKind::Normalized)
+            .await
+            .unwrap();
+        assert_eq!(source.version, 1);
+
+        let (header, messages) = read::<Value, _>(migrated.as_slice(), RecordingKind::Raw)
+            .await
+            .unwrap();
+        let messages = messages.map(|msg| msg.unwrap()).collect::<Vec<_>>().await;
+
+        assert_eq!(header.version, CURRENT_VERSION);
+        assert_eq!(header.kind, RecordingKind::Normalized);
+        assert_eq!(messages, vec![json!({"id": 1}), json!({"id": 2})]);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_version() {
+        let file = format!(
+            "{{\"format\":\"{}\",\"version\":{},\"kind\":\"raw\"}}\n",
+            FORMAT,
+            CURRENT_VERSION + 1
+        );
+
+        assert!(matches!(
+            read::<Value, _>(file.as_bytes(), RecordingKind::Raw).await,
+            Err(Error::UnsupportedRecording { .. })
+        ));
+    }
+}
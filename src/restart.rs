@@ -0,0 +1,375 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+
+/// A custom strategy computing the delay before restarting an operation.
+pub trait Backoff: fmt::Debug + Send + Sync {
+    /// Returns the delay before the given restart attempt, starting at 0 for the first restart.
+    fn delay(&self, attempt: u32) -> Duration;
+}
+
+/// A failure of an operation, which a [`RetryPolicy`] decides whether to retry.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Failure<'a> {
+    /// A HTTP request answered with an unsuccessful status.
+    Status(reqwest::StatusCode),
+
+    /// A HTTP request that failed to reach the server or to receive its response, eg. on a refused
+    /// connection or a timeout.
+    Request(&'a reqwest::Error),
+
+    /// A websocket connection to Tardis Machine that failed to be established.
+    #[cfg(feature = "machine")]
+    Connect(&'a crate::machine::Error),
+}
+
+/// Decides which failures are retried, the delays between the attempts being decided by the
+/// [`RestartPolicy`] it is set on, see [`RestartPolicy::retry_policy`].
+///
+/// ```
+/// use tardis_rs::{Failure, RestartPolicy, RetryPolicy};
+///
+/// /// Only retries the requests that were rate limited.
+/// #[derive(Debug)]
+/// struct RateLimitedOnly;
+///
+/// impl RetryPolicy for RateLimitedOnly {
+///     fn is_retryable(&self, failure: &Failure<'_>) -> bool {
+///         matches!(failure, Failure::Status(status) if status.as_u16() == 429)
+///     }
+/// }
+///
+/// let policy = RestartPolicy::default().max_attempts(5).retry_policy(RateLimitedOnly);
+/// ```
+pub trait RetryPolicy: fmt::Debug + Send + Sync {
+    /// Returns whether the operation is retried after `failure`.
+    fn is_retryable(&self, failure: &Failure<'_>) -> bool;
+}
+
+/// The [`RetryPolicy`] used by default, retrying the failures that are likely transient: HTTP
+/// requests that failed to connect or timed out, were rate limited (`429`) or hit a server error
+/// (`5xx`), and websocket connections that failed to connect or timed out.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DefaultRetryPolicy;
+
+impl RetryPolicy for DefaultRetryPolicy {
+    fn is_retryable(&self, failure: &Failure<'_>) -> bool {
+        match failure {
+            Failure::Status(status) => {
+                *status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            Failure::Request(e) => e.is_connect() || e.is_timeout(),
+            #[cfg(feature = "machine")]
+            Failure::Connect(e) => match e {
+                crate::machine::Error::ConnectFailed(e) => {
+                    matches!(**e, tokio_tungstenite::tungstenite::Error::Io(_))
+                }
+                e => matches!(e, crate::machine::Error::Timeout { .. }),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Strategy {
+    Fixed(Duration),
+    Exponential {
+        initial: Duration,
+        max: Duration,
+        jitter: bool,
+    },
+    DecorrelatedJitter {
+        base: Duration,
+        max: Duration,
+    },
+    Custom(Arc<dyn Backoff>),
+}
+
+/// Decides whether and when an operation that failed is restarted, eg. a dropped websocket
+/// connection or a failed HTTP request.
+///
+/// Every retrying part of the crate is configured with a [`RestartPolicy`], so the behavior can be
+/// tuned in one place:
+///
+/// ```
+/// use std::time::Duration;
+/// use tardis_rs::RestartPolicy;
+///
+/// let policy = RestartPolicy::exponential_with_jitter(Duration::from_millis(100), Duration::from_secs(10))
+///     .max_attempts(5);
+/// assert!(policy.delay(0).unwrap() <= Duration::from_millis(100));
+/// assert_eq!(policy.delay(5), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    strategy: Strategy,
+    max_attempts: Option<u32>,
+    retry_policy: Arc<dyn RetryPolicy>,
+}
+
+impl Default for RestartPolicy {
+    /// Exponential backoff with jitter from 500ms up to 30s, without any limit on the number of
+    /// attempts.
+    fn default() -> Self {
+        Self::exponential_with_jitter(Duration::from_millis(500), Duration::from_secs(30))
+    }
+}
+
+impl RestartPolicy {
+    /// Never restarts.
+    pub fn never() -> Self {
+        Self::fixed(Duration::ZERO).max_attempts(0)
+    }
+
+    /// Restarts after the same delay every time.
+    pub fn fixed(delay: Duration) -> Self {
+        Self {
+            strategy: Strategy::Fixed(delay),
+            max_attempts: None,
+            retry_policy: Arc::new(DefaultRetryPolicy),
+        }
+    }
+
+    /// Restarts after a delay starting at `initial` and doubling with every attempt, up to `max`.
+    pub fn exponential(initial: Duration, max: Duration) -> Self {
+        Self {
+            strategy: Strategy::Exponential {
+                initial,
+                max,
+                jitter: false,
+            },
+            max_attempts: None,
+            retry_policy: Arc::new(DefaultRetryPolicy),
+        }
+    }
+
+    /// Same as [`RestartPolicy::exponential`], with the delay picked randomly between zero and the
+    /// exponential delay ("full jitter"), so that many clients don't restart in lockstep.
+    pub fn exponential_with_jitter(initial: Duration, max: Duration) -> Self {
+        Self {
+            strategy: Strategy::Exponential {
+                initial,
+                max,
+                jitter: true,
+            },
+            max_attempts: None,
+            retry_policy: Arc::new(DefaultRetryPolicy),
+        }
+    }
+
+    /// Restarts after a delay picked randomly between `base` and three times the previous delay,
+    /// up to `max` ("decorrelated jitter"). Spreads restarts more evenly than
+    /// [`RestartPolicy::exponential_with_jitter`] while never restarting sooner than `base`.
+    pub fn decorrelated_jitter(base: Duration, max: Duration) -> Self {
+        Self {
+            strategy: Strategy::DecorrelatedJitter { base, max },
+            max_attempts: None,
+            retry_policy: Arc::new(DefaultRetryPolicy),
+        }
+    }
+
+    /// Restarts after the delays computed by a custom [`Backoff`].
+    pub fn custom(backoff: impl Backoff + 'static) -> Self {
+        Self {
+            strategy: Strategy::Custom(Arc::new(backoff)),
+            max_attempts: None,
+            retry_policy: Arc::new(DefaultRetryPolicy),
+        }
+    }
+
+    /// Gives up after the given number of restarts.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Sets which failures are retried, [`DefaultRetryPolicy`] by default. Only applies to the
+    /// HTTP requests and to establishing the websocket connections, the operations retried
+    /// otherwise deciding on their own, eg. reconnecting a dropped connection.
+    pub fn retry_policy(mut self, retry_policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Arc::new(retry_policy);
+        self
+    }
+
+    /// Returns whether the operation is retried after `failure`, as decided by the
+    /// [`RetryPolicy`], regardless of the attempts left.
+    pub fn is_retryable(&self, failure: &Failure<'_>) -> bool {
+        self.retry_policy.is_retryable(failure)
+    }
+
+    /// Returns the delay before the given restart attempt, starting at 0 for the first restart, or
+    /// `None` once the attempts are exhausted.
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt >= max) {
+            return None;
+        }
+
+        Some(match &self.strategy {
+            Strategy::Fixed(delay) => *delay,
+            Strategy::Exponential {
+                initial,
+                max,
+                jitter,
+            } => {
+                let delay = initial
+                    .checked_mul(2u32.saturating_pow(attempt))
+                    .map_or(*max, |delay| delay.min(*max));
+                if *jitter {
+                    delay.mul_f64(random_fraction())
+                } else {
+                    delay
+                }
+            }
+            Strategy::DecorrelatedJitter { base, max } => {
+                // Replays the chain of previous delays, which settles well within the bound.
+                let mut delay = *base;
+                for _ in 0..attempt.min(64) {
+                    let upper = delay.saturating_mul(3).min(*max).max(*base);
+                    delay = *base + (upper - *base).mul_f64(random_fraction());
+                }
+                delay.min(*max)
+            }
+            Strategy::Custom(backoff) => backoff.delay(attempt),
+        })
+    }
+
+    /// Returns the longest delay the policy ever waits, or `None` if it is unknown, eg. with a
+    /// [`Backoff`] of your own.
+    pub fn max_delay(&self) -> Option<Duration> {
+        match &self.strategy {
+            Strategy::Fixed(delay) => Some(*delay),
+            Strategy::Exponential { max, .. } | Strategy::DecorrelatedJitter { max, .. } => {
+                Some(*max)
+            }
+            Strategy::Custom(_) => None,
+        }
+    }
+
+    /// Runs `operation` until it succeeds, sleeping between attempts as decided by the policy and
+    /// returning the last error once the attempts are exhausted or `is_retryable` rejects it.
+    pub async fn retry<T, E, F, Fut>(
+        &self,
+        mut operation: F,
+        is_retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(e) => match self.delay(attempt).filter(|_| is_retryable(&e)) {
+                    Some(delay) => {
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+/// Returns a random number in `[0, 1)`, good enough for jitter without pulling a RNG dependency.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_delays() {
+        let ms = Duration::from_millis;
+
+        let fixed = RestartPolicy::fixed(ms(10)).max_attempts(2);
+        assert_eq!(fixed.delay(1), Some(ms(10)));
+        assert_eq!(fixed.delay(2), None);
+
+        let exponential = RestartPolicy::exponential(ms(100), ms(1_000));
+        let delays = (0..6).map(|attempt| exponential.delay(attempt).unwrap());
+        assert_eq!(
+            delays.collect::<Vec<_>>(),
+            vec![ms(100), ms(200), ms(400), ms(800), ms(1_000), ms(1_000)]
+        );
+        assert_eq!(exponential.delay(u32::MAX), Some(ms(1_000)));
+
+        let jitter = RestartPolicy::exponential_with_jitter(ms(100), ms(1_000));
+        assert!((0..100).all(|attempt| jitter.delay(attempt).unwrap() <= ms(1_000)));
+
+        let decorrelated = RestartPolicy::decorrelated_jitter(ms(100), ms(1_000));
+        assert_eq!(decorrelated.delay(0), Some(ms(100)));
+        assert!((0..100).all(|attempt| {
+            let delay = decorrelated.delay(attempt).unwrap();
+            ms(100) <= delay && delay <= ms(1_000)
+        }));
+        assert_eq!(
+            decorrelated.delay(u32::MAX).map(|d| d <= ms(1_000)),
+            Some(true)
+        );
+
+        #[derive(Debug)]
+        struct Linear;
+        impl Backoff for Linear {
+            fn delay(&self, attempt: u32) -> Duration {
+                Duration::from_secs(attempt.into())
+            }
+        }
+        assert_eq!(RestartPolicy::custom(Linear).delay(3), Some(ms(3_000)));
+        assert_eq!(RestartPolicy::never().delay(0), None);
+
+        assert_eq!(fixed.max_delay(), Some(ms(10)));
+        assert_eq!(jitter.max_delay(), Some(ms(1_000)));
+        assert_eq!(decorrelated.max_delay(), Some(ms(1_000)));
+        assert_eq!(RestartPolicy::custom(Linear).max_delay(), None);
+    }
+
+    #[test]
+    fn test_default_retry_policy() {
+        let policy = RestartPolicy::default();
+        let status = |code| Failure::Status(reqwest::StatusCode::from_u16(code).unwrap());
+        assert!(policy.is_retryable(&status(429)));
+        assert!(policy.is_retryable(&status(503)));
+        assert!(!policy.is_retryable(&status(404)));
+        assert!(!policy.is_retryable(&status(401)));
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let calls = AtomicU32::new(0);
+        let policy = RestartPolicy::fixed(Duration::from_millis(1)).max_attempts(3);
+
+        let result = policy
+            .retry(
+                || async { Err::<(), _>(calls.fetch_add(1, Ordering::SeqCst)) },
+                |_| true,
+            )
+            .await;
+        assert_eq!(result, Err(3));
+
+        let result = policy
+            .retry(
+                || async { Err::<(), _>(calls.fetch_add(1, Ordering::SeqCst)) },
+                |_| false,
+            )
+            .await;
+        assert_eq!(result, Err(4));
+    }
+}
@@ -0,0 +1,500 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt};
+
+use super::{Error, Result, RotatingFile, Rotation, Timestamped};
+use crate::machine::{
+    BookChange, BookSnapshot, BookTicker, DerivativeTicker, Liquidation, OptionSummary, Trade,
+    TradeBar,
+};
+
+/// The rows of CSV written for a message.
+#[derive(Debug)]
+pub struct CsvRows {
+    writer: csv::Writer<Vec<u8>>,
+}
+
+impl CsvRows {
+    pub(super) fn new() -> Self {
+        Self {
+            writer: Self::writer(),
+        }
+    }
+
+    fn writer() -> csv::Writer<Vec<u8>> {
+        csv::WriterBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_writer(Vec::new())
+    }
+
+    /// Appends a row.
+    pub fn push<I, F>(&mut self, row: I)
+    where
+        I: IntoIterator<Item = F>,
+        F: AsRef<[u8]>,
+    {
+        self.writer
+            .write_record(row)
+            .expect("writing to memory can't fail");
+    }
+
+    fn take(&mut self) -> Vec<u8> {
+        std::mem::replace(&mut self.writer, Self::writer())
+            .into_inner()
+            .expect("writing to memory can't fail")
+    }
+
+    /// Takes the rows written so far, parsed back into their fields.
+    #[cfg(feature = "parquet")]
+    pub(super) fn take_records(&mut self) -> Vec<csv::StringRecord> {
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(&self.take()[..])
+            .into_records()
+            .map(|record| record.expect("rows written as CSV parse back"))
+            .collect()
+    }
+}
+
+/// A normalized message that can be written as CSV, in the layout of the matching
+/// [Tardis dataset](https://docs.tardis.dev/downloadable-csv-files#data-types) where there is one.
+///
+/// Timestamps are written as microseconds since the epoch, and missing values as empty fields.
+pub trait CsvRecord: Timestamped {
+    /// Returns the names of the columns, which may depend on the message, eg. the depth of a book
+    /// snapshot.
+    fn header(&self) -> Vec<String>;
+
+    /// Writes the rows of the message, most messages having a single one.
+    fn write_rows(&self, rows: &mut CsvRows);
+}
+
+fn micros(timestamp: &DateTime<Utc>) -> String {
+    timestamp.timestamp_micros().to_string()
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn header(columns: &[&str]) -> Vec<String> {
+    columns.iter().map(|column| column.to_string()).collect()
+}
+
+macro_rules! timestamped {
+    ($($ty:ty),*) => {
+        $(
+            impl Timestamped for $ty {
+                fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+                    Some(self.local_timestamp)
+                }
+            }
+        )*
+    };
+}
+
+timestamped!(
+    Trade,
+    Liquidation,
+    BookChange,
+    DerivativeTicker,
+    BookTicker,
+    OptionSummary,
+    BookSnapshot,
+    TradeBar
+);
+
+const TRADE_COLUMNS: &[&str] = &[
+    "exchange",
+    "symbol",
+    "timestamp",
+    "local_timestamp",
+    "id",
+    "side",
+    "price",
+    "amount",
+];
+
+impl CsvRecord for Trade {
+    fn header(&self) -> Vec<String> {
+        header(TRADE_COLUMNS)
+    }
+
+    fn write_rows(&self, rows: &mut CsvRows) {
+        rows.push([
+            self.exchange.to_string(),
+            self.symbol.to_string(),
+            micros(&self.timestamp),
+            micros(&self.local_timestamp),
+            self.id.clone().unwrap_or_default(),
+            self.side.to_string(),
+            self.price.to_string(),
+            self.amount.to_string(),
+        ]);
+    }
+}
+
+impl CsvRecord for Liquidation {
+    fn header(&self) -> Vec<String> {
+        header(TRADE_COLUMNS)
+    }
+
+    fn write_rows(&self, rows: &mut CsvRows) {
+        rows.push([
+            self.exchange.to_string(),
+            self.symbol.to_string(),
+            micros(&self.timestamp),
+            micros(&self.local_timestamp),
+            self.id.clone().unwrap_or_default(),
+            self.side.to_string(),
+            self.price.to_string(),
+            self.amount.to_string(),
+        ]);
+    }
+}
+
+impl CsvRecord for BookChange {
+    fn header(&self) -> Vec<String> {
+        header(&[
+            "exchange",
+            "symbol",
+            "timestamp",
+            "local_timestamp",
+            "is_snapshot",
+            "side",
+            "price",
+            "amount",
+        ])
+    }
+
+    /// Writes a row per level, as in the `incremental_book_L2` dataset.
+    fn write_rows(&self, rows: &mut CsvRows) {
+        let levels = self
+            .bids
+            .iter()
+            .map(|level| ("bid", level))
+            .chain(self.asks.iter().map(|level| ("ask", level)));
+        for (side, level) in levels {
+            rows.push([
+                self.exchange.to_string(),
+                self.symbol.to_string(),
+                micros(&self.timestamp),
+                micros(&self.local_timestamp),
+                self.is_snapshot.to_string(),
+                side.to_string(),
+                level.price.to_string(),
+                level.amount.to_string(),
+            ]);
+        }
+    }
+}
+
+impl CsvRecord for DerivativeTicker {
+    fn header(&self) -> Vec<String> {
+        header(&[
+            "exchange",
+            "symbol",
+            "timestamp",
+            "local_timestamp",
+            "funding_rate",
+            "open_interest",
+            "last_price",
+            "index_price",
+            "mark_price",
+        ])
+    }
+
+    fn write_rows(&self, rows: &mut CsvRows) {
+        rows.push([
+            self.exchange.to_string(),
+            self.symbol.to_string(),
+            micros(&self.timestamp),
+            micros(&self.local_timestamp),
+            optional(self.funding_rate),
+            optional(self.open_interest),
+            optional(self.last_price),
+            optional(self.index_price),
+            optional(self.mark_price),
+        ]);
+    }
+}
+
+impl CsvRecord for BookTicker {
+    fn header(&self) -> Vec<String> {
+        header(&[
+            "exchange",
+            "symbol",
+            "timestamp",
+            "local_timestamp",
+            "ask_amount",
+            "ask_price",
+            "bid_price",
+            "bid_amount",
+        ])
+    }
+
+    fn write_rows(&self, rows: &mut CsvRows) {
+        rows.push([
+            self.exchange.to_string(),
+            self.symbol.to_string(),
+            micros(&self.timestamp),
+            micros(&self.local_timestamp),
+            optional(self.ask_amount),
+            optional(self.ask_price),
+            optional(self.bid_price),
+            optional(self.bid_amount),
+        ]);
+    }
+}
+
+impl CsvRecord for OptionSummary {
+    fn header(&self) -> Vec<String> {
+        header(&[
+            "exchange",
+            "symbol",
+            "timestamp",
+            "local_timestamp",
+            "type",
+            "strike_price",
+            "expiration",
+            "open_interest",
+            "last_price",
+            "bid_price",
+            "bid_amount",
+            "bid_iv",
+            "ask_price",
+            "ask_amount",
+            "ask_iv",
+            "mark_price",
+            "mark_iv",
+            "underlying_index",
+            "underlying_price",
+            "delta",
+            "gamma",
+            "vega",
+            "theta",
+            "rho",
+        ])
+    }
+
+    fn write_rows(&self, rows: &mut CsvRows) {
+        rows.push([
+            self.exchange.to_string(),
+            self.symbol.to_string(),
+            micros(&self.timestamp),
+            micros(&self.local_timestamp),
+            self.option_type.to_string(),
+            self.strike_price.to_string(),
+            micros(&self.expiration_date),
+            optional(self.open_interest),
+            optional(self.last_price),
+            optional(self.best_bid_price),
+            optional(self.best_bid_amount),
+            optional(self.best_bid_iv),
+            optional(self.best_ask_price),
+            optional(self.best_ask_amount),
+            optional(self.best_ask_iv),
+            optional(self.mark_price),
+            optional(self.mark_iv),
+            self.underlying_index.clone(),
+            optional(self.underlying_price),
+            optional(self.delta),
+            optional(self.gamma),
+            optional(self.vega),
+            optional(self.theta),
+            optional(self.rho),
+        ]);
+    }
+}
+
+impl CsvRecord for BookSnapshot {
+    /// Names the columns of every level up to the depth of the snapshot, eg. `asks[0].price`.
+    fn header(&self) -> Vec<String> {
+        let mut columns = header(&["exchange", "symbol", "timestamp", "local_timestamp"]);
+        for i in 0..self.depth {
+            for side in ["asks", "bids"] {
+                columns.push(format!("{}[{}].price", side, i));
+                columns.push(format!("{}[{}].amount", side, i));
+            }
+        }
+        columns
+    }
+
+    fn write_rows(&self, rows: &mut CsvRows) {
+        let mut row = vec![
+            self.exchange.to_string(),
+            self.symbol.to_string(),
+            micros(&self.timestamp),
+            micros(&self.local_timestamp),
+        ];
+        for i in 0..self.depth as usize {
+            for levels in [&self.asks, &self.bids] {
+                let level = levels.get(i);
+                row.push(optional(level.map(|level| level.price)));
+                row.push(optional(level.map(|level| level.amount)));
+            }
+        }
+        rows.push(row);
+    }
+}
+
+impl CsvRecord for TradeBar {
+    fn header(&self) -> Vec<String> {
+        header(&[
+            "exchange",
+            "symbol",
+            "timestamp",
+            "local_timestamp",
+            "name",
+            "interval",
+            "open",
+            "high",
+            "low",
+            "close",
+            "volume",
+            "buy_volume",
+            "sell_volume",
+            "trades",
+            "vwap",
+            "open_timestamp",
+            "close_timestamp",
+        ])
+    }
+
+    fn write_rows(&self, rows: &mut CsvRows) {
+        rows.push([
+            self.exchange.to_string(),
+            self.symbol.to_string(),
+            micros(&self.timestamp),
+            micros(&self.local_timestamp),
+            self.name.clone(),
+            self.interval.to_string(),
+            self.open.to_string(),
+            self.high.to_string(),
+            self.low.to_string(),
+            self.close.to_string(),
+            self.volume.to_string(),
+            self.buy_volume.to_string(),
+            self.sell_volume.to_string(),
+            self.trades.to_string(),
+            self.vwap.to_string(),
+            micros(&self.open_timestamp),
+            micros(&self.close_timestamp),
+        ]);
+    }
+}
+
+/// Writes normalized messages of one type to CSV files, every file starting with a header.
+///
+/// Pairs with [`TypedStreams`](crate::machine::split::TypedStreams) to write every type of a
+/// stream to its own file.
+#[derive(Debug)]
+pub struct CsvSink {
+    file: RotatingFile,
+    rows: CsvRows,
+}
+
+impl CsvSink {
+    /// Creates a new instance of [`CsvSink`] writing to `path`. The file is only created with the
+    /// first message.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            file: RotatingFile::new(path.as_ref()),
+            rows: CsvRows::new(),
+        }
+    }
+
+    /// Sets when to start writing to a new file, [`Rotation::Never`] by default.
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.file.rotation = rotation;
+        self
+    }
+
+    /// Appends the rows of a message.
+    pub async fn write<T: CsvRecord>(&mut self, message: &T) -> Result<()> {
+        message.write_rows(&mut self.rows);
+        let bytes = self.rows.take();
+        let rows = &mut self.rows;
+        self.file
+            .write(&bytes, message.local_timestamp(), || {
+                rows.push(message.header());
+                rows.take()
+            })
+            .await
+    }
+
+    /// Appends every message of a stream, stopping at the first error. Returns the number of
+    /// messages written.
+    pub async fn write_all<S, T, E>(&mut self, messages: S) -> Result<u64>
+    where
+        S: Stream<Item = std::result::Result<T, E>>,
+        T: CsvRecord,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        futures_util::pin_mut!(messages);
+        let mut written = 0;
+        while let Some(message) = messages.next().await {
+            let message = match message {
+                Ok(message) => message,
+                Err(e) => {
+                    // Flushed so that the file holds every message counted as written.
+                    self.file.flush().await?;
+                    return Err(Error::Stream(Box::new(e)));
+                }
+            };
+            self.write(&message).await?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Flushes the buffered rows to the current file and closes it.
+    pub async fn finish(mut self) -> Result<()> {
+        self.file.close().await
+    }
+}
+
+/// Writes every message of a stream to `path` as CSV, see [`CsvSink`]. Returns the number of
+/// messages written.
+pub async fn write_csv<S, T, E>(messages: S, path: impl AsRef<Path>) -> Result<u64>
+where
+    S: Stream<Item = std::result::Result<T, E>>,
+    T: CsvRecord,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let mut sink = CsvSink::new(path);
+    // Finished even if the stream fails, keeping the messages written before the error.
+    let written = sink.write_all(messages).await;
+    sink.finish().await?;
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_csv() {
+        let dir = super::super::tests::test_dir("csv");
+        let path = dir.join("trades.csv");
+        let trades = [
+            r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":"a","price":7996,"amount":50,"side":"sell","timestamp":"2019-10-23T10:32:49.669Z","localTimestamp":"2019-10-23T10:32:49.740Z"}"#,
+            r#"{"type":"trade","symbol":"XBTUSD","exchange":"bitmex","id":null,"price":7996.5,"amount":1,"side":"buy","timestamp":"2019-10-23T10:32:50.000Z","localTimestamp":"2019-10-23T10:32:50.100Z"}"#,
+        ]
+        .map(serde_json::from_str::<Trade>);
+
+        let written = write_csv(futures_util::stream::iter(trades), &path)
+            .await
+            .unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "exchange,symbol,timestamp,local_timestamp,id,side,price,amount\n\
+             bitmex,XBTUSD,1571826769669000,1571826769740000,a,sell,7996,50\n\
+             bitmex,XBTUSD,1571826770000000,1571826770100000,,buy,7996.5,1\n"
+        );
+    }
+}
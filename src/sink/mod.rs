@@ -0,0 +1,399 @@
+//! Writing streams of messages to files, eg. to record a replay to disk.
+//!
+//! [`NdjsonSink`] writes any serializable message as one line of JSON, and [`CsvSink`] writes the
+//! normalized messages of one type in the CSV layout of the Tardis datasets. Both can start new
+//! files as they go, see [`Rotation`].
+//!
+//! With the `parquet` feature, `ParquetSink` writes the same layout to a Parquet file with typed
+//! columns, from normalized messages or downloaded datasets.
+//!
+//! ```ignore
+//! let messages = client.replay_normalized(options).await?;
+//! tardis_rs::sink::write_ndjson(messages, "bitmex.ndjson").await?;
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+};
+
+/// The error that could happen while writing messages to a sink.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The error yielded by the stream being written.
+    #[error("Failed to receive message: {0}")]
+    Stream(Box<dyn std::error::Error + Send + Sync>),
+
+    /// The error that could happen when creating or writing a file.
+    #[error("Failed to write file: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The error that could happen when serializing a message.
+    #[error("Failed to serialize message: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    /// The error of the client, eg. when reading the dataset converted by
+    /// [`convert_to_parquet`](crate::datasets::convert_to_parquet).
+    #[error(transparent)]
+    Client(#[from] crate::Error),
+
+    /// The error when writing a Parquet file, see `ParquetSink`.
+    #[cfg(feature = "parquet")]
+    #[error("Failed to write Parquet: {0}")]
+    Parquet(#[from] ::parquet::errors::ParquetError),
+
+    /// The error when rows can't be converted to Arrow arrays, eg. a field of a numeric column
+    /// that isn't a number.
+    #[cfg(feature = "parquet")]
+    #[error("Invalid Arrow data: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+}
+
+/// The result of writing to a sink.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// When a sink starts writing to a new file.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Rotation {
+    /// Writes every message to the file at the given path.
+    #[default]
+    Never,
+
+    /// Starts a new file once the current one holds at least the given number of bytes. The files
+    /// are numbered, eg. `trades.0.csv`, `trades.1.csv` for the path `trades.csv`.
+    Size(u64),
+
+    /// Starts a new file for every UTC day of the messages, eg. `trades.2022-10-01.csv` for the
+    /// path `trades.csv`.
+    Daily,
+}
+
+/// A message knowing when it was received, which decides its file under [`Rotation::Daily`].
+pub trait Timestamped {
+    /// Returns when the message was received, `None` if unknown, in which case the current time
+    /// is used instead.
+    fn local_timestamp(&self) -> Option<DateTime<Utc>>;
+}
+
+impl Timestamped for serde_json::Value {
+    /// Reads the `localTimestamp` field of normalized messages.
+    fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.get("localTimestamp")?.as_str()?.parse().ok()
+    }
+}
+
+#[cfg(feature = "machine")]
+impl Timestamped for crate::machine::Message {
+    fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        crate::machine::Message::local_timestamp(self)
+    }
+}
+
+#[cfg(feature = "machine")]
+impl Timestamped for crate::machine::RawMessage {
+    /// Raw exchange messages don't carry their arrival time.
+    fn local_timestamp(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+}
+
+/// A file of a sink, rotated as decided by its [`Rotation`].
+///
+/// Files are created along with their directory, and appended to when they already exist.
+#[derive(Debug)]
+struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: Option<BufWriter<File>>,
+    len: u64,
+    index: u32,
+    day: Option<NaiveDate>,
+}
+
+impl RotatingFile {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            rotation: Rotation::Never,
+            file: None,
+            len: 0,
+            index: 0,
+            day: None,
+        }
+    }
+
+    /// Writes `bytes` to the file the message received at `timestamp` belongs to, starting with
+    /// the `header` if the file is new.
+    async fn write(
+        &mut self,
+        bytes: &[u8],
+        timestamp: Option<DateTime<Utc>>,
+        header: impl FnOnce() -> Vec<u8>,
+    ) -> Result<()> {
+        match self.rotation {
+            Rotation::Never if self.file.is_none() => self.open(self.path.clone()).await?,
+            Rotation::Never => {}
+            Rotation::Size(size) => {
+                if self.file.is_none() || self.len >= size {
+                    if self.file.is_some() {
+                        self.index += 1;
+                    }
+                    self.open(self.rotated_path(&self.index.to_string()))
+                        .await?;
+                    // Skips the files filled up by a previous run.
+                    while self.len >= size {
+                        self.index += 1;
+                        self.open(self.rotated_path(&self.index.to_string()))
+                            .await?;
+                    }
+                }
+            }
+            Rotation::Daily => {
+                let day = timestamp.unwrap_or_else(Utc::now).date_naive();
+                if self.day != Some(day) || self.file.is_none() {
+                    self.open(self.rotated_path(&day.format("%Y-%m-%d").to_string()))
+                        .await?;
+                    self.day = Some(day);
+                }
+            }
+        }
+
+        let file = self.file.as_mut().expect("file opened above");
+        if self.len == 0 {
+            let header = header();
+            file.write_all(&header).await?;
+            self.len += header.len() as u64;
+        }
+        file.write_all(bytes).await?;
+        self.len += bytes.len() as u64;
+        Ok(())
+    }
+
+    async fn open(&mut self, path: PathBuf) -> Result<()> {
+        self.flush().await?;
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        self.len = file.metadata().await?.len();
+        self.file = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    /// Returns the path with `suffix` inserted before the extension.
+    fn rotated_path(&self, suffix: &str) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(extension) => format!("{}.{}.{}", stem, suffix, extension.to_string_lossy()),
+            None => format!("{}.{}", stem, suffix),
+        };
+        self.path.with_file_name(name)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        if let Some(file) = &mut self.file {
+            file.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes and shuts down the current file.
+    async fn close(&mut self) -> Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes messages to files as newline-delimited JSON (NDJSON).
+///
+/// Unlike a [`RecordingWriter`](crate::recording::RecordingWriter), the files have no header, so
+/// they can be read back with any NDJSON tool.
+#[derive(Debug)]
+pub struct NdjsonSink {
+    file: RotatingFile,
+}
+
+impl NdjsonSink {
+    /// Creates a new instance of [`NdjsonSink`] writing to `path`. The file is only created with
+    /// the first message.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            file: RotatingFile::new(path.as_ref()),
+        }
+    }
+
+    /// Sets when to start writing to a new file, [`Rotation::Never`] by default.
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.file.rotation = rotation;
+        self
+    }
+
+    /// Appends a message.
+    pub async fn write<T: Serialize + Timestamped>(&mut self, message: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.file
+            .write(&line, message.local_timestamp(), Vec::new)
+            .await
+    }
+
+    /// Appends every message of a stream, stopping at the first error. Returns the number of
+    /// messages written.
+    pub async fn write_all<S, T, E>(&mut self, messages: S) -> Result<u64>
+    where
+        S: Stream<Item = std::result::Result<T, E>>,
+        T: Serialize + Timestamped,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        futures_util::pin_mut!(messages);
+        let mut written = 0;
+        while let Some(message) = messages.next().await {
+            let message = match message {
+                Ok(message) => message,
+                Err(e) => {
+                    // Flushed so that the file holds every message counted as written.
+                    self.file.flush().await?;
+                    return Err(Error::Stream(Box::new(e)));
+                }
+            };
+            self.write(&message).await?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Flushes the buffered messages to the current file and closes it.
+    pub async fn finish(mut self) -> Result<()> {
+        self.file.close().await
+    }
+}
+
+/// Writes every message of a stream to `path` as NDJSON, see [`NdjsonSink`]. Returns the number
+/// of messages written.
+pub async fn write_ndjson<S, T, E>(messages: S, path: impl AsRef<Path>) -> Result<u64>
+where
+    S: Stream<Item = std::result::Result<T, E>>,
+    T: Serialize + Timestamped,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let mut sink = NdjsonSink::new(path);
+    // Finished even if the stream fails, keeping the messages written before the error.
+    let written = sink.write_all(messages).await;
+    sink.finish().await?;
+    written
+}
+
+#[cfg(feature = "machine")]
+mod csv;
+#[cfg(feature = "machine")]
+pub use self::csv::*;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "parquet")]
+pub use self::parquet::*;
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Returns an empty directory for the files of a test.
+    pub(super) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tardis-rs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_daily() {
+        let dir = test_dir("ndjson-daily");
+        let messages = [
+            json!({"id": 1, "localTimestamp": "2022-10-01T23:59:59.000Z"}),
+            json!({"id": 2, "localTimestamp": "2022-10-02T00:00:00.000Z"}),
+            json!({"id": 3, "localTimestamp": "2022-10-02T00:00:01.000Z"}),
+        ];
+
+        let mut sink = NdjsonSink::new(dir.join("messages.ndjson")).rotation(Rotation::Daily);
+        let written = sink
+            .write_all(futures_util::stream::iter(
+                messages.iter().cloned().map(Ok::<_, Error>),
+            ))
+            .await
+            .unwrap();
+        sink.finish().await.unwrap();
+        assert_eq!(written, 3);
+
+        let read = |day: &str| {
+            std::fs::read_to_string(dir.join(format!("messages.{}.ndjson", day)))
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<Value>(line).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(read("2022-10-01"), messages[..1]);
+        assert_eq!(read("2022-10-02"), messages[1..]);
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_size() {
+        let dir = test_dir("ndjson-size");
+        let path = dir.join("messages.ndjson");
+        let messages = (0..5).map(|id| Ok::<_, Error>(json!({ "id": id })));
+
+        write_ndjson(futures_util::stream::iter(messages.clone().take(1)), &path)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"id\":0}\n");
+
+        let mut sink = NdjsonSink::new(&path).rotation(Rotation::Size(18));
+        sink.write_all(futures_util::stream::iter(messages))
+            .await
+            .unwrap();
+        sink.finish().await.unwrap();
+        let read = |index: u32| {
+            std::fs::read_to_string(dir.join(format!("messages.{}.ndjson", index))).unwrap()
+        };
+        assert_eq!(read(0), "{\"id\":0}\n{\"id\":1}\n");
+        assert_eq!(read(1), "{\"id\":2}\n{\"id\":3}\n");
+        assert_eq!(read(2), "{\"id\":4}\n");
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_stream_error() {
+        let dir = test_dir("ndjson-error");
+        let path = dir.join("messages.ndjson");
+        let messages = || {
+            futures_util::stream::iter((0..3).map(|id| Ok(json!({ "id": id })))).chain(
+                futures_util::stream::once(async { Err(std::io::Error::other("disconnected")) }),
+            )
+        };
+
+        // The messages written before the error are on disk, even without finishing the sink.
+        let mut sink = NdjsonSink::new(&path);
+        assert!(matches!(
+            sink.write_all(messages()).await,
+            Err(Error::Stream(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+        drop(sink);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(write_ndjson(messages(), &path).await.is_err());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"id\":0}\n{\"id\":1}\n{\"id\":2}\n"
+        );
+    }
+}
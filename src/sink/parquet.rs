@@ -0,0 +1,247 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use arrow_schema::SchemaRef;
+use csv::StringRecord;
+use parquet::{
+    arrow::AsyncArrowWriter,
+    basic::{Compression, ZstdLevel},
+    file::properties::WriterProperties,
+};
+
+use super::Result;
+use crate::arrow::{batch_from_rows, schema};
+
+/// Writes rows in the CSV layout of the
+/// [Tardis datasets](https://docs.tardis.dev/downloadable-csv-files#data-types) to a Parquet
+/// file, with typed columns: timestamps in microseconds, strings, booleans, integers and floats,
+/// empty fields being null.
+///
+/// The rows are either the normalized messages of a stream, see [`ParquetSink::write`], or the
+/// rows of a downloaded dataset, see
+/// [`convert_to_parquet`](crate::datasets::convert_to_parquet).
+///
+/// Unlike the other sinks, a Parquet file can't be appended to, so an existing file is replaced,
+/// and is only readable once [finished](ParquetSink::finish).
+pub struct ParquetSink {
+    path: PathBuf,
+    compression: Compression,
+    batch_size: usize,
+    schema: Option<SchemaRef>,
+    rows: Vec<StringRecord>,
+    writer: Option<AsyncArrowWriter<tokio::fs::File>>,
+}
+
+impl fmt::Debug for ParquetSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParquetSink")
+            .field("path", &self.path)
+            .field("compression", &self.compression)
+            .field("batch_size", &self.batch_size)
+            .field("schema", &self.schema)
+            .field("rows", &self.rows.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ParquetSink {
+    /// Creates a new instance of [`ParquetSink`] writing to `path`. The file is only created with
+    /// the first batch of rows.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            compression: Compression::UNCOMPRESSED,
+            batch_size: 100_000,
+            schema: None,
+            rows: vec![],
+            writer: None,
+        }
+    }
+
+    /// Compresses the file with zstd at `level`, between 1 and 22, uncompressed by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` is out of range.
+    pub fn zstd(mut self, level: i32) -> Self {
+        let level = ZstdLevel::try_new(level).expect("zstd level must be between 1 and 22");
+        self.compression = Compression::ZSTD(level);
+        self
+    }
+
+    /// Sets the number of rows buffered before being written as a row group of the file, 100 000
+    /// by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is zero.
+    pub fn batch_size(mut self, rows: usize) -> Self {
+        assert!(rows > 0, "batch size must not be zero");
+        self.batch_size = rows;
+        self
+    }
+
+    /// Appends a row, the schema of the file being decided by the `columns` of the first one.
+    pub(crate) async fn write_row(
+        &mut self,
+        columns: impl FnOnce() -> Vec<String>,
+        row: StringRecord,
+    ) -> Result<()> {
+        if self.schema.is_none() {
+            self.schema = Some(schema(&columns()));
+        }
+        self.rows.push(row);
+        if self.rows.len() >= self.batch_size {
+            self.write_batch().await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered rows to the file, creating it if needed.
+    async fn write_batch(&mut self) -> Result<()> {
+        let Some(schema) = &self.schema else {
+            return Ok(());
+        };
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let batch = batch_from_rows(schema, &self.rows)?;
+        self.rows.clear();
+
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    tokio::fs::create_dir_all(dir).await?;
+                }
+                let file = tokio::fs::File::create(&self.path).await?;
+                let properties = WriterProperties::builder()
+                    .set_compression(self.compression)
+                    .set_max_row_group_size(self.batch_size)
+                    .build();
+                self.writer.insert(AsyncArrowWriter::try_new(
+                    file,
+                    schema.clone(),
+                    Some(properties),
+                )?)
+            }
+        };
+        writer.write(&batch).await?;
+        Ok(())
+    }
+
+    /// Writes the buffered rows and the footer of the file.
+    pub async fn finish(mut self) -> Result<()> {
+        self.write_batch().await?;
+        if let Some(writer) = self.writer {
+            writer.close().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "machine")]
+mod messages {
+    use futures_util::{Stream, StreamExt};
+
+    use super::*;
+    use crate::sink::{CsvRecord, CsvRows, Error};
+
+    impl ParquetSink {
+        /// Appends the rows of a normalized message, in the layout of its [`CsvRecord`].
+        pub async fn write<T: CsvRecord>(&mut self, message: &T) -> Result<()> {
+            let mut rows = CsvRows::new();
+            message.write_rows(&mut rows);
+            for row in rows.take_records() {
+                self.write_row(|| message.header(), row).await?;
+            }
+            Ok(())
+        }
+
+        /// Appends every message of a stream, stopping at the first error. Returns the number of
+        /// messages written.
+        pub async fn write_all<S, T, E>(&mut self, messages: S) -> Result<u64>
+        where
+            S: Stream<Item = std::result::Result<T, E>>,
+            T: CsvRecord,
+            E: std::error::Error + Send + Sync + 'static,
+        {
+            futures_util::pin_mut!(messages);
+            let mut written = 0;
+            while let Some(message) = messages.next().await {
+                self.write(&message.map_err(|e| Error::Stream(Box::new(e)))?)
+                    .await?;
+                written += 1;
+            }
+            Ok(written)
+        }
+    }
+
+    /// Writes every message of a stream to `path` as Parquet, see [`ParquetSink`]. Returns the
+    /// number of messages written.
+    pub async fn write_parquet<S, T, E>(messages: S, path: impl AsRef<Path>) -> Result<u64>
+    where
+        S: Stream<Item = std::result::Result<T, E>>,
+        T: CsvRecord,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let mut sink = ParquetSink::new(path);
+        // Finished even if the stream fails, keeping the messages written before the error.
+        let written = sink.write_all(messages).await;
+        sink.finish().await?;
+        written
+    }
+}
+
+#[cfg(feature = "machine")]
+pub use messages::*;
+
+#[cfg(test)]
+mod tests {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_parquet_sink() {
+        let dir = super::super::tests::test_dir("parquet");
+        let path = dir.join("trades.parquet");
+        let columns = || {
+            ["symbol", "local_timestamp", "price"]
+                .map(String::from)
+                .to_vec()
+        };
+
+        let mut sink = ParquetSink::new(&path).zstd(3).batch_size(2);
+        for i in 0..5i64 {
+            let row = StringRecord::from(vec![
+                "BTCUSDT".to_string(),
+                (1664582400000000 + i).to_string(),
+                (19310 + i).to_string(),
+            ]);
+            sink.write_row(columns, row).await.unwrap();
+        }
+        sink.finish().await.unwrap();
+
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 3);
+        // The level isn't stored in the file.
+        assert!(matches!(
+            reader.metadata().row_group(0).column(2).compression(),
+            Compression::ZSTD(_)
+        ));
+        let batches = reader
+            .build()
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            batches.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+            5
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! An HTTP server for the tests, answering each request with the reply of a handler.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// A request received by a [`TestServer`], up to its first 4 KiB.
+#[derive(Debug, Clone)]
+pub(crate) struct Request {
+    /// The request line, headers and start of the body
+    pub(crate) text: String,
+}
+
+impl Request {
+    /// Returns the request line, eg. `GET /v1/exchanges HTTP/1.1`.
+    pub(crate) fn line(&self) -> &str {
+        self.text.lines().next().unwrap_or_default()
+    }
+
+    /// Returns the requested path, with its query.
+    pub(crate) fn path(&self) -> &str {
+        self.line().split(' ').nth(1).unwrap_or_default()
+    }
+
+    /// Returns the value of the header `name`, compared case-insensitively.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.text
+            .lines()
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.eq_ignore_ascii_case(name).then(|| value.trim())
+            })
+    }
+}
+
+/// A response of a [`TestServer`], closing the connection once sent.
+#[derive(Debug, Clone)]
+pub(crate) struct Reply {
+    status: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    /// Creates a reply with the status line `status`, eg. `404 Not Found`, and `body`.
+    pub(crate) fn new(status: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: status.to_string(),
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    /// Creates a `200 OK` reply of `body`.
+    pub(crate) fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::new("200 OK", body)
+    }
+
+    /// Creates a `200 OK` JSON reply of `body`.
+    pub(crate) fn json(body: impl Into<Vec<u8>>) -> Self {
+        Self::ok(body).header("content-type", "application/json")
+    }
+
+    /// Adds a header.
+    pub(crate) fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut head = format!("HTTP/1.1 {}\r\n", self.status);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!(
+            "content-length: {}\r\nconnection: close\r\n\r\n",
+            self.body.len()
+        ));
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+/// A running test server, see [`serve`].
+#[derive(Debug, Clone)]
+pub(crate) struct TestServer {
+    /// The base URL, eg. `http://127.0.0.1:8000`
+    pub(crate) url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl TestServer {
+    /// Returns the requests received so far.
+    pub(crate) fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Returns the paths requested so far.
+    pub(crate) fn paths(&self) -> Vec<String> {
+        self.requests()
+            .iter()
+            .map(|request| request.path().to_string())
+            .collect()
+    }
+}
+
+/// Serves every request with the reply of `handler`, one connection at a time. The server stops
+/// once the handler returns `None`, dropping that connection.
+pub(crate) async fn serve<F>(handler: F) -> TestServer
+where
+    F: FnMut(&Request) -> Option<Reply> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = TestServer {
+        url: format!("http://{}", listener.local_addr().unwrap()),
+        requests: Default::default(),
+    };
+    tokio::spawn(run(listener, handler, server.requests.clone()));
+    server
+}
+
+/// Serves the `replies` to the successive requests, then stops.
+pub(crate) async fn serve_replies(replies: Vec<Reply>) -> TestServer {
+    let mut replies = replies.into_iter();
+    serve(move |_| replies.next()).await
+}
+
+/// Serves every request with `reply`.
+pub(crate) async fn serve_reply(reply: Reply) -> TestServer {
+    serve(move |_| Some(reply.clone())).await
+}
+
+/// Like [`serve`], but nothing listens on the port of the server until `delay` has elapsed.
+pub(crate) async fn serve_after<F>(delay: Duration, handler: F) -> TestServer
+where
+    F: FnMut(&Request) -> Option<Reply> + Send + 'static,
+{
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = TestServer {
+        url: format!("http://{}", addr),
+        requests: Default::default(),
+    };
+    let requests = server.requests.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        let listener = TcpListener::bind(addr).await.unwrap();
+        run(listener, handler, requests).await;
+    });
+    server
+}
+
+async fn run<F>(listener: TcpListener, mut handler: F, requests: Arc<Mutex<Vec<Request>>>)
+where
+    F: FnMut(&Request) -> Option<Reply>,
+{
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let mut buf = vec![0; 4096];
+        let Ok(len) = stream.read(&mut buf).await else {
+            continue;
+        };
+        let request = Request {
+            text: String::from_utf8_lossy(&buf[..len]).into_owned(),
+        };
+        let reply = handler(&request);
+        requests.lock().unwrap().push(request);
+        match reply {
+            Some(reply) => {
+                let _ = stream.write_all(&reply.to_bytes()).await;
+            }
+            None => return,
+        }
+    }
+}